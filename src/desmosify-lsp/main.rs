@@ -0,0 +1,12 @@
+fn main() {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    match desmosify::lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(error) => {
+            eprintln!("desmosify-lsp: {error}");
+            std::process::exit(1);
+        },
+    }
+}
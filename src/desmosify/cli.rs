@@ -1,62 +1,447 @@
-use crate::target::Target;
+use crate::link::SourceFile;
+use crate::optimize::OptimizationLevel;
+use crate::target::{DEFAULT_TARGET, TargetOptions, TargetRegistry};
 
-use std::io::{Read, Write};
-use clap::Parser;
+use std::collections::BTreeMap;
+use std::io::Write;
+use clap::{Parser, Subcommand};
+use json::JsonValue;
 
 #[derive(Parser, Debug)]
-#[command(author, version)]
+#[command(author, version, subcommand_negates_reqs = true)]
 pub struct DesmosifyArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long)]
     src: Vec<String>,
-    #[arg(short, long)]
-    out: String,
+    /// Where to write the output; several source files are linked into one program
+    #[arg(short, long, required_unless_present_any = ["list_targets", "out_dir"])]
+    out: Option<String>,
+    /// Compile each source file on its own, writing '<name>.json' into this directory
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["out", "import"])]
+    out_dir: Option<String>,
     #[arg(long)]
     debug: bool,
+    /// The target to compile for, overriding any '#target' pragma in the source
+    #[arg(short, long)]
+    target: Option<String>,
+    /// List the available targets and their options, then exit
+    #[arg(long)]
+    list_targets: bool,
+    /// Set a target-specific option, e.g. '-T order=source' (may be repeated)
+    #[arg(short = 'T', long = "target-option", value_name = "KEY=VALUE", value_parser = parse_target_option)]
+    target_options: Vec<(String, String)>,
+    /// How definitions are ordered: 'dependency' or 'source' (same as '-T order=...')
+    #[arg(long)]
+    order: Option<String>,
+    /// How definitions are grouped into folders: 'section', 'file', or 'kind' (same as '-T group=...')
+    #[arg(long)]
+    group: Option<String>,
+    /// Decompile a Desmos graph state (JSON) into desmosify source instead of compiling
+    #[arg(long, value_name = "GRAPH_JSON", conflicts_with = "src")]
+    import: Option<String>,
+    /// Keep running, and recompile whenever a source file changes
+    #[arg(short, long, conflicts_with = "import")]
+    watch: bool,
+    /// How much to optimize: '1' folds constants and simplifies expressions, '2' (the level of a bare '-O') also
+    /// inlines constants and small definitions
+    #[arg(short = 'O', long = "opt-level", value_name = "LEVEL", num_args = 0..=1, default_value = "0", default_missing_value = "2")]
+    opt_level: OptimizationLevel,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Rewrite source files in the canonical style
+    Fmt(FormatArgs),
+    /// Start an interactive session for trying out declarations, expressions and actions
+    Repl(ReplArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FormatArgs {
+    /// The source files to format
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Report files that are not formatted instead of rewriting them
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplArgs {
+    /// Source files to load before the session starts
+    files: Vec<String>,
+}
+
+fn parse_target_option(option: &str) -> Result<(String, String), String> {
+    option.split_once('=')
+        .map(|(key, value)| (String::from(key.trim()), String::from(value.trim())))
+        .ok_or_else(|| String::from("expected 'KEY=VALUE'"))
 }
 
 impl DesmosifyArgs {
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
     pub fn source_paths(&self) -> &[String] {
         &self.src
     }
 
     pub fn output_path(&self) -> &str {
-        &self.out
+        self.out.as_deref().unwrap_or_default()
+    }
+
+    pub fn output_directory(&self) -> Option<&str> {
+        self.out_dir.as_deref()
     }
 
     pub fn is_debug(&self) -> bool {
         self.debug
     }
+
+    pub fn optimization_level(&self) -> OptimizationLevel {
+        self.opt_level
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watch
+    }
+
+    pub fn import_path(&self) -> Option<&str> {
+        self.import.as_deref()
+    }
+
+    pub fn target_name(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    pub fn target_options(&self) -> TargetOptions {
+        let mut options = TargetOptions::new();
+        if let Some(order) = &self.order {
+            options.insert(String::from("order"), order.clone());
+        }
+        if let Some(group) = &self.group {
+            options.insert(String::from("group"), group.clone());
+        }
+        options.extend(self.target_options.iter().cloned());
+        options
+    }
 }
 
 pub fn parse_command_line_args() -> DesmosifyArgs {
     DesmosifyArgs::parse()
 }
 
-pub fn invoke(args: &DesmosifyArgs) -> Result<(), crate::DesmosifyError> {
+pub fn list_targets(registry: &TargetRegistry) {
+    print!("{}", describe_targets(registry));
+}
+
+/// Describes every target in `registry` and its options, as printed by `--list-targets`.
+pub fn describe_targets(registry: &TargetRegistry) -> String {
+    let mut description = String::from("Available targets:\n");
+    for info in registry.iter() {
+        let default_marker = if info.name == DEFAULT_TARGET { " (default)" } else { "" };
+        description.push_str(&format!("  {}{default_marker} - {}\n", info.name, info.description));
+        for (option, option_description) in info.options {
+            description.push_str(&format!("      -T {option}=...  {option_description}\n"));
+        }
+    }
+    description
+}
+
+/// Compiles the source of one file (and the modules it imports) for `target_name`, or for the target selected by its
+/// `#target` pragma if `None`.
+pub fn compile_source(registry: &TargetRegistry, source: &str, file_name: &str, target_name: Option<&str>, options: &TargetOptions) -> Result<JsonValue, Vec<crate::DesmosifyError>> {
+    compile_sources(registry, &[SourceFile::new(file_name, source)], target_name, options)
+}
+
+/// Links several source files into one program and compiles it, like `compile_source` does for a single file.
+pub fn compile_sources(registry: &TargetRegistry, files: &[SourceFile], target_name: Option<&str>, options: &TargetOptions) -> Result<JsonValue, Vec<crate::DesmosifyError>> {
+    compile_with_warnings(registry, files, target_name, options, OptimizationLevel::default()).map(|(output, _)| output)
+}
+
+/// Like `compile_sources`, but optimizes the program at `level` and also returns warnings about it, such as
+/// definitions left out because nothing uses them and folds which may be undefined for long lists.
+pub fn compile_with_warnings(registry: &TargetRegistry, files: &[SourceFile], target_name: Option<&str>, options: &TargetOptions, level: OptimizationLevel) -> Result<(JsonValue, Vec<crate::DesmosifyError>), Vec<crate::DesmosifyError>> {
+    let (mut signatures, mut definitions) = crate::link::link(files)?;
+    let mut warnings = crate::dependencies::remove_unreachable(&mut signatures, &mut definitions);
+    warnings.extend(crate::higher_order::unbounded_folds(&definitions));
+    if level != OptimizationLevel::None {
+        crate::optimize::optimize(&mut signatures, &mut definitions, level);
+        // Definitions which were inlined everywhere are no longer needed, which is not worth a warning
+        crate::dependencies::remove_unreachable(&mut signatures, &mut definitions);
+    }
+
+    let target_name = target_name
+        .or(definitions.target.as_deref())
+        .unwrap_or(DEFAULT_TARGET);
+    let target = registry.create(target_name, options)?;
+    let output = target.compile(&definitions, &signatures)?;
+    Ok((output, warnings))
+}
+
+pub fn invoke(args: &DesmosifyArgs) -> Result<(), Vec<crate::DesmosifyError>> {
+    let registry = TargetRegistry::default();
+    if args.list_targets {
+        list_targets(&registry);
+        return Ok(());
+    }
+
+    match args.command() {
+        Some(Command::Fmt(format_args)) => return format_files(format_args),
+        Some(Command::Repl(repl_args)) => return repl(repl_args),
+        None => {},
+    }
+
+    if let Some(import_path) = args.import_path() {
+        return import(import_path, args.output_path());
+    }
+
+    if args.is_watching() {
+        return watch(&registry, args);
+    }
+
+    compile_files(&registry, args)
+}
+
+/// Compiles the source files given on the command line, writing the output only if it changed. With an output
+/// directory each file is compiled on its own; otherwise they are all linked into a single program.
+pub fn compile_files(registry: &TargetRegistry, args: &DesmosifyArgs) -> Result<(), Vec<crate::DesmosifyError>> {
+    let mut files = Vec::new();
     for source_path in args.source_paths() {
-        println!("Compiling '{source_path}'...");
+        let source = std::fs::read_to_string(source_path)
+            .map_err(|err| crate::DesmosifyError::new(format!("could not read '{source_path}': {err}"), None, None))?;
+        files.push(SourceFile::new(source_path, &source));
+    }
 
-        let mut source_file = std::fs::File::open(source_path)
-            .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
-        let mut source = String::new();
-        source_file.read_to_string(&mut source)
-            .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    if let Some(output_directory) = args.output_directory() {
+        return compile_to_directory(registry, args, &files, output_directory);
+    }
 
-        let tokens = crate::token::tokenize(&source)?;
-        let (signatures, mut definitions) = crate::syntax::parse(&tokens)?;
-        crate::semantics::analyze(&signatures, &mut definitions)?;
+    match files.as_slice() {
+        [file] => println!("Compiling '{}'...", file.name),
+        _ => println!("Linking {}...", Vec::from_iter(files.iter().map(|file| format!("'{}'", file.name))).join(", ")),
+    }
+    let (output, warnings) = compile_with_warnings(registry, &files, args.target_name(), &args.target_options(), args.optimization_level())?;
+    print_warnings(&warnings);
+    write_output(args.output_path(), &output)?;
+    Ok(())
+}
 
-        let target = crate::target::desmos::GeometryTarget;
-        let output = target.compile(&definitions, &signatures);
+/// Compiles each file on its own into `output_directory`, named after the file. Every file is attempted even if an
+/// earlier one fails.
+fn compile_to_directory(registry: &TargetRegistry, args: &DesmosifyArgs, files: &[SourceFile], output_directory: &str) -> Result<(), Vec<crate::DesmosifyError>> {
+    let mut output_paths = Vec::new();
+    let mut written_by: BTreeMap<String, &str> = BTreeMap::new();
+    for file in files {
+        let stem = std::path::Path::new(&file.name).file_stem().unwrap_or_default().to_string_lossy();
+        let output_path = std::path::Path::new(output_directory).join(format!("{stem}.json")).to_string_lossy().into_owned();
+        if let Some(original) = written_by.insert(output_path.clone(), &file.name) {
+            return Err(vec![crate::DesmosifyError::new(
+                format!("'{original}' and '{}' would both be written to '{output_path}'", file.name),
+                None,
+                None,
+            )]);
+        }
+        output_paths.push(output_path);
+    }
+    std::fs::create_dir_all(output_directory)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
 
-        let output_path = args.output_path();
-        let mut output_file = std::fs::File::create(output_path)
-            .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
-        write!(output_file, "{output}")
-            .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    let mut errors = Vec::new();
+    for (file, output_path) in files.iter().zip(&output_paths) {
+        println!("Compiling '{}'...", file.name);
+        let result = compile_with_warnings(registry, std::slice::from_ref(file), args.target_name(), &args.target_options(), args.optimization_level())
+            .and_then(|(output, warnings)| {
+                print_warnings(&Vec::from_iter(warnings.into_iter().map(|warning| warning.in_file(&file.name))));
+                write_output(output_path, &output).map_err(|error| vec![error])
+            });
+        if let Err(file_errors) = result {
+            errors.extend(file_errors.into_iter().map(|error| error.in_file(&file.name)));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn print_warnings(warnings: &[crate::DesmosifyError]) {
+    for warning in warnings {
+        println!("\x1b[33mWarning: {warning}\x1b[0m");
+    }
+}
 
+fn write_output(output_path: &str, output: &JsonValue) -> Result<(), crate::DesmosifyError> {
+    if write_if_changed(output_path, &output.to_string())? {
         println!("Successfully written to '{output_path}'.");
+    } else {
+        println!("'{output_path}' is already up to date.");
+    }
+    Ok(())
+}
+
+/// Writes `contents` to `path` unless the file already holds exactly that, so that tools watching the output (such as
+/// an editor or file sync) are not disturbed needlessly. Returns whether the file was written.
+pub fn write_if_changed(path: &str, contents: &str) -> Result<bool, crate::DesmosifyError> {
+    if std::fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return Ok(false);
+    }
+    std::fs::write(path, contents)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    Ok(true)
+}
+
+/// Prints the outcome of a run, in the same way whether it ran once or in watch mode.
+pub fn report(result: &Result<(), Vec<crate::DesmosifyError>>, time_taken: std::time::Duration) {
+    match result {
+        Err(errors) => for error in errors {
+            println!("\x1b[31mError: {error}\x1b[0m");
+        },
+        Ok(_) => println!("\x1b[32mFinished\x1b[0m"),
+    }
+    println!("\x1b[2mTime: {} ms\x1b[22m", time_taken.as_millis());
+}
+
+/// How often watch mode checks the source files for changes.
+pub const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Notices changes to a set of files by comparing their contents between polls, which (unlike modification times)
+/// ignores saves that change nothing and works the same on every platform.
+pub struct Watcher {
+    contents: BTreeMap<String, Option<String>>,
+}
+
+impl Watcher {
+    pub fn new(paths: &[String]) -> Self {
+        Self {
+            contents: paths.iter().map(|path| (path.clone(), None)).collect(),
+        }
+    }
+
+    /// Reads every file again, returning whether any of them changed (or appeared or disappeared) since the last poll.
+    /// Nothing has been read before the first poll, so it reports a change if any of the files exist.
+    pub fn poll(&mut self) -> bool {
+        let mut is_changed = false;
+        for (path, contents) in &mut self.contents {
+            let current = std::fs::read_to_string(path).ok();
+            if current != *contents {
+                *contents = current;
+                is_changed = true;
+            }
+        }
+        is_changed
     }
 
+    /// Starts watching `paths` as well, taking their current contents as unchanged.
+    pub fn add(&mut self, paths: &[String]) {
+        for path in paths {
+            if !self.contents.contains_key(path) {
+                self.contents.insert(path.clone(), std::fs::read_to_string(path).ok());
+            }
+        }
+    }
+}
+
+/// Compiles the source files every time they or the modules they import change, until interrupted.
+pub fn watch(registry: &TargetRegistry, args: &DesmosifyArgs) -> Result<(), Vec<crate::DesmosifyError>> {
+    let mut watcher = Watcher::new(args.source_paths());
+    let mut is_first = true;
+    loop {
+        if watcher.poll() || is_first {
+            is_first = false;
+            let start_time = std::time::Instant::now();
+            let result = compile_files(registry, args);
+            report(&result, start_time.elapsed());
+            let files = Vec::from_iter(args.source_paths().iter()
+                .filter_map(|path| std::fs::read_to_string(path).ok().map(|source| SourceFile::new(path, &source))));
+            watcher.add(&crate::link::module_paths(&files));
+            println!("Watching for changes (press Ctrl+C to stop)...");
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+pub fn import(import_path: &str, output_path: &str) -> Result<(), Vec<crate::DesmosifyError>> {
+    println!("Importing '{import_path}'...");
+
+    let state = std::fs::read_to_string(import_path)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    let state = json::parse(&state)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    let source = crate::target::desmos::decompile::decompile(&state)?;
+
+    let mut output_file = std::fs::File::create(output_path)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    write!(output_file, "{source}")
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+
+    println!("Successfully written to '{output_path}'.");
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn format_files(args: &FormatArgs) -> Result<(), Vec<crate::DesmosifyError>> {
+    let mut errors = Vec::new();
+    for path in &args.files {
+        println!("{} '{path}'...", if args.check { "Checking" } else { "Formatting" });
+
+        // A file that can't be read or written is reported, and the other files are still formatted
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                errors.push(crate::DesmosifyError::new(format!("could not read '{path}': {err}"), None, None));
+                continue;
+            }
+        };
+        let formatted = match crate::format::format(&source) {
+            Ok(formatted) => formatted,
+            Err(file_errors) => {
+                errors.extend(file_errors);
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+        if args.check {
+            let line = source.lines().zip(formatted.lines())
+                .position(|(original, formatted)| original != formatted)
+                .unwrap_or_else(|| source.lines().count().min(formatted.lines().count()));
+            errors.push(crate::DesmosifyError::new(
+                format!("'{path}' is not formatted (first difference on line {})", line + 1),
+                None,
+                None,
+            ));
+        } else {
+            match std::fs::write(path, formatted) {
+                Ok(()) => println!("Reformatted '{path}'."),
+                Err(err) => errors.push(crate::DesmosifyError::new(format!("could not write '{path}': {err}"), None, None)),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn repl(args: &ReplArgs) -> Result<(), Vec<crate::DesmosifyError>> {
+    let mut repl = crate::repl::Repl::new();
+    for path in &args.files {
+        for line in repl.execute(&format!(":load {path}"))? {
+            println!("{line}");
+        }
+    }
+    println!("Enter ':help' for help or ':quit' to exit.");
+
+    let stdin = std::io::stdin();
+    crate::repl::run(&mut repl, stdin.lock(), std::io::stdout(), true)
+        .map_err(|err| crate::DesmosifyError::new(err.to_string(), None, None))?;
+    Ok(())
+}
@@ -0,0 +1,645 @@
+//! A concrete syntax tree: unlike the definitions built by `syntax::parse`, it keeps every token of the source in
+//! order along with the whitespace and comments between them, so that `Node::text` reproduces the source exactly. It
+//! only records the shape of the source, and accepts some programs that `syntax::parse` would reject.
+
+use super::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NodeKind {
+    File,
+    Pragma,
+    Import,
+    Public,
+    Ticker,
+    Display,
+    DisplayElement,
+    Attribute,
+    Section,
+    Action,
+    Const,
+    Let,
+    Var,
+    Enum,
+    Parameters,
+    Parameter,
+    Type,
+    /// `{ ... }` containing actions separated by commas.
+    ActionBlock,
+    /// `if ...: ... elif ...: ... else: ...` inside an action block.
+    ActionIf,
+    Expression,
+    /// `( ... )` in operand position: parentheses or a point.
+    Parenthesized,
+    /// `[ ... ]` in operand position: a list literal, fill, range or comprehension.
+    List,
+    /// `{ ... }` in operand position: a piecewise expression.
+    Conditional,
+    /// `( ... )` following an operand.
+    Arguments,
+    /// `[ ... ]` following an operand.
+    Index,
+}
+
+impl NodeKind {
+    pub fn is_group(self) -> bool {
+        matches!(self, Self::Parenthesized | Self::List | Self::Conditional | Self::Arguments | Self::Index)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Element {
+    Node(Node),
+    Token { token: Token, text: String },
+    Trivia(Trivia),
+}
+
+impl Element {
+    pub fn node(&self) -> Option<&Node> {
+        match self {
+            Self::Node(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Self::Token { token, .. } => Some(token),
+            _ => None,
+        }
+    }
+
+    pub fn trivia(&self) -> Option<&Trivia> {
+        match self {
+            Self::Trivia(trivia) => Some(trivia),
+            _ => None,
+        }
+    }
+
+    pub fn is_symbol(&self, symbol: Symbol) -> bool {
+        self.token().is_some_and(|token| token.value == TokenValue::Symbol(symbol))
+    }
+
+    pub fn is_keyword(&self, keyword: Keyword) -> bool {
+        self.token().is_some_and(|token| token.value == TokenValue::Keyword(keyword))
+    }
+
+    fn write_text(&self, text: &mut String) {
+        match self {
+            Self::Node(node) => node.write_text(text),
+            Self::Token { text: token_text, .. } => text.push_str(token_text),
+            Self::Trivia(trivia) => text.push_str(&trivia.text),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Self { kind, children: Vec::new() }
+    }
+
+    /// The source text covered by this node, including trivia.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text);
+        text
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in &self.children {
+            child.write_text(text);
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(Element::node)
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(Element::token)
+    }
+
+    /// Every token under this node, in source order.
+    pub fn descendant_tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token { token, .. } => tokens.push(token),
+                Element::Trivia(_) => {},
+            }
+        }
+    }
+}
+
+/// Builds the tree. Trivia is attached to the outermost node that starts after it, or to the node containing the next
+/// token if no node starts there, so comments between declarations end up as children of the file.
+struct Builder<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    trivia: Vec<Trivia>,
+    token_index: usize,
+    trivia_index: usize,
+    stack: Vec<Node>,
+}
+
+impl<'a> Builder<'a> {
+    fn token(&self) -> Result<&Token, DesmosifyError> {
+        self.tokens.get(self.token_index).ok_or_else(|| {
+            let location = self.tokens.last().map(|token| token.end);
+            DesmosifyError::new(String::from("unexpected end of file"), location, location)
+        })
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.token_index >= self.tokens.len()
+    }
+
+    fn is_at_symbol(&self, symbol: Symbol) -> bool {
+        self.tokens.get(self.token_index).is_some_and(|token| token.value == TokenValue::Symbol(symbol))
+    }
+
+    fn is_at_keyword(&self, keyword: Keyword) -> bool {
+        self.tokens.get(self.token_index).is_some_and(|token| token.value == TokenValue::Keyword(keyword))
+    }
+
+    fn is_at_one_of(&self, symbols: &[Symbol], keywords: &[Keyword]) -> bool {
+        self.tokens.get(self.token_index).is_some_and(|token| token.is_one_of(symbols, keywords))
+    }
+
+    fn flush_trivia(&mut self, before: usize) {
+        while let Some(trivia) = self.trivia.get(self.trivia_index) {
+            if trivia.start.index >= before {
+                break;
+            }
+            self.stack.last_mut().unwrap().children.push(Element::Trivia(trivia.clone()));
+            self.trivia_index += 1;
+        }
+    }
+
+    fn start(&mut self, kind: NodeKind) {
+        let before = self.tokens.get(self.token_index).map_or(usize::MAX, |token| token.start.index);
+        self.flush_trivia(before);
+        self.stack.push(Node::new(kind));
+    }
+
+    fn finish(&mut self) {
+        let node = self.stack.pop().unwrap();
+        self.stack.last_mut().unwrap().children.push(Element::Node(node));
+    }
+
+    fn bump(&mut self) -> Result<(), DesmosifyError> {
+        let token = self.token()?.clone();
+        self.flush_trivia(token.start.index);
+        let text = String::from(&self.source[token.start.index..token.end.index]);
+        self.stack.last_mut().unwrap().children.push(Element::Token { token, text });
+        self.token_index += 1;
+        Ok(())
+    }
+
+    fn expect_symbol(&mut self, symbol: Symbol) -> Result<(), DesmosifyError> {
+        if self.is_at_symbol(symbol) {
+            self.bump()
+        } else {
+            let token = self.token()?;
+            Err(DesmosifyError::new(format!("expected '{symbol}'"), Some(token.start), Some(token.end)))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<(), DesmosifyError> {
+        if let TokenValue::Name(_) = self.token()?.value {
+            self.bump()
+        } else {
+            let token = self.token()?;
+            Err(DesmosifyError::new(String::from("expected a name"), Some(token.start), Some(token.end)))
+        }
+    }
+
+    /// A name, possibly qualified as in `easing::ease_out`.
+    fn expect_path(&mut self) -> Result<(), DesmosifyError> {
+        self.expect_name()?;
+        while self.is_at_symbol(Symbol::Colon2) {
+            self.bump()?;
+            self.expect_name()?;
+        }
+        Ok(())
+    }
+
+    fn expect_string(&mut self) -> Result<(), DesmosifyError> {
+        if let TokenValue::String(_) = self.token()?.value {
+            self.bump()
+        } else {
+            let token = self.token()?;
+            Err(DesmosifyError::new(String::from("expected a string"), Some(token.start), Some(token.end)))
+        }
+    }
+
+    fn file(&mut self) -> Result<(), DesmosifyError> {
+        while !self.is_at_end() {
+            self.declaration()?;
+        }
+        self.flush_trivia(usize::MAX);
+        Ok(())
+    }
+
+    /// Starts the node for a declaration, taking the `pub` in front of it if there is one.
+    fn start_declaration(&mut self, kind: NodeKind) -> Result<(), DesmosifyError> {
+        self.start(kind);
+        if self.is_at_keyword(Keyword::Pub) {
+            self.bump()?;
+        }
+        Ok(())
+    }
+
+    fn declaration(&mut self) -> Result<(), DesmosifyError> {
+        let token = if self.is_at_keyword(Keyword::Pub) {
+            let token = self.tokens.get(self.token_index + 1)
+                .filter(|token| token.is_one_of(&[], &[Keyword::Action, Keyword::Const, Keyword::Let, Keyword::Var, Keyword::Enum]));
+            match token {
+                Some(token) => token,
+                None => {
+                    let token = self.token()?;
+                    return Err(DesmosifyError::new(
+                        String::from("expected a declaration after 'pub'"),
+                        Some(token.start),
+                        Some(token.end),
+                    ));
+                },
+            }
+        } else {
+            self.token()?
+        };
+        match token.value {
+            TokenValue::Symbol(Symbol::Semicolon) => self.bump(),
+            TokenValue::Symbol(Symbol::Hash) => {
+                self.start(NodeKind::Pragma);
+                self.bump()?;
+                self.expect_name()?;
+                self.expect_string()?;
+                self.expect_symbol(Symbol::Semicolon)?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Public) => {
+                self.start(NodeKind::Public);
+                self.bump()?;
+                self.expect_symbol(Symbol::CurlyLeft)?;
+                while !self.is_at_symbol(Symbol::CurlyRight) {
+                    if self.is_at_symbol(Symbol::Semicolon) {
+                        self.bump()?;
+                    } else {
+                        self.expression(&[Symbol::Semicolon, Symbol::CurlyRight], &[])?;
+                    }
+                }
+                self.bump()?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Ticker) => {
+                self.start(NodeKind::Ticker);
+                self.bump()?;
+                if self.is_at_symbol(Symbol::ParenLeft) {
+                    self.bump()?;
+                    self.expression(&[Symbol::ParenRight], &[])?;
+                    self.expect_symbol(Symbol::ParenRight)?;
+                }
+                self.action_block()?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Display) => {
+                self.start(NodeKind::Display);
+                self.bump()?;
+                self.expect_symbol(Symbol::CurlyLeft)?;
+                while !self.is_at_symbol(Symbol::CurlyRight) {
+                    if self.is_at_symbol(Symbol::Semicolon) {
+                        self.bump()?;
+                    } else {
+                        self.display_element()?;
+                    }
+                }
+                self.bump()?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Import) => {
+                self.start(NodeKind::Import);
+                self.bump()?;
+                if let TokenValue::String(_) = self.token()?.value {
+                    self.bump()?;
+                } else {
+                    self.expect_name()?;
+                    if self.is_at_symbol(Symbol::Colon2) {
+                        self.bump()?;
+                        self.expect_symbol(Symbol::CurlyLeft)?;
+                        while !self.is_at_symbol(Symbol::CurlyRight) {
+                            self.expect_name()?;
+                            if self.is_at_symbol(Symbol::Comma) {
+                                self.bump()?;
+                            }
+                        }
+                        self.bump()?;
+                    }
+                }
+                self.expect_symbol(Symbol::Semicolon)?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Section) => {
+                self.start(NodeKind::Section);
+                self.bump()?;
+                self.expect_string()?;
+                self.expect_symbol(Symbol::Semicolon)?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Action) => {
+                self.start_declaration(NodeKind::Action)?;
+                self.bump()?;
+                self.expect_name()?;
+                self.parameters()?;
+                self.action_block()?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(keyword @ (Keyword::Const | Keyword::Let | Keyword::Var)) => {
+                self.start_declaration(match keyword {
+                    Keyword::Const => NodeKind::Const,
+                    Keyword::Let => NodeKind::Let,
+                    _ => NodeKind::Var,
+                })?;
+                self.bump()?;
+                if keyword == Keyword::Var && self.is_at_keyword(Keyword::Timer) {
+                    self.bump()?;
+                }
+                self.expect_name()?;
+                if keyword != Keyword::Var && self.is_at_symbol(Symbol::ParenLeft) {
+                    self.parameters()?;
+                }
+                if self.is_at_symbol(Symbol::Colon) {
+                    self.bump()?;
+                    self.data_type(&[Symbol::Equal])?;
+                }
+                self.expect_symbol(Symbol::Equal)?;
+                self.expression(&[Symbol::Semicolon], &[])?;
+                self.expect_symbol(Symbol::Semicolon)?;
+                self.finish();
+                Ok(())
+            },
+            TokenValue::Keyword(Keyword::Enum) => {
+                self.start_declaration(NodeKind::Enum)?;
+                self.bump()?;
+                self.expect_name()?;
+                self.expect_symbol(Symbol::CurlyLeft)?;
+                while !self.is_at_symbol(Symbol::CurlyRight) {
+                    self.expect_name()?;
+                    if self.is_at_symbol(Symbol::Comma) {
+                        self.bump()?;
+                    }
+                }
+                self.bump()?;
+                self.finish();
+                Ok(())
+            },
+            _ => Err(DesmosifyError::new(
+                String::from("expected a declaration"),
+                Some(token.start),
+                Some(token.end),
+            )),
+        }
+    }
+
+    fn parameters(&mut self) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::Parameters);
+        self.expect_symbol(Symbol::ParenLeft)?;
+        while !self.is_at_symbol(Symbol::ParenRight) {
+            self.start(NodeKind::Parameter);
+            self.expect_name()?;
+            if self.is_at_symbol(Symbol::Colon) {
+                self.bump()?;
+                self.data_type(&[Symbol::Equal, Symbol::Comma, Symbol::ParenRight])?;
+            }
+            if self.is_at_symbol(Symbol::Equal) {
+                self.bump()?;
+                self.expression(&[Symbol::Comma, Symbol::ParenRight], &[])?;
+            }
+            self.finish();
+            if self.is_at_symbol(Symbol::Comma) {
+                self.bump()?;
+            }
+        }
+        self.bump()?;
+        self.finish();
+        Ok(())
+    }
+
+    fn data_type(&mut self, end_symbols: &[Symbol]) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::Type);
+        while !self.is_at_one_of(end_symbols, &[]) {
+            self.bump()?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    fn action_block(&mut self) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::ActionBlock);
+        self.expect_symbol(Symbol::CurlyLeft)?;
+        while !self.is_at_symbol(Symbol::CurlyRight) {
+            if self.is_at_keyword(Keyword::If) {
+                self.action_if()?;
+            } else {
+                self.expression(&[Symbol::Comma, Symbol::CurlyRight], &[])?;
+            }
+            if self.is_at_symbol(Symbol::Comma) {
+                self.bump()?;
+            }
+        }
+        self.bump()?;
+        self.finish();
+        Ok(())
+    }
+
+    fn action_if(&mut self) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::ActionIf);
+        self.bump()?;
+        self.expression(&[Symbol::Colon], &[])?;
+        self.bump()?;
+        self.action_branch()?;
+        while self.is_at_keyword(Keyword::Elif) {
+            self.bump()?;
+            self.expression(&[Symbol::Colon], &[])?;
+            self.bump()?;
+            self.action_branch()?;
+        }
+        if self.is_at_keyword(Keyword::Else) {
+            self.bump()?;
+            self.expect_symbol(Symbol::Colon)?;
+            self.action_branch()?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    fn action_branch(&mut self) -> Result<(), DesmosifyError> {
+        if self.is_at_symbol(Symbol::CurlyLeft) {
+            self.action_block()
+        } else {
+            self.expression(&[Symbol::Comma, Symbol::CurlyRight], &[])?;
+            // Like `Parser::parse_action`, a single action used as a branch takes the comma following it
+            if self.is_at_symbol(Symbol::Comma) {
+                self.bump()?;
+            }
+            Ok(())
+        }
+    }
+
+    fn display_element(&mut self) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::DisplayElement);
+        self.expression(&[Symbol::Colon], &[])?;
+        self.bump()?;
+        self.expression(&[Symbol::Comma, Symbol::Semicolon], &[])?;
+        while !self.is_at_symbol(Symbol::Semicolon) {
+            if self.is_at_symbol(Symbol::Comma) {
+                self.bump()?;
+                continue;
+            }
+            self.start(NodeKind::Attribute);
+            self.expect_name()?;
+            if self.is_at_symbol(Symbol::CurlyLeft) {
+                self.action_block()?;
+            } else {
+                self.group(NodeKind::Arguments, Symbol::ParenRight)?;
+            }
+            self.finish();
+        }
+        self.bump()?;
+        self.finish();
+        Ok(())
+    }
+
+    fn expression(&mut self, end_symbols: &[Symbol], end_keywords: &[Keyword]) -> Result<(), DesmosifyError> {
+        self.start(NodeKind::Expression);
+        let mut expect_operand = true;
+        let mut is_empty = true;
+        loop {
+            let token = self.token()?;
+            if (!expect_operand || is_empty) && token.is_one_of(end_symbols, end_keywords) {
+                break;
+            }
+            is_empty = false;
+            match (&token.value, expect_operand) {
+                (TokenValue::Symbol(Symbol::ParenLeft), true) => {
+                    self.group(NodeKind::Parenthesized, Symbol::ParenRight)?;
+                    expect_operand = false;
+                },
+                (TokenValue::Symbol(Symbol::SquareLeft), true) => {
+                    self.group(NodeKind::List, Symbol::SquareRight)?;
+                    expect_operand = false;
+                },
+                (TokenValue::Symbol(Symbol::CurlyLeft), true) => {
+                    self.group(NodeKind::Conditional, Symbol::CurlyRight)?;
+                    expect_operand = false;
+                },
+                (TokenValue::Keyword(Keyword::Action), true) => {
+                    self.bump()?;
+                    self.expect_path()?;
+                    self.group(NodeKind::Arguments, Symbol::ParenRight)?;
+                    expect_operand = false;
+                },
+                (TokenValue::Symbol(Symbol::ParenLeft), false) => {
+                    self.group(NodeKind::Arguments, Symbol::ParenRight)?;
+                },
+                (TokenValue::Symbol(Symbol::SquareLeft), false) => {
+                    self.group(NodeKind::Index, Symbol::SquareRight)?;
+                },
+                (TokenValue::Symbol(_) | TokenValue::Keyword(_), true) => {
+                    if Operation::from_token_value(&token.value, true).is_none() {
+                        return Err(DesmosifyError::new(
+                            String::from("expected an operand"),
+                            Some(token.start),
+                            Some(token.end),
+                        ));
+                    }
+                    self.bump()?;
+                },
+                (TokenValue::Symbol(_) | TokenValue::Keyword(_), false) => {
+                    if Operation::from_token_value(&token.value, false).is_none() {
+                        return Err(DesmosifyError::new(
+                            format!("unexpected '{}'", &self.source[token.start.index..token.end.index]),
+                            Some(token.start),
+                            Some(token.end),
+                        ));
+                    }
+                    self.bump()?;
+                    expect_operand = true;
+                },
+                (TokenValue::Name(_), true) => {
+                    self.expect_path()?;
+                    expect_operand = false;
+                },
+                (_, true) => {
+                    self.bump()?;
+                    expect_operand = false;
+                },
+                (_, false) => return Err(DesmosifyError::new(
+                    String::from("expected an operator"),
+                    Some(token.start),
+                    Some(token.end),
+                )),
+            }
+        }
+        self.finish();
+        Ok(())
+    }
+
+    /// Reads a bracketed group of expressions and the separators between them, whatever those separators are.
+    fn group(&mut self, kind: NodeKind, close: Symbol) -> Result<(), DesmosifyError> {
+        const SEPARATORS: &[Symbol] = &[
+            Symbol::Comma,
+            Symbol::Colon,
+            Symbol::Semicolon,
+            Symbol::ExclusiveRange,
+            Symbol::InclusiveRange,
+        ];
+        const SEPARATOR_KEYWORDS: &[Keyword] = &[Keyword::For, Keyword::In, Keyword::Where];
+        self.start(kind);
+        self.bump()?;
+        while !self.is_at_symbol(close) {
+            if self.is_at_one_of(SEPARATORS, SEPARATOR_KEYWORDS) {
+                self.bump()?;
+            } else {
+                let end_symbols: Vec<Symbol> = SEPARATORS.iter().copied().chain([close]).collect();
+                self.expression(&end_symbols, SEPARATOR_KEYWORDS)?;
+            }
+        }
+        self.bump()?;
+        self.finish();
+        Ok(())
+    }
+}
+
+/// Parses `source` into a concrete syntax tree whose root has kind `NodeKind::File`.
+pub fn parse(source: &str) -> Result<Node, DesmosifyError> {
+    let (tokens, trivia) = tokenize_with_trivia(source)?;
+    let mut builder = Builder {
+        source,
+        tokens,
+        trivia,
+        token_index: 0,
+        trivia_index: 0,
+        stack: vec![Node::new(NodeKind::File)],
+    };
+    builder.file()?;
+    Ok(builder.stack.pop().unwrap())
+}
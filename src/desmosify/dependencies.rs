@@ -0,0 +1,299 @@
+use super::*;
+
+use std::collections::BTreeSet;
+
+/// Collects the names referenced by `expression` into `references`, skipping any name in `locals`
+/// as well as names bound inside the expression itself (comprehension variables and `with` bindings).
+pub fn collect_expression_references(expression: &Expression, locals: &BTreeSet<String>, references: &mut BTreeSet<String>) {
+    match &expression.value {
+        ExpressionValue::Literal(_) => {},
+        ExpressionValue::Name(name) => if !locals.contains(name) {
+            references.insert(name.clone());
+        },
+        ExpressionValue::Operator(operation, operands) => match operation {
+            // The operand of a built-in is its name, not a reference
+            Operation::BuiltIn => {},
+            // Only the object being accessed can be a reference, not the member name
+            Operation::MemberAccess => {
+                collect_expression_references(&operands[0], locals, references);
+            },
+            // Likewise, the name of a named argument is a parameter of the callee
+            Operation::NamedArgument => {
+                collect_expression_references(&operands[1], locals, references);
+            },
+            Operation::ListMap => {
+                collect_expression_references(&operands[2], locals, references);
+                let mut inner_locals = locals.clone();
+                if let ExpressionValue::Name(name) = &operands[1].value {
+                    inner_locals.insert(name.clone());
+                }
+                collect_expression_references(&operands[0], &inner_locals, references);
+            },
+            Operation::With => match &operands[1].value {
+                ExpressionValue::Operator(Operation::Assignment, binding) => {
+                    collect_expression_references(&binding[1], locals, references);
+                    let mut inner_locals = locals.clone();
+                    if let ExpressionValue::Name(name) = &binding[0].value {
+                        inner_locals.insert(name.clone());
+                    }
+                    collect_expression_references(&operands[0], &inner_locals, references);
+                },
+                _ => for operand in operands {
+                    collect_expression_references(operand, locals, references);
+                },
+            },
+            _ => for operand in operands {
+                collect_expression_references(operand, locals, references);
+            },
+        },
+    }
+}
+
+/// Collects the names referenced by `action` into `references`, including updated variables and called actions.
+pub fn collect_action_references(action: &Action, locals: &BTreeSet<String>, references: &mut BTreeSet<String>) {
+    match action {
+        Action::Block(sub_actions) => for sub_action in sub_actions {
+            collect_action_references(sub_action, locals, references);
+        },
+        Action::Update(target, value) => {
+            collect_expression_references(target, locals, references);
+            collect_expression_references(value, locals, references);
+        },
+        Action::Call(callee, arguments) => {
+            collect_expression_references(callee, locals, references);
+            for argument in arguments {
+                collect_expression_references(argument, locals, references);
+            }
+        },
+        Action::Conditional(branches, default_branch) => {
+            for (condition, branch) in branches {
+                collect_expression_references(condition, locals, references);
+                collect_action_references(branch, locals, references);
+            }
+            if let Some(default_branch) = default_branch {
+                collect_action_references(default_branch, locals, references);
+            }
+        },
+    }
+}
+
+/// Collects the names referenced by a display element, including its attributes and click handler.
+pub fn collect_element_references(element: &display::Element, locals: &BTreeSet<String>, references: &mut BTreeSet<String>) {
+    collect_expression_references(&element.what, locals, references);
+    collect_expression_references(&element.color, locals, references);
+    if let Some(point) = &element.point {
+        collect_expression_references(&point.size_pixels, locals, references);
+        collect_expression_references(&point.opacity, locals, references);
+    }
+    if let Some(stroke) = &element.stroke {
+        collect_expression_references(&stroke.width_pixels, locals, references);
+        collect_expression_references(&stroke.opacity, locals, references);
+    }
+    if let Some(fill) = &element.fill {
+        collect_expression_references(&fill.opacity, locals, references);
+    }
+    if let Some(label) = &element.label {
+        collect_expression_references(&label.opacity, locals, references);
+        collect_expression_references(&label.scale_factor, locals, references);
+        collect_expression_references(&label.angle_degrees, locals, references);
+    }
+    if let Some(click) = &element.click {
+        collect_action_references(&click.action, locals, references);
+    }
+    if let Some(domain) = &element.domain {
+        collect_expression_references(&domain.min, locals, references);
+        collect_expression_references(&domain.max, locals, references);
+    }
+    if let Some(surface_domain) = &element.surface_domain {
+        collect_expression_references(&surface_domain.u_min, locals, references);
+        collect_expression_references(&surface_domain.u_max, locals, references);
+        collect_expression_references(&surface_domain.v_min, locals, references);
+        collect_expression_references(&surface_domain.v_max, locals, references);
+    }
+}
+
+/// The names of the parameters of a function or action, which its definition refers to as locals.
+pub fn parameter_names(signature: Option<&Signature>) -> BTreeSet<String> {
+    signature.and_then(|signature| signature.parameters())
+        .map_or_else(BTreeSet::new, |parameters| parameters.iter().map(|parameter| parameter.name.clone()).collect())
+}
+
+/// Computes, for every identifier and action, the set of other identifiers and actions its definition refers to.
+pub fn definition_dependencies(signatures: &Signatures, definitions: &Definitions) -> BTreeMap<String, BTreeSet<String>> {
+    let is_definition = |name: &String| definitions.identifiers.contains_key(name) || definitions.actions.contains_key(name);
+    let mut dependencies = BTreeMap::new();
+
+    for (name, value) in &definitions.identifiers {
+        let mut references = BTreeSet::new();
+        collect_expression_references(value, &parameter_names(signatures.user_defined.get(name)), &mut references);
+        references.retain(is_definition);
+        dependencies.insert(name.clone(), references);
+    }
+
+    for (name, content) in &definitions.actions {
+        let mut references = BTreeSet::new();
+        collect_action_references(content, &parameter_names(signatures.user_defined.get(name)), &mut references);
+        references.retain(is_definition);
+        dependencies.insert(name.clone(), references);
+    }
+
+    dependencies
+}
+
+fn is_function(signatures: &Signatures, name: &str) -> bool {
+    match signatures.user_defined.get(name) {
+        Some(Signature::Const { parameters, .. } | Signature::Let { parameters, .. }) => parameters.is_some(),
+        _ => false
+    }
+}
+
+struct DependencySorter<'a> {
+    signatures: &'a Signatures,
+    definitions: &'a Definitions,
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+    source_indices: BTreeMap<&'a str, usize>,
+    visited: BTreeSet<String>,
+    stack: Vec<String>,
+    order: Vec<String>,
+}
+
+impl<'a> DependencySorter<'a> {
+    fn visit(&mut self, name: &str) -> Result<(), DesmosifyError> {
+        if self.visited.contains(name) {
+            return Ok(());
+        }
+        if let Some(position) = self.stack.iter().position(|entry| entry == name) {
+            // Desmos can evaluate (mutually) recursive functions, but any other cycle is an error
+            let cycle = &self.stack[position..];
+            if cycle.iter().all(|entry| is_function(self.signatures, entry)) {
+                return Ok(());
+            }
+            let location = cycle.iter()
+                .find_map(|entry| self.definitions.identifiers.get(entry))
+                .map_or((None, None), |value| (value.start, value.end));
+            let mut names = Vec::from(cycle);
+            names.push(String::from(name));
+            return Err(DesmosifyError::new(
+                format!("cyclic definition: {}", names.join(" -> ")),
+                location.0,
+                location.1,
+            ));
+        }
+
+        self.stack.push(String::from(name));
+        let mut dependencies = Vec::from_iter(self.dependencies.get(name).into_iter().flatten().cloned());
+        dependencies.sort_by_key(|dependency| self.source_indices.get(dependency.as_str()).copied().unwrap_or(usize::MAX));
+        for dependency in dependencies {
+            self.visit(&dependency)?;
+        }
+        self.stack.pop();
+
+        self.visited.insert(String::from(name));
+        self.order.push(String::from(name));
+        Ok(())
+    }
+}
+
+/// Orders all identifiers and actions so that every definition comes after the definitions it depends on.
+/// Otherwise, declarations keep their relative order from the source.
+///
+/// Returns an error naming the cycle if definitions depend on each other, unless every definition involved
+/// is a function (which Desmos evaluates recursively).
+pub fn dependency_order(signatures: &Signatures, definitions: &Definitions) -> Result<Vec<String>, DesmosifyError> {
+    let mut sorter = DependencySorter {
+        signatures,
+        definitions,
+        dependencies: definition_dependencies(signatures, definitions),
+        source_indices: definitions.source_order().enumerate().map(|(index, name)| (name, index)).collect(),
+        visited: BTreeSet::new(),
+        stack: Vec::new(),
+        order: Vec::new(),
+    };
+
+    let roots = Vec::from_iter(definitions.source_order()
+        .chain(definitions.identifiers.keys().map(String::as_str))
+        .chain(definitions.actions.keys().map(String::as_str))
+        .filter(|name| definitions.identifiers.contains_key(*name) || definitions.actions.contains_key(*name)));
+    for name in roots {
+        sorter.visit(name)?;
+    }
+
+    Ok(sorter.order)
+}
+
+/// Collects the names referenced by the parts of the program which are shown in the graph or run on their own: the
+/// `public` block, the `display` elements (with their click handlers), the ticker and the variables.
+fn root_references(signatures: &Signatures, definitions: &Definitions, dependencies: &BTreeMap<String, BTreeSet<String>>) -> BTreeSet<String> {
+    let locals = BTreeSet::new();
+    let mut references = BTreeSet::new();
+    for (name, name_references) in dependencies {
+        if let Some(Signature::Var { .. }) = signatures.user_defined.get(name) {
+            references.insert(name.clone());
+            references.extend(name_references.iter().cloned());
+        }
+    }
+    for expression in definitions.public.iter().flatten() {
+        collect_expression_references(expression, &locals, &mut references);
+    }
+    if let Some(ticker) = &definitions.ticker {
+        if let Some(interval_ms) = &ticker.interval_ms {
+            collect_expression_references(interval_ms, &locals, &mut references);
+        }
+        collect_action_references(&ticker.tick_action, &locals, &mut references);
+    }
+    for element in definitions.display.iter().flatten() {
+        collect_element_references(element, &locals, &mut references);
+    }
+    references
+}
+
+/// Removes the `let`, `const` and `action` definitions which nothing in the graph uses, directly or through other
+/// definitions, since every expression slows Desmos down. Returns a warning for each definition removed from the files
+/// being compiled. The unused declarations of modules (including the standard library) are removed without one, as a
+/// program rarely needs everything a module offers.
+///
+/// A program without a `public`, `display` or `ticker` block has nothing to be reached from, so its own definitions
+/// are all kept and only the unused declarations of modules are removed.
+pub fn remove_unreachable(signatures: &mut Signatures, definitions: &mut Definitions) -> Vec<DesmosifyError> {
+    let dependencies = definition_dependencies(signatures, definitions);
+    let is_module_name = |name: &str| name.contains("::");
+    let has_roots = definitions.public.is_some() || definitions.display.is_some() || definitions.ticker.is_some();
+    let mut pending = if has_roots {
+        Vec::from_iter(root_references(signatures, definitions, &dependencies))
+    } else {
+        Vec::from_iter(dependencies.keys().filter(|name| !is_module_name(name)).cloned())
+    };
+    let mut reachable = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        if let Some(name_dependencies) = dependencies.get(&name).filter(|_| !reachable.contains(&name)) {
+            pending.extend(name_dependencies.iter().cloned());
+            reachable.insert(name);
+        }
+    }
+
+    let is_kept = |name: &String| !dependencies.contains_key(name) || reachable.contains(name);
+    let warnings = Vec::from_iter(definitions.source_order()
+        .filter(|name| !is_module_name(name) && !is_kept(&String::from(*name)))
+        .map(|name| {
+            let signature = &signatures.user_defined[name];
+            let (start, end) = signatures.locations.get(name).copied().unzip();
+            DesmosifyError::new(
+                format!("{} '{name}' is never used, so it is left out (list it in 'public' to keep it)", signature.variant_name()),
+                start,
+                end,
+            )
+        }));
+
+    definitions.identifiers.retain(|name, _| is_kept(name));
+    definitions.actions.retain(|name, _| is_kept(name));
+    signatures.user_defined.retain(|name, _| is_kept(name));
+    signatures.locations.retain(|name, _| is_kept(name));
+    signatures.exported.retain(is_kept);
+    // Sections left empty would still take up a folder
+    definitions.sections.retain_mut(|section| {
+        let was_empty = section.names.is_empty();
+        section.names.retain(is_kept);
+        was_empty || !section.names.is_empty()
+    });
+    warnings
+}
@@ -10,8 +10,9 @@ pub trait Attribute {
 
 // TODO: images
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum PointStyle {
+    #[default]
     Point,
     Open,
     Cross,
@@ -29,12 +30,6 @@ impl PointStyle {
     }
 }
 
-impl Default for PointStyle {
-    fn default() -> Self {
-        Self::Point
-    }
-}
-
 impl FromStr for PointStyle {
     type Err = String;
 
@@ -61,8 +56,8 @@ impl Attribute for PointAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
-        if arguments.len() < 1 || 3 < arguments.len() {
+        let mut arguments = parser.parse_positional_call()?;
+        if arguments.is_empty() || 3 < arguments.len() {
             return Err(DesmosifyError::new(
                 String::from("expected 1-3 arguments for 'point' attribute"),
                 Some(start),
@@ -88,8 +83,9 @@ impl Attribute for PointAttribute {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum StrokeStyle {
+    #[default]
     Solid,
     Dashed,
     Dotted,
@@ -107,12 +103,6 @@ impl StrokeStyle {
     }
 }
 
-impl Default for StrokeStyle {
-    fn default() -> Self {
-        Self::Solid
-    }
-}
-
 impl FromStr for StrokeStyle {
     type Err = String;
 
@@ -139,8 +129,8 @@ impl Attribute for StrokeAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
-        if arguments.len() < 1 || 3 < arguments.len() {
+        let mut arguments = parser.parse_positional_call()?;
+        if arguments.is_empty() || 3 < arguments.len() {
             return Err(DesmosifyError::new(
                 String::from("expected 1-3 arguments for 'stroke' attribute"),
                 Some(start),
@@ -177,7 +167,7 @@ impl Attribute for FillAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
+        let mut arguments = parser.parse_positional_call()?;
         if arguments.len() > 1 {
             return Err(DesmosifyError::new(
                 String::from("expected 0-1 arguments for 'fill' attribute"),
@@ -196,8 +186,9 @@ impl Attribute for FillAttribute {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum LabelOrientation {
+    #[default]
     Center,
     Left,
     Right,
@@ -221,12 +212,6 @@ impl LabelOrientation {
     }
 }
 
-impl Default for LabelOrientation {
-    fn default() -> Self {
-        Self::Center
-    }
-}
-
 impl FromStr for LabelOrientation {
     type Err = String;
 
@@ -261,8 +246,8 @@ impl Attribute for LabelAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
-        if arguments.len() < 1 || 5 < arguments.len() {
+        let mut arguments = parser.parse_positional_call()?;
+        if arguments.is_empty() || 5 < arguments.len() {
             return Err(DesmosifyError::new(
                 String::from("expected 1-5 arguments for 'label' attribute"),
                 Some(start),
@@ -300,8 +285,9 @@ impl Attribute for LabelAttribute {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum DragMode {
+    #[default]
     XY,
     X,
     Y,
@@ -319,12 +305,6 @@ impl DragMode {
     }
 }
 
-impl Default for DragMode {
-    fn default() -> Self {
-        Self::XY
-    }
-}
-
 impl FromStr for DragMode {
     type Err = String;
 
@@ -349,7 +329,7 @@ impl Attribute for DragAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
+        let mut arguments = parser.parse_positional_call()?;
         if arguments.len() > 1 {
             return Err(DesmosifyError::new(
                 String::from("expected 0-1 arguments for 'drag' attribute"),
@@ -396,7 +376,7 @@ impl Attribute for DescriptionAttribute {
     fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let start = parser.token()?.start;
         parser.next();
-        let mut arguments = parser.parse_call()?;
+        let mut arguments = parser.parse_positional_call()?;
         if arguments.len() != 1 {
             return Err(DesmosifyError::new(
                 String::from("expected 1 argument for 'description' attribute"),
@@ -411,6 +391,71 @@ impl Attribute for DescriptionAttribute {
     }
 }
 
+#[derive(Debug)]
+pub struct DomainAttribute {
+    pub min: Box<Expression>,
+    pub max: Box<Expression>,
+}
+
+impl Attribute for DomainAttribute {
+    const NAME: &'static str = "domain";
+
+    fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
+        let start = parser.token()?.start;
+        parser.next();
+        let mut arguments = parser.parse_positional_call()?;
+        if arguments.len() != 2 {
+            return Err(DesmosifyError::new(
+                String::from("expected 2 arguments for 'domain' attribute"),
+                Some(start),
+                Some(parser.token()?.end),
+            ));
+        }
+        let max = Box::new(arguments.pop().unwrap());
+        let min = Box::new(arguments.pop().unwrap());
+        Ok(Self {
+            min,
+            max,
+        })
+    }
+}
+
+/// The `u` and `v` parameter ranges of a parametric surface, which is only drawn by the 3D calculator.
+#[derive(Debug)]
+pub struct SurfaceDomainAttribute {
+    pub u_min: Box<Expression>,
+    pub u_max: Box<Expression>,
+    pub v_min: Box<Expression>,
+    pub v_max: Box<Expression>,
+}
+
+impl Attribute for SurfaceDomainAttribute {
+    const NAME: &'static str = "surface_domain";
+
+    fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
+        let start = parser.token()?.start;
+        parser.next();
+        let mut arguments = parser.parse_positional_call()?;
+        if arguments.len() != 4 {
+            return Err(DesmosifyError::new(
+                String::from("expected 4 arguments for 'surface_domain' attribute"),
+                Some(start),
+                Some(parser.token()?.end),
+            ));
+        }
+        let v_max = Box::new(arguments.pop().unwrap());
+        let v_min = Box::new(arguments.pop().unwrap());
+        let u_max = Box::new(arguments.pop().unwrap());
+        let u_min = Box::new(arguments.pop().unwrap());
+        Ok(Self {
+            u_min,
+            u_max,
+            v_min,
+            v_max,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Element {
     pub what: Box<Expression>,
@@ -423,6 +468,8 @@ pub struct Element {
     pub drag: Option<DragAttribute>,
     pub click: Option<ClickAttribute>,
     pub description: Option<DescriptionAttribute>,
+    pub domain: Option<DomainAttribute>,
+    pub surface_domain: Option<SurfaceDomainAttribute>,
 }
 
 impl Element {
@@ -437,9 +484,75 @@ impl Element {
             drag: None,
             click: None,
             description: None,
+            domain: None,
+            surface_domain: None,
         }
     }
 
+    /// Calls `visitor` on every expression (and sub-expression) in this element, including its attributes.
+    pub fn visit_expressions<F: FnMut(&Expression)>(&self, visitor: &mut F) {
+        self.what.visit(visitor);
+        self.color.visit(visitor);
+        if let Some(point) = &self.point {
+            point.size_pixels.visit(visitor);
+            point.opacity.visit(visitor);
+        }
+        if let Some(stroke) = &self.stroke {
+            stroke.width_pixels.visit(visitor);
+            stroke.opacity.visit(visitor);
+        }
+        if let Some(fill) = &self.fill {
+            fill.opacity.visit(visitor);
+        }
+        if let Some(label) = &self.label {
+            label.opacity.visit(visitor);
+            label.scale_factor.visit(visitor);
+            label.angle_degrees.visit(visitor);
+        }
+        if let Some(click) = &self.click {
+            click.action.visit_expressions(visitor);
+        }
+        if let Some(domain) = &self.domain {
+            domain.min.visit(visitor);
+            domain.max.visit(visitor);
+        }
+        if let Some(surface_domain) = &self.surface_domain {
+            surface_domain.u_min.visit(visitor);
+            surface_domain.u_max.visit(visitor);
+            surface_domain.v_min.visit(visitor);
+            surface_domain.v_max.visit(visitor);
+        }
+    }
+
+    /// The expressions giving the attributes of this element, which leaves out `what` and the click handler.
+    pub fn attributes_mut(&mut self) -> Vec<&mut Expression> {
+        let mut expressions = vec![&mut *self.color];
+        if let Some(point) = &mut self.point {
+            expressions.extend([&mut *point.size_pixels, &mut *point.opacity]);
+        }
+        if let Some(stroke) = &mut self.stroke {
+            expressions.extend([&mut *stroke.width_pixels, &mut *stroke.opacity]);
+        }
+        if let Some(fill) = &mut self.fill {
+            expressions.push(&mut *fill.opacity);
+        }
+        if let Some(label) = &mut self.label {
+            expressions.extend([&mut *label.opacity, &mut *label.scale_factor, &mut *label.angle_degrees]);
+        }
+        if let Some(domain) = &mut self.domain {
+            expressions.extend([&mut *domain.min, &mut *domain.max]);
+        }
+        if let Some(surface_domain) = &mut self.surface_domain {
+            expressions.extend([
+                &mut *surface_domain.u_min,
+                &mut *surface_domain.u_max,
+                &mut *surface_domain.v_min,
+                &mut *surface_domain.v_max,
+            ]);
+        }
+        expressions
+    }
+
     pub fn parse(parser: &mut Parser) -> Result<Self, DesmosifyError> {
         let what = Box::new(parser.parse_expression(&[Symbol::Colon], &[])?);
         parser.next();
@@ -485,6 +598,16 @@ impl Element {
                 } else {
                     return Err(Self::duplicate_attribute_error(parser, DescriptionAttribute::NAME));
                 },
+                DomainAttribute::NAME => if element.domain.is_none() {
+                    element.domain = Some(DomainAttribute::parse(parser)?);
+                } else {
+                    return Err(Self::duplicate_attribute_error(parser, DomainAttribute::NAME));
+                },
+                SurfaceDomainAttribute::NAME => if element.surface_domain.is_none() {
+                    element.surface_domain = Some(SurfaceDomainAttribute::parse(parser)?);
+                } else {
+                    return Err(Self::duplicate_attribute_error(parser, SurfaceDomainAttribute::NAME));
+                },
                 name => {
                     let token = parser.token()?;
                     return Err(DesmosifyError::new(
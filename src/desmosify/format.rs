@@ -0,0 +1,898 @@
+//! The source formatter behind `desmosify fmt`. The concrete syntax tree is turned into a document saying where lines
+//! may be broken, which is then printed so that lines stay within `MAX_WIDTH` where possible. Comments are kept next to
+//! the token they were written against, as is a single blank line wherever the source had any between declarations or
+//! between the items of a block.
+//!
+//! The layout is canonical: every action in a block ends with a comma, piecewise expressions with more than one piece
+//! put each piece on its own line (with a comma after every piece but the default), and the attributes of a display
+//! element stay on one line unless they do not fit, in which case each gets its own line.
+
+use super::*;
+use cst::{Element, Node, NodeKind};
+
+pub const MAX_WIDTH: usize = 100;
+pub const INDENT_WIDTH: usize = 4;
+
+#[derive(Clone, Debug)]
+enum Doc {
+    Text(String),
+    Concat(Vec<Doc>),
+    /// A space, or a line break if the enclosing group is broken.
+    Line,
+    /// Nothing, or a line break if the enclosing group is broken.
+    SoftLine,
+    /// A line break, even if the enclosing group is not broken.
+    HardLine,
+    /// Text only written if the enclosing group is broken, like a trailing comma.
+    IfBroken(&'static str),
+    /// Text written just before the next line break, used for comments that end a line.
+    LineSuffix(String),
+    /// Forces the enclosing groups to be broken.
+    BreakParent,
+    /// Indents the lines within, if the enclosing group is broken.
+    Indent(Vec<Doc>),
+    /// Alternating items and `Line`s, where only the lines needed to keep within `MAX_WIDTH` are broken if the
+    /// enclosing group is. Items are measured as if flat.
+    Fill(Vec<Doc>),
+    /// Contents whose lines are either all broken or all kept flat. A group that is not `broken` is only broken if it
+    /// would not fit on the rest of the line; measuring stops at its first hard line break.
+    Group { broken: bool, contents: Vec<Doc> },
+}
+
+fn text(text: impl Into<String>) -> Doc {
+    Doc::Text(text.into())
+}
+
+fn group(contents: Vec<Doc>) -> Doc {
+    Doc::Group { broken: false, contents }
+}
+
+/// Whether `doc` has a line break that is not up to the enclosing group.
+fn has_forced_break(doc: &Doc) -> bool {
+    match doc {
+        Doc::HardLine | Doc::BreakParent | Doc::Group { broken: true, .. } => true,
+        Doc::Concat(contents) | Doc::Indent(contents) | Doc::Fill(contents) | Doc::Group { contents, .. } => {
+            contents.iter().any(has_forced_break)
+        },
+        _ => false,
+    }
+}
+
+fn width(doc: &Doc, mode: Mode) -> usize {
+    match doc {
+        Doc::Text(text) => text.chars().count(),
+        Doc::IfBroken(text) if mode == Mode::Broken => text.len(),
+        Doc::Line if mode == Mode::Flat => 1,
+        Doc::Concat(contents) | Doc::Indent(contents) | Doc::Fill(contents) | Doc::Group { contents, .. } => {
+            contents.iter().map(|doc| width(doc, mode)).sum()
+        },
+        _ => 0,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Mode {
+    Flat,
+    Broken,
+}
+
+fn fits(mut width: isize, contents: &[Doc], rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack: Vec<(Mode, &Doc)> = contents.iter().rev().map(|doc| (Mode::Flat, doc)).collect();
+    let mut rest = rest.iter().rev();
+    while width >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => match text.split_once('\n') {
+                Some((first_line, _)) => return width >= first_line.chars().count() as isize,
+                None => width -= text.chars().count() as isize,
+            },
+            Doc::Line | Doc::SoftLine if mode == Mode::Broken => return true,
+            Doc::Line => width -= 1,
+            Doc::SoftLine => {},
+            Doc::HardLine => return true,
+            Doc::IfBroken(text) => if mode == Mode::Broken {
+                width -= text.len() as isize;
+            },
+            Doc::LineSuffix(_) | Doc::BreakParent => if mode == Mode::Flat {
+                return false;
+            },
+            Doc::Concat(contents) | Doc::Indent(contents) | Doc::Fill(contents) => {
+                stack.extend(contents.iter().rev().map(|doc| (mode, doc)));
+            },
+            Doc::Group { broken, contents } => {
+                let mode = if *broken { Mode::Broken } else { mode };
+                stack.extend(contents.iter().rev().map(|doc| (mode, doc)));
+            },
+        }
+    }
+    false
+}
+
+fn print(doc: &Doc) -> String {
+    let mut output = String::new();
+    let mut column = 0;
+    let mut line_suffixes: Vec<&str> = Vec::new();
+    let mut stack = vec![(0, Mode::Broken, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                output.push_str(text);
+                column = match text.rsplit_once('\n') {
+                    Some((_, last_line)) => last_line.chars().count(),
+                    None => column + text.chars().count(),
+                };
+            },
+            Doc::Line if mode == Mode::Flat => {
+                output.push(' ');
+                column += 1;
+            },
+            Doc::SoftLine if mode == Mode::Flat => {},
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                for suffix in line_suffixes.drain(..) {
+                    output.push_str(suffix);
+                }
+                output.truncate(output.trim_end_matches(' ').len());
+                output.push('\n');
+                output.extend(std::iter::repeat_n(' ', indent));
+                column = indent;
+            },
+            Doc::IfBroken(text) => if mode == Mode::Broken {
+                output.push_str(text);
+                column += text.len();
+            },
+            Doc::LineSuffix(text) => line_suffixes.push(text),
+            Doc::BreakParent => {},
+            Doc::Concat(contents) => {
+                stack.extend(contents.iter().rev().map(|doc| (indent, mode, doc)));
+            },
+            Doc::Fill(contents) if mode == Mode::Flat => {
+                stack.extend(contents.iter().rev().map(|doc| (indent, mode, doc)));
+            },
+            Doc::Fill(contents) => {
+                // Decide every line up front, as the items are measured flat anyway
+                let mut fill = Vec::new();
+                let mut fill_column = column;
+                for (index, item) in contents.iter().enumerate().step_by(2) {
+                    let item_width = width(item, Mode::Broken);
+                    if index > 0 {
+                        let line_mode = if fill_column + 1 + item_width <= MAX_WIDTH { Mode::Flat } else { Mode::Broken };
+                        fill_column = if line_mode == Mode::Flat { fill_column + 1 } else { indent };
+                        fill.push((indent, line_mode, &contents[index - 1]));
+                    }
+                    fill.push((indent, mode, item));
+                    fill_column += item_width;
+                }
+                stack.extend(fill.into_iter().rev());
+            },
+            Doc::Indent(contents) => {
+                let indent = if mode == Mode::Broken { indent + INDENT_WIDTH } else { indent };
+                stack.extend(contents.iter().rev().map(|doc| (indent, mode, doc)));
+            },
+            Doc::Group { broken, contents } => {
+                let mode = if *broken {
+                    Mode::Broken
+                } else if mode == Mode::Flat || fits(MAX_WIDTH as isize - column as isize, contents, &stack) {
+                    Mode::Flat
+                } else {
+                    Mode::Broken
+                };
+                stack.extend(contents.iter().rev().map(|doc| (indent, mode, doc)));
+            },
+        }
+    }
+    for suffix in line_suffixes {
+        output.push_str(suffix);
+    }
+    output.truncate(output.trim_end().len());
+    output.push('\n');
+    output
+}
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+enum Break {
+    None,
+    Line,
+    Hard,
+    Blank,
+}
+
+/// Collects the parts of a group or block along with the comments between them. A break is only inserted once the
+/// part after it is known, so that a comment ending the line of one part stays on that line.
+struct Parts {
+    docs: Vec<Doc>,
+    pending: Break,
+    keep_blank_lines: bool,
+    /// How many line breaks there have been since the last token.
+    line_breaks: usize,
+    /// Where the contents end if comments after the last part are not counted.
+    end_of_last_part: usize,
+    /// Comments to write in front of the next part.
+    leading: Vec<Doc>,
+}
+
+impl Parts {
+    fn new(keep_blank_lines: bool) -> Self {
+        Self {
+            docs: Vec::new(),
+            pending: Break::None,
+            keep_blank_lines,
+            line_breaks: 0,
+            end_of_last_part: 0,
+            leading: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Requests a break before the next part.
+    fn separate(&mut self, separator: Break) {
+        if !self.is_empty() && separator > self.pending {
+            self.pending = separator;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.keep_blank_lines && self.line_breaks >= 2 && self.pending != Break::None {
+            self.pending = Break::Blank;
+        }
+        match self.pending {
+            Break::None => {},
+            Break::Line => self.docs.push(Doc::Line),
+            Break::Hard => self.docs.push(Doc::HardLine),
+            Break::Blank => self.docs.extend([Doc::HardLine, Doc::HardLine]),
+        }
+        self.pending = Break::None;
+    }
+
+    fn push(&mut self, doc: Doc) {
+        self.flush();
+        self.docs.append(&mut self.leading);
+        self.docs.push(doc);
+        self.end_of_last_part = self.docs.len();
+        self.line_breaks = 0;
+    }
+
+    fn token(&mut self) {
+        self.line_breaks = 0;
+    }
+
+    /// Takes whitespace or a comment, given the elements after it.
+    fn trivia(&mut self, trivia: &Trivia, rest: &[Element]) {
+        if !trivia.is_comment() {
+            self.line_breaks += trivia.line_break_count();
+            return;
+        }
+        match placement(trivia, rest) {
+            Placement::BeforeNext => {
+                self.leading.push(text(format!("{} ", trivia.text)));
+                return;
+            },
+            Placement::AfterPrevious if !self.is_empty() => {
+                self.docs.push(text(format!(" {}", trivia.text)));
+                self.end_of_last_part = self.docs.len();
+                return;
+            },
+            _ => {},
+        }
+        if self.line_breaks == 0 && !self.is_empty() {
+            self.docs.extend([Doc::LineSuffix(format!(" {}", trivia.text)), Doc::BreakParent]);
+        } else {
+            self.separate(Break::Hard);
+            self.flush();
+            self.docs.extend([Doc::BreakParent, text(&trivia.text)]);
+            self.pending = Break::Hard;
+            self.line_breaks = 0;
+        }
+    }
+
+    fn finish(self) -> Vec<Doc> {
+        self.docs
+    }
+
+    /// Like `finish`, but with `trailing` after the last part (and before any comments following it).
+    fn finish_with(mut self, trailing: Doc) -> Vec<Doc> {
+        self.docs.insert(self.end_of_last_part, trailing);
+        self.docs
+    }
+}
+
+/// Where a comment is written, going by what follows it in the source.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Placement {
+    /// At the end of the line: line comments, and block comments that were the last thing on their line.
+    EndOfLine,
+    /// Right after the part before it, as for a comment in front of a comma or a closing bracket.
+    AfterPrevious,
+    /// In front of the part after it.
+    BeforeNext,
+}
+
+fn placement(trivia: &Trivia, rest: &[Element]) -> Placement {
+    if trivia.kind == TriviaKind::LineComment {
+        return Placement::EndOfLine;
+    }
+    let next = rest.iter().find(|element| element.trivia().is_none_or(|trivia| trivia.is_comment() || trivia.line_break_count() > 0));
+    match next {
+        None => Placement::EndOfLine,
+        Some(Element::Trivia(trivia)) if !trivia.is_comment() => Placement::EndOfLine,
+        Some(Element::Token { token: Token { value: TokenValue::Symbol(symbol), .. }, .. })
+            if !matches!(symbol, Symbol::ParenLeft | Symbol::SquareLeft | Symbol::CurlyLeft) => Placement::AfterPrevious,
+        Some(_) => Placement::BeforeNext,
+    }
+}
+
+/// Comments inside a declaration or expression stay next to the token they were written against, except that those
+/// ending a line are moved to the end of the line they end up on.
+fn inline_comment(trivia: &Trivia, rest: &[Element]) -> Vec<Doc> {
+    if !trivia.is_comment() {
+        return Vec::new();
+    }
+    match placement(trivia, rest) {
+        Placement::EndOfLine => vec![Doc::LineSuffix(format!(" {}", trivia.text)), Doc::BreakParent],
+        Placement::AfterPrevious => vec![text(format!(" {}", trivia.text))],
+        Placement::BeforeNext => vec![text(format!("{} ", trivia.text))],
+    }
+}
+
+fn token_text(element: &Element) -> &str {
+    match element {
+        Element::Token { text, .. } => text,
+        _ => "",
+    }
+}
+
+/// `{` and `}` around parts that always go on their own lines.
+fn block(parts: Vec<Doc>) -> Doc {
+    if parts.is_empty() {
+        text("{}")
+    } else {
+        Doc::Group { broken: true, contents: vec![text("{"), Doc::Indent([vec![Doc::HardLine], parts].concat()), Doc::HardLine, text("}")] }
+    }
+}
+
+/// Brackets around parts that are kept on one line if they fit.
+fn bracketed(open: &str, parts: Vec<Doc>, close: &str) -> Doc {
+    if parts.is_empty() {
+        text(format!("{open}{close}"))
+    } else {
+        group(vec![text(open), Doc::Indent([vec![Doc::SoftLine], parts].concat()), Doc::SoftLine, text(close)])
+    }
+}
+
+fn file(node: &Node) -> Doc {
+    let mut parts = Parts::new(true);
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Node(declaration_node) => {
+                parts.separate(Break::Hard);
+                parts.push(declaration(declaration_node));
+            },
+            Element::Token { .. } => parts.token(),
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 1..]),
+        }
+    }
+    Doc::Concat(parts.finish())
+}
+
+fn declaration(node: &Node) -> Doc {
+    match node.kind {
+        NodeKind::Import => import(node),
+        NodeKind::Public => public(node),
+        NodeKind::Display => display(node),
+        NodeKind::Enum => enumeration(node),
+        NodeKind::Ticker | NodeKind::Action => action_definition(node),
+        _ => definition(node),
+    }
+}
+
+/// Pragmas, sections, and `const`, `let` and `var` definitions.
+fn definition(node: &Node) -> Doc {
+    let mut docs = Vec::new();
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Token { token, text: token_text } => docs.push(match token.value {
+                TokenValue::Symbol(Symbol::Colon) => text(": "),
+                TokenValue::Symbol(Symbol::Equal) => text(" = "),
+                TokenValue::Keyword(_) => text(format!("{token_text} ")),
+                TokenValue::String(_) if node.kind == NodeKind::Pragma => text(format!(" {token_text}")),
+                _ => text(token_text),
+            }),
+            Element::Node(child) => docs.push(match child.kind {
+                NodeKind::Parameters => parameters(child),
+                NodeKind::Type => data_type(child),
+                _ => expression(child),
+            }),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+        }
+    }
+    Doc::Concat(docs)
+}
+
+/// `import "path";` and `import name::{a, b};`, which are kept on one line.
+fn import(node: &Node) -> Doc {
+    let mut docs = Vec::new();
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Token { token, text: token_text } => docs.push(match token.value {
+                TokenValue::Keyword(_) => text(format!("{token_text} ")),
+                TokenValue::Symbol(Symbol::Comma) => {
+                    let is_trailing = node.children[index + 1..].iter()
+                        .find_map(Element::token)
+                        .is_some_and(|next| next.value == TokenValue::Symbol(Symbol::CurlyRight));
+                    text(if is_trailing { "" } else { ", " })
+                },
+                _ => text(token_text),
+            }),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+            Element::Node(_) => {},
+        }
+    }
+    Doc::Concat(docs)
+}
+
+fn public(node: &Node) -> Doc {
+    let mut parts = Parts::new(true);
+    for (index, child) in node.children.iter().enumerate().take(node.children.len() - 1).skip(2) {
+        match child {
+            Element::Node(item) => {
+                parts.separate(Break::Hard);
+                parts.push(Doc::Concat(vec![expression(item), text(";")]));
+            },
+            Element::Token { .. } => parts.token(),
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 1..]),
+        }
+    }
+    Doc::Concat(vec![text("public "), block(parts.finish())])
+}
+
+fn display(node: &Node) -> Doc {
+    let mut parts = Parts::new(true);
+    for (index, child) in node.children.iter().enumerate().take(node.children.len() - 1).skip(2) {
+        match child {
+            Element::Node(element) => {
+                parts.separate(Break::Hard);
+                parts.push(display_element(element));
+            },
+            Element::Token { .. } => parts.token(),
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 1..]),
+        }
+    }
+    Doc::Concat(vec![text("display "), block(parts.finish())])
+}
+
+/// `what: color, attribute, ...;` with the list after the colon broken one per line if it does not fit.
+fn display_element(node: &Node) -> Doc {
+    let mut head = Vec::new();
+    let mut attributes = Parts::new(false);
+    let mut after_colon = false;
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Node(child) if !after_colon => head.push(expression(child)),
+            Element::Node(child) => {
+                if !attributes.is_empty() {
+                    attributes.push(text(","));
+                    attributes.separate(Break::Line);
+                }
+                attributes.push(if child.kind == NodeKind::Attribute {
+                    attribute(child)
+                } else {
+                    expression(child)
+                });
+            },
+            Element::Token { token, .. } => {
+                if token.value == TokenValue::Symbol(Symbol::Colon) {
+                    after_colon = true;
+                }
+                attributes.token();
+            },
+            Element::Trivia(trivia) if !after_colon => head.extend(inline_comment(trivia, &node.children[index + 1..])),
+            Element::Trivia(trivia) => attributes.trivia(trivia, &node.children[index + 1..]),
+        }
+    }
+    head.extend([
+        text(":"),
+        group(vec![Doc::Indent([vec![Doc::Line], attributes.finish()].concat())]),
+        text(";"),
+    ]);
+    Doc::Concat(head)
+}
+
+fn attribute(node: &Node) -> Doc {
+    let mut docs = Vec::new();
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Node(child) if child.kind == NodeKind::ActionBlock => {
+                docs.extend([text(" "), action_block(child, true)]);
+            },
+            Element::Node(child) => docs.push(delimited(child)),
+            Element::Token { text: name, .. } => docs.push(text(name)),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+        }
+    }
+    Doc::Concat(docs)
+}
+
+fn enumeration(node: &Node) -> Doc {
+    let open = node.children.iter().position(|child| child.is_symbol(Symbol::CurlyLeft)).unwrap();
+    let mut head = Vec::new();
+    for (index, child) in node.children.iter().enumerate().take(open) {
+        match child {
+            Element::Trivia(trivia) => head.extend(inline_comment(trivia, &node.children[index + 1..])),
+            _ => head.push(text(format!("{} ", token_text(child)))),
+        }
+    }
+    let mut parts = Parts::new(true);
+    for (index, child) in node.children.iter().enumerate().take(node.children.len() - 1).skip(open + 1) {
+        match child {
+            Element::Token { token: Token { value: TokenValue::Name(_), .. }, text: variant } => {
+                parts.separate(Break::Hard);
+                parts.push(text(format!("{variant},")));
+            },
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 1..]),
+            _ => parts.token(),
+        }
+    }
+    head.push(block(parts.finish()));
+    Doc::Concat(head)
+}
+
+/// `action name(...) { ... }` and `ticker { ... }`.
+fn action_definition(node: &Node) -> Doc {
+    let mut docs = Vec::new();
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Token { token: Token { value: TokenValue::Keyword(keyword @ (Keyword::Pub | Keyword::Action)), .. }, .. } => {
+                docs.push(text(format!("{keyword} ")));
+            },
+            Element::Token { text: token_text, .. } => docs.push(text(token_text)),
+            Element::Node(child) => docs.push(match child.kind {
+                NodeKind::Parameters => parameters(child),
+                NodeKind::ActionBlock => Doc::Concat(vec![text(" "), action_block(child, false)]),
+                _ => expression(child),
+            }),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+        }
+    }
+    Doc::Concat(docs)
+}
+
+/// Blocks are laid out one action per line with trailing commas, except that `inline` blocks of simple actions (as in
+/// `click { ... }`) are kept on one line if they fit.
+fn action_block(node: &Node, inline: bool) -> Doc {
+    let inner = &node.children[1..node.children.len() - 1];
+    let is_simple = inner.iter().all(|child| match child {
+        Element::Node(child) => child.kind == NodeKind::Expression,
+        Element::Trivia(trivia) => !trivia.is_comment(),
+        Element::Token { .. } => true,
+    });
+    if inline && is_simple {
+        let mut parts = Parts::new(false);
+        for child in inner {
+            if let Element::Node(action) = child {
+                if !parts.is_empty() {
+                    parts.push(text(","));
+                    parts.separate(Break::Line);
+                }
+                parts.push(expression(action));
+            }
+        }
+        let parts = parts.finish_with(Doc::IfBroken(","));
+        return if parts.len() == 1 {
+            text("{}")
+        } else {
+            group(vec![text("{"), Doc::Indent([vec![Doc::Line], parts].concat()), Doc::Line, text("}")])
+        };
+    }
+    let mut parts = Parts::new(true);
+    for (index, child) in inner.iter().enumerate() {
+        match child {
+            Element::Node(action) => {
+                parts.separate(Break::Hard);
+                parts.push(Doc::Concat(vec![
+                    if action.kind == NodeKind::ActionIf {
+                        action_if(action)
+                    } else {
+                        expression(action)
+                    },
+                    text(","),
+                ]));
+            },
+            Element::Token { .. } => parts.token(),
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 2..]),
+        }
+    }
+    block(parts.finish())
+}
+
+/// Branches are always written as blocks, even if the source used a single action.
+fn action_if(node: &Node) -> Doc {
+    let mut docs = Vec::new();
+    let mut after_colon = false;
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Token { token, .. } => match token.value {
+                TokenValue::Keyword(Keyword::If) => docs.push(text("if ")),
+                TokenValue::Keyword(Keyword::Elif) => docs.push(text(" elif ")),
+                TokenValue::Keyword(Keyword::Else) => docs.push(text(" else")),
+                TokenValue::Symbol(Symbol::Colon) => {
+                    docs.push(text(": "));
+                    after_colon = true;
+                },
+                _ => {},
+            },
+            Element::Node(branch) if branch.kind == NodeKind::ActionBlock => {
+                docs.push(action_block(branch, false));
+                after_colon = false;
+            },
+            Element::Node(branch) if after_colon => {
+                docs.push(block(vec![expression(branch), text(",")]));
+                after_colon = false;
+            },
+            Element::Node(condition) => docs.push(expression(condition)),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+        }
+    }
+    Doc::Concat(docs)
+}
+
+fn parameters(node: &Node) -> Doc {
+    let mut parts = Parts::new(false);
+    for (index, child) in node.children.iter().enumerate().take(node.children.len() - 1).skip(1) {
+        match child {
+            Element::Node(parameter) => {
+                if !parts.is_empty() {
+                    parts.push(text(","));
+                    parts.separate(Break::Line);
+                }
+                let mut docs = Vec::new();
+                for (parameter_index, child) in parameter.children.iter().enumerate() {
+                    match child {
+                        Element::Node(default) if default.kind == NodeKind::Expression => docs.extend([text(" = "), expression(default)]),
+                        Element::Node(parameter_type) => docs.extend([text(": "), data_type(parameter_type)]),
+                        Element::Token { token: Token { value: TokenValue::Name(_), .. }, text: name } => docs.push(text(name)),
+                        Element::Token { .. } => {},
+                        Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &parameter.children[parameter_index + 1..])),
+                    }
+                }
+                parts.push(Doc::Concat(docs));
+            },
+            Element::Token { .. } => parts.token(),
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 1..]),
+        }
+    }
+    let parts = if parts.is_empty() { parts.finish() } else { parts.finish_with(Doc::IfBroken(",")) };
+    bracketed("(", parts, ")")
+}
+
+fn data_type(node: &Node) -> Doc {
+    text(node.tokens().map(|token| match &token.value {
+        TokenValue::Symbol(symbol) => String::from(symbol.literal()),
+        TokenValue::Name(name) => name.clone(),
+        _ => String::new(),
+    }).collect::<String>())
+}
+
+/// Operators of the loosest precedence in an expression may be broken before if it does not fit, as long as they are
+/// `||`/`&&` or `+`/`-`. The others are always written on one line.
+fn breakable_precedence(operations: &[(usize, Operation)]) -> Option<Precedence> {
+    let loosest = operations.iter()
+        .map(|(_, operation)| operation.precedence())
+        .reduce(|lhs, rhs| if rhs < lhs { rhs } else { lhs })?;
+    match loosest {
+        Precedence::Logical | Precedence::Additive => Some(loosest),
+        _ => None,
+    }
+}
+
+/// Finds the binary operators among the children of an expression, by index.
+fn binary_operations(node: &Node) -> Vec<(usize, Operation)> {
+    let mut operations = Vec::new();
+    let mut expect_operand = true;
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Node(_) => expect_operand = false,
+            Element::Token { token, .. } => match Operation::from_token_value(&token.value, expect_operand) {
+                Some(operation) if !expect_operand => {
+                    operations.push((index, operation));
+                    expect_operand = true;
+                },
+                Some(_) => {},
+                None => expect_operand = false,
+            },
+            Element::Trivia(_) => {},
+        }
+    }
+    operations
+}
+
+fn expression(node: &Node) -> Doc {
+    let operations = binary_operations(node);
+    let breakable = breakable_precedence(&operations);
+    let mut docs = Vec::new();
+    for (index, child) in node.children.iter().enumerate() {
+        match child {
+            Element::Node(child) if child.kind == NodeKind::Conditional => docs.push(conditional(child)),
+            Element::Node(child) => docs.push(delimited(child)),
+            Element::Trivia(trivia) => docs.extend(inline_comment(trivia, &node.children[index + 1..])),
+            Element::Token { token, text: token_text } => {
+                let operation = operations.iter().find(|&&(operator_index, _)| operator_index == index).map(|&(_, operation)| operation);
+                docs.push(match operation {
+                    Some(Operation::MemberAccess | Operation::Exponent) => text(token_text),
+                    Some(operation) if Some(operation.precedence()) == breakable => {
+                        Doc::Concat(vec![Doc::Line, text(format!("{token_text} "))])
+                    },
+                    Some(_) => text(format!(" {token_text} ")),
+                    None if token.value == TokenValue::Keyword(Keyword::Action) => text("action "),
+                    None => text(token_text),
+                });
+            },
+        }
+    }
+    if breakable.is_some() {
+        group(vec![Doc::Indent(docs)])
+    } else {
+        Doc::Concat(docs)
+    }
+}
+
+/// Parentheses, points, lists, arguments and indices.
+fn delimited(node: &Node) -> Doc {
+    let inner = &node.children[1..node.children.len() - 1];
+    let mut parts = Parts::new(false);
+    let mut expression_count = 0;
+    let mut is_comma_separated = true;
+    for (index, child) in inner.iter().enumerate() {
+        match child {
+            Element::Node(item) => {
+                parts.push(expression(item));
+                expression_count += 1;
+            },
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 2..]),
+            Element::Token { token, text: separator } => {
+                match token.value {
+                    TokenValue::Symbol(Symbol::Comma) => {
+                        // A trailing comma is only kept if the group is broken
+                        if inner[index + 1..].iter().any(|child| child.node().is_some()) {
+                            parts.push(text(","));
+                            parts.separate(Break::Line);
+                        }
+                    },
+                    TokenValue::Symbol(Symbol::Semicolon) => {
+                        is_comma_separated = false;
+                        parts.push(text(";"));
+                        parts.separate(Break::Line);
+                    },
+                    // A named argument stays on the line of its value
+                    TokenValue::Symbol(Symbol::Colon) if node.kind == NodeKind::Arguments => parts.push(text(": ")),
+                    TokenValue::Keyword(Keyword::In) => {
+                        is_comma_separated = false;
+                        parts.push(text(" in "));
+                    },
+                    _ => {
+                        is_comma_separated = false;
+                        parts.separate(Break::Line);
+                        parts.push(text(format!("{separator} ")));
+                    },
+                }
+                parts.token();
+            },
+        }
+    }
+    let minimum_for_trailing_comma = match node.kind {
+        NodeKind::Parenthesized => 2,
+        NodeKind::Index => usize::MAX,
+        _ => 1,
+    };
+    let parts = if is_comma_separated && expression_count >= minimum_for_trailing_comma {
+        parts.finish_with(Doc::IfBroken(","))
+    } else if !is_comma_separated {
+        // A comprehension, range or fill is only split up further if it does not fit on its own line
+        vec![group(parts.finish())]
+    } else {
+        parts.finish()
+    };
+    let is_literal_list = node.kind == NodeKind::List && is_comma_separated && expression_count > 1
+        && inner.iter().all(|child| match child {
+            Element::Node(item) => is_literal(item),
+            Element::Trivia(trivia) => !trivia.is_comment(),
+            Element::Token { .. } => true,
+        });
+    let parts = if is_literal_list {
+        // Lists of literals fill the lines rather than taking one each
+        vec![fill(parts)]
+    } else {
+        parts
+    };
+    bracketed(token_text(&node.children[0]), parts, token_text(node.children.last().unwrap()))
+}
+
+/// Whether an expression is a literal, possibly negated.
+fn is_literal(node: &Node) -> bool {
+    let tokens: Vec<&Token> = node.tokens().collect();
+    node.nodes().next().is_none() && match tokens.as_slice() {
+        [literal] | [Token { value: TokenValue::Symbol(Symbol::Minus), .. }, literal] => {
+            ConstantValue::from_token_value(&literal.value).is_some()
+        },
+        _ => false,
+    }
+}
+
+/// Joins each item with the comma after it, so that `Fill` can move them to the next line as a whole.
+fn fill(parts: Vec<Doc>) -> Doc {
+    let mut contents: Vec<Doc> = Vec::new();
+    for part in parts {
+        match (part, contents.last_mut()) {
+            (Doc::Line, _) => contents.push(Doc::Line),
+            (part, Some(Doc::Concat(item))) => item.push(part),
+            (part, _) => contents.push(Doc::Concat(vec![part])),
+        }
+    }
+    Doc::Fill(contents)
+}
+
+/// Piecewise expressions with more than one piece are always broken, one piece per line.
+fn conditional(node: &Node) -> Doc {
+    let inner = &node.children[1..node.children.len() - 1];
+    let expression_count = inner.iter().filter(|child| child.node().is_some()).count();
+    let colon_count = inner.iter().filter(|child| child.is_symbol(Symbol::Colon)).count();
+    let piece_count = expression_count - colon_count;
+    let mut parts = Parts::new(false);
+    let mut pieces_done = 0;
+    let mut after_colon = false;
+    let mut ends_with_branch = false;
+    for (index, child) in inner.iter().enumerate() {
+        match child {
+            Element::Node(item) if after_colon => {
+                parts.push(expression(item));
+                pieces_done += 1;
+                after_colon = false;
+                if pieces_done < piece_count {
+                    parts.push(text(","));
+                    parts.separate(Break::Line);
+                } else {
+                    ends_with_branch = true;
+                }
+            },
+            Element::Node(item) => {
+                parts.push(expression(item));
+                let is_condition = inner[index + 1..].iter()
+                    .find(|other| other.node().is_some() || other.is_symbol(Symbol::Colon))
+                    .is_some_and(|other| other.is_symbol(Symbol::Colon));
+                if !is_condition {
+                    // The default value
+                    pieces_done += 1;
+                    if pieces_done < piece_count {
+                        parts.separate(Break::Line);
+                    }
+                }
+            },
+            Element::Token { token, .. } => {
+                if token.value == TokenValue::Symbol(Symbol::Colon) {
+                    parts.push(text(": "));
+                    after_colon = true;
+                }
+                parts.token();
+            },
+            Element::Trivia(trivia) => parts.trivia(trivia, &node.children[index + 2..]),
+        }
+    }
+    let parts = if ends_with_branch { parts.finish_with(Doc::IfBroken(",")) } else { parts.finish() };
+    let contents = vec![text("{"), Doc::Indent([vec![Doc::SoftLine], parts].concat()), Doc::SoftLine, text("}")];
+    let broken = piece_count > 1 || has_forced_break(&contents[1]);
+    Doc::Group { broken, contents }
+}
+
+/// Formats desmosify source. Source that does not parse is rejected with the parser's errors rather than guessed at.
+pub fn format(source: &str) -> Result<String, Vec<DesmosifyError>> {
+    syntax::parse(&tokenize(source)?)?;
+    let tree = cst::parse(source)?;
+    Ok(print(&file(&tree)))
+}
@@ -0,0 +1,507 @@
+//! Lambdas, and the higher-order built-ins which take them.
+//!
+//! Desmos has no function values, so a call of a higher-order built-in is specialized as soon as it is parsed into the
+//! comprehensions, filters and piecewise definitions it stands for, with its function written out inline. A function
+//! is either a lambda (`x => x * 2`, or `(acc, x) => acc + x` for several parameters) or the name of a function, which
+//! is called with the items. Nothing after parsing sees a lambda, so a lambda anywhere else is an error.
+//!
+//! - `@map(L, f)` is `[f(x) for x in L]`
+//! - `@filter(L, f)` is `[L where [f(x) for x in L]]`
+//! - `@count(L, f)` is `@count(@filter(L, f))`, while `@count(L)` is still the Desmos command
+//! - `@any(L, f)` and `@all(L, f)` count the items for which `f` holds
+//! - `@sort_by(L, f)` is `@sort(L, [f(x) for x in L])`
+//! - `@zip(A, B)` is the list of points `(A[i], B[i])`, and `@zip(A, B, f)` the list of `f(A[i], B[i])`, as long as
+//!   the shorter list
+//! - `@fold(L, init, f)` and `@reduce(L, f)` are `@total`, `@max` or `@min` of a comprehension where `f` adds the item
+//!   to the accumulator or takes the larger or smaller of them, and otherwise a step per item of a list written out in
+//!   the call, which is an error past [`MAX_FOLD_LENGTH`] items, or steps up to [`MAX_FOLD_LENGTH`] with a warning
+//!   (from [`unbounded_folds`]) for a list whose length isn't known
+//!
+//! Where a list is needed more than once it is bound to a generated name with `with`, so it is only evaluated once.
+//! Generated names are chosen to differ from every name in the call's arguments, and values bound to a lambda's
+//! parameters only refer to generated names, so nothing is captured.
+
+use super::*;
+
+/// The built-ins which take a function, given either as a lambda or as the name of a function.
+pub const HIGHER_ORDER_BUILTINS: &[&str] = &["map", "filter", "count", "any", "all", "sort_by", "zip", "fold", "reduce"];
+
+/// The longest list `@fold` and `@reduce` work on when they can't be written with `@total`, `@max` or `@min`, as every
+/// item is a step written out in the output. Longer lists give undefined rather than a partial result, so a list known
+/// to be longer is an error.
+pub const MAX_FOLD_LENGTH: i64 = 32;
+
+pub fn message_stray_lambda() -> String {
+    String::from("lambdas can only be passed to higher-order built-ins such as '@map'")
+}
+
+/// Checks the operator expression just built by the parser, specializing it if it calls a higher-order built-in and
+/// normalizing the operands of a lambda to its parameter names followed by its body.
+pub fn specialize(expression: Expression) -> Result<Expression, DesmosifyError> {
+    let ExpressionValue::Operator(operation, operands) = expression.value else {
+        return Ok(expression);
+    };
+    match operation {
+        Operation::Lambda => {
+            let [parameters, body] = <[Expression; 2]>::try_from(operands).unwrap();
+            let mut names = match parameters.value {
+                ExpressionValue::Operator(Operation::PointLiteral, components) => components,
+                _ => vec![parameters],
+            };
+            if let Some(lambda) = names.iter().chain([&body]).find(|operand| operand.is_lambda()) {
+                return Err(DesmosifyError::new(message_stray_lambda(), lambda.start, lambda.end));
+            }
+            if let Some(name) = names.iter().find(|name| !matches!(&name.value, ExpressionValue::Name(name) if !name.contains("::"))) {
+                return Err(DesmosifyError::new(String::from("expected the names of the parameters before '=>'"), name.start, name.end));
+            }
+            names.push(body);
+            Ok(Expression {
+                value: ExpressionValue::Operator(Operation::Lambda, names),
+                ..expression
+            })
+        }
+        Operation::Call => match operands.first().and_then(builtin_name) {
+            Some(name) if HIGHER_ORDER_BUILTINS.contains(&name) && (name != "count" || operands.len() != 2) => {
+                let name = String::from(name);
+                let mut specializer = Specializer::new(name, &operands[1..]);
+                let mut specialized = specializer.specialize(operands.into_iter().skip(1).collect(), expression.start, expression.end)?;
+                set_location(&mut specialized, expression.start, expression.end);
+                Ok(specialized)
+            }
+            _ => check_operands(expression.start, expression.end, operation, operands, expression.data_type),
+        },
+        _ => check_operands(expression.start, expression.end, operation, operands, expression.data_type),
+    }
+}
+
+fn check_operands(
+    start: Option<SourceLocation>,
+    end: Option<SourceLocation>,
+    operation: Operation,
+    operands: Vec<Expression>,
+    data_type: DataType,
+) -> Result<Expression, DesmosifyError> {
+    if let Some(lambda) = operands.iter().find(|operand| operand.is_lambda()) {
+        return Err(DesmosifyError::new(message_stray_lambda(), lambda.start, lambda.end));
+    }
+    Ok(Expression { data_type, value: ExpressionValue::Operator(operation, operands), start, end })
+}
+
+fn builtin_name(callee: &Expression) -> Option<&str> {
+    match &callee.value {
+        ExpressionValue::Operator(Operation::BuiltIn, operands) => match &operands.first()?.value {
+            ExpressionValue::Name(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Gives every generated expression without a location that of the call it was specialized from, so errors in them
+/// point somewhere useful.
+fn set_location(expression: &mut Expression, start: Option<SourceLocation>, end: Option<SourceLocation>) {
+    if expression.start.is_none() {
+        expression.start = start;
+        expression.end = end;
+    }
+    if let ExpressionValue::Operator(_, operands) = &mut expression.value {
+        for operand in operands {
+            set_location(operand, start, end);
+        }
+    }
+}
+
+fn expression(value: ExpressionValue) -> Expression {
+    Expression { data_type: DataType::Unknown, value, start: None, end: None }
+}
+
+fn operator(operation: Operation, operands: Vec<Expression>) -> Expression {
+    expression(ExpressionValue::Operator(operation, operands))
+}
+
+fn name(name: &str) -> Expression {
+    expression(ExpressionValue::Name(String::from(name)))
+}
+
+fn int(value: i64) -> Expression {
+    Expression::from_constant(ConstantValue::Int(value))
+}
+
+fn call_builtin(builtin: &str, arguments: Vec<Expression>) -> Expression {
+    let mut operands = vec![operator(Operation::BuiltIn, vec![name(builtin)])];
+    operands.extend(arguments);
+    operator(Operation::Call, operands)
+}
+
+fn index(list: &str, position: Expression) -> Expression {
+    operator(Operation::Index, vec![name(list), position])
+}
+
+fn with(body: Expression, variable: &str, value: Expression) -> Expression {
+    operator(Operation::With, vec![body, operator(Operation::Assignment, vec![name(variable), value])])
+}
+
+/// Whether `expression` refers to `variable` where it isn't bound by a comprehension or `with` inside it, such as in a
+/// fold specialized within it.
+fn mentions(expression: &Expression, variable: &str) -> bool {
+    let mut references = BTreeSet::new();
+    dependencies::collect_expression_references(expression, &BTreeSet::new(), &mut references);
+    references.contains(variable)
+}
+
+/// A function passed to a higher-order built-in.
+enum Function {
+    Lambda { parameters: Vec<String>, body: Expression },
+    Named(Expression),
+}
+
+impl Function {
+    fn parameters(&self) -> &[String] {
+        match self {
+            Self::Lambda { parameters, .. } => parameters,
+            Self::Named(_) => &[],
+        }
+    }
+
+    /// The function applied to `accumulator` and `item`, which a step of a fold binds: a lambda's own parameters, or
+    /// generated names passed to a named function.
+    fn step(&self, accumulator: &str, item: &str) -> Expression {
+        match self {
+            Self::Lambda { body, .. } => body.clone(),
+            Self::Named(_) => self.apply(vec![name(accumulator), name(item)]),
+        }
+    }
+
+    /// The function applied to `arguments`, which must only refer to generated names so that binding them to the
+    /// parameters of a lambda can't capture anything.
+    fn apply(&self, arguments: Vec<Expression>) -> Expression {
+        match self {
+            Self::Lambda { parameters, body } => parameters.iter().zip(arguments).rev()
+                .fold(body.clone(), |body, (parameter, argument)| with(body, parameter, argument)),
+            Self::Named(callee) => {
+                let mut operands = vec![callee.clone()];
+                operands.extend(arguments);
+                operator(Operation::Call, operands)
+            }
+        }
+    }
+}
+
+struct Specializer {
+    builtin: String,
+    taken: BTreeSet<String>,
+}
+
+impl Specializer {
+    fn new(builtin: String, arguments: &[Expression]) -> Self {
+        let mut taken = BTreeSet::new();
+        for argument in arguments {
+            argument.visit(&mut |expression| if let ExpressionValue::Name(name) = &expression.value {
+                taken.insert(name.clone());
+            });
+        }
+        Self { builtin, taken }
+    }
+
+    /// A name based on `base` which appears nowhere in the arguments and hasn't been generated before.
+    fn fresh(&mut self, base: &str) -> String {
+        let mut name = String::from(base);
+        let mut suffix = 1;
+        while self.taken.contains(&name) {
+            suffix += 1;
+            name = format!("{base}{suffix}");
+        }
+        self.taken.insert(name.clone());
+        name
+    }
+
+    fn error(&self, message: String, expression: &Expression) -> DesmosifyError {
+        DesmosifyError::new(format!("@{} {message}", self.builtin), expression.start, expression.end)
+    }
+
+    fn function(&self, argument: Expression, parameter_count: usize) -> Result<Function, DesmosifyError> {
+        match argument.value {
+            ExpressionValue::Operator(Operation::Lambda, mut operands) => {
+                let body = operands.pop().unwrap();
+                if operands.len() != parameter_count {
+                    return Err(DesmosifyError::new(
+                        format!("@{} expects a lambda with {parameter_count} parameter(s), but it has {}", self.builtin, operands.len()),
+                        argument.start,
+                        argument.end,
+                    ));
+                }
+                let parameters = Vec::from_iter(operands.into_iter().map(|parameter| match parameter.value {
+                    ExpressionValue::Name(name) => name,
+                    _ => unreachable!(),
+                }));
+                Ok(Function::Lambda { parameters, body })
+            }
+            ExpressionValue::Name(_) => Ok(Function::Named(argument)),
+            _ => Err(self.error(String::from("expects a lambda or the name of a function"), &argument)),
+        }
+    }
+
+    /// Calls `build` with the name of a variable holding `list`, binding one with `with` unless `list` is already a
+    /// name which isn't one of the function's parameters.
+    fn with_list(&mut self, list: Expression, function: Option<&Function>, build: impl FnOnce(&mut Self, &str) -> Expression) -> Expression {
+        match &list.value {
+            ExpressionValue::Name(list_name) if !function.is_some_and(|function| function.parameters().contains(list_name))
+                => build(self, list_name),
+            _ => {
+                let variable = self.fresh("items");
+                let body = build(self, &variable);
+                with(body, &variable, list)
+            }
+        }
+    }
+
+    /// `[f(x) for x in list]`, using the parameter of a lambda as the variable.
+    fn comprehension(&mut self, function: &Function, list: Expression) -> Expression {
+        match function {
+            Function::Lambda { parameters, body } => operator(Operation::ListMap, vec![body.clone(), name(&parameters[0]), list]),
+            Function::Named(_) => {
+                let item = self.fresh("item");
+                operator(Operation::ListMap, vec![function.apply(vec![name(&item)]), name(&item), list])
+            }
+        }
+    }
+
+    /// `[list where [f(x) for x in list]]`.
+    fn filter(&mut self, list: &str, function: &Function) -> Expression {
+        let condition = self.comprehension(function, name(list));
+        operator(Operation::ListFilter, vec![name(list), condition])
+    }
+
+    fn specialize(&mut self, arguments: Vec<Expression>, start: Option<SourceLocation>, end: Option<SourceLocation>) -> Result<Expression, DesmosifyError> {
+        let expected: &[usize] = match self.builtin.as_str() {
+            "zip" => &[2, 3],
+            "fold" => &[3],
+            _ => &[2],
+        };
+        if !expected.contains(&arguments.len()) {
+            let expected = Vec::from_iter(expected.iter().map(ToString::to_string)).join(" or ");
+            return Err(DesmosifyError::new(
+                format!("@{} expects {expected} arguments, but was provided {}", self.builtin, arguments.len()),
+                start,
+                end,
+            ));
+        }
+        let mut arguments = arguments.into_iter();
+        let list = arguments.next().unwrap();
+        Ok(match self.builtin.as_str() {
+            "map" => {
+                let function = self.function(arguments.next().unwrap(), 1)?;
+                self.comprehension(&function, list)
+            }
+            "filter" => {
+                let function = self.function(arguments.next().unwrap(), 1)?;
+                self.with_list(list, Some(&function), |this, list| this.filter(list, &function))
+            }
+            "count" | "any" => {
+                let function = self.function(arguments.next().unwrap(), 1)?;
+                let count = self.with_list(list, Some(&function), |this, list| call_builtin("count", vec![this.filter(list, &function)]));
+                if self.builtin == "any" {
+                    operator(Operation::GreaterThan, vec![count, int(0)])
+                } else {
+                    count
+                }
+            }
+            "all" => {
+                let function = self.function(arguments.next().unwrap(), 1)?;
+                self.with_list(list, Some(&function), |this, list| operator(Operation::Equal, vec![
+                    call_builtin("count", vec![this.filter(list, &function)]),
+                    call_builtin("count", vec![name(list)]),
+                ]))
+            }
+            "sort_by" => {
+                let function = self.function(arguments.next().unwrap(), 1)?;
+                self.with_list(list, Some(&function), |this, list| {
+                    let keys = this.comprehension(&function, name(list));
+                    call_builtin("sort", vec![name(list), keys])
+                })
+            }
+            "zip" => {
+                let other = arguments.next().unwrap();
+                let function = arguments.next().map(|function| self.function(function, 2)).transpose()?;
+                let function = function.as_ref();
+                self.with_list(list, function, |this, first| this.with_list(other, function, |this, second| {
+                    let position = this.fresh("position");
+                    let items = vec![index(first, name(&position)), index(second, name(&position))];
+                    let item = match function {
+                        Some(function) => function.apply(items),
+                        None => operator(Operation::PointLiteral, items),
+                    };
+                    let length = call_builtin("min", vec![
+                        call_builtin("count", vec![name(first)]),
+                        call_builtin("count", vec![name(second)]),
+                    ]);
+                    operator(Operation::ListMap, vec![
+                        item,
+                        name(&position),
+                        operator(Operation::InclusiveRange, vec![int(1), length]),
+                    ])
+                }))
+            }
+            _ => {
+                let initial = if self.builtin == "fold" {
+                    Some(arguments.next().unwrap())
+                } else {
+                    None
+                };
+                let function = self.function(arguments.next().unwrap(), 2)?;
+                if let Some(aggregate) = self.aggregate(list.clone(), initial.clone(), &function) {
+                    aggregate
+                } else {
+                    self.unroll(list, initial, &function)?
+                }
+            }
+        })
+    }
+
+    /// `@fold` or `@reduce` with a lambda which adds each item to the accumulator, or takes the larger or smaller of
+    /// the two, written with `@total`, `@max` or `@min` instead of a step per item. An item added by `@reduce` can't be
+    /// changed by the lambda, as the first item isn't passed to it.
+    fn aggregate(&mut self, list: Expression, initial: Option<Expression>, function: &Function) -> Option<Expression> {
+        let Function::Lambda { parameters, body } = function else {
+            return None;
+        };
+        let (accumulator, item) = (parameters[0].as_str(), parameters[1].as_str());
+        let (builtin, operands) = match &body.value {
+            ExpressionValue::Operator(Operation::Add, operands) => ("total", &operands[..]),
+            ExpressionValue::Operator(Operation::Call, operands) => match builtin_name(&operands[0]) {
+                Some(builtin @ ("max" | "min")) => (builtin, &operands[1..]),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let step = match operands {
+            [lhs, rhs] if matches!(&lhs.value, ExpressionValue::Name(name) if name == accumulator) => rhs,
+            [lhs, rhs] if matches!(&rhs.value, ExpressionValue::Name(name) if name == accumulator) => lhs,
+            _ => return None,
+        };
+        if mentions(step, accumulator) {
+            return None;
+        }
+        let Some(initial) = initial else {
+            if !matches!(&step.value, ExpressionValue::Name(name) if name == item) {
+                return None;
+            }
+            return Some(if builtin == "total" {
+                // The total of no items is 0, where reducing them is undefined
+                self.with_list(list, Some(function), |_, list| operator(Operation::Conditional, vec![
+                    operator(Operation::GreaterThan, vec![call_builtin("count", vec![name(list)]), int(0)]),
+                    call_builtin("total", vec![name(list)]),
+                ]))
+            } else {
+                call_builtin(builtin, vec![list])
+            });
+        };
+        let items = operator(Operation::ListMap, vec![step.clone(), name(item), list]);
+        Some(if builtin == "total" {
+            operator(Operation::Add, vec![initial, call_builtin("total", vec![items])])
+        } else {
+            call_builtin(builtin, vec![call_builtin("join", vec![initial, items])])
+        })
+    }
+
+    /// `@fold` or `@reduce` as a step per item, each binding the accumulator to the previous step. If the length of
+    /// the list is known there is exactly a step per item, and otherwise there are steps up to [`MAX_FOLD_LENGTH`]
+    /// which give the accumulator back unchanged once past the end of the list. The list (and its length, if it isn't
+    /// known) is always bound to a generated name, so that the optimizer doesn't take the parts of every step using it
+    /// for repeated subexpressions.
+    fn unroll(&mut self, list: Expression, initial: Option<Expression>, function: &Function) -> Result<Expression, DesmosifyError> {
+        let known_length = known_length(&list);
+        if let Some(length) = known_length.filter(|&length| length > MAX_FOLD_LENGTH as usize) {
+            return Err(self.error(
+                format!("can only step through lists of up to {MAX_FOLD_LENGTH} items, but this one has {length}"),
+                &list,
+            ));
+        }
+        let items = self.fresh("items");
+        let (mut result, first) = match initial {
+            Some(initial) => (initial, 1),
+            None => (index(&items, int(1)), 2),
+        };
+        let (accumulator, item) = match function {
+            Function::Lambda { parameters, .. } => (parameters[0].clone(), parameters[1].clone()),
+            Function::Named(_) => (self.fresh("accumulator"), self.fresh("item")),
+        };
+        let Some(length) = known_length else {
+            let length = self.fresh("length");
+            for position in first..=MAX_FOLD_LENGTH {
+                let step = operator(Operation::Conditional, vec![
+                    operator(Operation::LessEqual, vec![int(position), name(&length)]),
+                    with(function.step(&accumulator, &item), &item, index(&items, int(position))),
+                    name(&accumulator),
+                ]);
+                result = with(step, &accumulator, result);
+            }
+            let result = operator(Operation::Conditional, vec![
+                operator(Operation::LessEqual, vec![name(&length), int(MAX_FOLD_LENGTH)]),
+                result,
+            ]);
+            return Ok(with(with(result, &length, call_builtin("count", vec![name(&items)])), &items, list));
+        };
+        for position in first..=length as i64 {
+            let step = with(function.step(&accumulator, &item), &item, index(&items, int(position)));
+            result = with(step, &accumulator, result);
+        }
+        Ok(with(result, &items, list))
+    }
+}
+
+/// The number of items in `list` if it is written out in the source, as a list literal or a range between numbers.
+fn known_length(list: &Expression) -> Option<usize> {
+    let number = |bound: &Expression| match &bound.value {
+        ExpressionValue::Literal(ConstantValue::Int(value)) => Some(*value as f64),
+        ExpressionValue::Literal(ConstantValue::Real(value)) => Some(*value),
+        ExpressionValue::Operator(Operation::Negate, operands) => match &operands[0].value {
+            ExpressionValue::Literal(ConstantValue::Int(value)) => Some(-*value as f64),
+            ExpressionValue::Literal(ConstantValue::Real(value)) => Some(-*value),
+            _ => None,
+        },
+        _ => None,
+    };
+    match &list.value {
+        ExpressionValue::Literal(ConstantValue::List(_, items)) => Some(items.len()),
+        ExpressionValue::Operator(Operation::ListLiteral, items) => Some(items.len()),
+        ExpressionValue::Operator(operation @ (Operation::InclusiveRange | Operation::ExclusiveRange), bounds) => {
+            let bounds = bounds.iter().map(|bound| number(bound).map(interpret::Value::Number)).collect::<Option<Vec<_>>>()?;
+            match interpret::range(&bounds, *operation == Operation::InclusiveRange) {
+                Ok(interpret::Value::List(items)) => Some(items.len()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Warns about every `@fold` or `@reduce` which was unrolled for a list whose length isn't known, as it gives undefined
+/// once the list is longer than [`MAX_FOLD_LENGTH`]. An unrolled fold is recognized by the shape [`Specializer::unroll`]
+/// gives it: the steps guarded by the length, which is bound to the count of the list.
+pub fn unbounded_folds(definitions: &Definitions) -> Vec<DesmosifyError> {
+    let mut warnings = Vec::new();
+    definitions.visit_expressions(&mut |expression| {
+        let ExpressionValue::Operator(Operation::With, operands) = &expression.value else { return };
+        let [body, binding] = operands.as_slice() else { return };
+        let (ExpressionValue::Operator(Operation::With, inner), ExpressionValue::Operator(Operation::Assignment, items)) = (&body.value, &binding.value) else { return };
+        let ([guarded, length_binding], [items, list]) = (inner.as_slice(), items.as_slice()) else { return };
+        let (ExpressionValue::Operator(Operation::Conditional, branches), ExpressionValue::Operator(Operation::Assignment, length)) = (&guarded.value, &length_binding.value) else { return };
+        let is_guard = matches!(&branches[0].value, ExpressionValue::Operator(Operation::LessEqual, bound)
+            if same_name(&bound[0], &length[0]) && matches!(bound[1].value, ExpressionValue::Literal(ConstantValue::Int(MAX_FOLD_LENGTH))));
+        let is_count = matches!(&length[1].value, ExpressionValue::Operator(Operation::Call, call)
+            if builtin_name(&call[0]) == Some("count") && call.get(1).is_some_and(|counted| same_name(counted, items)));
+        if branches.len() == 2 && is_guard && is_count && known_length(list).is_none() {
+            warnings.push(DesmosifyError::new(
+                format!("this fold steps through at most {MAX_FOLD_LENGTH} items, so it is undefined for a longer list"),
+                expression.start,
+                expression.end,
+            ));
+        }
+    });
+    warnings
+}
+
+fn same_name(first: &Expression, second: &Expression) -> bool {
+    matches!((&first.value, &second.value), (ExpressionValue::Name(first), ExpressionValue::Name(second)) if first == second)
+}
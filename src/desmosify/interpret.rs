@@ -0,0 +1,941 @@
+use super::*;
+
+/// The longest list Desmos will create, which also bounds ranges and fills here.
+pub const MAX_LIST_LENGTH: usize = 10000;
+
+/// A value computed by the interpreter. Booleans and enum variants are numbers, as they are in Desmos.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    /// Undefined values are represented by NaN.
+    Number(f64),
+    Point(f64, f64),
+    Point3(f64, f64, f64),
+    Color(Color),
+    Polygon(Vec<(f64, f64)>),
+    /// A segment between two points, which are either both 2D or both 3D.
+    Segment(Box<Value>, Box<Value>),
+    Triangle(Box<Value>, Box<Value>, Box<Value>),
+    Sphere(Box<Value>, f64),
+    Str(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn bool(value: bool) -> Self {
+        Self::Number(if value { 1.0 } else { 0.0 })
+    }
+
+    pub fn number(&self) -> Option<f64> {
+        match *self {
+            Self::Number(value) => Some(value),
+            _ => None
+        }
+    }
+
+    /// Whether this value counts as `true` in a condition, which in Desmos means it is exactly 1.
+    pub fn is_true(&self) -> bool {
+        self.number() == Some(1.0)
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "number",
+            Self::Point(_, _) => "point",
+            Self::Point3(_, _, _) => "point3",
+            Self::Color(_) => "color",
+            Self::Polygon(_) => "polygon",
+            Self::Segment(_, _) => "segment",
+            Self::Triangle(_, _, _) => "triangle",
+            Self::Sphere(_, _) => "sphere",
+            Self::Str(_) => "str",
+            Self::List(_) => "list",
+        }
+    }
+
+    pub(crate) fn coordinates(&self) -> Option<Vec<f64>> {
+        match *self {
+            Self::Point(x, y) => Some(vec![x, y]),
+            Self::Point3(x, y, z) => Some(vec![x, y, z]),
+            _ => None
+        }
+    }
+
+    pub(crate) fn from_coordinates(coordinates: &[f64]) -> Option<Self> {
+        match *coordinates {
+            [x, y] => Some(Self::Point(x, y)),
+            [x, y, z] => Some(Self::Point3(x, y, z)),
+            _ => None
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::bool(value)
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        String::from("undefined")
+    } else {
+        // Avoid printing `-0`, which Desmos shows as `0`
+        (value + 0.0).to_string()
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            &Self::Number(value) => write!(f, "{}", format_number(value)),
+            &Self::Point(x, y) => write!(f, "({}, {})", format_number(x), format_number(y)),
+            &Self::Point3(x, y, z) => write!(f, "({}, {}, {})", format_number(x), format_number(y), format_number(z)),
+            Self::Color(Color::Rgb { red, green, blue }) => write!(f, "@rgb({red}, {green}, {blue})"),
+            Self::Color(Color::Hsv { hue, saturation, value }) => write!(f, "@hsv({hue}, {saturation}, {value})"),
+            Self::Polygon(vertices) => write!(f, "@polygon({})", Vec::from_iter(vertices.iter()
+                .map(|&(x, y)| Self::Point(x, y).to_string())).join(", ")),
+            Self::Segment(start, end) => write!(f, "@segment({start}, {end})"),
+            Self::Triangle(a, b, c) => write!(f, "@triangle({a}, {b}, {c})"),
+            Self::Sphere(center, radius) => write!(f, "@sphere({center}, {})", format_number(*radius)),
+            Self::Str(value) => write!(f, "{value:?}"),
+            Self::List(items) => write!(f, "[{}]", Vec::from_iter(items.iter().map(Self::to_string)).join(", ")),
+        }
+    }
+}
+
+/// Values bound while evaluating a single expression or action, as opposed to the program's variables.
+#[derive(Clone, Debug, Default)]
+struct Bindings {
+    locals: BTreeMap<String, Value>,
+    dt: Option<f64>,
+    index: Option<f64>,
+}
+
+/// Runs an analyzed program without Desmos, so that its logic can be checked from plain Rust code.
+///
+/// Expressions are evaluated the way the Desmos targets translate them: lists broadcast through arithmetic and
+/// function calls, conditions are true only when they equal 1, and undefined results are NaN rather than errors.
+/// Actions follow Desmos' simultaneous update semantics, where every new value is computed from the state before
+/// the action ran.
+pub struct Interpreter<'a> {
+    signatures: &'a Signatures,
+    definitions: &'a Definitions,
+    variables: BTreeMap<String, Value>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter with every `var` set to its initial value.
+    pub fn new(signatures: &'a Signatures, definitions: &'a Definitions) -> Result<Self, DesmosifyError> {
+        let mut interpreter = Self {
+            signatures,
+            definitions,
+            variables: BTreeMap::new(),
+        };
+        for (name, signature) in &signatures.user_defined {
+            if let (Signature::Var { .. }, Some(value)) = (signature, definitions.identifiers.get(name)) {
+                let value = interpreter.evaluate(value)?;
+                interpreter.variables.insert(name.clone(), value);
+            }
+        }
+        Ok(interpreter)
+    }
+
+    /// The current values of all variables, by name.
+    pub fn variables(&self) -> &BTreeMap<String, Value> {
+        &self.variables
+    }
+
+    /// Evaluates the variable, constant or definition called `name`.
+    pub fn value(&self, name: &str) -> Result<Value, DesmosifyError> {
+        self.evaluate_name(name, &Bindings::default(), None, None)
+    }
+
+    /// Overwrites the current value of the variable `name`, such as to set up a particular game state.
+    pub fn set_value(&mut self, name: &str, value: Value) -> Result<(), DesmosifyError> {
+        match self.variables.get_mut(name) {
+            Some(variable) => {
+                *variable = value;
+                Ok(())
+            },
+            None => Err(DesmosifyError::new(
+                format!("'{name}' is not declared with 'var'"),
+                None,
+                None,
+            ))
+        }
+    }
+
+    /// Evaluates an expression against the current state of the program.
+    pub fn evaluate(&self, expression: &Expression) -> Result<Value, DesmosifyError> {
+        self.evaluate_in(expression, &Bindings::default())
+    }
+
+    /// Runs the action called `name` with the given arguments.
+    pub fn run(&mut self, name: &str, arguments: &[Value]) -> Result<(), DesmosifyError> {
+        let mut updates = Vec::new();
+        self.collect_call_updates(name, arguments.to_vec(), None, None, &mut updates)?;
+        self.apply(updates)
+    }
+
+    /// Runs an action which is not part of the program, such as one entered in the REPL.
+    pub fn perform(&mut self, action: &Action) -> Result<(), DesmosifyError> {
+        let mut updates = Vec::new();
+        self.collect_updates(action, &Bindings::default(), &mut updates)?;
+        self.apply(updates)
+    }
+
+    /// The minimum interval between ticks in milliseconds, if the ticker specifies one.
+    pub fn ticker_interval(&self) -> Result<Option<f64>, DesmosifyError> {
+        let Some(interval_ms) = self.definitions.ticker.as_ref().and_then(|ticker| ticker.interval_ms.as_ref()) else {
+            return Ok(None);
+        };
+        match self.evaluate(interval_ms)? {
+            Value::Number(value) => Ok(Some(value)),
+            value => Err(self.error(interval_ms, format!("expected a number for the ticker interval, but got a {}", value.type_name()))),
+        }
+    }
+
+    /// Advances the program by one tick which took `dt_ms` milliseconds, running the ticker action with `@dt` set
+    /// and advancing every `var timer` at the same time.
+    pub fn tick(&mut self, dt_ms: f64) -> Result<(), DesmosifyError> {
+        let mut updates = Vec::new();
+        if let Some(ticker) = &self.definitions.ticker {
+            let bindings = Bindings {
+                dt: Some(dt_ms),
+                ..Default::default()
+            };
+            self.collect_updates(&ticker.tick_action, &bindings, &mut updates)?;
+        }
+        for (name, signature) in &self.signatures.user_defined {
+            if let Signature::Var { qualifier: Some(VariableQualifier::Timer), .. } = signature {
+                let value = match self.variables.get(name) {
+                    Some(&Value::Number(value)) => Value::Number(value + dt_ms / 1000.0),
+                    _ => Value::Number(f64::NAN),
+                };
+                updates.push((name.clone(), value, None));
+            }
+        }
+        self.apply(updates)
+    }
+
+    /// Clicks the display element at `element_index` (counting from 0 in source order). For elements which draw a
+    /// list, `index` is the 1-based position of the item clicked and is available to the action as `@index`.
+    pub fn click(&mut self, element_index: usize, index: usize) -> Result<(), DesmosifyError> {
+        let element = self.definitions.display.iter().flatten().nth(element_index)
+            .ok_or_else(|| DesmosifyError::new(
+                format!("there is no display element at index {element_index}"),
+                None,
+                None,
+            ))?;
+        let click = element.click.as_ref()
+            .ok_or_else(|| self.error(&element.what, String::from("this display element has no 'click' attribute")))?;
+        let bindings = Bindings {
+            index: Some(index as f64),
+            ..Default::default()
+        };
+        let mut updates = Vec::new();
+        self.collect_updates(&click.action, &bindings, &mut updates)?;
+        self.apply(updates)
+    }
+
+    fn error(&self, expression: &Expression, message: String) -> DesmosifyError {
+        DesmosifyError::new(message, expression.start, expression.end)
+    }
+
+    fn apply(&mut self, updates: Vec<(String, Value, Option<&Expression>)>) -> Result<(), DesmosifyError> {
+        for (position, (name, _, target)) in updates.iter().enumerate() {
+            if updates[..position].iter().any(|(other, _, _)| other == name) {
+                let (start, end) = target.map_or((None, None), |target| (target.start, target.end));
+                return Err(DesmosifyError::new(
+                    format!("'{name}' is updated more than once by the same action"),
+                    start,
+                    end,
+                ));
+            }
+        }
+        for (name, value, _) in updates {
+            self.variables.insert(name, value);
+        }
+        Ok(())
+    }
+
+    /// Collects the updates made by `action` without applying them, so that every value is computed from the
+    /// state before the action.
+    fn collect_updates<'b>(&self, action: &'b Action, bindings: &Bindings, updates: &mut Vec<(String, Value, Option<&'b Expression>)>) -> Result<(), DesmosifyError> {
+        match action {
+            Action::Block(sub_actions) => {
+                for sub_action in sub_actions {
+                    self.collect_updates(sub_action, bindings, updates)?;
+                }
+                Ok(())
+            },
+            Action::Update(target, value) => {
+                let ExpressionValue::Name(name) = &target.value else {
+                    return Err(self.error(target, String::from("expected a variable name")));
+                };
+                if !self.variables.contains_key(name) {
+                    return Err(self.error(target, format!("cannot update the value of '{name}' as it is not declared with 'var'")));
+                }
+                let value = self.evaluate_in(value, bindings)?;
+                updates.push((name.clone(), value, Some(target.as_ref())));
+                Ok(())
+            },
+            Action::Call(callee, arguments) => {
+                let ExpressionValue::Name(name) = &callee.value else {
+                    return Err(self.error(callee, String::from("expected an action name")));
+                };
+                let arguments = arguments.iter()
+                    .map(|argument| self.evaluate_in(argument, bindings))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // The updates inside the called action are attributed to its own definition
+                let mut call_updates = Vec::new();
+                self.collect_call_updates(name, arguments, callee.start, callee.end, &mut call_updates)?;
+                updates.extend(call_updates.into_iter().map(|(name, value, _)| (name, value, Some(callee.as_ref()))));
+                Ok(())
+            },
+            Action::Conditional(branches, default_branch) => {
+                for (condition, branch) in branches {
+                    if self.evaluate_in(condition, bindings)?.is_true() {
+                        return self.collect_updates(branch, bindings, updates);
+                    }
+                }
+                match default_branch {
+                    Some(default_branch) => self.collect_updates(default_branch, bindings, updates),
+                    None => Ok(()),
+                }
+            },
+        }
+    }
+
+    fn collect_call_updates(&self, name: &str, arguments: Vec<Value>, start: Option<SourceLocation>, end: Option<SourceLocation>, updates: &mut Vec<(String, Value, Option<&'a Expression>)>) -> Result<(), DesmosifyError> {
+        let (Some(Signature::Action { parameters, .. }), Some(content)) = (self.signatures.user_defined.get(name), self.definitions.actions.get(name)) else {
+            return Err(DesmosifyError::new(format!("could not find an action named '{name}'"), start, end));
+        };
+        if arguments.len() != parameters.len() {
+            return Err(DesmosifyError::new(
+                format!("action {name} expects {} argument(s), but was provided {}", parameters.len(), arguments.len()),
+                start,
+                end,
+            ));
+        }
+        let bindings = Bindings {
+            locals: parameters.iter().map(|parameter| parameter.name.clone()).zip(arguments).collect(),
+            ..Default::default()
+        };
+        self.collect_updates(content, &bindings, updates)
+    }
+
+    fn evaluate_name(&self, name: &str, bindings: &Bindings, start: Option<SourceLocation>, end: Option<SourceLocation>) -> Result<Value, DesmosifyError> {
+        if let Some(value) = bindings.locals.get(name).or_else(|| self.variables.get(name)) {
+            return Ok(value.clone());
+        }
+        match (self.signatures.user_defined.get(name), self.definitions.identifiers.get(name)) {
+            (Some(Signature::Const { parameters: None, .. } | Signature::Let { parameters: None, .. }), Some(value)) => {
+                self.evaluate_in(value, &Bindings::default())
+            },
+            (Some(Signature::Const { .. } | Signature::Let { .. }), _) => Err(DesmosifyError::new(
+                format!("'{name}' is a function, so it must be called"),
+                start,
+                end,
+            )),
+            _ => Err(DesmosifyError::new(format!("could not find a definition for '{name}'"), start, end)),
+        }
+    }
+
+    fn evaluate_constant(&self, value: &ConstantValue) -> Value {
+        match value {
+            &ConstantValue::Real(value) => Value::Number(value),
+            &ConstantValue::Int(value) => Value::Number(value as f64),
+            &ConstantValue::Bool(value) => Value::bool(value),
+            &ConstantValue::Point(x, y) => Value::Point(x, y),
+            &ConstantValue::IPoint(x, y) => Value::Point(x as f64, y as f64),
+            &ConstantValue::Color(color) => Value::Color(color),
+            ConstantValue::Polygon(vertices) => Value::Polygon(vertices.clone()),
+            &ConstantValue::Segment((x1, y1), (x2, y2)) => Value::Segment(
+                Box::new(Value::Point(x1, y1)),
+                Box::new(Value::Point(x2, y2)),
+            ),
+            &ConstantValue::Point3(x, y, z) => Value::Point3(x, y, z),
+            &ConstantValue::IPoint3(x, y, z) => Value::Point3(x as f64, y as f64, z as f64),
+            ConstantValue::Str(value) => Value::Str(value.clone()),
+            ConstantValue::List(_, items) => Value::List(items.iter().map(|item| self.evaluate_constant(item)).collect()),
+            &ConstantValue::EnumVariant(_, _, position) => Value::Number(position as f64),
+        }
+    }
+
+    /// Enum variants are numbered from 0 in the order they are declared.
+    fn enum_variant(&self, name: &str, variant: &str, expression: &Expression) -> Result<Value, DesmosifyError> {
+        let Some(Signature::Enum { variants, .. }) = self.signatures.user_defined.get(name) else {
+            return Err(self.error(expression, format!("could not find an enum named '{name}'")));
+        };
+        variants.iter().position(|other| other == variant)
+            .map(|position| Value::Number(position as f64))
+            .ok_or_else(|| self.error(expression, format!("enum {name} has no variant named '{variant}'")))
+    }
+
+    fn evaluate_in(&self, expression: &Expression, bindings: &Bindings) -> Result<Value, DesmosifyError> {
+        match &expression.value {
+            ExpressionValue::Literal(value) => Ok(self.evaluate_constant(value)),
+            ExpressionValue::Name(name) => self.evaluate_name(name, bindings, expression.start, expression.end),
+            ExpressionValue::Operator(operation, operands) => self.evaluate_operator(expression, *operation, operands, bindings),
+        }
+    }
+
+    fn evaluate_operator(&self, expression: &Expression, operation: Operation, operands: &[Expression], bindings: &Bindings) -> Result<Value, DesmosifyError> {
+        // These operations have operands which are not expressions of their own, or which are evaluated lazily
+        match operation {
+            Operation::BuiltIn => {
+                let ExpressionValue::Name(name) = &operands[0].value else {
+                    return Err(self.error(expression, String::from("expected the name of a built-in")));
+                };
+                return match (name.as_str(), bindings) {
+                    ("dt", Bindings { dt: Some(dt), .. }) => Ok(Value::Number(*dt)),
+                    ("index", Bindings { index: Some(index), .. }) => Ok(Value::Number(*index)),
+                    ("pi", _) => Ok(Value::Number(std::f64::consts::PI)),
+                    ("e", _) => Ok(Value::Number(std::f64::consts::E)),
+                    _ => Err(self.error(expression, format!("'@{name}' has no value here"))),
+                };
+            },
+            Operation::MemberAccess => {
+                let ExpressionValue::Name(member) = &operands[1].value else {
+                    return Err(self.error(expression, String::from("expected a member name")));
+                };
+                if let ExpressionValue::Name(name) = &operands[0].value {
+                    if let Some(Signature::Enum { .. }) = self.signatures.user_defined.get(name) {
+                        return self.enum_variant(name, member, expression);
+                    }
+                }
+                let component = match member.as_str() {
+                    "x" => 0,
+                    "y" => 1,
+                    "z" => 2,
+                    _ => return Err(self.error(expression, format!("unknown member '.{member}'"))),
+                };
+                let object = self.evaluate_in(&operands[0], bindings)?;
+                return map_value(&object, &|value| match value.coordinates() {
+                    Some(coordinates) if component < coordinates.len() => Ok(Value::Number(coordinates[component])),
+                    _ => Err(format!("a {} has no member '.{member}'", value.type_name())),
+                }).map_err(|message| self.error(expression, message));
+            },
+            Operation::ListMap => {
+                // Nested `for` clauses iterate together like a Desmos comprehension, with the first varying fastest
+                let mut variables = Vec::new();
+                let mut body = expression;
+                while let ExpressionValue::Operator(Operation::ListMap, operands) = &body.value {
+                    let ExpressionValue::Name(name) = &operands[1].value else {
+                        return Err(self.error(&operands[1], String::from("expected a name")));
+                    };
+                    let Value::List(items) = self.evaluate_in(&operands[2], bindings)? else {
+                        return Err(self.error(&operands[2], String::from("expected a list to iterate over")));
+                    };
+                    variables.push((name, items));
+                    body = &operands[0];
+                }
+                variables.reverse();
+                let length = variables.iter().map(|(_, items)| items.len()).product::<usize>();
+                if MAX_LIST_LENGTH < length {
+                    return Err(self.error(expression, format!("lists can have at most {MAX_LIST_LENGTH} items, but this has {length}")));
+                }
+                let mut results = Vec::with_capacity(length);
+                for position in 0..length {
+                    let mut inner_bindings = bindings.clone();
+                    let mut remainder = position;
+                    for (name, items) in &variables {
+                        inner_bindings.locals.insert((*name).clone(), items[remainder % items.len()].clone());
+                        remainder /= items.len();
+                    }
+                    results.push(self.evaluate_in(body, &inner_bindings)?);
+                }
+                return Ok(Value::List(results));
+            },
+            Operation::With => {
+                let ExpressionValue::Operator(Operation::Assignment, binding) = &operands[1].value else {
+                    return Err(self.error(&operands[1], String::from("expected an assignment")));
+                };
+                let ExpressionValue::Name(name) = &binding[0].value else {
+                    return Err(self.error(&binding[0], String::from("expected a name")));
+                };
+                let mut inner_bindings = bindings.clone();
+                inner_bindings.locals.insert(name.clone(), self.evaluate_in(&binding[1], bindings)?);
+                return self.evaluate_in(&operands[0], &inner_bindings);
+            },
+            Operation::Conditional => return self.evaluate_conditional(operands, bindings),
+            Operation::Call => {
+                let arguments = operands[1..].iter()
+                    .map(|argument| self.evaluate_in(argument, bindings))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return match &operands[0].value {
+                    ExpressionValue::Name(name) => self.call_function(name, arguments, expression),
+                    ExpressionValue::Operator(Operation::BuiltIn, builtin) => match &builtin[0].value {
+                        ExpressionValue::Name(name) => call_builtin(name, &arguments)
+                            .map_err(|message| self.error(expression, message)),
+                        _ => Err(self.error(expression, String::from("expected the name of a built-in"))),
+                    },
+                    _ => Err(self.error(&operands[0], String::from("expected the name of a function"))),
+                };
+            },
+            Operation::ActionCall | Operation::Assignment | Operation::Update | Operation::Regression => {
+                return Err(self.error(expression, String::from("this expression does not have a value")));
+            },
+            _ => {}
+        }
+
+        let values = operands.iter()
+            .map(|operand| self.evaluate_in(operand, bindings))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match operation {
+            Operation::PointLiteral => broadcast(&values, &|components| {
+                let coordinates = components.iter()
+                    .map(|component| component.number().ok_or_else(|| format!("cannot use a {} as a coordinate", component.type_name())))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::from_coordinates(&coordinates).ok_or_else(|| String::from("points must have 2 or 3 coordinates"))
+            }),
+            Operation::ListLiteral => Ok(Value::List(values)),
+            Operation::ListFill => match values[1] {
+                Value::Number(count) if count.is_nan() || count <= 0.0 => Ok(Value::List(Vec::new())),
+                Value::Number(count) if count <= MAX_LIST_LENGTH as f64 => Ok(Value::List(vec![values[0].clone(); count as usize])),
+                Value::Number(count) => Err(format!("lists can have at most {MAX_LIST_LENGTH} items, but this has {count}")),
+                _ => Err(String::from("expected a number of items")),
+            },
+            Operation::ListFilter => match (&values[0], &values[1]) {
+                (Value::List(items), Value::List(conditions)) => Ok(Value::List(items.iter().zip(conditions)
+                    .filter(|(_, condition)| condition.is_true())
+                    .map(|(item, _)| item.clone())
+                    .collect())),
+                (Value::List(items), condition) => Ok(Value::List(if condition.is_true() {
+                    items.clone()
+                } else {
+                    Vec::new()
+                })),
+                (value, _) => Err(format!("cannot filter a {}", value.type_name())),
+            },
+            Operation::Index => index(&values[0], &values[1]),
+            Operation::Posate => Ok(values[0].clone()),
+            Operation::Negate => map_value(&values[0], &|value| match value.coordinates() {
+                Some(coordinates) => Ok(Value::from_coordinates(&Vec::from_iter(coordinates.iter().map(|coordinate| -coordinate))).unwrap()),
+                None => map_number(value, &|value| -value),
+            }),
+            Operation::Not => map_number(&values[0], &|value| if value == 1.0 { 0.0 } else { 1.0 }),
+            Operation::Exponent => broadcast(&values, &|values| numbers(values, |values| values[0].powf(values[1]))),
+            Operation::Multiply => broadcast(&values, &|values| match (&values[0], &values[1]) {
+                (point, &Value::Number(scale)) | (&Value::Number(scale), point) if point.coordinates().is_some() => Ok(
+                    Value::from_coordinates(&Vec::from_iter(point.coordinates().unwrap().iter().map(|coordinate| coordinate * scale))).unwrap(),
+                ),
+                _ => numbers(values, |values| values[0] * values[1]),
+            }),
+            Operation::Divide => broadcast(&values, &|values| match (&values[0], &values[1]) {
+                (point, &Value::Number(scale)) if point.coordinates().is_some() => Ok(
+                    Value::from_coordinates(&Vec::from_iter(point.coordinates().unwrap().iter().map(|coordinate| divide(*coordinate, scale)))).unwrap(),
+                ),
+                _ => numbers(values, |values| divide(values[0], values[1])),
+            }),
+            Operation::IntegerDivide => broadcast(&values, &|values| numbers(values, |values| divide(values[0], values[1]).floor())),
+            Operation::Modulus => broadcast(&values, &|values| numbers(values, |values| modulo(values[0], values[1]))),
+            Operation::Add | Operation::Subtract => {
+                let sign = if operation == Operation::Add { 1.0 } else { -1.0 };
+                broadcast(&values, &|values| match (values[0].coordinates(), values[1].coordinates()) {
+                    (Some(lhs), Some(rhs)) if lhs.len() == rhs.len() => Ok(Value::from_coordinates(&Vec::from_iter(lhs.iter().zip(&rhs)
+                        .map(|(lhs, rhs)| lhs + sign * rhs))).unwrap()),
+                    _ => numbers(values, |values| values[0] + sign * values[1]),
+                })
+            },
+            Operation::BitwiseNot => map_number(&values[0], &|value| -value.floor() - 1.0),
+            Operation::BitwiseAnd => broadcast(&values, &|values| numbers(values, |values| bitwise(values[0], values[1], |lhs, rhs| lhs & rhs))),
+            Operation::BitwiseXor => broadcast(&values, &|values| numbers(values, |values| bitwise(values[0], values[1], |lhs, rhs| lhs ^ rhs))),
+            Operation::BitwiseOr => broadcast(&values, &|values| numbers(values, |values| bitwise(values[0], values[1], |lhs, rhs| lhs | rhs))),
+            Operation::ShiftLeft => broadcast(&values, &|values| numbers(values, |values| (values[0] * 2f64.powf(values[1])).floor())),
+            Operation::ShiftRight => broadcast(&values, &|values| numbers(values, |values| divide(values[0], 2f64.powf(values[1])).floor())),
+            Operation::LessThan => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] < values[1]))),
+            Operation::GreaterThan => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] > values[1]))),
+            Operation::LessEqual => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] <= values[1]))),
+            Operation::GreaterEqual => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] >= values[1]))),
+            Operation::Equal => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] == values[1]))),
+            Operation::NotEqual => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] != values[1]))),
+            Operation::And => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] == 1.0 && values[1] == 1.0))),
+            Operation::Or => broadcast(&values, &|values| numbers(values, |values| bool_number(values[0] == 1.0 || values[1] == 1.0))),
+            Operation::InclusiveRange => range(&values, true),
+            Operation::ExclusiveRange => range(&values, false),
+            _ => Err(format!("cannot evaluate {operation:?}")),
+        }.map_err(|message| self.error(expression, message))
+    }
+
+    fn evaluate_conditional(&self, operands: &[Expression], bindings: &Bindings) -> Result<Value, DesmosifyError> {
+        let mut branches = operands.chunks_exact(2);
+        for branch in branches.by_ref() {
+            match self.evaluate_in(&branch[0], bindings)? {
+                Value::List(_) => return self.evaluate_list_conditional(operands, bindings),
+                condition if condition.is_true() => return self.evaluate_in(&branch[1], bindings),
+                _ => {}
+            }
+        }
+        match branches.remainder() {
+            [default] => self.evaluate_in(default, bindings),
+            _ => Ok(Value::Number(f64::NAN)),
+        }
+    }
+
+    /// Desmos evaluates a conditional with list conditions once for every item, so every branch is evaluated up front.
+    fn evaluate_list_conditional(&self, operands: &[Expression], bindings: &Bindings) -> Result<Value, DesmosifyError> {
+        let values = operands.iter()
+            .map(|operand| self.evaluate_in(operand, bindings))
+            .collect::<Result<Vec<_>, _>>()?;
+        broadcast(&values, &|values| {
+            let mut branches = values.chunks_exact(2);
+            for branch in branches.by_ref() {
+                if branch[0].is_true() {
+                    return Ok(branch[1].clone());
+                }
+            }
+            Ok(branches.remainder().first().cloned().unwrap_or(Value::Number(f64::NAN)))
+        }).map_err(|message| self.error(&operands[0], message))
+    }
+
+    fn call_function(&self, name: &str, arguments: Vec<Value>, expression: &Expression) -> Result<Value, DesmosifyError> {
+        let (Some(Signature::Const { parameters: Some(parameters), .. } | Signature::Let { parameters: Some(parameters), .. }), Some(body)) =
+            (self.signatures.user_defined.get(name), self.definitions.identifiers.get(name)) else {
+            return Err(self.error(expression, format!("could not find a function named '{name}'")));
+        };
+        if arguments.len() != parameters.len() {
+            return Err(self.error(expression, format!("function {name} expects {} argument(s), but was provided {}", parameters.len(), arguments.len())));
+        }
+        // A list passed for a parameter which is not a list calls the function once for every item
+        let broadcast_position = arguments.iter().zip(parameters)
+            .position(|(argument, parameter)| matches!(argument, Value::List(_)) && !matches!(parameter.data_type, DataType::List { .. }));
+        if let Some(position) = broadcast_position {
+            let Value::List(items) = &arguments[position] else {
+                unreachable!();
+            };
+            return items.iter()
+                .map(|item| {
+                    let mut item_arguments = arguments.clone();
+                    item_arguments[position] = item.clone();
+                    self.call_function(name, item_arguments, expression)
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::List);
+        }
+        let bindings = Bindings {
+            locals: parameters.iter().map(|parameter| parameter.name.clone()).zip(arguments).collect(),
+            ..Default::default()
+        };
+        self.evaluate_in(body, &bindings)
+    }
+}
+
+pub(crate) fn bool_number(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+/// Desmos gives undefined rather than infinity when dividing by zero.
+pub(crate) fn divide(lhs: f64, rhs: f64) -> f64 {
+    if rhs == 0.0 { f64::NAN } else { lhs / rhs }
+}
+
+/// The number of values which the 32 bits used by bitwise operators can represent.
+const BIT_RANGE: f64 = 4294967296.0;
+
+/// Applies a bitwise operator to the low 32 bits of two numbers rounded down, as Desmos does with the sums over
+/// their digits which the operator is lowered to. Infinite operands have no digits, so give undefined.
+fn bitwise(lhs: f64, rhs: f64, function: fn(u32, u32) -> u32) -> f64 {
+    if !lhs.is_finite() || !rhs.is_finite() {
+        return f64::NAN;
+    }
+    let bits = |value: f64| value.floor().rem_euclid(BIT_RANGE) as u32;
+    f64::from(function(bits(lhs), bits(rhs)))
+}
+
+/// The result of `mod` in Desmos has the sign of the divisor.
+pub(crate) fn modulo(lhs: f64, rhs: f64) -> f64 {
+    if rhs == 0.0 { f64::NAN } else { lhs - rhs * (lhs / rhs).floor() }
+}
+
+/// Applies `function` to every item of a list (recursively), or to the value itself if it is not a list.
+pub(crate) fn map_value(value: &Value, function: &dyn Fn(&Value) -> Result<Value, String>) -> Result<Value, String> {
+    match value {
+        Value::List(items) => items.iter()
+            .map(|item| map_value(item, function))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        _ => function(value),
+    }
+}
+
+pub(crate) fn map_number(value: &Value, function: &dyn Fn(f64) -> f64) -> Result<Value, String> {
+    map_value(value, &|value| match *value {
+        Value::Number(value) => Ok(Value::Number(function(value))),
+        _ => Err(format!("expected a number, but got a {}", value.type_name())),
+    })
+}
+
+/// Applies `function` to several values, calling it once for every item if any of them are lists.
+/// Like Desmos, the result is as long as the shortest list.
+pub(crate) fn broadcast(values: &[Value], function: &dyn Fn(&[Value]) -> Result<Value, String>) -> Result<Value, String> {
+    let length = values.iter()
+        .filter_map(|value| match value {
+            Value::List(items) => Some(items.len()),
+            _ => None
+        })
+        .min();
+    let Some(length) = length else {
+        return function(values);
+    };
+    (0..length)
+        .map(|position| broadcast(&Vec::from_iter(values.iter().map(|value| match value {
+            Value::List(items) => items[position].clone(),
+            _ => value.clone(),
+        })), function))
+        .collect::<Result<_, _>>()
+        .map(Value::List)
+}
+
+pub(crate) fn numbers(values: &[Value], function: impl Fn(&[f64]) -> f64) -> Result<Value, String> {
+    let values = values.iter()
+        .map(|value| value.number().ok_or_else(|| format!("expected a number, but got a {}", value.type_name())))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Number(function(&values)))
+}
+
+/// Indexes a list from 1, giving undefined for positions outside the list.
+pub(crate) fn index(list: &Value, position: &Value) -> Result<Value, String> {
+    let Value::List(items) = list else {
+        return Err(format!("cannot index a {}", list.type_name()));
+    };
+    map_value(position, &|position| match *position {
+        Value::Number(position) if position.fract() == 0.0 && 1.0 <= position && position <= items.len() as f64 => {
+            Ok(items[position as usize - 1].clone())
+        },
+        Value::Number(_) => Ok(Value::Number(f64::NAN)),
+        _ => Err(format!("cannot index a list with a {}", position.type_name())),
+    })
+}
+
+/// Builds `[first...end]` or `[first, second...end]`, counting down if `end` is below `first`.
+pub(crate) fn range(values: &[Value], inclusive: bool) -> Result<Value, String> {
+    let bounds = values.iter()
+        .map(|value| value.number().ok_or_else(|| format!("expected a number for a range bound, but got a {}", value.type_name())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (first, end) = (bounds[0], bounds[bounds.len() - 1]);
+    let step = match *bounds.as_slice() {
+        [first, second, _] => second - first,
+        _ if end < first => -1.0,
+        _ => 1.0,
+    };
+    if !(first.is_finite() && end.is_finite() && step.is_finite()) || step == 0.0 {
+        return Ok(Value::List(Vec::new()));
+    }
+    let count = ((end - first) / step + 1e-9).floor();
+    if count < 0.0 {
+        return Ok(Value::List(Vec::new()));
+    }
+    let mut count = count as usize + 1;
+    if !inclusive && first + (count - 1) as f64 * step == end {
+        count -= 1;
+    }
+    if MAX_LIST_LENGTH < count {
+        return Err(format!("lists can have at most {MAX_LIST_LENGTH} items, but this has {count}"));
+    }
+    Ok(Value::List(Vec::from_iter((0..count).map(|position| Value::Number(first + position as f64 * step)))))
+}
+
+/// The numbers in a list, or in the arguments themselves if there are several.
+fn list_numbers(arguments: &[Value]) -> Result<Vec<f64>, String> {
+    let items = match arguments {
+        [Value::List(items)] => items.as_slice(),
+        _ => arguments,
+    };
+    items.iter()
+        .map(|item| item.number().ok_or_else(|| format!("expected a number, but got a {}", item.type_name())))
+        .collect()
+}
+
+fn point_arguments(arguments: &[Value], count: usize) -> Result<Vec<Vec<f64>>, String> {
+    if arguments.len() != count {
+        return Err(format!("expected {count} point(s), but was provided {} argument(s)", arguments.len()));
+    }
+    arguments.iter()
+        .map(|argument| argument.coordinates().ok_or_else(|| format!("expected a point, but got a {}", argument.type_name())))
+        .collect()
+}
+
+fn gcd(lhs: f64, rhs: f64) -> f64 {
+    let (mut lhs, mut rhs) = (lhs.abs().round(), rhs.abs().round());
+    while rhs != 0.0 {
+        (lhs, rhs) = (rhs, lhs % rhs);
+    }
+    lhs
+}
+
+fn factorial_ratio(n: f64, k: f64) -> f64 {
+    ((n - k + 1.0).round() as i64..=n.round() as i64).map(|value| value as f64).product()
+}
+
+/// Calls one of the Desmos commands available through `@name(...)`. Commands which produce random or plotted
+/// results (such as `random` or `histogram`) are not supported.
+pub(crate) fn call_builtin(name: &str, arguments: &[Value]) -> Result<Value, String> {
+    let unary = |function: fn(f64) -> f64| match arguments {
+        [argument] => map_number(argument, &function),
+        _ => Err(format!("@{name} expects 1 argument, but was provided {}", arguments.len())),
+    };
+    let binary = |function: fn(f64, f64) -> f64| match arguments {
+        [_, _] => broadcast(arguments, &|values| numbers(values, |values| function(values[0], values[1]))),
+        _ => Err(format!("@{name} expects 2 arguments, but was provided {}", arguments.len())),
+    };
+    match name {
+        "sin" => unary(f64::sin),
+        "cos" => unary(f64::cos),
+        "tan" => unary(f64::tan),
+        "csc" => unary(|value| 1.0 / value.sin()),
+        "sec" => unary(|value| 1.0 / value.cos()),
+        "cot" => unary(|value| 1.0 / value.tan()),
+        "arcsin" => unary(f64::asin),
+        "arccos" => unary(f64::acos),
+        "arctan" if arguments.len() == 2 => binary(f64::atan2),
+        "arctan" => unary(f64::atan),
+        "arccsc" => unary(|value| (1.0 / value).asin()),
+        "arcsec" => unary(|value| (1.0 / value).acos()),
+        "arccot" => unary(|value| (1.0 / value).atan()),
+        "sinh" => unary(f64::sinh),
+        "cosh" => unary(f64::cosh),
+        "tanh" => unary(f64::tanh),
+        "csch" => unary(|value| 1.0 / value.sinh()),
+        "sech" => unary(|value| 1.0 / value.cosh()),
+        "coth" => unary(|value| 1.0 / value.tanh()),
+        "exp" => unary(f64::exp),
+        "ln" => unary(f64::ln),
+        "log" => unary(f64::log10),
+        "sqrt" => unary(f64::sqrt),
+        "cbrt" => unary(f64::cbrt),
+        "abs" => unary(f64::abs),
+        "floor" => unary(f64::floor),
+        "ceil" => unary(f64::ceil),
+        "round" if arguments.len() == 2 => binary(|value, places| {
+            let scale = 10f64.powf(places.round());
+            (value * scale).round() / scale
+        }),
+        "round" => unary(f64::round),
+        "sign" => unary(|value| if value == 0.0 || value.is_nan() { value } else { value.signum() }),
+        "mod" => binary(modulo),
+        "nthroot" => binary(|value, root| if value < 0.0 && root % 2.0 == 1.0 {
+            -(-value).powf(1.0 / root)
+        } else {
+            value.powf(1.0 / root)
+        }),
+        "gcd" => binary(gcd),
+        "lcm" => binary(|lhs, rhs| divide((lhs * rhs).abs().round(), gcd(lhs, rhs))),
+        "nPr" => binary(factorial_ratio),
+        "nCr" => binary(|n, k| factorial_ratio(n, k) / factorial_ratio(k, k)),
+        "total" => Ok(Value::Number(list_numbers(arguments)?.iter().sum())),
+        "count" => match arguments {
+            [Value::List(items)] => Ok(Value::Number(items.len() as f64)),
+            _ => Ok(Value::Number(arguments.len() as f64)),
+        },
+        "length" => match arguments {
+            [Value::Segment(start, end)] => call_builtin("distance", &[(**start).clone(), (**end).clone()]),
+            [Value::List(items)] => Ok(Value::Number(items.len() as f64)),
+            _ => Ok(Value::Number(arguments.len() as f64)),
+        },
+        "mean" => {
+            let values = list_numbers(arguments)?;
+            Ok(Value::Number(divide(values.iter().sum(), values.len() as f64)))
+        },
+        "median" => {
+            let mut values = list_numbers(arguments)?;
+            values.sort_by(f64::total_cmp);
+            Ok(Value::Number(match values.len() {
+                0 => f64::NAN,
+                length if length % 2 == 0 => (values[length / 2 - 1] + values[length / 2]) / 2.0,
+                length => values[length / 2],
+            }))
+        },
+        "min" => Ok(Value::Number(list_numbers(arguments)?.into_iter().reduce(f64::min).unwrap_or(f64::NAN))),
+        "max" => Ok(Value::Number(list_numbers(arguments)?.into_iter().reduce(f64::max).unwrap_or(f64::NAN))),
+        "join" => Ok(Value::List(arguments.iter()
+            .flat_map(|argument| match argument {
+                Value::List(items) => items.clone(),
+                _ => vec![argument.clone()],
+            })
+            .collect())),
+        "sort" => match arguments {
+            [Value::List(items)] => {
+                let mut keys = list_numbers(arguments)?.into_iter().zip(items.iter().cloned()).collect::<Vec<_>>();
+                keys.sort_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+                Ok(Value::List(keys.into_iter().map(|(_, item)| item).collect()))
+            },
+            [Value::List(items), Value::List(keys)] => {
+                let keys = list_numbers(&[Value::List(keys.clone())])?;
+                let mut pairs = keys.into_iter().zip(items.iter().cloned()).collect::<Vec<_>>();
+                pairs.sort_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+                Ok(Value::List(pairs.into_iter().map(|(_, item)| item).collect()))
+            },
+            _ => Err(format!("@{name} expects a list and optionally a list of keys")),
+        },
+        "unique" => match arguments {
+            [Value::List(items)] => {
+                let mut unique = Vec::new();
+                for item in items {
+                    if !unique.contains(item) {
+                        unique.push(item.clone());
+                    }
+                }
+                Ok(Value::List(unique))
+            },
+            _ => Err(format!("@{name} expects a list")),
+        },
+        "rgb" => match *list_numbers(arguments)?.as_slice() {
+            [red, green, blue] => Ok(Value::Color(Color::rgb(red, green, blue))),
+            _ => Err(format!("@{name} expects 3 arguments")),
+        },
+        "hsv" => match *list_numbers(arguments)?.as_slice() {
+            [hue, saturation, value] => Ok(Value::Color(Color::hsv(hue, saturation, value))),
+            _ => Err(format!("@{name} expects 3 arguments")),
+        },
+        "polygon" => {
+            let vertices = match arguments {
+                [Value::List(items)] => items.as_slice(),
+                _ => arguments,
+            };
+            vertices.iter()
+                .map(|vertex| match *vertex {
+                    Value::Point(x, y) => Ok((x, y)),
+                    _ => Err(format!("expected a point, but got a {}", vertex.type_name())),
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Polygon)
+        },
+        "segment" => {
+            let points = point_arguments(arguments, 2)?;
+            if points[0].len() != points[1].len() {
+                return Err(String::from("cannot create a segment between points with different dimensions"));
+            }
+            Ok(Value::Segment(Box::new(arguments[0].clone()), Box::new(arguments[1].clone())))
+        },
+        "triangle" => {
+            point_arguments(arguments, 3)?;
+            Ok(Value::Triangle(Box::new(arguments[0].clone()), Box::new(arguments[1].clone()), Box::new(arguments[2].clone())))
+        },
+        "sphere" => match arguments {
+            [center @ Value::Point3(_, _, _), Value::Number(radius)] => Ok(Value::Sphere(Box::new(center.clone()), *radius)),
+            _ => Err(format!("@{name} expects a point3 and a radius")),
+        },
+        "distance" => {
+            let points = point_arguments(arguments, 2)?;
+            Ok(Value::Number(points[0].iter().zip(&points[1]).map(|(lhs, rhs)| (lhs - rhs).powi(2)).sum::<f64>().sqrt()))
+        },
+        "midpoint" => {
+            let points = point_arguments(arguments, 2)?;
+            if points[0].len() != points[1].len() {
+                return Err(String::from("cannot find the midpoint of points with different dimensions"));
+            }
+            Value::from_coordinates(&Vec::from_iter(points[0].iter().zip(&points[1]).map(|(lhs, rhs)| (lhs + rhs) / 2.0)))
+                .ok_or_else(|| String::from("expected 2D or 3D points"))
+        },
+        _ => Err(format!("the interpreter does not support '@{name}'")),
+    }
+}
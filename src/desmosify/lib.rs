@@ -2,10 +2,19 @@
 #![allow(unused_variables)]
 #![allow(unused_imports)]
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub mod cli;
+pub mod cst;
+pub mod dependencies;
 pub mod display;
+pub mod format;
+pub mod higher_order;
+pub mod interpret;
+pub mod link;
+pub mod lsp;
+pub mod optimize;
+pub mod repl;
 pub mod semantics;
 pub mod syntax;
 pub mod target;
@@ -42,6 +51,8 @@ pub struct DesmosifyError {
     message: String,
     start: Option<SourceLocation>,
     end: Option<SourceLocation>,
+    /// The file the locations are in, when there is more than one file they could be in.
+    file_name: Option<String>,
 }
 
 impl DesmosifyError {
@@ -50,12 +61,45 @@ impl DesmosifyError {
             message,
             start,
             end,
+            file_name: None,
         }
     }
+
+    /// Records which file the error is in, unless that is already known.
+    pub fn in_file(mut self, file_name: &str) -> Self {
+        self.file_name.get_or_insert_with(|| String::from(file_name));
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn start(&self) -> Option<SourceLocation> {
+        self.start
+    }
+
+    pub fn end(&self) -> Option<SourceLocation> {
+        self.end
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+}
+
+// Lets `?` pass a single error up from functions which report several
+impl From<DesmosifyError> for Vec<DesmosifyError> {
+    fn from(error: DesmosifyError) -> Self {
+        vec![error]
+    }
 }
 
 impl std::fmt::Display for DesmosifyError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(file_name) = &self.file_name {
+            write!(f, "{file_name} ")?;
+        }
         if let Some(location) = self.start {
             write!(f, "{} ", location)?;
         }
@@ -79,10 +123,23 @@ impl Color {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+/// Writes the default value of a parameter as it is declared, if it is a simple enough constant to show.
+fn describe_default(default: &Expression) -> String {
+    match default.constant_value() {
+        Some(&ConstantValue::Real(value)) => format!(" = {value:?}"),
+        Some(&ConstantValue::Int(value)) => format!(" = {value}"),
+        Some(&ConstantValue::Bool(value)) => format!(" = {value}"),
+        Some(ConstantValue::Str(value)) => format!(" = {value:?}"),
+        _ => String::from(" = ..."),
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Parameter {
     name: String,
     data_type: DataType,
+    /// The value used when a call leaves this parameter out, which is filled in by `semantics`.
+    default: Option<Expression>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -97,6 +154,11 @@ pub enum DataType {
     Color,
     Polygon,
     Segment,
+    Point3,
+    IPoint3,
+    Segment3,
+    Triangle3,
+    Sphere,
     Str,
     List { item_type: Box<DataType> },
     Function { name: String },
@@ -115,10 +177,24 @@ impl DataType {
             "color" => Self::Color,
             "polygon" => Self::Polygon,
             "segment" => Self::Segment,
+            "point3" => Self::Point3,
+            "ipoint3" => Self::IPoint3,
+            "segment3" => Self::Segment3,
+            "triangle3" => Self::Triangle3,
+            "sphere" => Self::Sphere,
             "str" => Self::Str,
             _ => Self::User { name: String::from(name) }
         }
     }
+
+    /// Whether values of this type (or the items of a list of it) only exist in the 3D calculator.
+    pub fn is_3d(&self) -> bool {
+        match self {
+            Self::Point3 | Self::IPoint3 | Self::Segment3 | Self::Triangle3 | Self::Sphere => true,
+            Self::List { item_type } => item_type.is_3d(),
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for DataType {
@@ -134,6 +210,11 @@ impl std::fmt::Display for DataType {
             Self::Color => write!(f, "color"),
             Self::Polygon => write!(f, "polygon"),
             Self::Segment => write!(f, "segment"),
+            Self::Point3 => write!(f, "point3"),
+            Self::IPoint3 => write!(f, "ipoint3"),
+            Self::Segment3 => write!(f, "segment3"),
+            Self::Triangle3 => write!(f, "triangle3"),
+            Self::Sphere => write!(f, "sphere"),
             Self::Str => write!(f, "str"),
             Self::List { item_type } => write!(f, "[{item_type}]"),
             Self::Function { name } => write!(f, "<function {name}>"),
@@ -153,9 +234,12 @@ pub enum ConstantValue {
     Color(Color),
     Polygon(Vec<(f64, f64)>),
     Segment((f64, f64), (f64, f64)),
+    Point3(f64, f64, f64),
+    IPoint3(i64, i64, i64),
     Str(String),
     List(DataType, Vec<ConstantValue>),
-    EnumVariant(String, String),
+    /// A variant of the enum with the given name, and its position among the enum's variants.
+    EnumVariant(String, String, usize),
 }
 
 impl ConstantValue {
@@ -179,11 +263,13 @@ impl ConstantValue {
             Self::Color(_) => DataType::Color,
             Self::Polygon(_) => DataType::Polygon,
             Self::Segment(_, _) => DataType::Segment,
+            Self::Point3(_, _, _) => DataType::Point3,
+            Self::IPoint3(_, _, _) => DataType::IPoint3,
             Self::Str(_) => DataType::Str,
             Self::List(item_type, _) => DataType::List {
                 item_type: Box::new(item_type.clone()),
             },
-            Self::EnumVariant(name, _) => DataType::User {
+            Self::EnumVariant(name, _, _) => DataType::User {
                 name: name.clone(),
             },
         }
@@ -198,6 +284,36 @@ pub enum Action {
     Conditional(Vec<(Expression, Action)>, Option<Box<Action>>),
 }
 
+impl Action {
+    /// Calls `visitor` on every expression (and sub-expression) contained in this action.
+    pub fn visit_expressions<F: FnMut(&Expression)>(&self, visitor: &mut F) {
+        match self {
+            Self::Block(sub_actions) => for sub_action in sub_actions {
+                sub_action.visit_expressions(visitor);
+            },
+            Self::Update(target, value) => {
+                target.visit(visitor);
+                value.visit(visitor);
+            },
+            Self::Call(callee, arguments) => {
+                callee.visit(visitor);
+                for argument in arguments {
+                    argument.visit(visitor);
+                }
+            },
+            Self::Conditional(branches, default_branch) => {
+                for (condition, branch) in branches {
+                    condition.visit(visitor);
+                    branch.visit_expressions(visitor);
+                }
+                if let Some(default_branch) = default_branch {
+                    default_branch.visit_expressions(visitor);
+                }
+            },
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum VariableQualifier {
     Timer,
@@ -259,6 +375,44 @@ impl Signature {
             _ => None
         }
     }
+
+    /// Writes this signature the way it would be declared, filling in the type found by analysis if none was declared.
+    pub fn describe(&self, definitions: &Definitions) -> String {
+        let parameter_list = |parameters: &[Parameter]| format!(
+            "({})",
+            Vec::from_iter(parameters.iter().map(|parameter| format!(
+                "{}: {}{}",
+                parameter.name,
+                parameter.data_type,
+                parameter.default.as_ref().map_or_else(String::new, describe_default),
+            ))).join(", "),
+        );
+        let type_annotation = |value_type: &DataType| {
+            let value_type = match value_type {
+                DataType::Unknown => definitions.identifiers.get(self.name()).map_or(&DataType::Unknown, |value| &value.data_type),
+                value_type => value_type,
+            };
+            match value_type {
+                DataType::Unknown => String::new(),
+                value_type => format!(": {value_type}"),
+            }
+        };
+        match self {
+            Self::Const { name, parameters, value_type } | Self::Let { name, parameters, value_type } => format!(
+                "{} {name}{}{}",
+                self.variant_name(),
+                parameters.as_deref().map(parameter_list).unwrap_or_default(),
+                type_annotation(value_type),
+            ),
+            Self::Var { name, qualifier, value_type } => format!(
+                "var {}{name}{}",
+                if qualifier.is_some() { "timer " } else { "" },
+                type_annotation(value_type),
+            ),
+            Self::Action { name, parameters } => format!("action {name}{}", parameter_list(parameters)),
+            Self::Enum { name, variants } => format!("enum {name} {{ {} }}", variants.join(", ")),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -267,6 +421,39 @@ pub struct Ticker {
     pub tick_action: Box<Action>,
 }
 
+/// A run of declarations which should be grouped together in the output, either started by a
+/// `section "Title";` statement or implicitly at the top of a source file.
+#[derive(Debug)]
+pub struct Section {
+    pub title: Option<String>,
+    pub file_name: Option<String>,
+    /// Names of the declarations in this section, in the order they appear in the source.
+    pub names: Vec<String>,
+}
+
+impl Section {
+    pub fn new(title: Option<String>) -> Self {
+        Self {
+            title,
+            file_name: None,
+            names: Vec::new(),
+        }
+    }
+}
+
+/// An `import "path";` or `import name::{...};` declaration.
+#[derive(Debug)]
+pub struct Import {
+    /// The imported file, relative to the file containing the import.
+    pub path: String,
+    /// The name the imported declarations are qualified with, as in `easing::ease_out`.
+    pub namespace: String,
+    /// Declarations of the module which can also be used without qualifying them, with their locations.
+    pub names: Vec<(String, SourceLocation, SourceLocation)>,
+    pub start: SourceLocation,
+    pub end: SourceLocation,
+}
+
 #[derive(Debug)]
 pub struct Definitions {
     pub identifiers: BTreeMap<String, Box<Expression>>,
@@ -274,6 +461,10 @@ pub struct Definitions {
     pub public: Option<Vec<Expression>>,
     pub ticker: Option<Ticker>,
     pub display: Option<Vec<display::Element>>,
+    pub sections: Vec<Section>,
+    /// The target selected by a `#target "name";` pragma, if any.
+    pub target: Option<String>,
+    pub imports: Vec<Import>,
 }
 
 impl Definitions {
@@ -284,19 +475,81 @@ impl Definitions {
             public: None,
             ticker: None,
             display: None,
+            sections: vec![Section::new(None)],
+            target: None,
+            imports: Vec::new(),
+        }
+    }
+
+    /// Records `name` as declared at the current position in the source.
+    pub fn declare(&mut self, name: &str) {
+        if let Some(section) = self.sections.last_mut() {
+            section.names.push(String::from(name));
+        }
+    }
+
+    /// Iterates over all declared names in source order.
+    pub fn source_order(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().flat_map(|section| section.names.iter().map(String::as_str))
+    }
+
+    /// Calls `visitor` on every expression (and sub-expression) in the program.
+    pub fn visit_expressions<F: FnMut(&Expression)>(&self, visitor: &mut F) {
+        for value in self.identifiers.values() {
+            value.visit(visitor);
+        }
+        for content in self.actions.values() {
+            content.visit_expressions(visitor);
+        }
+        for expression in self.public.iter().flatten() {
+            expression.visit(visitor);
+        }
+        if let Some(ticker) = &self.ticker {
+            if let Some(interval_ms) = &ticker.interval_ms {
+                interval_ms.visit(visitor);
+            }
+            ticker.tick_action.visit_expressions(visitor);
+        }
+        for element in self.display.iter().flatten() {
+            element.visit_expressions(visitor);
         }
     }
+
+    /// Marks every section which does not already belong to a file as coming from `file_name`.
+    pub fn set_file_name(&mut self, file_name: &str) {
+        for section in &mut self.sections {
+            section.file_name.get_or_insert_with(|| String::from(file_name));
+        }
+    }
+}
+
+impl Default for Definitions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug)]
 pub struct Signatures {
     pub user_defined: BTreeMap<String, Signature>,
+    /// The start and end of the name in each declaration. Enum variants are recorded as `Enum.VARIANT`.
+    pub locations: BTreeMap<String, (SourceLocation, SourceLocation)>,
+    /// The names of declarations marked `pub`, which other files can use when they import this one.
+    pub exported: BTreeSet<String>,
 }
 
 impl Signatures {
     pub fn new() -> Self {
         Self {
             user_defined: BTreeMap::new(),
+            locations: BTreeMap::new(),
+            exported: BTreeSet::new(),
         }
     }
 }
+
+impl Default for Signatures {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,605 @@
+//! Linking several source files into a single program.
+//!
+//! Each file is parsed on its own, along with every module it imports. The declarations of a module are qualified
+//! with its namespace (`easing::ease_out`), while those of the files given to `link` share one namespace so that
+//! any of them can refer to names declared in the others. Errors name the file they were found in whenever more than
+//! one file is involved.
+//!
+//! The standard library is a module bundled with the compiler, which every file can use without importing it.
+
+use crate::{Action, DesmosifyError, Definitions, DataType, Import, Parameter, Signature, Signatures};
+use crate::syntax::{Expression, ExpressionValue, Operation};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// The namespace of the standard library.
+pub const PRELUDE_NAMESPACE: &str = "std";
+/// The name the standard library is linked under, in its errors and in `Section::file_name`.
+pub const PRELUDE_FILE_NAME: &str = "std";
+pub const PRELUDE_SOURCE: &str = include_str!("std.desmos");
+
+/// A source file to be linked, with the name used in its errors and in `Section::file_name`. Imports are resolved
+/// relative to the directory in the name.
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, source: &str) -> Self {
+        Self {
+            name: String::from(name),
+            source: String::from(source),
+        }
+    }
+}
+
+/// A parsed file, and the modules its imports refer to.
+struct ParsedFile {
+    name: String,
+    /// The namespace of a module, or `None` for the files given to `link`.
+    namespace: Option<String>,
+    signatures: Signatures,
+    definitions: Definitions,
+    /// Each import of the file, with the index of the imported module.
+    imports: Vec<(Import, usize)>,
+}
+
+/// Reads and parses files and the modules they import, depth first so that every module comes before the files which
+/// import it. Each module is only loaded once, however many files import it.
+struct Loader {
+    files: Vec<ParsedFile>,
+    modules: BTreeMap<PathBuf, usize>,
+    /// The file which each namespace was loaded from, as generated names must not clash between modules.
+    namespaces: BTreeMap<String, String>,
+    /// The files currently being loaded, innermost last, for reporting import cycles.
+    loading: Vec<(PathBuf, String)>,
+    /// The index of the standard library, once it has been loaded.
+    prelude: Option<usize>,
+    errors: Vec<(String, DesmosifyError)>,
+}
+
+fn canonical_path(file_name: &str) -> PathBuf {
+    std::fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name))
+}
+
+impl Loader {
+    fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            modules: BTreeMap::new(),
+            namespaces: BTreeMap::new(),
+            loading: Vec::new(),
+            prelude: None,
+            errors: Vec::new(),
+        }
+    }
+
+    fn load(&mut self, name: &str, source: &str, namespace: Option<String>) -> Option<usize> {
+        let result = crate::token::tokenize(source)
+            .and_then(|tokens| crate::syntax::parse(&tokens));
+        let (signatures, mut definitions) = match result {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                self.errors.push((String::from(name), error));
+                return None;
+            },
+        };
+        definitions.set_file_name(name);
+
+        self.loading.push((canonical_path(name), String::from(name)));
+        let mut imports = Vec::new();
+        for import in std::mem::take(&mut definitions.imports) {
+            if let Some(index) = self.load_import(name, &import) {
+                imports.push((import, index));
+            }
+        }
+        self.loading.pop();
+
+        self.files.push(ParsedFile {
+            name: String::from(name),
+            namespace,
+            signatures,
+            definitions,
+            imports,
+        });
+        Some(self.files.len() - 1)
+    }
+
+    /// Loads the standard library the first time it is needed, returning its index.
+    fn prelude(&mut self) -> Option<usize> {
+        if self.prelude.is_none() {
+            self.namespaces.insert(String::from(PRELUDE_NAMESPACE), String::from(PRELUDE_FILE_NAME));
+            self.prelude = self.load(PRELUDE_FILE_NAME, PRELUDE_SOURCE, Some(String::from(PRELUDE_NAMESPACE)));
+        }
+        self.prelude
+    }
+
+    fn load_import(&mut self, importer: &str, import: &Import) -> Option<usize> {
+        let error = |message: String| (String::from(importer), DesmosifyError::new(message, Some(import.start), Some(import.end)));
+        if import.namespace == PRELUDE_NAMESPACE && import.path == format!("{PRELUDE_NAMESPACE}.desmos") {
+            return self.prelude();
+        }
+        let path = Path::new(importer).parent().unwrap_or(Path::new("")).join(&import.path);
+        let name = path.to_string_lossy().into_owned();
+        let canonical = canonical_path(&name);
+
+        if let Some(position) = self.loading.iter().position(|(loading, _)| *loading == canonical) {
+            let mut cycle = Vec::from_iter(self.loading[position..].iter().map(|(_, name)| format!("'{name}'")));
+            cycle.push(format!("'{name}'"));
+            self.errors.push(error(format!("import cycle: {}", cycle.join(" -> "))));
+            return None;
+        }
+        if let Some(&index) = self.modules.get(&canonical) {
+            return Some(index);
+        }
+        if let Some(original) = self.namespaces.get(&import.namespace) {
+            self.errors.push(error(format!("'{name}' cannot be imported as module '{}', which is already '{original}'", import.namespace)));
+            return None;
+        }
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.errors.push(error(format!("could not import '{name}': {err}")));
+                return None;
+            },
+        };
+        self.namespaces.insert(import.namespace.clone(), name.clone());
+        let index = self.load(&name, &source, Some(import.namespace.clone()))?;
+        self.modules.insert(canonical, index);
+        Some(index)
+    }
+}
+
+fn qualify(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}::{name}"),
+        None => String::from(name),
+    }
+}
+
+/// How the names used in one file are found: its own declarations, names imported from modules, and names qualified
+/// with the namespace of an imported module.
+struct Scope {
+    is_module: bool,
+    names: BTreeMap<String, String>,
+    /// The declared and exported names of each imported module, by namespace.
+    modules: BTreeMap<String, (BTreeSet<String>, BTreeSet<String>)>,
+}
+
+impl Scope {
+    /// Finds the name which `name` refers to after linking. Names that cannot be found are kept as they are for
+    /// analysis to report, except in modules, which cannot refer to the declarations of the files importing them.
+    fn resolve(&self, name: &str) -> Result<String, String> {
+        if let Some(resolved) = self.names.get(name) {
+            return Ok(resolved.clone());
+        }
+        let Some((namespace, member)) = name.split_once("::") else {
+            return if self.is_module {
+                Err(format!("could not find a definition for '{name}'"))
+            } else {
+                Ok(String::from(name))
+            };
+        };
+        let Some((declared, exported)) = self.modules.get(namespace) else {
+            return Err(format!("module '{namespace}' is not imported"));
+        };
+        if !declared.contains(member) {
+            Err(format!("module '{namespace}' has no declaration named '{member}'"))
+        } else if !exported.contains(member) {
+            Err(format!("'{member}' is not 'pub' in module '{namespace}'"))
+        } else {
+            Ok(String::from(name))
+        }
+    }
+
+    fn resolve_type(&self, data_type: &mut DataType) {
+        match data_type {
+            DataType::User { name } => if let Ok(resolved) = self.resolve(name) {
+                *name = resolved;
+            },
+            DataType::List { item_type } => self.resolve_type(item_type),
+            _ => {},
+        }
+    }
+
+    /// Resolves every name referenced by `expression`, skipping names in `locals` as well as names bound inside the
+    /// expression itself, in the same way as `dependencies::collect_expression_references`.
+    fn resolve_expression(&self, expression: &mut Expression, locals: &BTreeSet<String>) -> Result<(), DesmosifyError> {
+        match &mut expression.value {
+            ExpressionValue::Literal(_) => {},
+            ExpressionValue::Name(name) => if !locals.contains(name.as_str()) {
+                *name = self.resolve(name)
+                    .map_err(|message| DesmosifyError::new(message, expression.start, expression.end))?;
+                if let DataType::Action { name: action_name } = &mut expression.data_type {
+                    action_name.clone_from(name);
+                }
+            },
+            ExpressionValue::Operator(operation, operands) => match operation {
+                Operation::BuiltIn => {},
+                Operation::MemberAccess => self.resolve_expression(&mut operands[0], locals)?,
+                Operation::NamedArgument => self.resolve_expression(&mut operands[1], locals)?,
+                Operation::ListMap => {
+                    self.resolve_expression(&mut operands[2], locals)?;
+                    let mut inner_locals = locals.clone();
+                    if let ExpressionValue::Name(name) = &operands[1].value {
+                        inner_locals.insert(name.clone());
+                    }
+                    self.resolve_expression(&mut operands[0], &inner_locals)?;
+                },
+                Operation::With => {
+                    let (body, binding) = operands.split_at_mut(1);
+                    match &mut binding[0].value {
+                        ExpressionValue::Operator(Operation::Assignment, binding) => {
+                            self.resolve_expression(&mut binding[1], locals)?;
+                            let mut inner_locals = locals.clone();
+                            if let ExpressionValue::Name(name) = &binding[0].value {
+                                inner_locals.insert(name.clone());
+                            }
+                            self.resolve_expression(&mut body[0], &inner_locals)?;
+                        },
+                        _ => for operand in operands.iter_mut() {
+                            self.resolve_expression(operand, locals)?;
+                        },
+                    }
+                },
+                _ => for operand in operands {
+                    self.resolve_expression(operand, locals)?;
+                },
+            },
+        }
+        Ok(())
+    }
+
+    fn resolve_action(&self, action: &mut Action, locals: &BTreeSet<String>) -> Result<(), DesmosifyError> {
+        match action {
+            Action::Block(sub_actions) => for sub_action in sub_actions {
+                self.resolve_action(sub_action, locals)?;
+            },
+            Action::Update(target, value) => {
+                self.resolve_expression(target, locals)?;
+                self.resolve_expression(value, locals)?;
+            },
+            Action::Call(callee, arguments) => {
+                self.resolve_expression(callee, locals)?;
+                for argument in arguments {
+                    self.resolve_expression(argument, locals)?;
+                }
+            },
+            Action::Conditional(branches, default_branch) => {
+                for (condition, branch) in branches {
+                    self.resolve_expression(condition, locals)?;
+                    self.resolve_action(branch, locals)?;
+                }
+                if let Some(default_branch) = default_branch {
+                    self.resolve_action(default_branch, locals)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn resolve_element(&self, element: &mut crate::display::Element) -> Result<(), DesmosifyError> {
+        let locals = BTreeSet::new();
+        let mut expressions = vec![&mut element.what, &mut element.color];
+        if let Some(point) = &mut element.point {
+            expressions.extend([&mut point.size_pixels, &mut point.opacity]);
+        }
+        if let Some(stroke) = &mut element.stroke {
+            expressions.extend([&mut stroke.width_pixels, &mut stroke.opacity]);
+        }
+        if let Some(fill) = &mut element.fill {
+            expressions.push(&mut fill.opacity);
+        }
+        if let Some(label) = &mut element.label {
+            expressions.extend([&mut label.opacity, &mut label.scale_factor, &mut label.angle_degrees]);
+        }
+        if let Some(domain) = &mut element.domain {
+            expressions.extend([&mut domain.min, &mut domain.max]);
+        }
+        if let Some(surface_domain) = &mut element.surface_domain {
+            expressions.extend([
+                &mut surface_domain.u_min,
+                &mut surface_domain.u_max,
+                &mut surface_domain.v_min,
+                &mut surface_domain.v_max,
+            ]);
+        }
+        for expression in expressions {
+            self.resolve_expression(expression, &locals)?;
+        }
+        if let Some(click) = &mut element.click {
+            self.resolve_action(&mut click.action, &locals)?;
+        }
+        Ok(())
+    }
+}
+
+/// The declared and exported names of `module`.
+fn module_names(module: &ParsedFile) -> (BTreeSet<String>, BTreeSet<String>) {
+    (module.signatures.user_defined.keys().cloned().collect(), module.signatures.exported.clone())
+}
+
+/// Works out how the names in `files[index]` are found, reporting imports of names which cannot be imported.
+fn scope(files: &[ParsedFile], index: usize, prelude: Option<usize>) -> Result<Scope, Vec<DesmosifyError>> {
+    let file = &files[index];
+    let mut errors = Vec::new();
+    let mut scope = Scope {
+        is_module: file.namespace.is_some(),
+        names: file.signatures.user_defined.keys()
+            .map(|name| (name.clone(), qualify(file.namespace.as_deref(), name)))
+            .collect(),
+        modules: BTreeMap::new(),
+    };
+    let mut imported_from = BTreeMap::new();
+    for (import, module_index) in &file.imports {
+        let module = &files[*module_index];
+        scope.modules.insert(import.namespace.clone(), module_names(module));
+        for (name, start, end) in &import.names {
+            let error = |message: String| DesmosifyError::new(message, Some(*start), Some(*end));
+            if !module.signatures.user_defined.contains_key(name) {
+                errors.push(error(format!("module '{}' has no declaration named '{name}'", import.namespace)));
+            } else if !module.signatures.exported.contains(name) {
+                errors.push(error(format!("'{name}' is not 'pub' in module '{}'", import.namespace)));
+            } else if file.signatures.user_defined.contains_key(name) {
+                errors.push(error(format!("'{name}' is imported from module '{}' but also declared here", import.namespace)));
+            } else if let Some(original) = imported_from.insert(name.as_str(), import.namespace.as_str()).filter(|original| *original != import.namespace) {
+                errors.push(error(format!("'{name}' is imported from both module '{original}' and module '{}'", import.namespace)));
+            } else {
+                scope.names.insert(name.clone(), format!("{}::{name}", import.namespace));
+            }
+        }
+    }
+    if let Some(prelude) = prelude.filter(|&prelude| prelude != index) {
+        scope.modules.entry(String::from(PRELUDE_NAMESPACE)).or_insert_with(|| module_names(&files[prelude]));
+    }
+    if errors.is_empty() {
+        Ok(scope)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Qualifies the declarations of a file with its namespace and resolves every name it uses, so that names mean the
+/// same thing in every file once they are merged.
+fn resolve_file(file: &mut ParsedFile, scope: &Scope) -> Result<(), Vec<DesmosifyError>> {
+    let namespace = file.namespace.as_deref();
+    let mut errors = Vec::new();
+    let blocks = [
+        ("public", file.definitions.public.is_some()),
+        ("ticker", file.definitions.ticker.is_some()),
+        ("display", file.definitions.display.is_some()),
+    ];
+    for (block, is_declared) in blocks {
+        if is_declared && namespace.is_some() {
+            errors.push(DesmosifyError::new(format!("a module cannot declare a '{block}' block"), None, None));
+        }
+    }
+
+    // Default values are filled in at each call, so they can't refer to the other parameters
+    let mut resolve_parameter = |parameter: &mut Parameter| {
+        scope.resolve_type(&mut parameter.data_type);
+        if let Some(Err(error)) = parameter.default.as_mut().map(|default| scope.resolve_expression(default, &BTreeSet::new())) {
+            errors.push(error);
+        }
+    };
+    let signatures = &mut file.signatures;
+    signatures.user_defined = std::mem::take(&mut signatures.user_defined).into_iter()
+        .map(|(name, mut signature)| {
+            match &mut signature {
+                Signature::Const { name, parameters, value_type } | Signature::Let { name, parameters, value_type } => {
+                    *name = qualify(namespace, name);
+                    scope.resolve_type(value_type);
+                    parameters.iter_mut().flatten().for_each(&mut resolve_parameter);
+                },
+                Signature::Var { name, value_type, .. } => {
+                    *name = qualify(namespace, name);
+                    scope.resolve_type(value_type);
+                },
+                Signature::Action { name, parameters } => {
+                    *name = qualify(namespace, name);
+                    parameters.iter_mut().for_each(&mut resolve_parameter);
+                },
+                Signature::Enum { name, .. } => *name = qualify(namespace, name),
+            }
+            (qualify(namespace, &name), signature)
+        })
+        .collect();
+    signatures.locations = std::mem::take(&mut signatures.locations).into_iter()
+        .map(|(name, location)| (qualify(namespace, &name), location))
+        .collect();
+    signatures.exported = std::mem::take(&mut signatures.exported).into_iter()
+        .map(|name| qualify(namespace, &name))
+        .collect();
+
+    let parameters = |name: &str| -> BTreeSet<String> {
+        signatures.user_defined.get(name)
+            .and_then(Signature::parameters)
+            .map_or_else(BTreeSet::new, |parameters| parameters.iter().map(|parameter| parameter.name.clone()).collect())
+    };
+    let definitions = &mut file.definitions;
+    let mut identifiers = BTreeMap::new();
+    for (name, mut value) in std::mem::take(&mut definitions.identifiers) {
+        let name = qualify(namespace, &name);
+        if let Err(error) = scope.resolve_expression(&mut value, &parameters(&name)) {
+            errors.push(error);
+        }
+        identifiers.insert(name, value);
+    }
+    definitions.identifiers = identifiers;
+    let mut actions = BTreeMap::new();
+    for (name, mut content) in std::mem::take(&mut definitions.actions) {
+        let name = qualify(namespace, &name);
+        if let Err(error) = scope.resolve_action(&mut content, &parameters(&name)) {
+            errors.push(error);
+        }
+        actions.insert(name, content);
+    }
+    definitions.actions = actions;
+    for section in &mut definitions.sections {
+        for name in &mut section.names {
+            *name = qualify(namespace, name);
+        }
+    }
+
+    let locals = BTreeSet::new();
+    for expression in definitions.public.iter_mut().flatten() {
+        if let Err(error) = scope.resolve_expression(expression, &locals) {
+            errors.push(error);
+        }
+    }
+    if let Some(ticker) = &mut definitions.ticker {
+        let interval_result = ticker.interval_ms.as_mut().map_or(Ok(()), |interval_ms| scope.resolve_expression(interval_ms, &locals));
+        if let Err(error) = interval_result.and_then(|_| scope.resolve_action(&mut ticker.tick_action, &locals)) {
+            errors.push(error);
+        }
+    }
+    for element in definitions.display.iter_mut().flatten() {
+        if let Err(error) = scope.resolve_element(element) {
+            errors.push(error);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The paths of the modules imported by `files`, directly or through other modules, as far as they can be loaded.
+pub fn module_paths(files: &[SourceFile]) -> Vec<String> {
+    let mut loader = Loader::new();
+    for file in files {
+        loader.load(&file.name, &file.source, None);
+    }
+    let prelude = loader.prelude;
+    Vec::from_iter(loader.files.into_iter().enumerate()
+        .filter(|(index, file)| file.namespace.is_some() && Some(*index) != prelude)
+        .map(|(_, file)| file.name))
+}
+
+/// Parses, merges and analyzes `files` as one program, along with the modules they import and the standard library.
+/// Declarations keep the order of `files`, with each module placed before the first file which imports it.
+///
+/// Errors are collected from every file rather than stopping at the first file with a problem.
+pub fn link(files: &[SourceFile]) -> Result<(Signatures, Definitions), Vec<DesmosifyError>> {
+    let mut loader = Loader::new();
+    let prelude = loader.prelude();
+    for file in files {
+        loader.load(&file.name, &file.source, None);
+    }
+    let is_single_file = loader.files.len() - usize::from(prelude.is_some()) + loader.errors.len() <= 1;
+    let in_file = |file_name: &str, error: DesmosifyError| if is_single_file { error } else { error.in_file(file_name) };
+    if !loader.errors.is_empty() {
+        return Err(Vec::from_iter(loader.errors.into_iter().map(|(file_name, error)| in_file(&file_name, error))));
+    }
+
+    let mut errors = Vec::new();
+    let mut parsed = loader.files;
+    let scopes = Vec::from_iter((0..parsed.len()).map(|index| scope(&parsed, index, prelude)));
+    for (file, scope) in parsed.iter_mut().zip(scopes) {
+        if let Err(file_errors) = scope.and_then(|scope| resolve_file(file, &scope)) {
+            errors.extend(file_errors.into_iter().map(|error| in_file(&file.name, error)));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut signatures = Signatures::new();
+    let mut declared_in = BTreeMap::new();
+    let mut file_definitions = Vec::new();
+    for file in parsed {
+        let mut locations = file.signatures.locations;
+        for (name, signature) in file.signatures.user_defined {
+            let (start, end) = locations.get(&name).copied().unzip();
+            if let Some(original) = signatures.user_defined.get(&name) {
+                let original_start = signatures.locations.get(&name).map_or_else(String::new, |(start, _)| format!(" {start}"));
+                errors.push(in_file(&file.name, DesmosifyError::new(
+                    format!(
+                        "name conflicts with '{} {}' declared in '{}'{original_start}",
+                        original.variant_name(),
+                        original.name(),
+                        declared_in[&name],
+                    ),
+                    start,
+                    end,
+                )));
+                continue;
+            }
+            declared_in.insert(name.clone(), file.name.clone());
+            signatures.user_defined.insert(name, signature);
+        }
+        // Enum variants are only recorded for enums which did not conflict
+        locations.retain(|name, _| declared_in.get(name.split('.').next().unwrap_or(name)) == Some(&file.name));
+        signatures.locations.extend(locations);
+        signatures.exported.extend(file.signatures.exported);
+        file_definitions.push((file.name, file.definitions));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    for (file_name, definitions) in &mut file_definitions {
+        if let Err(error) = crate::semantics::analyze(&signatures, definitions) {
+            errors.push(in_file(file_name, error));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut linked = Definitions::new();
+    linked.sections.clear();
+    let mut declared_in = BTreeMap::new();
+    for (file_name, definitions) in file_definitions {
+        linked.identifiers.extend(definitions.identifiers);
+        linked.actions.extend(definitions.actions);
+        linked.sections.extend(definitions.sections);
+
+        let blocks = [
+            ("public", definitions.public.is_some()),
+            ("ticker", definitions.ticker.is_some()),
+            ("display", definitions.display.is_some()),
+        ];
+        for (block, is_declared) in blocks {
+            if !is_declared {
+                continue;
+            }
+            if let Some(original) = declared_in.insert(block, file_name.clone()) {
+                errors.push(in_file(&file_name, DesmosifyError::new(
+                    format!("only one '{block}' block can be declared, but one is also declared in '{original}'"),
+                    None,
+                    None,
+                )));
+            }
+        }
+        linked.public = linked.public.or(definitions.public);
+        linked.ticker = linked.ticker.or(definitions.ticker);
+        linked.display = linked.display.or(definitions.display);
+
+        match (&linked.target, definitions.target) {
+            (Some(target), Some(other)) if *target != other => errors.push(in_file(&file_name, DesmosifyError::new(
+                format!("'#target \"{other}\"' conflicts with '#target \"{target}\"' in '{}'", declared_in["#target"]),
+                None,
+                None,
+            ))),
+            (None, Some(target)) => {
+                declared_in.insert("#target", file_name.clone());
+                linked.target = Some(target);
+            },
+            _ => {},
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Each file has been checked on its own, but definitions in different files may still depend on each other
+    crate::dependencies::dependency_order(&signatures, &linked)?;
+
+    Ok((signatures, linked))
+}
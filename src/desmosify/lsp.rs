@@ -0,0 +1,578 @@
+//! A Language Server Protocol server for desmosify source, spoken as JSON-RPC over a pair of streams (normally stdin and
+//! stdout). Every change re-runs `tokenize`, `parse` and `analyze` on the whole document, which is plenty fast for the
+//! size of program Desmos can handle anyway.
+
+use crate::*;
+use crate::higher_order::HIGHER_ORDER_BUILTINS;
+use crate::semantics::analyze;
+use crate::target::DEFAULT_TARGET;
+use crate::target::desmos::translate::BUILTIN_VARIABLES;
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use json::JsonValue;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const REQUEST_FAILED: i32 = -32803;
+
+const SYNC_FULL: i32 = 1;
+const SEVERITY_ERROR: i32 = 1;
+
+const COMPLETION_FUNCTION: i32 = 3;
+const COMPLETION_VARIABLE: i32 = 6;
+const COMPLETION_ENUM: i32 = 13;
+const COMPLETION_EVENT: i32 = 23;
+const COMPLETION_CONSTANT: i32 = 21;
+const COMPLETION_ENUM_MEMBER: i32 = 20;
+
+/// The file name documents without a path on disk are linked under.
+const UNSAVED_FILE_NAME: &str = "untitled.desmos";
+
+/// Reads the content of one message, which is framed by a `Content-Length` header. Returns `None` at the end of the
+/// stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> std::io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && content_length.is_some() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut content = vec![0; content_length.unwrap()];
+    reader.read_exact(&mut content)?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &JsonValue) -> std::io::Result<()> {
+    let content = message.dump();
+    write!(writer, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    writer.flush()
+}
+
+/// Serves requests from `reader` until the client sends `exit` or closes the stream. Returns whether the client asked
+/// the server to shut down first, which decides the exit code.
+pub fn run<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> std::io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(content) = read_message(&mut reader)? {
+        for message in server.handle_content(&content) {
+            write_message(&mut writer, &message)?;
+        }
+        if server.has_exited() {
+            break;
+        }
+    }
+    Ok(server.is_shut_down())
+}
+
+struct ResponseError {
+    code: i32,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i32, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+/// What a name in the source refers to.
+#[derive(Clone, PartialEq, Debug)]
+enum Reference {
+    Name(String),
+    Variant { enum_name: String, variant: String },
+    BuiltIn(String),
+}
+
+struct Document {
+    text: String,
+    /// The tokens of `text`, unless it could not be tokenized.
+    tokens: Option<Vec<Token>>,
+    /// The program from the last version of the document which parsed, so that completion and hover keep working while
+    /// the user is in the middle of typing something.
+    program: Option<(Signatures, Definitions)>,
+    /// Whether `program` was parsed from the current `text`.
+    is_current: bool,
+}
+
+impl Document {
+    fn new() -> Self {
+        Self {
+            text: String::new(),
+            tokens: None,
+            program: None,
+            is_current: false,
+        }
+    }
+
+    /// Replaces the text of the document and analyzes it, returning the first error. Documents which import modules are
+    /// checked along with the modules if `path` says where to find them.
+    fn update(&mut self, text: String, path: Option<&str>) -> Option<DesmosifyError> {
+        self.text = text;
+        self.tokens = None;
+        self.is_current = false;
+        let tokens = match tokenize(&self.text) {
+            Ok(tokens) => tokens,
+            Err(error) => return Some(error),
+        };
+        let program = parse(&tokens);
+        self.tokens = Some(tokens);
+        let (signatures, mut definitions) = match program {
+            Ok(program) => program,
+            Err(error) => return Some(error),
+        };
+        // Names from modules and the standard library are only known once they are linked in, which checks the
+        // document too. Documents which have not been saved yet can still import modules from the current directory.
+        let _ = analyze(&signatures, &mut definitions);
+        let path = path.unwrap_or(UNSAVED_FILE_NAME);
+        let result = crate::link::link(&[crate::link::SourceFile::new(path, &self.text)]).err()
+            .and_then(|errors| errors.into_iter().next())
+            .map_or(Ok(()), |error| match error.file_name() {
+                Some(file_name) if file_name != path => Err(DesmosifyError::new(
+                    format!("in '{file_name}': {}", error.message()),
+                    None,
+                    None,
+                )),
+                _ => Err(error),
+            });
+        self.program = Some((signatures, definitions));
+        self.is_current = true;
+        result.err()
+    }
+
+    fn signatures(&self) -> Option<&Signatures> {
+        self.program.as_ref().map(|(signatures, _)| signatures)
+    }
+
+    fn is_enum(&self, name: &str) -> bool {
+        matches!(self.signatures().and_then(|signatures| signatures.user_defined.get(name)), Some(Signature::Enum { .. }))
+    }
+
+    /// Finds the name touching `offset` and what it refers to.
+    fn reference_at(&self, offset: usize) -> Option<(Reference, &Token)> {
+        let tokens = self.tokens.as_ref()?;
+        let index = tokens.iter().position(|token| {
+            matches!(token.value, TokenValue::Name(_)) && token.start.index <= offset && offset <= token.end.index
+        })?;
+        let TokenValue::Name(name) = &tokens[index].value else {
+            unreachable!()
+        };
+        let previous = |distance: usize| index.checked_sub(distance).map(|index| &tokens[index].value);
+        let reference = match previous(1) {
+            Some(TokenValue::Symbol(Symbol::AtSign)) => Reference::BuiltIn(name.clone()),
+            Some(TokenValue::Symbol(Symbol::Hash)) => return None,
+            Some(TokenValue::Symbol(Symbol::Dot)) => match previous(2) {
+                Some(TokenValue::Name(enum_name)) if self.is_enum(enum_name) => Reference::Variant {
+                    enum_name: enum_name.clone(),
+                    variant: name.clone(),
+                },
+                _ => return None,
+            },
+            _ => Reference::Name(name.clone()),
+        };
+        Some((reference, &tokens[index]))
+    }
+
+    /// Finds every token which refers to `reference`, including the declaration, for renaming.
+    fn occurrences(&self, reference: &Reference) -> Result<Vec<(SourceLocation, SourceLocation)>, String> {
+        let (Some(tokens), Some((signatures, _)), true) = (&self.tokens, &self.program, self.is_current) else {
+            return Err(String::from("cannot rename until the document parses"));
+        };
+        let is_name = |index: usize, expected: &str| matches!(&tokens[index].value, TokenValue::Name(name) if name == expected);
+        let previous_is = |index: usize, symbol: Symbol| index > 0 && tokens[index - 1].value == TokenValue::Symbol(symbol);
+        match reference {
+            Reference::Name(name) => {
+                if !signatures.user_defined.contains_key(name) {
+                    return Err(format!("'{name}' is not declared in this file"));
+                }
+                let is_parameter = signatures.user_defined.values()
+                    .filter_map(Signature::parameters)
+                    .flatten()
+                    .any(|parameter| &parameter.name == name);
+                let is_list_variable = (1..tokens.len())
+                    .any(|index| is_name(index, name) && tokens[index - 1].value == TokenValue::Keyword(Keyword::For));
+                if is_parameter || is_list_variable {
+                    return Err(format!("cannot rename '{name}' while a parameter or list variable shares its name"));
+                }
+                // Enum variants are only ever used after a '.', but are declared without one
+                let variant_starts: Vec<usize> = signatures.locations.iter()
+                    .filter(|(key, _)| key.contains('.'))
+                    .map(|(_, (start, _))| start.index)
+                    .collect();
+                Ok((0..tokens.len())
+                    .filter(|&index| is_name(index, name))
+                    .filter(|&index| ![Symbol::Dot, Symbol::AtSign, Symbol::Hash].iter().any(|&symbol| previous_is(index, symbol)))
+                    .filter(|&index| !variant_starts.contains(&tokens[index].start.index))
+                    .map(|index| (tokens[index].start, tokens[index].end))
+                    .collect())
+            },
+            Reference::Variant { enum_name, variant } => {
+                let declaration = signatures.locations.get(&format!("{enum_name}.{variant}"))
+                    .ok_or_else(|| format!("'{enum_name}' has no variant '{variant}'"))?;
+                Ok(std::iter::once(*declaration)
+                    .chain((2..tokens.len())
+                        .filter(|&index| is_name(index, variant) && previous_is(index, Symbol::Dot) && is_name(index - 2, enum_name))
+                        .map(|index| (tokens[index].start, tokens[index].end)))
+                    .collect())
+            },
+            Reference::BuiltIn(name) => Err(format!("'@{name}' is built in and cannot be renamed")),
+        }
+    }
+}
+
+/// The state of the server between messages.
+pub struct Server {
+    documents: BTreeMap<String, Document>,
+    is_shut_down: bool,
+    has_exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            documents: BTreeMap::new(),
+            is_shut_down: false,
+            has_exited: false,
+        }
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.is_shut_down
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.has_exited
+    }
+
+    /// Handles the raw content of one message, returning the messages to send back.
+    pub fn handle_content(&mut self, content: &str) -> Vec<JsonValue> {
+        match json::parse(content) {
+            Ok(message) => self.handle(&message),
+            Err(error) => vec![response(&JsonValue::Null, Err(ResponseError::new(PARSE_ERROR, error.to_string())))],
+        }
+    }
+
+    /// Handles one request or notification, returning the responses and notifications to send back.
+    pub fn handle(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        // Responses to requests the server never makes have no method
+        let Some(method) = message["method"].as_str() else {
+            return Vec::new();
+        };
+        let id = &message["id"];
+        let params = &message["params"];
+        if self.is_shut_down && method != "exit" {
+            return if id.is_null() {
+                Vec::new()
+            } else {
+                vec![response(id, Err(ResponseError::new(INVALID_REQUEST, String::from("the server has been shut down"))))]
+            };
+        }
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "initialized" => return Vec::new(),
+            "shutdown" => {
+                self.is_shut_down = true;
+                Ok(JsonValue::Null)
+            },
+            "exit" => {
+                self.has_exited = true;
+                return Vec::new();
+            },
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                return self.update(document["uri"].as_str(), document["text"].as_str());
+            },
+            "textDocument/didChange" => {
+                // Only full synchronization is offered, so the last change holds the whole text
+                let text = params["contentChanges"].members().last().and_then(|change| change["text"].as_str());
+                return self.update(params["textDocument"]["uri"].as_str(), text);
+            },
+            "textDocument/didClose" => {
+                let Some(uri) = params["textDocument"]["uri"].as_str() else {
+                    return Vec::new();
+                };
+                self.documents.remove(uri);
+                return vec![diagnostics_notification(uri, JsonValue::new_array())];
+            },
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/rename" => self.rename(params),
+            _ if id.is_null() => return Vec::new(),
+            _ => Err(ResponseError::new(METHOD_NOT_FOUND, format!("unsupported method '{method}'"))),
+        };
+        vec![response(id, result)]
+    }
+
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> Vec<JsonValue> {
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return Vec::new();
+        };
+        let document = self.documents.entry(String::from(uri)).or_insert_with(Document::new);
+        let diagnostics = match document.update(String::from(text), uri.strip_prefix("file://")) {
+            Some(error) => json::array![diagnostic(&document.text, &error)],
+            None => JsonValue::new_array(),
+        };
+        vec![diagnostics_notification(uri, diagnostics)]
+    }
+
+    /// Finds the document and offset named by the `TextDocumentPositionParams` in `params`.
+    fn position<'a>(&'a self, params: &'a JsonValue) -> Result<(&'a str, &'a Document, usize), ResponseError> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, String::from("missing text document")))?;
+        let document = self.documents.get(uri)
+            .ok_or_else(|| ResponseError::new(REQUEST_FAILED, format!("'{uri}' is not open")))?;
+        Ok((uri, document, offset(&document.text, &params["position"])))
+    }
+
+    fn definition(&self, params: &JsonValue) -> Result<JsonValue, ResponseError> {
+        let (uri, document, offset) = self.position(params)?;
+        let (Some((reference, _)), Some(signatures), true) = (document.reference_at(offset), document.signatures(), document.is_current) else {
+            return Ok(JsonValue::Null);
+        };
+        let key = match reference {
+            Reference::Name(name) => name,
+            Reference::Variant { enum_name, variant } => format!("{enum_name}.{variant}"),
+            Reference::BuiltIn(_) => return Ok(JsonValue::Null),
+        };
+        Ok(signatures.locations.get(&key).map_or(JsonValue::Null, |&(start, end)| json::object!{
+            uri: uri,
+            range: range(&document.text, start, end),
+        }))
+    }
+
+    fn hover(&self, params: &JsonValue) -> Result<JsonValue, ResponseError> {
+        let (_, document, offset) = self.position(params)?;
+        let (Some((reference, token)), Some((signatures, definitions))) = (document.reference_at(offset), &document.program) else {
+            return Ok(JsonValue::Null);
+        };
+        let contents = match &reference {
+            Reference::Name(name) => match signatures.user_defined.get(name) {
+                Some(signature) => format!("```desmosify\n{}\n```", signature.describe(definitions)),
+                None => return Ok(JsonValue::Null),
+            },
+            Reference::Variant { enum_name, variant } => format!("```desmosify\n{enum_name}.{variant}: {enum_name}\n```"),
+            Reference::BuiltIn(name) => {
+                let target = definitions.target.as_deref().unwrap_or(DEFAULT_TARGET);
+                if BUILTIN_VARIABLES.contains(&name.as_str()) {
+                    format!("`@{name}`: built-in variable")
+                } else if builtin_commands(definitions).contains(&name.as_str()) {
+                    format!("`@{name}`: built-in command")
+                } else if HIGHER_ORDER_BUILTINS.contains(&name.as_str()) {
+                    format!("`@{name}`: built-in higher-order function")
+                } else {
+                    format!("`@{name}` is not available in {target}")
+                }
+            },
+        };
+        Ok(json::object!{
+            contents: {
+                kind: "markdown",
+                value: contents,
+            },
+            range: range(&document.text, token.start, token.end),
+        })
+    }
+
+    fn completion(&self, params: &JsonValue) -> Result<JsonValue, ResponseError> {
+        let (_, document, offset) = self.position(params)?;
+        let Some((signatures, definitions)) = &document.program else {
+            return Ok(JsonValue::new_array());
+        };
+        let before = &document.text[..offset];
+        let context = before.trim_end_matches(is_name_character);
+        let mut items = JsonValue::new_array();
+        if context.ends_with('@') {
+            for &name in builtin_commands(definitions) {
+                items.push(completion_item(name, COMPLETION_FUNCTION, "built-in command")).unwrap();
+            }
+            for &name in HIGHER_ORDER_BUILTINS.iter().filter(|name| !builtin_commands(definitions).contains(name)) {
+                items.push(completion_item(name, COMPLETION_FUNCTION, "built-in higher-order function")).unwrap();
+            }
+            for &name in BUILTIN_VARIABLES {
+                items.push(completion_item(name, COMPLETION_VARIABLE, "built-in variable")).unwrap();
+            }
+        } else if let Some(context) = context.strip_suffix('.') {
+            let enum_name = &context[context.trim_end_matches(is_name_character).len()..];
+            if let Some(Signature::Enum { variants, .. }) = signatures.user_defined.get(enum_name) {
+                for variant in variants {
+                    items.push(completion_item(variant, COMPLETION_ENUM_MEMBER, enum_name)).unwrap();
+                }
+            }
+        } else {
+            for signature in signatures.user_defined.values() {
+                let kind = match signature {
+                    Signature::Const { parameters: None, .. } => COMPLETION_CONSTANT,
+                    Signature::Const { .. } | Signature::Let { parameters: Some(_), .. } => COMPLETION_FUNCTION,
+                    Signature::Let { .. } | Signature::Var { .. } => COMPLETION_VARIABLE,
+                    Signature::Action { .. } => COMPLETION_EVENT,
+                    Signature::Enum { .. } => COMPLETION_ENUM,
+                };
+                items.push(completion_item(signature.name(), kind, &signature.describe(definitions))).unwrap();
+            }
+        }
+        Ok(items)
+    }
+
+    fn rename(&self, params: &JsonValue) -> Result<JsonValue, ResponseError> {
+        let (uri, document, offset) = self.position(params)?;
+        let new_name = params["newName"].as_str()
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, String::from("missing new name")))?;
+        let failure = |message: String| ResponseError::new(REQUEST_FAILED, message);
+        if !is_valid_name(new_name) {
+            return Err(failure(format!("'{new_name}' is not a valid name")));
+        }
+        let (reference, _) = document.reference_at(offset)
+            .ok_or_else(|| failure(String::from("there is no name to rename here")))?;
+        let is_taken = match (&reference, document.signatures()) {
+            (Reference::Name(_), Some(signatures)) => signatures.user_defined.contains_key(new_name),
+            (Reference::Variant { enum_name, .. }, Some(signatures)) => signatures.locations.contains_key(&format!("{enum_name}.{new_name}")),
+            _ => false,
+        };
+        if is_taken {
+            return Err(failure(format!("'{new_name}' is already declared")));
+        }
+        let mut edits = JsonValue::new_array();
+        for (start, end) in document.occurrences(&reference).map_err(failure)? {
+            edits.push(json::object!{
+                range: range(&document.text, start, end),
+                newText: new_name,
+            }).unwrap();
+        }
+        let mut changes = JsonValue::new_object();
+        changes[uri] = edits;
+        Ok(json::object!{ changes: changes })
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn capabilities() -> JsonValue {
+    json::object!{
+        capabilities: {
+            textDocumentSync: SYNC_FULL,
+            definitionProvider: true,
+            hoverProvider: true,
+            completionProvider: {
+                triggerCharacters: [".", "@"],
+            },
+            renameProvider: true,
+        },
+        serverInfo: {
+            name: "desmosify-lsp",
+            version: env!("CARGO_PKG_VERSION"),
+        },
+    }
+}
+
+fn response(id: &JsonValue, result: Result<JsonValue, ResponseError>) -> JsonValue {
+    match result {
+        Ok(result) => json::object!{
+            jsonrpc: "2.0",
+            id: id.clone(),
+            result: result,
+        },
+        Err(error) => json::object!{
+            jsonrpc: "2.0",
+            id: id.clone(),
+            error: {
+                code: error.code,
+                message: error.message,
+            },
+        },
+    }
+}
+
+fn diagnostics_notification(uri: &str, diagnostics: JsonValue) -> JsonValue {
+    json::object!{
+        jsonrpc: "2.0",
+        method: "textDocument/publishDiagnostics",
+        params: {
+            uri: uri,
+            diagnostics: diagnostics,
+        },
+    }
+}
+
+fn diagnostic(text: &str, error: &DesmosifyError) -> JsonValue {
+    let start = error.start().unwrap_or(SourceLocation::start());
+    json::object!{
+        range: range(text, start, error.end().unwrap_or(start)),
+        severity: SEVERITY_ERROR,
+        source: "desmosify",
+        message: error.message(),
+    }
+}
+
+fn completion_item(label: &str, kind: i32, detail: &str) -> JsonValue {
+    json::object!{
+        label: label,
+        kind: kind,
+        detail: detail,
+    }
+}
+
+/// The commands available in the target selected by the program, or the default target.
+fn builtin_commands(definitions: &Definitions) -> &'static [&'static str] {
+    target::desmos::valid_commands(definitions.target.as_deref().unwrap_or(DEFAULT_TARGET)).unwrap_or_default()
+}
+
+fn is_name_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_valid_name(name: &str) -> bool {
+    matches!(tokenize(name).as_deref(), Ok([Token { value: TokenValue::Name(_), .. }]))
+}
+
+/// Converts a byte offset in `text` to an LSP position, which counts UTF-16 code units from the start of the line.
+fn position(text: &str, index: usize) -> JsonValue {
+    let index = index.min(text.len());
+    let line_start = text[..index].rfind('\n').map_or(0, |newline| newline + 1);
+    json::object!{
+        line: text[..line_start].matches('\n').count(),
+        character: text[line_start..index].chars().map(char::len_utf16).sum::<usize>(),
+    }
+}
+
+fn range(text: &str, start: SourceLocation, end: SourceLocation) -> JsonValue {
+    json::object!{
+        start: position(text, start.index),
+        end: position(text, end.index),
+    }
+}
+
+/// Converts an LSP position to a byte offset in `text`, clamping positions past the end of a line or of the text.
+fn offset(text: &str, position: &JsonValue) -> usize {
+    let line = position["line"].as_usize().unwrap_or(0);
+    let character = position["character"].as_usize().unwrap_or(0);
+    let Some(line_start) = (0..line).try_fold(0, |start, _| text[start..].find('\n').map(|newline| start + newline + 1)) else {
+        return text.len();
+    };
+    let mut units = 0;
+    for (index, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
@@ -0,0 +1,740 @@
+//! Simplification of linked programs before they are compiled for a target, controlled by an `-O` level.
+//!
+//! At level 1, constant subtrees are folded into literals, algebraic identities such as `x * 1` and `!!b` are
+//! removed, and piecewise expressions lose the pieces whose conditions are known, with a piecewise default merged
+//! into the piecewise containing it. Level 2 also replaces uses of `const` values and of small `let` definitions with
+//! their values, which leaves fewer expressions for Desmos to evaluate, and then computes repeated subexpressions only
+//! once: those which only refer to definitions become generated `let`s, while those which use the parameters of a
+//! function are bound with `with` in its body. Level 0 compiles everything as written, so the output can be traced
+//! back to the source.
+
+use super::*;
+use crate::dependencies::{collect_expression_references, dependency_order, parameter_names};
+
+/// The largest `let` value (counted in expression nodes) which is inlined at level 2.
+pub const MAX_INLINED_SIZE: usize = 3;
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default)]
+pub enum OptimizationLevel {
+    /// Every expression is compiled as written.
+    #[default]
+    None,
+    /// Constants are folded, and identities and piecewise expressions are simplified.
+    Simplify,
+    /// Also inlines `const` values and small `let` definitions where they are used, and shares repeated
+    /// subexpressions.
+    Full,
+}
+
+impl std::str::FromStr for OptimizationLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::None),
+            "1" => Ok(Self::Simplify),
+            "2" => Ok(Self::Full),
+            _ => Err(String::from("expected '0', '1', or '2'"))
+        }
+    }
+}
+
+/// Optimizes every expression in the program at the given level. Definitions are visited in dependency order, so a
+/// definition which is inlined has already been optimized itself.
+pub fn optimize(signatures: &mut Signatures, definitions: &mut Definitions, level: OptimizationLevel) {
+    if level == OptimizationLevel::None {
+        return;
+    }
+    // A cycle is reported when the program is compiled
+    let Ok(order) = dependency_order(signatures, definitions) else {
+        return;
+    };
+
+    let mut optimizer = Optimizer {
+        inlined: BTreeMap::new(),
+    };
+    for name in order {
+        let signature = signatures.user_defined.get(&name);
+        let locals = parameter_names(signature);
+        if let Some(value) = definitions.identifiers.remove(&name) {
+            let value = optimizer.expression(*value, &locals);
+            if level == OptimizationLevel::Full && is_inlined(signature, &value) {
+                let mut references = BTreeSet::new();
+                collect_expression_references(&value, &BTreeSet::new(), &mut references);
+                optimizer.inlined.insert(name.clone(), (value.clone(), references));
+            }
+            definitions.identifiers.insert(name, Box::new(value));
+        } else if let Some(content) = definitions.actions.get_mut(&name) {
+            optimizer.action(content, &locals);
+        }
+    }
+
+    let locals = BTreeSet::new();
+    // A name on its own in the graph keeps it, as it is what the entry shows
+    let is_name = |expression: &Expression| matches!(expression.value, ExpressionValue::Name(_));
+    for expression in definitions.public.iter_mut().flatten() {
+        if !is_name(expression) {
+            optimizer.rewrite(expression, &locals);
+        }
+    }
+    if let Some(ticker) = &mut definitions.ticker {
+        if let Some(interval_ms) = &mut ticker.interval_ms {
+            optimizer.rewrite(interval_ms, &locals);
+        }
+        optimizer.action(&mut ticker.tick_action, &locals);
+    }
+    for element in definitions.display.iter_mut().flatten() {
+        if !is_name(&element.what) {
+            optimizer.rewrite(&mut element.what, &locals);
+        }
+        optimizer.element(element);
+    }
+
+    if level == OptimizationLevel::Full {
+        share_repeated(signatures, definitions);
+        bind_repeated(signatures, definitions);
+    }
+}
+
+/// Whether uses of a definition with the (optimized) value `value` are replaced by the value.
+fn is_inlined(signature: Option<&Signature>, value: &Expression) -> bool {
+    // Lists are left where they are, as every copy would be evaluated again
+    let is_literal = value.constant_value().is_some_and(|value| !matches!(value, ConstantValue::List(..)));
+    match signature {
+        Some(Signature::Const { parameters: None, .. }) => is_literal,
+        Some(Signature::Let { parameters: None, .. }) => is_literal || (size(value) <= MAX_INLINED_SIZE && is_pure(value)),
+        _ => false
+    }
+}
+
+fn size(expression: &Expression) -> usize {
+    let mut count = 0;
+    expression.visit(&mut |_| count += 1);
+    count
+}
+
+/// Whether `expression` gives the same value wherever it is evaluated, unlike `@random`, which Desmos reseeds for
+/// every expression it appears in.
+fn is_pure(expression: &Expression) -> bool {
+    let mut is_pure = true;
+    expression.visit(&mut |expression| match &expression.value {
+        ExpressionValue::Operator(Operation::ActionCall, _) => is_pure = false,
+        ExpressionValue::Operator(Operation::BuiltIn, operands) => if let ExpressionValue::Name(name) = &operands[0].value {
+            is_pure &= name != "random";
+        },
+        _ => {},
+    });
+    is_pure
+}
+
+/// The values and conditions in `action` and its sub-actions, leaving out the variables it updates and the actions it
+/// calls.
+fn action_expressions(action: &mut Action) -> Vec<&mut Expression> {
+    match action {
+        Action::Block(sub_actions) => Vec::from_iter(sub_actions.iter_mut().flat_map(action_expressions)),
+        Action::Update(_, value) => vec![&mut **value],
+        Action::Call(_, arguments) => Vec::from_iter(arguments.iter_mut()),
+        Action::Conditional(branches, default_branch) => {
+            let mut expressions = Vec::new();
+            for (condition, branch) in branches {
+                expressions.push(condition);
+                expressions.extend(action_expressions(branch));
+            }
+            if let Some(default_branch) = default_branch {
+                expressions.extend(action_expressions(default_branch));
+            }
+            expressions
+        },
+    }
+}
+
+struct Optimizer {
+    /// The values of the definitions which are inlined, with the names each value refers to.
+    inlined: BTreeMap<String, (Expression, BTreeSet<String>)>,
+}
+
+impl Optimizer {
+    fn rewrite(&self, expression: &mut Expression, locals: &BTreeSet<String>) {
+        let original = std::mem::replace(expression, Expression::from_constant(ConstantValue::Bool(false)));
+        *expression = self.expression(original, locals);
+    }
+
+    /// Optimizes `expression`, where the names in `locals` are parameters or bound variables rather than definitions.
+    fn expression(&self, expression: Expression, locals: &BTreeSet<String>) -> Expression {
+        let Expression { data_type, value, start, end } = expression;
+        match value {
+            ExpressionValue::Name(name) => match self.inlined.get(&name) {
+                // A value cannot be inlined where one of the names it uses means something else
+                Some((value, references)) if !locals.contains(&name) && references.is_disjoint(locals) => Expression {
+                    start,
+                    end,
+                    ..value.clone()
+                },
+                _ => Expression { data_type, value: ExpressionValue::Name(name), start, end },
+            },
+            ExpressionValue::Literal(_) => Expression { data_type, value, start, end },
+            ExpressionValue::Operator(operation, operands) => simplify(Expression {
+                data_type,
+                value: ExpressionValue::Operator(operation, self.operands(operation, operands, locals)),
+                start,
+                end,
+            }),
+        }
+    }
+
+    /// Optimizes the operands of an operator, leaving alone those which are names rather than references (member
+    /// names, called functions and bound variables) and binding comprehension variables and `with` bindings in the
+    /// operand they are bound in.
+    fn operands(&self, operation: Operation, operands: Vec<Expression>, locals: &BTreeSet<String>) -> Vec<Expression> {
+        let bound_name = |expression: &Expression| match &expression.value {
+            ExpressionValue::Name(name) => Some(name.clone()),
+            _ => None
+        };
+        let bound = match (operation, operands.as_slice()) {
+            (Operation::ListMap, [_, variable, _]) => bound_name(variable),
+            (Operation::With, [_, binding]) => match &binding.value {
+                ExpressionValue::Operator(Operation::Assignment, binding) => bound_name(&binding[0]),
+                _ => None
+            },
+            _ => None
+        };
+        let inner_locals = bound.map(|name| {
+            let mut inner_locals = locals.clone();
+            inner_locals.insert(name);
+            inner_locals
+        });
+
+        Vec::from_iter(operands.into_iter().enumerate().map(|(index, operand)| match (operation, index) {
+            (Operation::BuiltIn | Operation::Assignment | Operation::Update | Operation::Regression, _)
+            | (Operation::MemberAccess | Operation::ListMap, 1)
+            | (Operation::Call | Operation::ActionCall, 0) => operand,
+            (Operation::With, 1) => self.binding(operand, locals),
+            (Operation::ListMap | Operation::With, 0) => self.expression(operand, inner_locals.as_ref().unwrap_or(locals)),
+            _ => self.expression(operand, locals),
+        }))
+    }
+
+    /// Optimizes the value of a `with` binding.
+    fn binding(&self, binding: Expression, locals: &BTreeSet<String>) -> Expression {
+        match binding.value {
+            ExpressionValue::Operator(Operation::Assignment, mut operands) if operands.len() == 2 => {
+                let value = self.expression(operands.pop().unwrap(), locals);
+                operands.push(value);
+                Expression { value: ExpressionValue::Operator(Operation::Assignment, operands), ..binding }
+            },
+            _ => binding,
+        }
+    }
+
+    /// Optimizes the values and conditions in `action`. The variables it updates and the actions it calls are kept.
+    fn action(&self, action: &mut Action, locals: &BTreeSet<String>) {
+        for expression in action_expressions(action) {
+            self.rewrite(expression, locals);
+        }
+    }
+
+    /// Optimizes the attributes and click handler of a display element.
+    fn element(&self, element: &mut display::Element) {
+        let locals = BTreeSet::new();
+        for expression in element.attributes_mut() {
+            self.rewrite(expression, &locals);
+        }
+        if let Some(click) = &mut element.click {
+            self.action(&mut click.action, &locals);
+        }
+    }
+}
+
+/// Simplifies an operator whose operands have already been simplified.
+fn simplify(expression: Expression) -> Expression {
+    let Expression { data_type, value, start, end } = expression;
+    let ExpressionValue::Operator(operation, mut operands) = value else {
+        return Expression { data_type, value, start, end };
+    };
+    if let Some(value) = fold(operation, &operands) {
+        return Expression { start, end, ..Expression::from_constant(value) };
+    }
+
+    match operation {
+        Operation::Posate => return operands.pop().unwrap(),
+        // `--x` and `!!b` (but not `!!x` for a number, which turns it into a boolean)
+        Operation::Negate | Operation::Not => match &operands[0].value {
+            ExpressionValue::Operator(inner_operation, inner_operands) if *inner_operation == operation
+                && (operation == Operation::Negate || inner_operands[0].is_boolean()) => {
+                let ExpressionValue::Operator(_, mut inner_operands) = operands.pop().unwrap().value else {
+                    unreachable!();
+                };
+                return inner_operands.pop().unwrap();
+            },
+            _ => {},
+        },
+        Operation::Conditional => return simplify_conditional(operands, |operands| Expression {
+            data_type,
+            value: ExpressionValue::Operator(operation, operands),
+            start,
+            end,
+        }),
+        _ => if let Some(index) = identity_operand(operation, &operands) {
+            return operands.swap_remove(index);
+        },
+    }
+    Expression { data_type, value: ExpressionValue::Operator(operation, operands), start, end }
+}
+
+fn number(value: &ConstantValue) -> Option<f64> {
+    match *value {
+        ConstantValue::Real(value) => Some(value),
+        ConstantValue::Int(value) => Some(value as f64),
+        _ => None
+    }
+}
+
+/// The value of an operator whose operands are all numbers or all booleans, if it can be worked out exactly.
+pub fn fold(operation: Operation, operands: &[Expression]) -> Option<ConstantValue> {
+    use ConstantValue::*;
+    let values = operands.iter().map(Expression::constant_value).collect::<Option<Vec<_>>>()?;
+    let integer = match (operation, values.as_slice()) {
+        (Operation::Negate, [Int(value)]) => value.checked_neg(),
+        (Operation::Add, [Int(lhs), Int(rhs)]) => lhs.checked_add(*rhs),
+        (Operation::Subtract, [Int(lhs), Int(rhs)]) => lhs.checked_sub(*rhs),
+        (Operation::Multiply, [Int(lhs), Int(rhs)]) => lhs.checked_mul(*rhs),
+        (Operation::Divide, [Int(lhs), Int(rhs)]) if *rhs != 0 && lhs % rhs == 0 => lhs.checked_div(*rhs),
+        (Operation::Exponent, [Int(lhs), Int(rhs)]) => u32::try_from(*rhs).ok().and_then(|rhs| lhs.checked_pow(rhs)),
+        (Operation::IntegerDivide, [Int(lhs), Int(rhs)]) => lhs.checked_div(*rhs)
+            .map(|quotient| if lhs % rhs != 0 && (*lhs < 0) != (*rhs < 0) { quotient - 1 } else { quotient }),
+        (Operation::BitwiseNot | Operation::BitwiseAnd | Operation::BitwiseXor | Operation::BitwiseOr
+        | Operation::ShiftLeft | Operation::ShiftRight, _) => values.iter()
+            .map(|value| match value {
+                Int(value) => Some(*value),
+                _ => None
+            })
+            .collect::<Option<Vec<_>>>()
+            .and_then(|values| semantics::fold_bitwise(operation, &values)),
+        (Operation::Not, [Bool(value)]) => return Some(Bool(!value)),
+        (Operation::And, [Bool(lhs), Bool(rhs)]) => return Some(Bool(*lhs && *rhs)),
+        (Operation::Or, [Bool(lhs), Bool(rhs)]) => return Some(Bool(*lhs || *rhs)),
+        (Operation::Equal, [Bool(lhs), Bool(rhs)]) => return Some(Bool(lhs == rhs)),
+        (Operation::NotEqual, [Bool(lhs), Bool(rhs)]) => return Some(Bool(lhs != rhs)),
+        _ => None
+    };
+    if let Some(integer) = integer {
+        return Some(Int(integer));
+    }
+
+    let numbers = values.iter().map(|value| number(value)).collect::<Option<Vec<_>>>()?;
+    let value = match (operation, numbers.as_slice()) {
+        (Operation::Negate, [value]) => -value,
+        (Operation::Add, [lhs, rhs]) => lhs + rhs,
+        (Operation::Subtract, [lhs, rhs]) => lhs - rhs,
+        (Operation::Multiply, [lhs, rhs]) => lhs * rhs,
+        // Other quotients are left as fractions, which Desmos shows (and treats as exponents) more precisely
+        (Operation::Divide, [lhs, rhs]) if (lhs / rhs).fract() == 0.0 => lhs / rhs,
+        (Operation::Exponent, [lhs, rhs]) => lhs.powf(*rhs),
+        // Only quotients which are exact as numbers, so the cast can't saturate
+        (Operation::IntegerDivide, [lhs, rhs]) => {
+            let quotient = (lhs / rhs).floor();
+            return (quotient.abs() <= 2f64.powi(53)).then_some(Int(quotient as i64));
+        },
+        (Operation::LessThan, [lhs, rhs]) => return Some(Bool(lhs < rhs)),
+        (Operation::GreaterThan, [lhs, rhs]) => return Some(Bool(lhs > rhs)),
+        (Operation::LessEqual, [lhs, rhs]) => return Some(Bool(lhs <= rhs)),
+        (Operation::GreaterEqual, [lhs, rhs]) => return Some(Bool(lhs >= rhs)),
+        (Operation::Equal, [lhs, rhs]) => return Some(Bool(lhs == rhs)),
+        (Operation::NotEqual, [lhs, rhs]) => return Some(Bool(lhs != rhs)),
+        _ => return None,
+    };
+    value.is_finite().then_some(Real(value))
+}
+
+/// The operand which a binary operator can be replaced with, because the other operand leaves it unchanged (as in
+/// `x + 0`) or decides the result (as in `false && x`).
+fn identity_operand(operation: Operation, operands: &[Expression]) -> Option<usize> {
+    let is_number = |index: usize, value: f64| operands[index].constant_value().and_then(number) == Some(value);
+    let is_bool = |index: usize, value: bool| operands[index].constant_value() == Some(&ConstantValue::Bool(value));
+    match operation {
+        Operation::Add if is_number(1, 0.0) => Some(0),
+        Operation::Add if is_number(0, 0.0) => Some(1),
+        Operation::Subtract if is_number(1, 0.0) => Some(0),
+        Operation::Multiply if is_number(1, 1.0) => Some(0),
+        Operation::Multiply if is_number(0, 1.0) => Some(1),
+        Operation::Divide | Operation::Exponent if is_number(1, 1.0) => Some(0),
+        Operation::And if is_bool(0, false) => Some(0),
+        Operation::And if is_bool(1, false) => Some(1),
+        Operation::And if is_bool(0, true) => Some(1),
+        Operation::And if is_bool(1, true) && operands[0].is_boolean() => Some(0),
+        Operation::Or if is_bool(0, true) => Some(0),
+        Operation::Or if is_bool(1, true) => Some(1),
+        Operation::Or if is_bool(0, false) => Some(1),
+        Operation::Or if is_bool(1, false) && operands[0].is_boolean() => Some(0),
+        _ => None
+    }
+}
+
+/// Simplifies a piecewise expression, whose operands are its conditions and values followed by an optional default.
+/// Pieces whose conditions are false are removed, a piece whose condition is true becomes the default, and a
+/// piecewise default is merged into this one. Any piecewise left is built with `rebuild`.
+fn simplify_conditional(mut operands: Vec<Expression>, rebuild: impl FnOnce(Vec<Expression>) -> Expression) -> Expression {
+    while !operands.len().is_multiple_of(2) {
+        match operands.last().map(|default| &default.value) {
+            Some(ExpressionValue::Operator(Operation::Conditional, _)) => {
+                let ExpressionValue::Operator(_, inner_operands) = operands.pop().unwrap().value else {
+                    unreachable!();
+                };
+                operands.extend(inner_operands);
+            },
+            _ => break,
+        }
+    }
+
+    let is_condition = |condition: &Expression, value: bool| condition.constant_value() == Some(&ConstantValue::Bool(value));
+    // Without a default, a piecewise whose conditions are all false is undefined, which has no literal
+    if operands.len().is_multiple_of(2) && operands.chunks(2).all(|piece| is_condition(&piece[0], false)) {
+        return rebuild(operands);
+    }
+
+    let mut default = if !operands.len().is_multiple_of(2) { operands.pop() } else { None };
+    let mut pieces = Vec::new();
+    let mut operands = operands.into_iter();
+    while let (Some(condition), Some(value)) = (operands.next(), operands.next()) {
+        if is_condition(&condition, true) {
+            default = Some(value);
+            break;
+        } else if !is_condition(&condition, false) {
+            pieces.extend([condition, value]);
+        }
+    }
+    match default {
+        Some(default) if pieces.is_empty() => default,
+        default => {
+            pieces.extend(default);
+            rebuild(pieces)
+        },
+    }
+}
+
+/// The smallest repeated expression (counted in nodes) which is computed only once, unless it calls a function.
+pub const MIN_SHARED_SIZE: usize = 5;
+
+/// Built-ins whose value depends on where they are used, so that expressions using them are never shared.
+const CONTEXTUAL_BUILTINS: &[&str] = &["random", "index", "dt", "x", "y", "z", "t", "u", "v"];
+
+/// What is known about an expression when looking for repeated subexpressions.
+struct Summary {
+    /// Equal for expressions which are written the same way, wherever they are.
+    key: String,
+    /// The names the expression refers to, leaving out those it binds itself.
+    references: BTreeSet<String>,
+    size: usize,
+    has_call: bool,
+    /// Whether the expression gives the same value wherever its references mean the same thing.
+    is_pure: bool,
+}
+
+impl Summary {
+    /// Summarizes an operand which is a name but not a reference, such as a member name or a bound variable.
+    fn of_name(expression: &Expression) -> Self {
+        Self {
+            key: match &expression.value {
+                ExpressionValue::Name(name) => name.clone(),
+                _ => String::new(),
+            },
+            references: BTreeSet::new(),
+            size: 1,
+            has_call: false,
+            is_pure: true,
+        }
+    }
+
+    fn is_worth_sharing(&self) -> bool {
+        self.is_pure && (self.has_call || MIN_SHARED_SIZE <= self.size)
+    }
+}
+
+/// Summarizes `expression` and every subexpression that is a value of its own, calling `visitor` on each from the
+/// innermost out with its summary, the names bound where it is (starting from `locals`), and whether it is
+/// `expression` itself. The visitor may replace the expression it is given.
+fn walk<F: FnMut(&mut Expression, &Summary, &BTreeSet<String>, bool)>(expression: &mut Expression, locals: &BTreeSet<String>, is_root: bool, visitor: &mut F) -> Summary {
+    let summary = match &mut expression.value {
+        ExpressionValue::Literal(value) => Summary {
+            key: format!("{value:?}"),
+            references: BTreeSet::new(),
+            size: 1,
+            has_call: false,
+            is_pure: true,
+        },
+        ExpressionValue::Name(name) => Summary {
+            key: name.clone(),
+            references: BTreeSet::from([name.clone()]),
+            size: 1,
+            has_call: false,
+            is_pure: true,
+        },
+        ExpressionValue::Operator(operation, operands) => {
+            let operation = *operation;
+            let bound_name = |expression: &Expression| match &expression.value {
+                ExpressionValue::Name(name) => Some(name.clone()),
+                _ => None
+            };
+            let bound = match (operation, operands.as_slice()) {
+                (Operation::ListMap, [_, variable, _]) => bound_name(variable),
+                (Operation::With, [_, binding]) => match &binding.value {
+                    ExpressionValue::Operator(Operation::Assignment, binding) => bound_name(&binding[0]),
+                    _ => None
+                },
+                _ => None
+            };
+            let mut inner_locals = locals.clone();
+            inner_locals.extend(bound.clone());
+
+            let summaries = Vec::from_iter(operands.iter_mut().enumerate().map(|(index, operand)| match (operation, index) {
+                (Operation::BuiltIn, _) | (Operation::MemberAccess | Operation::ListMap, 1) => Summary::of_name(operand),
+                (Operation::With, 1) => match &mut operand.value {
+                    ExpressionValue::Operator(Operation::Assignment, binding) if binding.len() == 2 => {
+                        let name = Summary::of_name(&binding[0]);
+                        let value = walk(&mut binding[1], locals, false, visitor);
+                        Summary { key: format!("{}={}", name.key, value.key), size: value.size + 2, ..value }
+                    },
+                    _ => walk(operand, locals, false, visitor),
+                },
+                (Operation::ListMap | Operation::With, 0) => {
+                    let mut summary = walk(operand, &inner_locals, false, visitor);
+                    if let Some(bound) = &bound {
+                        summary.references.remove(bound);
+                    }
+                    summary
+                },
+                _ => walk(operand, locals, false, visitor),
+            }));
+
+            let is_contextual = match (operation, summaries.first()) {
+                (Operation::BuiltIn, Some(name)) => CONTEXTUAL_BUILTINS.contains(&name.key.as_str()),
+                _ => false
+            };
+            Summary {
+                key: format!("{operation:?}({})", Vec::from_iter(summaries.iter().map(|summary| summary.key.as_str())).join(",")),
+                references: summaries.iter().flat_map(|summary| summary.references.iter().cloned()).collect(),
+                size: 1 + summaries.iter().map(|summary| summary.size).sum::<usize>(),
+                has_call: operation == Operation::Call || summaries.iter().any(|summary| summary.has_call),
+                is_pure: !is_contextual
+                    && !matches!(operation, Operation::ActionCall | Operation::Assignment | Operation::Update | Operation::Regression)
+                    && summaries.iter().all(|summary| summary.is_pure),
+            }
+        },
+    };
+    visitor(expression, &summary, locals, is_root);
+    summary
+}
+
+/// An expression searched for repeated subexpressions.
+#[derive(Copy, Clone)]
+struct Site<'a> {
+    /// The definition the expression belongs to, if any.
+    definition: Option<&'a str>,
+    /// Whether the expression as a whole may be replaced, rather than only its parts.
+    is_replaceable: bool,
+}
+
+/// Calls `visitor` on every expression in the program which is evaluated by Desmos, with the parameters in scope.
+/// The initial values of variables are left out, as they are constants.
+fn visit_sites<F: FnMut(Site, &mut Expression, &BTreeSet<String>)>(signatures: &Signatures, definitions: &mut Definitions, visitor: &mut F) {
+    for (name, value) in &mut definitions.identifiers {
+        let signature = signatures.user_defined.get(name);
+        if !matches!(signature, Some(Signature::Var { .. })) {
+            visitor(Site { definition: Some(name), is_replaceable: true }, value, &parameter_names(signature));
+        }
+    }
+    for (name, content) in &mut definitions.actions {
+        let locals = parameter_names(signatures.user_defined.get(name));
+        for expression in action_expressions(content) {
+            visitor(Site { definition: Some(name), is_replaceable: true }, expression, &locals);
+        }
+    }
+
+    let locals = BTreeSet::new();
+    // A public expression or a displayed value is what its entry shows, so it is kept as written
+    let shown = Site { definition: None, is_replaceable: false };
+    let evaluated = Site { definition: None, is_replaceable: true };
+    for expression in definitions.public.iter_mut().flatten() {
+        visitor(shown, expression, &locals);
+    }
+    if let Some(ticker) = &mut definitions.ticker {
+        if let Some(interval_ms) = &mut ticker.interval_ms {
+            visitor(evaluated, interval_ms, &locals);
+        }
+        for expression in action_expressions(&mut ticker.tick_action) {
+            visitor(evaluated, expression, &locals);
+        }
+    }
+    for element in definitions.display.iter_mut().flatten() {
+        visitor(shown, &mut element.what, &locals);
+        for expression in element.attributes_mut() {
+            visitor(evaluated, expression, &locals);
+        }
+        if let Some(click) = &mut element.click {
+            for expression in action_expressions(&mut click.action) {
+                visitor(evaluated, expression, &locals);
+            }
+        }
+    }
+}
+
+/// A repeated subexpression, found at least `count` times.
+struct Repeated {
+    expression: Expression,
+    size: usize,
+    references: BTreeSet<String>,
+    count: usize,
+    /// The definitions containing it.
+    definitions: BTreeSet<String>,
+    /// A `let` whose whole value it is, which the other places can refer to instead.
+    definition: Option<String>,
+}
+
+/// Chooses a name for a generated definition or binding which cannot be mistaken for any name in the program, even
+/// once `_` and `::` are dropped from it in the graph.
+fn unused_name(signatures: &Signatures, definitions: &Definitions) -> String {
+    let mut used = BTreeSet::new();
+    let mut add = |name: &str| used.insert(name.replace(['_', ':'], ""));
+    for (name, signature) in &signatures.user_defined {
+        add(name);
+        for parameter in signature.parameters().into_iter().flatten() {
+            add(&parameter.name);
+        }
+    }
+    definitions.visit_expressions(&mut |expression| if let ExpressionValue::Name(name) = &expression.value {
+        add(name);
+    });
+    (1..).map(|index| format!("shared_{index}"))
+        .find(|name| !used.contains(&name.replace('_', "")))
+        .unwrap()
+}
+
+/// Computes repeated subexpressions which only refer to definitions once, as a `let` the places using them refer to
+/// instead. Where one of the places is the whole value of a `let`, that `let` is used rather than a new one. The
+/// largest repeated subexpression is shared first, so that its parts are only counted once.
+fn share_repeated(signatures: &mut Signatures, definitions: &mut Definitions) {
+    loop {
+        let mut repeated: BTreeMap<String, Repeated> = BTreeMap::new();
+        visit_sites(signatures, definitions, &mut |site, expression, locals| {
+            walk(expression, locals, true, &mut |expression, summary, locals, is_root| {
+                if !summary.is_worth_sharing() || !summary.references.is_disjoint(locals) || (is_root && !site.is_replaceable) {
+                    return;
+                }
+                let entry = repeated.entry(summary.key.clone()).or_insert_with(|| Repeated {
+                    expression: expression.clone(),
+                    size: summary.size,
+                    references: summary.references.clone(),
+                    count: 0,
+                    definitions: BTreeSet::new(),
+                    definition: None,
+                });
+                entry.count += 1;
+                entry.definitions.extend(site.definition.map(String::from));
+                if let (true, Some(name)) = (is_root, site.definition) {
+                    if let Some(Signature::Let { parameters: None, .. }) = signatures.user_defined.get(name) {
+                        entry.definition.get_or_insert_with(|| String::from(name));
+                    }
+                }
+            });
+        });
+
+        // Referring to a definition from one it depends on would be a cycle
+        let dependencies = crate::dependencies::definition_dependencies(signatures, definitions);
+        let is_cyclic = |repeated: &Repeated| {
+            let mut reachable = BTreeSet::new();
+            let mut pending = Vec::from_iter(repeated.references.iter().cloned());
+            while let Some(name) = pending.pop() {
+                if reachable.insert(name.clone()) {
+                    pending.extend(dependencies.get(&name).into_iter().flatten().cloned());
+                }
+            }
+            repeated.definitions.iter().any(|name| Some(name) != repeated.definition.as_ref() && reachable.contains(name))
+        };
+        let Some((key, chosen)) = repeated.into_iter()
+            .filter(|(_, repeated)| 2 <= repeated.count && !is_cyclic(repeated))
+            .max_by_key(|(_, repeated)| repeated.size) else {
+            return;
+        };
+
+        let name = match &chosen.definition {
+            Some(name) => name.clone(),
+            None => {
+                let name = unused_name(signatures, definitions);
+                // Placed just before the first definition using it, in the same section
+                let first_user = definitions.source_order().find(|user| chosen.definitions.contains(*user)).map(String::from);
+                if let Some(first_user) = first_user {
+                    for section in &mut definitions.sections {
+                        if let Some(position) = section.names.iter().position(|user| *user == first_user) {
+                            section.names.insert(position, name.clone());
+                        }
+                    }
+                }
+                signatures.user_defined.insert(name.clone(), Signature::Let {
+                    name: name.clone(),
+                    parameters: None,
+                    value_type: chosen.expression.data_type.clone(),
+                });
+                definitions.identifiers.insert(name.clone(), Box::new(chosen.expression.clone()));
+                name
+            },
+        };
+        visit_sites(signatures, definitions, &mut |site, expression, locals| {
+            walk(expression, locals, true, &mut |expression, summary, locals, is_root| {
+                let is_definition = is_root && site.definition == Some(name.as_str());
+                if summary.key == key && summary.references.is_disjoint(locals) && (!is_root || site.is_replaceable) && !is_definition {
+                    expression.value = ExpressionValue::Name(name.clone());
+                }
+            });
+        });
+    }
+}
+
+/// Computes repeated subexpressions which use the parameters of a function only once, by binding them with `with`
+/// around the function's value.
+fn bind_repeated(signatures: &Signatures, definitions: &mut Definitions) {
+    let functions = Vec::from_iter(definitions.identifiers.keys()
+        .filter(|name| matches!(signatures.user_defined.get(*name), Some(Signature::Const { parameters: Some(_), .. } | Signature::Let { parameters: Some(_), .. })))
+        .cloned());
+    for function in functions {
+        let parameters = parameter_names(signatures.user_defined.get(&function));
+        loop {
+            // Only the names bound inside the value are locals here, so parameters stay visible
+            let mut repeated: BTreeMap<String, (Expression, usize, usize)> = BTreeMap::new();
+            let value = definitions.identifiers.get_mut(&function).unwrap();
+            walk(value, &BTreeSet::new(), true, &mut |expression, summary, locals, _| {
+                if summary.is_worth_sharing() && summary.references.is_disjoint(locals) && !summary.references.is_disjoint(&parameters) {
+                    repeated.entry(summary.key.clone()).or_insert_with(|| (expression.clone(), summary.size, 0)).2 += 1;
+                }
+            });
+            let Some((key, (expression, _, _))) = repeated.into_iter()
+                .filter(|(_, (_, _, count))| 2 <= *count)
+                .max_by_key(|(_, (_, size, _))| *size) else {
+                break;
+            };
+
+            let name = unused_name(signatures, definitions);
+            let value = definitions.identifiers.get_mut(&function).unwrap();
+            walk(value, &BTreeSet::new(), true, &mut |expression, summary, locals, _| {
+                if summary.key == key && summary.references.is_disjoint(locals) {
+                    expression.value = ExpressionValue::Name(name.clone());
+                }
+            });
+            let original = std::mem::replace(&mut **value, Expression::from_constant(ConstantValue::Bool(false)));
+            let binding = Expression {
+                data_type: DataType::Unknown,
+                value: ExpressionValue::Operator(Operation::Assignment, vec![
+                    Expression { data_type: DataType::Unknown, value: ExpressionValue::Name(name), start: expression.start, end: expression.end },
+                    expression,
+                ]),
+                start: original.start,
+                end: original.end,
+            };
+            **value = Expression {
+                data_type: original.data_type.clone(),
+                start: original.start,
+                end: original.end,
+                value: ExpressionValue::Operator(Operation::With, vec![original, binding]),
+            };
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! An interactive session which builds up a program one input at a time, showing the type, value and Desmos LaTeX of
+//! expressions and running actions against the current values of the program's variables.
+
+use crate::*;
+use crate::interpret::{Interpreter, Value};
+use crate::semantics::{analyze_action, analyze_expression, expand_action_arguments, expand_arguments, Scope};
+use crate::target::DEFAULT_TARGET;
+use crate::target::desmos::{DesmosTarget, GeometryTarget, Graphing3DTarget, GraphingTarget};
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+pub const HELP: &str = "\
+Enter declarations to add them to the program (declaring a name again replaces it), expressions to see their
+type, value and LaTeX, or actions such as 'count := count + 1' to run them. The standard library is available as
+'std::name'. Commands:
+    :help            show this message
+    :vars            show the value of every variable
+    :list            show the declarations entered so far
+    :reset           set every variable back to its initial value
+    :clear           forget every declaration
+    :load FILE       add the declarations in FILE (imports are then found from its directory)
+    :tick [MS]       run the ticker once, taking MS milliseconds (the ticker interval by default)
+    :click N [I]     click display element N (from 0), on item I of a list (1 by default)
+    :quit            end the session";
+
+/// The file name the session's declarations are linked under, in the directory imports are found from.
+const INPUT_FILE_NAME: &str = "<input>";
+
+/// The declarations in one piece of source, which are replaced together when any of them is declared again.
+#[derive(Clone, Debug)]
+struct Entry {
+    /// The names declared, with `public`, `ticker`, `display` and `#target` standing for those blocks and
+    /// `import module` for an import.
+    names: Vec<String>,
+    /// Names imported without their namespace, and the names they stand for.
+    imported_names: Vec<(String, String)>,
+    source: String,
+}
+
+pub struct Repl {
+    entries: Vec<Entry>,
+    signatures: Signatures,
+    definitions: Definitions,
+    variables: BTreeMap<String, Value>,
+    /// Where imports are found from: the current directory, or the directory of the last file loaded.
+    directory: PathBuf,
+    is_finished: bool,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        // An empty program still has the standard library
+        let (signatures, definitions) = link::link(&[]).expect("the standard library should link on its own");
+        Self {
+            entries: Vec::new(),
+            signatures,
+            definitions,
+            variables: BTreeMap::new(),
+            directory: PathBuf::new(),
+            is_finished: false,
+        }
+    }
+
+    /// Whether `:quit` has been entered.
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    /// Runs one input, returning the lines to show. Errors leave the session as it was before the input.
+    pub fn execute(&mut self, input: &str) -> Result<Vec<String>, DesmosifyError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        let tokens = tokenize(input)?;
+        let first = &tokens[0];
+        // 'action' starts both declarations and calls, but only declarations have a block
+        let is_action_call = first.value == TokenValue::Keyword(Keyword::Action)
+            && !tokens.iter().any(|token| token.value == TokenValue::Symbol(Symbol::CurlyLeft));
+        if !is_action_call && first.is_one_of(&[Symbol::Hash, Symbol::Semicolon], &[
+            Keyword::Public,
+            Keyword::Ticker,
+            Keyword::Display,
+            Keyword::Action,
+            Keyword::Const,
+            Keyword::Let,
+            Keyword::Var,
+            Keyword::Enum,
+            Keyword::Section,
+            Keyword::Import,
+            Keyword::Pub,
+        ]) {
+            self.declare(input)
+        } else if is_action_call
+            || first.value == TokenValue::Keyword(Keyword::If)
+            || tokens.iter().any(|token| token.value == TokenValue::Symbol(Symbol::ColonEqual))
+        {
+            self.perform(tokens)
+        } else {
+            self.evaluate(tokens)
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Result<Vec<String>, DesmosifyError> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arguments = Vec::from_iter(words);
+        let number = |index: usize| arguments.get(index)
+            .map(|argument| argument.parse::<f64>().map_err(|_| DesmosifyError::new(
+                format!("expected a number, but got '{argument}'"),
+                None,
+                None,
+            )))
+            .transpose();
+        match name {
+            "help" | "h" => Ok(HELP.lines().map(String::from).collect()),
+            "quit" | "q" => {
+                self.is_finished = true;
+                Ok(Vec::new())
+            },
+            "vars" => Ok(self.variables.iter().map(|(name, value)| format!("{name} = {value}")).collect()),
+            "list" => Ok(self.entries.iter().map(|entry| entry.source.clone()).collect()),
+            "reset" => {
+                self.variables = Interpreter::new(&self.signatures, &self.definitions)?.variables().clone();
+                Ok(vec![String::from("variables reset")])
+            },
+            "clear" => {
+                *self = Self::new();
+                Ok(vec![String::from("declarations cleared")])
+            },
+            "load" => {
+                let path = command[name.len()..].trim();
+                let source = std::fs::read_to_string(path)
+                    .map_err(|error| DesmosifyError::new(format!("could not read '{path}': {error}"), None, None))?;
+                let directory = std::mem::replace(&mut self.directory, PathBuf::from(Path::new(path).parent().unwrap_or(Path::new(""))));
+                self.declare(&source).inspect_err(|_| self.directory = directory)
+            },
+            "tick" => {
+                let mut interpreter = self.interpreter()?;
+                let dt_ms = match number(0)? {
+                    Some(dt_ms) => dt_ms,
+                    None => interpreter.ticker_interval()?.unwrap_or(1000.0 / 60.0),
+                };
+                interpreter.tick(dt_ms)?;
+                let variables = interpreter.variables().clone();
+                Ok(self.update_variables(variables))
+            },
+            "click" => {
+                let element_index = number(0)?
+                    .ok_or_else(|| DesmosifyError::new(String::from("expected the index of a display element"), None, None))?;
+                let index = number(1)?.unwrap_or(1.0);
+                let mut interpreter = self.interpreter()?;
+                interpreter.click(element_index as usize, index as usize)?;
+                let variables = interpreter.variables().clone();
+                Ok(self.update_variables(variables))
+            },
+            _ => Err(DesmosifyError::new(format!("unknown command ':{name}' (enter ':help' for a list)"), None, None)),
+        }
+    }
+
+    /// Adds the declarations in `source`, replacing any earlier declarations of the same names.
+    fn declare(&mut self, source: &str) -> Result<Vec<String>, DesmosifyError> {
+        // A declaration typed on its own can leave off the semicolon
+        let mut source = String::from(source.trim_end());
+        let is_import = source.starts_with(Keyword::Import.literal());
+        if !source.ends_with(';') && (is_import || !source.ends_with('}')) {
+            source.push(';');
+        }
+        let mut new_entries = Vec::new();
+        for node in cst::parse(&source)?.nodes() {
+            let source = String::from(node.text().trim());
+            let (signatures, definitions) = parse(&tokenize(&source)?)?;
+            // Enums are not part of any section, so are missing from the source order
+            let mut names = Vec::from_iter(definitions.source_order().map(String::from));
+            let enum_names = Vec::from_iter(signatures.user_defined.keys().filter(|name| !names.contains(name)).cloned());
+            names.extend(enum_names);
+            let mut imported_names = Vec::new();
+            for import in &definitions.imports {
+                names.push(format!("import {}", import.namespace));
+                imported_names.extend(import.names.iter().map(|(name, _, _)| (name.clone(), format!("{}::{name}", import.namespace))));
+            }
+            for (block, is_present) in [
+                ("#target", definitions.target.is_some()),
+                ("public", definitions.public.is_some()),
+                ("ticker", definitions.ticker.is_some()),
+                ("display", definitions.display.is_some()),
+            ] {
+                if is_present {
+                    names.push(String::from(block));
+                }
+            }
+            new_entries.push(Entry { names, imported_names, source });
+        }
+        let new_names = Vec::from_iter(new_entries.iter().flat_map(|entry| entry.names.iter().cloned()));
+        let mut entries = Vec::from_iter(self.entries.iter()
+            .filter(|entry| !entry.names.iter().any(|name| new_names.contains(name)))
+            .cloned());
+        let unchanged_count = entries.len();
+        entries.extend(new_entries);
+
+        // Locations are reported relative to the new declarations, since those are what was just typed
+        let combined = Vec::from_iter(entries.iter().map(|entry| entry.source.as_str())).join("\n");
+        let line_offset = entries[..unchanged_count].iter().map(|entry| entry.source.lines().count()).sum::<usize>();
+        let file_name = self.directory.join(INPUT_FILE_NAME).to_string_lossy().into_owned();
+        let relocate = |error: DesmosifyError| {
+            // Errors in imported modules are reported as they are, with the module they are in
+            if error.file_name().is_some_and(|error_file_name| error_file_name != file_name) {
+                return DesmosifyError::new(error.to_string(), None, None);
+            }
+            let shift = |location: Option<SourceLocation>| location
+                .filter(|location| location.line > line_offset)
+                .map(|location| SourceLocation { line: location.line - line_offset, ..location });
+            match error.start() {
+                Some(start) if start.line > line_offset => DesmosifyError::new(String::from(error.message()), shift(error.start()), shift(error.end())),
+                _ => DesmosifyError::new(String::from(error.message()), None, None),
+            }
+        };
+        let (signatures, definitions) = link::link(&[link::SourceFile::new(&file_name, &combined)])
+            .map_err(|errors| relocate(errors.into_iter().next().unwrap()))?;
+
+        // Variables keep their values unless they were just declared again
+        let mut interpreter = Interpreter::new(&signatures, &definitions)?;
+        for (name, value) in &self.variables {
+            if !new_names.contains(name) && interpreter.variables().contains_key(name) {
+                interpreter.set_value(name, value.clone())?;
+            }
+        }
+        let variables = interpreter.variables().clone();
+        drop(interpreter);
+
+        let mut lines = Vec::new();
+        for name in &new_names {
+            match signatures.user_defined.get(name) {
+                Some(signature @ Signature::Var { .. }) => lines.push(format!("{} = {}", signature.describe(&definitions), variables[name])),
+                Some(signature) => lines.push(signature.describe(&definitions)),
+                None if name == "#target" => lines.push(format!("#target {:?}", definitions.target.as_deref().unwrap_or_default())),
+                None if name.starts_with("import ") => lines.push(name.clone()),
+                None => lines.push(format!("{name} block declared")),
+            }
+        }
+        self.entries = entries;
+        self.signatures = signatures;
+        self.definitions = definitions;
+        self.variables = variables;
+        Ok(lines)
+    }
+
+    /// Replaces names imported without their namespace by the names they stand for, as expressions and actions which are
+    /// run are not linked with the rest of the program.
+    fn qualify_imported_names(&self, tokens: &mut [Token]) {
+        let imported_names = BTreeMap::from_iter(self.entries.iter().flat_map(|entry| entry.imported_names.iter().cloned()));
+        for index in 0..tokens.len() {
+            // Members, built-ins and the parts of qualified names are not names in scope
+            let is_scoped = index == 0 || !matches!(tokens[index - 1].value, TokenValue::Symbol(Symbol::Dot | Symbol::AtSign | Symbol::Colon2));
+            if let TokenValue::Name(name) = &mut tokens[index].value {
+                if let Some(qualified) = imported_names.get(name).filter(|_| is_scoped) {
+                    name.clone_from(qualified);
+                }
+            }
+        }
+    }
+
+    /// Runs an action (or several, separated by commas) against the current variables.
+    fn perform(&mut self, mut tokens: Vec<Token>) -> Result<Vec<String>, DesmosifyError> {
+        self.qualify_imported_names(&mut tokens);
+        // Treat the input as the body of an action block
+        let (start, end) = (tokens[0].start, tokens[tokens.len() - 1].end);
+        tokens.insert(0, Token::new(TokenValue::Symbol(Symbol::CurlyLeft), start, start));
+        // Inline branches of an 'if' need a comma after them
+        if tokens[tokens.len() - 1].value != TokenValue::Symbol(Symbol::Comma) {
+            tokens.push(Token::new(TokenValue::Symbol(Symbol::Comma), end, end));
+        }
+        tokens.push(Token::new(TokenValue::Symbol(Symbol::CurlyRight), end, end));
+        let mut parser = Parser::new(&tokens);
+        let mut action = parser.parse_action(false)?;
+        expect_end(&mut parser)?;
+        expand_action_arguments(&self.signatures, &BTreeSet::new(), &mut action)?;
+        analyze_action(&self.signatures, &Scope::default(), &mut action)?;
+
+        let mut interpreter = self.interpreter()?;
+        interpreter.perform(&action)?;
+        let variables = interpreter.variables().clone();
+        Ok(self.update_variables(variables))
+    }
+
+    fn evaluate(&self, mut tokens: Vec<Token>) -> Result<Vec<String>, DesmosifyError> {
+        self.qualify_imported_names(&mut tokens);
+        let end = tokens[tokens.len() - 1].end;
+        if tokens[tokens.len() - 1].value != TokenValue::Symbol(Symbol::Semicolon) {
+            tokens.push(Token::new(TokenValue::Symbol(Symbol::Semicolon), end, end));
+        }
+        let mut parser = Parser::new(&tokens);
+        let mut expression = parser.parse_expression(&[Symbol::Semicolon], &[])?;
+        expect_end(&mut parser)?;
+        expand_arguments(&self.signatures, &BTreeSet::new(), &mut expression)?;
+        analyze_expression(&self.signatures, &Scope::default(), &mut expression)?;
+
+        // The value and the translation can fail independently, and seeing one is still useful without the other
+        let value = match self.interpreter()?.evaluate(&expression) {
+            Ok(value) if expression.constant_value().is_some() => format!("{value} (constant)"),
+            Ok(value) => value.to_string(),
+            Err(error) => format!("error: {error}"),
+        };
+        let latex = self.latex(&expression).unwrap_or_else(|error| format!("error: {error}"));
+        Ok(vec![
+            format!("type:  {}", expression.data_type),
+            format!("value: {value}"),
+            format!("latex: {latex}"),
+        ])
+    }
+
+    /// Translates `expression` for the target selected by a `#target` pragma, or the default target.
+    fn latex(&self, expression: &Expression) -> Result<String, DesmosifyError> {
+        let node = match self.definitions.target.as_deref().unwrap_or(DEFAULT_TARGET) {
+            "desmos-graphing" => GraphingTarget::default().translate_expression(expression)?,
+            "desmos-graphing-3d" => Graphing3DTarget::default().translate_expression(expression)?,
+            _ => GeometryTarget::default().translate_expression(expression)?,
+        };
+        Ok(node.to_latex().to_string())
+    }
+
+    /// Creates an interpreter for the program with the variables as they currently are.
+    fn interpreter(&self) -> Result<Interpreter<'_>, DesmosifyError> {
+        let mut interpreter = Interpreter::new(&self.signatures, &self.definitions)?;
+        for (name, value) in &self.variables {
+            interpreter.set_value(name, value.clone())?;
+        }
+        Ok(interpreter)
+    }
+
+    /// Replaces the variables with their values after running something, describing the ones which changed.
+    fn update_variables(&mut self, variables: BTreeMap<String, Value>) -> Vec<String> {
+        let lines = Vec::from_iter(variables.iter()
+            .filter(|&(name, value)| self.variables.get(name) != Some(value))
+            .map(|(name, value)| format!("{name} = {value}")));
+        self.variables = variables;
+        if lines.is_empty() {
+            vec![String::from("no variables changed")]
+        } else {
+            lines
+        }
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn expect_end(parser: &mut Parser) -> Result<(), DesmosifyError> {
+    parser.next();
+    match parser.token() {
+        Ok(token) => Err(DesmosifyError::new(String::from("unexpected input after the end"), Some(token.start), Some(token.end))),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Whether `input` could be a whole entry, as opposed to the first lines of a declaration with brackets left open.
+pub fn is_complete(input: &str) -> bool {
+    let Ok(tokens) = tokenize(input) else {
+        // Let the error be reported
+        return true;
+    };
+    let depth = tokens.iter().fold(0, |depth, token| match token.value {
+        TokenValue::Symbol(Symbol::ParenLeft | Symbol::SquareLeft | Symbol::CurlyLeft) => depth + 1,
+        TokenValue::Symbol(Symbol::ParenRight | Symbol::SquareRight | Symbol::CurlyRight) => depth - 1,
+        _ => depth,
+    });
+    depth <= 0
+}
+
+/// Runs a session on `input` until `:quit` or the end of the input. Prompts are only written if `interactive`.
+pub fn run<R: BufRead, W: Write>(repl: &mut Repl, input: R, mut output: W, interactive: bool) -> std::io::Result<()> {
+    let mut lines = input.lines();
+    while !repl.is_finished() {
+        if interactive {
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        let Some(mut entry) = lines.next().transpose()? else {
+            break;
+        };
+        while !is_complete(&entry) {
+            if interactive {
+                write!(output, ". ")?;
+                output.flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                break;
+            };
+            entry.push('\n');
+            entry.push_str(&line);
+        }
+        match repl.execute(&entry) {
+            Ok(result) => for line in result {
+                writeln!(output, "{line}")?;
+            },
+            Err(error) => writeln!(output, "error: {error}")?,
+        }
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@ use super::*;
 
 impl Signatures {
     pub fn get_type_from_name(&self, scope: &Scope, name: &str) -> Option<DataType> {
-        scope.parameters.get(name).map(|data_type| data_type.clone()).or_else(
+        scope.parameters.get(name).cloned().or_else(
             || self.user_defined.get(name).and_then(|signature| match signature {
                 Signature::Const { parameters, value_type, .. } => if parameters.is_some() {
                     Some(DataType::Function { name: String::from(name) })
@@ -25,42 +25,20 @@ impl DataType {
     pub fn can_coerce_to(&self, target: &DataType) -> bool {
         use DataType::*;
         match self {
-            Unknown => match target {
-                Void | Function { .. } | Action { .. } | Str => false,
-                _ => true
-            },
-            Real => match target {
-                Unknown | Real => true,
-                _ => false
-            },
-            Int => match target {
-                Unknown | Real | Int | User { .. } => true,
-                _ => false
-            },
-            Bool => match target {
-                Unknown | Real | Int | Bool => true,
-                _ => false
-            },
-            Point => match target {
-                Unknown | Point => true,
-                _ => false
-            },
-            IPoint => match target {
-                Unknown | Point | IPoint => true,
-                _ => false
-            },
-            Color => match target {
-                Unknown | Color => true,
-                _ => false
-            },
-            Polygon => match target {
-                Unknown | Polygon => true,
-                _ => false
-            },
-            Segment => match target {
-                Unknown | Segment => true,
-                _ => false
-            },
+            Unknown => !matches!(target, Void | Function { .. } | Action { .. } | Str),
+            Real => matches!(target, Unknown | Real),
+            Int => matches!(target, Unknown | Real | Int | User { .. }),
+            Bool => matches!(target, Unknown | Real | Int | Bool),
+            Point => matches!(target, Unknown | Point),
+            IPoint => matches!(target, Unknown | Point | IPoint),
+            Color => matches!(target, Unknown | Color),
+            Polygon => matches!(target, Unknown | Polygon),
+            Segment => matches!(target, Unknown | Segment),
+            Point3 => matches!(target, Unknown | Point3),
+            IPoint3 => matches!(target, Unknown | Point3 | IPoint3),
+            Segment3 => matches!(target, Unknown | Segment3),
+            Triangle3 => matches!(target, Unknown | Triangle3),
+            Sphere => matches!(target, Unknown | Sphere),
             List { item_type } => match target {
                 List { item_type: target_item_type } => item_type.can_coerce_to(target_item_type),
                 _ => item_type.can_coerce_to(target)
@@ -107,6 +85,9 @@ impl DataType {
             (Unknown, 2) => Some(Point),
             (Int, 2) => Some(IPoint),
             (Real, 2) => Some(Point),
+            (Unknown, 3) => Some(Point3),
+            (Int, 3) => Some(IPoint3),
+            (Real, 3) => Some(Point3),
             _ => None
         }
     }
@@ -120,36 +101,143 @@ impl DataType {
     }
 }
 
+/// The type of an operator applied to `operands`, which like every operator is applied to each item of a list: `of`
+/// gives the type for the types of single items, and a list of it is the type if any operand is a list.
+fn broadcast(operands: &[Expression], of: impl FnOnce(&[DataType]) -> DataType) -> DataType {
+    let item_types = Vec::from_iter(operands.iter().map(|operand| match &operand.data_type {
+        DataType::List { item_type } => (**item_type).clone(),
+        data_type => data_type.clone(),
+    }));
+    let data_type = of(&item_types);
+    if data_type != DataType::Unknown && operands.iter().any(|operand| matches!(operand.data_type, DataType::List { .. })) {
+        data_type.list_type().unwrap_or(DataType::Unknown)
+    } else {
+        data_type
+    }
+}
+
+/// The type of an arithmetic operator applied to single items of `types`, or `Unknown` where it isn't clear, which is
+/// left for Desmos to make sense of. Division always gives a real, and so does a power unless it raises an int to a
+/// constant which isn't negative. Points can be added to and subtracted from each other, and scaled by numbers.
+fn arithmetic_type(operation: Operation, types: &[DataType], operands: &[Expression]) -> DataType {
+    use DataType::*;
+    let is_numeric = |data_type: &DataType| *data_type != Unknown && data_type.can_coerce_to(&Real);
+    let scaled = |point: &DataType, factor: &DataType| match point {
+        IPoint if factor.can_coerce_to(&Int) => IPoint,
+        IPoint | Point => Point,
+        IPoint3 if factor.can_coerce_to(&Int) => IPoint3,
+        IPoint3 | Point3 => Point3,
+        _ => Unknown,
+    };
+    match (operation, types) {
+        (Operation::Posate | Operation::Negate, [operand]) => match operand {
+            Point | IPoint | Point3 | IPoint3 => operand.clone(),
+            operand if is_numeric(operand) => operand.merge_numeric(&Int).unwrap_or(Unknown),
+            _ => Unknown,
+        },
+        (_, [left, right]) if is_numeric(left) && is_numeric(right) => match operation {
+            Operation::Divide => Real,
+            Operation::Exponent => {
+                let is_natural = matches!(operands[1].constant_value(), Some(&ConstantValue::Int(0..)));
+                if is_natural && left.can_coerce_to(&Int) { Int } else { Real }
+            },
+            _ => left.merge_numeric(right).unwrap_or(Unknown),
+        },
+        (Operation::Add | Operation::Subtract, [left, right]) => match (left, right) {
+            (Point | IPoint, Point | IPoint) | (Point3 | IPoint3, Point3 | IPoint3) => left.merge(right).unwrap_or(Unknown),
+            _ => Unknown,
+        },
+        (Operation::Multiply, [point, factor]) | (Operation::Multiply, [factor, point]) if is_numeric(factor) => scaled(point, factor),
+        (Operation::Divide, [point, factor]) if is_numeric(factor) => scaled(point, &Real),
+        _ => Unknown,
+    }
+}
+
 pub fn message_cannot_coerce(from_type: &DataType, to_type: &DataType) -> String {
     format!("cannot coerce value of type '{from_type}' to '{to_type}'")
 }
 
-#[derive(Clone, Debug)]
+pub fn message_named_argument() -> String {
+    String::from("named arguments can only be passed to user-defined functions and actions")
+}
+
+/// The value of a bitwise or shift operation on integers, as it is lowered for Desmos: `&`, `^^` and `|` work on the
+/// low 32 bits of their operands (so the result is never negative), `~x` is `-x - 1` as for signed integers, and `<<`
+/// and `>>` multiply or divide by a power of two and round down. Gives `None` if the result would overflow.
+pub fn fold_bitwise(operation: Operation, operands: &[i64]) -> Option<i64> {
+    let shift = |value: i64, amount: i64| match amount {
+        0..=62 => value.checked_mul(1 << amount),
+        -62..=-1 => Some(value >> -amount),
+        _ => None
+    };
+    match (operation, operands) {
+        (Operation::BitwiseNot, &[value]) => Some(!value),
+        (Operation::BitwiseAnd, &[lhs, rhs]) => Some(i64::from(lhs as u32 & rhs as u32)),
+        (Operation::BitwiseXor, &[lhs, rhs]) => Some(i64::from(lhs as u32 ^ rhs as u32)),
+        (Operation::BitwiseOr, &[lhs, rhs]) => Some(i64::from(lhs as u32 | rhs as u32)),
+        (Operation::ShiftLeft, &[lhs, rhs]) => shift(lhs, rhs),
+        (Operation::ShiftRight, &[lhs, rhs]) => shift(lhs, rhs.checked_neg()?),
+        _ => None
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Scope {
     pub can_use_dt: bool,
     pub can_use_index: bool,
     pub parameters: BTreeMap<String, DataType>,
 }
 
-impl Default for Scope {
-    fn default() -> Self {
-        Self {
-            can_use_dt: false,
-            can_use_index: false,
-            parameters: BTreeMap::new(),
+pub fn analyze(signatures: &Signatures, definitions: &mut Definitions) -> Result<(), DesmosifyError> {
+    let scope = Scope::default();
+
+    // Calls are rewritten to pass every argument by position first, which is all the later stages understand
+    for (name, value) in &mut definitions.identifiers {
+        expand_arguments(signatures, &parameter_names(signatures, name), value.as_mut())?;
+    }
+    for (name, content) in &mut definitions.actions {
+        expand_action_arguments(signatures, &parameter_names(signatures, name), content.as_mut())?;
+    }
+    for element in definitions.public.iter_mut().flatten() {
+        expand_arguments(signatures, &BTreeSet::new(), element)?;
+    }
+    if let Some(ticker) = &mut definitions.ticker {
+        if let Some(interval_ms) = &mut ticker.interval_ms {
+            expand_arguments(signatures, &BTreeSet::new(), interval_ms.as_mut())?;
+        }
+        expand_action_arguments(signatures, &BTreeSet::new(), ticker.tick_action.as_mut())?;
+    }
+    for element in definitions.display.iter_mut().flatten() {
+        expand_arguments(signatures, &BTreeSet::new(), element.what.as_mut())?;
+        for attribute in element.attributes_mut() {
+            expand_arguments(signatures, &BTreeSet::new(), attribute)?;
+        }
+        if let Some(click) = &mut element.click {
+            expand_action_arguments(signatures, &BTreeSet::new(), click.action.as_mut())?;
         }
     }
-}
 
-pub fn analyze(signatures: &Signatures, definitions: &mut Definitions) -> Result<(), DesmosifyError> {
-    let scope = Scope::default();
+    // Constants and the initial values of variables may be written in terms of other constants and enum variants
+    let folded = Vec::from_iter(definitions.identifiers.iter()
+        .filter(|(name, _)| matches!(
+            signatures.user_defined.get(*name),
+            Some(Signature::Const { parameters: None, .. } | Signature::Var { .. }),
+        ))
+        .filter_map(|(name, value)| {
+            Some((name.clone(), evaluate_constant(signatures, &definitions.identifiers, value, &mut BTreeSet::new())?))
+        }));
+    for (name, value) in folded {
+        if let Some(expression) = definitions.identifiers.get_mut(&name) {
+            expression.value = ExpressionValue::Literal(value);
+        }
+    }
 
     for (name, value) in &mut definitions.identifiers {
-        analyze_identifier(signatures, &scope, signatures.user_defined.get(name).unwrap(), value.as_mut())?;
+        analyze_identifier(signatures, &scope, declared_signature(signatures, name)?, value.as_mut())?;
     }
 
     for (name, content) in &mut definitions.actions {
-        analyze_named_action(signatures, &scope, signatures.user_defined.get(name).unwrap(), content.as_mut())?;
+        analyze_named_action(signatures, &scope, declared_signature(signatures, name)?, content.as_mut())?;
     }
 
     if let Some(elements) = &mut definitions.public {
@@ -168,11 +256,18 @@ pub fn analyze(signatures: &Signatures, definitions: &mut Definitions) -> Result
         }
     }
 
+    // Desmos rejects cyclic definitions with a rather cryptic message, so report them here
+    dependencies::dependency_order(signatures, definitions)?;
+
     Ok(())
 }
 
 pub fn analyze_expression(signatures: &Signatures, scope: &Scope, expression: &mut Expression) -> Result<(), DesmosifyError> {
     match &mut expression.value {
+        ExpressionValue::Literal(value) => {
+            expression.data_type = value.data_type();
+            Ok(())
+        },
         ExpressionValue::Name(name) => if let Some(data_type) = signatures.get_type_from_name(scope, name) {
             expression.data_type = data_type;
             Ok(())
@@ -188,32 +283,30 @@ pub fn analyze_expression(signatures: &Signatures, scope: &Scope, expression: &m
                 for component in operands.iter_mut() {
                     analyze_expression(signatures, scope, component)?;
                 }
-                expression.data_type = operands[0].data_type.merge_numeric(&operands[1].data_type)
-                        .ok_or_else(|| DesmosifyError::new(
-                            format!("cannot create a point of types ({}, {})", operands[0].data_type, operands[1].data_type),
-                            expression.start,
-                            expression.end,
-                        ))?
-                        .point_type(operands.len()).unwrap();
-                if let (Some(x_value), Some(y_value)) = (operands[0].constant_value(), operands[1].constant_value()) {
-                    let value = match (x_value, y_value) {
-                        (&ConstantValue::Real(x_value), &ConstantValue::Real(y_value)) => {
-                            ConstantValue::Point(x_value, y_value)
-                        },
-                        (&ConstantValue::Int(x_value), &ConstantValue::Real(y_value)) => {
-                            ConstantValue::Point(x_value as f64, y_value)
-                        },
-                        (&ConstantValue::Real(x_value), &ConstantValue::Int(y_value)) => {
-                            ConstantValue::Point(x_value, y_value as f64)
-                        },
-                        (&ConstantValue::Int(x_value), &ConstantValue::Int(y_value)) => {
-                            ConstantValue::IPoint(x_value, y_value)
-                        },
-                        _ => return Err(DesmosifyError::new(
-                            format!("cannot create a point of types ({}, {})", operands[0].data_type, operands[1].data_type),
-                            expression.start,
-                            expression.end,
-                        ))
+                let message_cannot_create = || format!(
+                    "cannot create a point of types ({})",
+                    Vec::from_iter(operands.iter().map(|component| component.data_type.to_string())).join(", "),
+                );
+                let component_type = operands[1..].iter()
+                    .try_fold(operands[0].data_type.clone(), |component_type, component| component_type.merge_numeric(&component.data_type))
+                    .ok_or_else(|| DesmosifyError::new(message_cannot_create(), expression.start, expression.end))?;
+                expression.data_type = component_type.point_type(operands.len())
+                    .ok_or_else(|| DesmosifyError::new(message_cannot_create(), expression.start, expression.end))?;
+                let constant_components = operands.iter()
+                    .map(|component| match *component.constant_value()? {
+                        ConstantValue::Real(value) => Some((value, None)),
+                        ConstantValue::Int(value) => Some((value as f64, Some(value))),
+                        _ => None
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(components) = constant_components {
+                    let is_integer = components.iter().all(|(_, int_value)| int_value.is_some());
+                    let value = match (components.as_slice(), is_integer) {
+                        (&[(_, Some(x_value)), (_, Some(y_value))], true) => ConstantValue::IPoint(x_value, y_value),
+                        (&[(x_value, _), (y_value, _)], _) => ConstantValue::Point(x_value, y_value),
+                        (&[(_, Some(x_value)), (_, Some(y_value)), (_, Some(z_value))], true) => ConstantValue::IPoint3(x_value, y_value, z_value),
+                        (&[(x_value, _), (y_value, _), (z_value, _)], _) => ConstantValue::Point3(x_value, y_value, z_value),
+                        _ => return Err(DesmosifyError::new(message_cannot_create(), expression.start, expression.end))
                     };
 
                     expression.value = ExpressionValue::Literal(value);
@@ -269,34 +362,306 @@ pub fn analyze_expression(signatures: &Signatures, scope: &Scope, expression: &m
             Operation::Call => Ok(()),
             Operation::ActionCall => Ok(()),
             Operation::Index => Ok(()),
-            Operation::Posate => Ok(()),
-            Operation::Negate => Ok(()),
-            Operation::Not => Ok(()),
-            Operation::Exponent => Ok(()),
-            Operation::Multiply => Ok(()),
-            Operation::Divide => Ok(()),
-            Operation::Modulus => Ok(()),
-            Operation::Add => Ok(()),
-            Operation::Subtract => Ok(()),
-            Operation::LessThan => Ok(()),
-            Operation::GreaterThan => Ok(()),
-            Operation::LessEqual => Ok(()),
-            Operation::GreaterEqual => Ok(()),
-            Operation::Equal => Ok(()),
-            Operation::NotEqual => Ok(()),
-            Operation::And => Ok(()),
-            Operation::Or => Ok(()),
+            Operation::Posate | Operation::Negate | Operation::Exponent | Operation::Multiply | Operation::Divide
+            | Operation::Modulus | Operation::Add | Operation::Subtract => {
+                for operand in operands.iter_mut() {
+                    analyze_expression(signatures, scope, operand)?;
+                }
+                expression.data_type = broadcast(operands, |types| arithmetic_type(*operation, types, operands));
+                Ok(())
+            },
+            Operation::Not | Operation::LessThan | Operation::GreaterThan | Operation::LessEqual | Operation::GreaterEqual
+            | Operation::Equal | Operation::NotEqual | Operation::And | Operation::Or => {
+                for operand in operands.iter_mut() {
+                    analyze_expression(signatures, scope, operand)?;
+                }
+                expression.data_type = broadcast(operands, |_| DataType::Bool);
+                Ok(())
+            },
+            Operation::IntegerDivide => {
+                for operand in operands.iter_mut() {
+                    analyze_expression(signatures, scope, operand)?;
+                    if !operand.data_type.can_coerce_to(&DataType::Real) {
+                        return Err(DesmosifyError::new(
+                            message_cannot_coerce(&operand.data_type, &DataType::Real),
+                            operand.start,
+                            operand.end,
+                        ));
+                    }
+                }
+                // Like every operator, it is applied to each item of a list
+                expression.data_type = if operands.iter().any(|operand| matches!(operand.data_type, DataType::List { .. })) {
+                    DataType::List { item_type: Box::new(DataType::Int) }
+                } else {
+                    DataType::Int
+                };
+                Ok(())
+            },
+            Operation::BitwiseNot | Operation::BitwiseAnd | Operation::BitwiseXor | Operation::BitwiseOr
+            | Operation::ShiftLeft | Operation::ShiftRight => {
+                // Lists aren't broadcast over, as the lowering for Desmos sums over a list of bits
+                for operand in operands.iter_mut() {
+                    analyze_expression(signatures, scope, operand)?;
+                    if matches!(operand.data_type, DataType::List { .. }) || !operand.data_type.can_coerce_to(&DataType::Int) {
+                        return Err(DesmosifyError::new(
+                            message_cannot_coerce(&operand.data_type, &DataType::Int),
+                            operand.start,
+                            operand.end,
+                        ));
+                    }
+                }
+                expression.data_type = DataType::Int;
+                let constant_operands = operands.iter()
+                    .map(|operand| match *operand.constant_value()? {
+                        ConstantValue::Int(value) => Some(value),
+                        _ => None
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(value) = constant_operands.and_then(|constant_operands| fold_bitwise(*operation, &constant_operands)) {
+                    expression.value = ExpressionValue::Literal(ConstantValue::Int(value));
+                }
+                Ok(())
+            },
             Operation::ExclusiveRange => Ok(()),
             Operation::InclusiveRange => Ok(()),
             Operation::Conditional => Ok(()),
             Operation::Assignment => Ok(()),
+            Operation::Regression => {
+                // Unresolved names on the right-hand side are regression parameters, which Desmos solves for
+                analyze_expression(signatures, scope, &mut operands[0])?;
+                expression.data_type = DataType::Void;
+                Ok(())
+            },
             Operation::Update => Ok(()),
             Operation::With => Ok(()),
+            // Lambdas passed to higher-order built-ins were specialized away while parsing
+            Operation::Lambda => Err(DesmosifyError::new(
+                higher_order::message_stray_lambda(),
+                expression.start,
+                expression.end,
+            )),
+            // Named arguments passed to user-defined functions and actions were replaced by `expand_arguments`
+            Operation::NamedArgument => Err(DesmosifyError::new(
+                message_named_argument(),
+                expression.start,
+                expression.end,
+            )),
+        },
+    }
+}
+
+/// Rewrites every call in `expression` to a user-defined function or action so that it passes its arguments in the
+/// order of the parameters, matching named arguments up by name and filling in default values for the rest. Enum
+/// variants such as `Mode.PLAY` become literals at the same time. Unlike analysis, this also descends into
+/// comprehensions and `with` bodies.
+///
+/// `locals` holds the parameters in scope around `expression`. A default value is written where its function is
+/// declared, so one that refers to a name which a local hides at the call is reported rather than pasted in.
+pub fn expand_arguments(signatures: &Signatures, locals: &BTreeSet<String>, expression: &mut Expression) -> Result<(), DesmosifyError> {
+    expand_expression_arguments(signatures, expression, locals, &mut Vec::new())
+}
+
+/// Rewrites the calls in `action` and in its expressions in the same way as `expand_arguments`.
+pub fn expand_action_arguments(signatures: &Signatures, locals: &BTreeSet<String>, action: &mut Action) -> Result<(), DesmosifyError> {
+    let mut expanding = Vec::new();
+    match action {
+        Action::Block(sub_actions) => {
+            for sub_action in sub_actions {
+                expand_action_arguments(signatures, locals, sub_action)?;
+            }
+        },
+        Action::Update(target, value) => {
+            expand_expression_arguments(signatures, target.as_mut(), locals, &mut expanding)?;
+            expand_expression_arguments(signatures, value.as_mut(), locals, &mut expanding)?;
+        },
+        Action::Call(callee, arguments) => {
+            bind_call_arguments(signatures, Operation::ActionCall, callee, arguments, locals, &mut expanding)?;
+        },
+        Action::Conditional(branches, default_branch) => {
+            for (condition, branch) in branches {
+                expand_expression_arguments(signatures, condition, locals, &mut expanding)?;
+                expand_action_arguments(signatures, locals, branch)?;
+            }
+            if let Some(default_branch) = default_branch {
+                expand_action_arguments(signatures, locals, default_branch.as_mut())?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// The names of the parameters of the function or action `name`, which are in scope throughout its body.
+fn parameter_names(signatures: &Signatures, name: &str) -> BTreeSet<String> {
+    signatures.user_defined.get(name)
+        .and_then(Signature::parameters)
+        .map(|parameters| parameters.iter().map(|parameter| parameter.name.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// `locals` also gains the variables bound by comprehensions and `with` inside `expression`. `expanding` holds the
+/// functions and actions whose default values are being expanded, so that a default value which leads back to its own
+/// function is reported rather than expanded forever.
+fn expand_expression_arguments(
+    signatures: &Signatures,
+    expression: &mut Expression,
+    locals: &BTreeSet<String>,
+    expanding: &mut Vec<String>,
+) -> Result<(), DesmosifyError> {
+    let ExpressionValue::Operator(operation, operands) = &mut expression.value else {
+        return Ok(());
+    };
+    match *operation {
+        Operation::NamedArgument => Err(DesmosifyError::new(message_named_argument(), expression.start, expression.end)),
+        Operation::MemberAccess => match enum_variant(signatures, operands) {
+            Some(Ok(variant)) => {
+                expression.data_type = variant.data_type();
+                expression.value = ExpressionValue::Literal(variant);
+                Ok(())
+            },
+            Some(Err(message)) => Err(DesmosifyError::new(message, expression.start, expression.end)),
+            None => expand_expression_arguments(signatures, &mut operands[0], locals, expanding),
+        },
+        Operation::Call | Operation::ActionCall => {
+            let mut arguments = operands.split_off(1);
+            bind_call_arguments(signatures, *operation, &operands[0], &mut arguments, locals, expanding)?;
+            operands.append(&mut arguments);
+            Ok(())
+        },
+        Operation::ListMap => {
+            expand_expression_arguments(signatures, &mut operands[2], locals, expanding)?;
+            let mut inner_locals = locals.clone();
+            if let ExpressionValue::Name(name) = &operands[1].value {
+                inner_locals.insert(name.clone());
+            }
+            expand_expression_arguments(signatures, &mut operands[0], &inner_locals, expanding)
+        },
+        Operation::With => {
+            let mut inner_locals = locals.clone();
+            if let ExpressionValue::Operator(Operation::Assignment, binding) = &mut operands[1].value {
+                expand_expression_arguments(signatures, &mut binding[1], locals, expanding)?;
+                if let ExpressionValue::Name(name) = &binding[0].value {
+                    inner_locals.insert(name.clone());
+                }
+            } else {
+                expand_expression_arguments(signatures, &mut operands[1], locals, expanding)?;
+            }
+            expand_expression_arguments(signatures, &mut operands[0], &inner_locals, expanding)
+        },
+        _ => {
+            for operand in operands.iter_mut() {
+                expand_expression_arguments(signatures, operand, locals, expanding)?;
+            }
+            Ok(())
         },
-        _ => Ok(())
     }
 }
 
+/// Replaces `arguments` by the arguments for each parameter of `callee` in order, if it is a user-defined function or
+/// action (depending on `operation`), and expands the calls inside them.
+fn bind_call_arguments(
+    signatures: &Signatures,
+    operation: Operation,
+    callee: &Expression,
+    arguments: &mut Vec<Expression>,
+    locals: &BTreeSet<String>,
+    expanding: &mut Vec<String>,
+) -> Result<(), DesmosifyError> {
+    let signature = match &callee.value {
+        ExpressionValue::Name(name) => signatures.user_defined.get(name),
+        _ => None,
+    };
+    let (kind, name, parameters) = match (operation, signature) {
+        (
+            Operation::Call,
+            Some(Signature::Const { name, parameters: Some(parameters), .. } | Signature::Let { name, parameters: Some(parameters), .. }),
+        ) => ("function", name, parameters),
+        (Operation::ActionCall, Some(Signature::Action { name, parameters })) => ("action", name, parameters),
+        // Anything else is left to analysis, which can tell what is wrong with it
+        _ => {
+            for argument in arguments.iter_mut() {
+                expand_expression_arguments(signatures, argument, locals, expanding)?;
+            }
+            return Ok(());
+        },
+    };
+
+    let provided = arguments.len();
+    let mut bound: Vec<Option<Expression>> = vec![None; parameters.len()];
+    for (position, argument) in std::mem::take(arguments).into_iter().enumerate() {
+        let (index, mut value) = match argument.value {
+            ExpressionValue::Operator(Operation::NamedArgument, mut operands) => {
+                let value = operands.pop().unwrap();
+                let parameter_name = operands.pop().unwrap();
+                let ExpressionValue::Name(parameter_name_value) = &parameter_name.value else {
+                    unreachable!()
+                };
+                let index = parameters.iter()
+                    .position(|parameter| &parameter.name == parameter_name_value)
+                    .ok_or_else(|| DesmosifyError::new(
+                        format!("{kind} {name} has no parameter named '{parameter_name_value}'"),
+                        parameter_name.start,
+                        parameter_name.end,
+                    ))?;
+                if bound[index].is_some() {
+                    return Err(DesmosifyError::new(
+                        format!("{kind} {name} was provided more than one argument for '{parameter_name_value}'"),
+                        parameter_name.start,
+                        parameter_name.end,
+                    ));
+                }
+                (index, value)
+            },
+            _ if position < parameters.len() => (position, argument),
+            _ => return Err(DesmosifyError::new(
+                format!("{kind} {name} expects {} argument(s), but was provided {provided}", parameters.len()),
+                callee.start,
+                callee.end,
+            )),
+        };
+        expand_expression_arguments(signatures, &mut value, locals, expanding)?;
+        bound[index] = Some(value);
+    }
+
+    for (parameter, argument) in parameters.iter().zip(bound) {
+        let argument = match (argument, &parameter.default) {
+            (Some(argument), _) => argument,
+            (None, Some(default)) => {
+                if expanding.contains(name) {
+                    return Err(DesmosifyError::new(
+                        format!("the default value of '{}' leads back to {kind} {name}", parameter.name),
+                        default.start,
+                        default.end,
+                    ));
+                }
+                let mut references = BTreeSet::new();
+                dependencies::collect_expression_references(default, &BTreeSet::new(), &mut references);
+                if let Some(hidden) = references.intersection(locals).next() {
+                    return Err(DesmosifyError::new(
+                        format!(
+                            "the default value of '{}' refers to '{hidden}', which is hidden here; pass the argument to {kind} {name} explicitly",
+                            parameter.name,
+                        ),
+                        callee.start,
+                        callee.end,
+                    ));
+                }
+                let mut default = default.clone();
+                expanding.push(name.clone());
+                expand_expression_arguments(signatures, &mut default, locals, expanding)?;
+                expanding.pop();
+                default
+            },
+            (None, None) => return Err(DesmosifyError::new(
+                format!("{kind} {name} was not provided an argument for '{}'", parameter.name),
+                callee.start,
+                callee.end,
+            )),
+        };
+        arguments.push(argument);
+    }
+    Ok(())
+}
+
 pub fn analyze_action(signatures: &Signatures, scope: &Scope, action: &mut Action) -> Result<(), DesmosifyError> {
     // TODO: check concurrent modification
     match action {
@@ -384,12 +749,89 @@ pub fn analyze_action(signatures: &Signatures, scope: &Scope, action: &mut Actio
     }
 }
 
+/// The variant named by the operands of a member access, if they are the name of an enum and a member, or an error if
+/// the enum has no such variant. `None` for other member accesses, such as point components.
+fn enum_variant(signatures: &Signatures, operands: &[Expression]) -> Option<Result<ConstantValue, String>> {
+    let (ExpressionValue::Name(name), ExpressionValue::Name(variant)) = (&operands[0].value, &operands[1].value) else {
+        return None;
+    };
+    let Some(Signature::Enum { variants, .. }) = signatures.user_defined.get(name) else {
+        return None;
+    };
+    Some(variants.iter().position(|other| other == variant)
+        .map(|position| ConstantValue::EnumVariant(name.clone(), variant.clone(), position))
+        .ok_or_else(|| format!("enum {name} has no variant named '{variant}'")))
+}
+
+/// The value of `expression` if it only uses literals, enum variants and `const` definitions without parameters (found
+/// in `identifiers`), combined by operators that can be worked out exactly. `visiting` holds the constants whose values
+/// are being worked out, so that a cycle gives `None` rather than recursing forever.
+fn evaluate_constant(signatures: &Signatures, identifiers: &BTreeMap<String, Box<Expression>>, expression: &Expression, visiting: &mut BTreeSet<String>) -> Option<ConstantValue> {
+    match &expression.value {
+        ExpressionValue::Literal(value) => Some(value.clone()),
+        ExpressionValue::Name(name) => {
+            if !matches!(signatures.user_defined.get(name), Some(Signature::Const { parameters: None, .. })) || !visiting.insert(name.clone()) {
+                return None;
+            }
+            let value = evaluate_constant(signatures, identifiers, identifiers.get(name)?, visiting);
+            visiting.remove(name);
+            value
+        },
+        ExpressionValue::Operator(Operation::MemberAccess, operands) => enum_variant(signatures, operands)?.ok(),
+        ExpressionValue::Operator(Operation::ListLiteral, items) => {
+            let items = items.iter()
+                .map(|item| evaluate_constant(signatures, identifiers, item, visiting))
+                .collect::<Option<Vec<_>>>()?;
+            let item_type = items.iter().try_fold(DataType::Unknown, |item_type, item| item_type.merge(&item.data_type()))?;
+            Some(ConstantValue::List(item_type, items))
+        },
+        ExpressionValue::Operator(Operation::ListFill, operands) => {
+            let item = evaluate_constant(signatures, identifiers, &operands[0], visiting)?;
+            let ConstantValue::Int(count) = evaluate_constant(signatures, identifiers, &operands[1], visiting)? else {
+                return None;
+            };
+            Some(ConstantValue::List(item.data_type(), vec![item; usize::try_from(count).ok()?]))
+        },
+        ExpressionValue::Operator(operation, operands) => {
+            let operands = operands.iter()
+                .map(|operand| evaluate_constant(signatures, identifiers, operand, visiting).map(Expression::from_constant))
+                .collect::<Option<Vec<_>>>()?;
+            match operation {
+                Operation::PointLiteral => {
+                    let mut point = Expression {
+                        data_type: DataType::Unknown,
+                        value: ExpressionValue::Operator(Operation::PointLiteral, operands),
+                        start: expression.start,
+                        end: expression.end,
+                    };
+                    analyze_expression(signatures, &Scope::default(), &mut point).ok()?;
+                    point.constant_value().cloned()
+                },
+                _ => crate::optimize::fold(*operation, &operands)
+            }
+        },
+    }
+}
+
+/// The signature of a definition, which parsing records alongside it; an error at the declaration if there is none.
+fn declared_signature<'a>(signatures: &'a Signatures, name: &str) -> Result<&'a Signature, DesmosifyError> {
+    signatures.user_defined.get(name).ok_or_else(|| {
+        let location = signatures.locations.get(name);
+        DesmosifyError::new(
+            format!("'{name}' is defined without a declaration"),
+            location.map(|(start, _)| *start),
+            location.map(|(_, end)| *end),
+        )
+    })
+}
+
 pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Signature, value: &mut Expression) -> Result<(), DesmosifyError> {
     match signature {
         Signature::Const { name, parameters, value_type, .. } => {
             if let Some(parameters) = parameters {
                 let mut call_scope = scope.clone();
-                for Parameter { name, data_type } in parameters {
+                analyze_defaults(signatures, scope, parameters)?;
+                for Parameter { name, data_type, .. } in parameters {
                     call_scope.parameters.insert(name.clone(), data_type.clone());
                 }
                 analyze_expression(signatures, &call_scope, value)?;
@@ -404,7 +846,7 @@ pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Si
                 ))
             } else if !value.data_type.can_coerce_to(value_type) {
                 Err(DesmosifyError::new(
-                    message_cannot_coerce(&value.data_type, &value_type),
+                    message_cannot_coerce(&value.data_type, value_type),
                     value.start,
                     value.end,
                 ))
@@ -415,7 +857,8 @@ pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Si
         Signature::Let { parameters, value_type, .. } => {
             if let Some(parameters) = parameters {
                 let mut call_scope = scope.clone();
-                for Parameter { name, data_type } in parameters {
+                analyze_defaults(signatures, scope, parameters)?;
+                for Parameter { name, data_type, .. } in parameters {
                     call_scope.parameters.insert(name.clone(), data_type.clone());
                 }
                 analyze_expression(signatures, &call_scope, value)?;
@@ -424,7 +867,7 @@ pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Si
             }
             if !value.data_type.can_coerce_to(value_type) {
                 Err(DesmosifyError::new(
-                    message_cannot_coerce(&value.data_type, &value_type),
+                    message_cannot_coerce(&value.data_type, value_type),
                     value.start,
                     value.end,
                 ))
@@ -442,7 +885,7 @@ pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Si
                 ))
             } else if !value.data_type.can_coerce_to(value_type) {
                 Err(DesmosifyError::new(
-                    message_cannot_coerce(&value.data_type, &value_type),
+                    message_cannot_coerce(&value.data_type, value_type),
                     value.start,
                     value.end,
                 ))
@@ -461,10 +904,31 @@ pub fn analyze_identifier(signatures: &Signatures, scope: &Scope, signature: &Si
     }
 }
 
+/// Checks the default values of `parameters` where they are declared, as they are only otherwise seen at the calls
+/// which leave them out.
+fn analyze_defaults(signatures: &Signatures, scope: &Scope, parameters: &[Parameter]) -> Result<(), DesmosifyError> {
+    for parameter in parameters {
+        if let Some(default) = &parameter.default {
+            let mut default = default.clone();
+            expand_arguments(signatures, &BTreeSet::new(), &mut default)?;
+            analyze_expression(signatures, scope, &mut default)?;
+            if !default.data_type.can_coerce_to(&parameter.data_type) {
+                return Err(DesmosifyError::new(
+                    message_cannot_coerce(&default.data_type, &parameter.data_type),
+                    default.start,
+                    default.end,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn analyze_named_action(signatures: &Signatures, scope: &Scope, signature: &Signature, content: &mut Action) -> Result<(), DesmosifyError> {
     if let Signature::Action { parameters, .. } = signature {
+        analyze_defaults(signatures, scope, parameters)?;
         let mut call_scope = scope.clone();
-        for Parameter { name, data_type } in parameters {
+        for Parameter { name, data_type, .. } in parameters {
             call_scope.parameters.insert(name.clone(), data_type.clone());
         }
         analyze_action(signatures, &call_scope, content)
@@ -34,15 +34,20 @@ fn message_identifier_conflict(original: Signature) -> String {
 #[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
 pub enum Precedence {
     // Lowest
+    Lambda,
     With,
     Assignment,
     Logical,
     Equality,
     Comparison,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Shift,
     Additive,
     Multiplicative,
-    Exponent,
     Prefix,
+    Exponent,
     Postfix,
     Access,
     Container,
@@ -51,10 +56,7 @@ pub enum Precedence {
 
 impl Precedence {
     pub fn is_left_to_right_associative(self) -> bool {
-        match self {
-            Self::Prefix | Self::Assignment | Self::With => false,
-            _ => true
-        }
+        !matches!(self, Self::Exponent | Self::Prefix | Self::Assignment | Self::With | Self::Lambda)
     }
 
     pub fn precedes(self, rhs: Self) -> bool {
@@ -81,12 +83,19 @@ pub enum Operation {
     Posate,
     Negate,
     Not,
+    BitwiseNot,
     Exponent,
     Multiply,
     Divide,
+    IntegerDivide,
     Modulus,
     Add,
     Subtract,
+    ShiftLeft,
+    ShiftRight,
+    BitwiseAnd,
+    BitwiseXor,
+    BitwiseOr,
     LessThan,
     GreaterThan,
     LessEqual,
@@ -100,7 +109,10 @@ pub enum Operation {
     Conditional,
     Assignment,
     Update,
+    Regression,
     With,
+    Lambda,
+    NamedArgument,
 }
 
 impl Operation {
@@ -116,23 +128,25 @@ impl Operation {
             } else {
                 Self::Subtract
             },
-            Symbol::Bang => if expect_operand {
-                Self::Not
-            } else {
-                return None;
-            },
-            Symbol::AtSign => if expect_operand {
-                Self::BuiltIn
+            Symbol::Bang if expect_operand => Self::Not,
+            Symbol::AtSign if expect_operand => Self::BuiltIn,
+            Symbol::Tilde => if expect_operand {
+                Self::BitwiseNot
             } else {
-                return None;
+                Self::Regression
             },
             _ if expect_operand => return None,
             Symbol::Star => Self::Multiply,
             Symbol::Slash => Self::Divide,
             Symbol::Percent => Self::Modulus,
-            Symbol::Caret => Self::Exponent,
+            Symbol::Caret | Symbol::Star2 => Self::Exponent,
             Symbol::Ampersand2 => Self::And,
             Symbol::Pipe2 => Self::Or,
+            Symbol::Ampersand => Self::BitwiseAnd,
+            Symbol::Caret2 => Self::BitwiseXor,
+            Symbol::Pipe => Self::BitwiseOr,
+            Symbol::LessThan2 => Self::ShiftLeft,
+            Symbol::GreaterThan2 => Self::ShiftRight,
             Symbol::Equal2 => Self::Equal,
             Symbol::NotEqual => Self::NotEqual,
             Symbol::LessThan => Self::LessThan,
@@ -141,6 +155,7 @@ impl Operation {
             Symbol::GreaterEqual => Self::GreaterEqual,
             Symbol::Equal => Self::Assignment,
             Symbol::ColonEqual => Self::Update,
+            Symbol::RightEqualArrow => Self::Lambda,
             Symbol::Dot => Self::MemberAccess,
             Symbol::ParenLeft => Self::Call,
             Symbol::SquareLeft => Self::Index,
@@ -150,45 +165,60 @@ impl Operation {
 
     pub fn from_keyword(keyword: Keyword, expect_operand: bool) -> Option<Self> {
         Some(match keyword {
-            Keyword::Action => if expect_operand {
-                Self::ActionCall
-            } else {
-                return None;
-            },
+            Keyword::Action if expect_operand => Self::ActionCall,
             _ if expect_operand => return None,
             Keyword::With => Self::With,
+            Keyword::Div => Self::IntegerDivide,
             _ => return None
         })
     }
 
+    pub fn from_token_value(value: &TokenValue, expect_operand: bool) -> Option<Self> {
+        match *value {
+            TokenValue::Symbol(symbol) => Self::from_symbol(symbol, expect_operand),
+            TokenValue::Keyword(keyword) => Self::from_keyword(keyword, expect_operand),
+            _ => None,
+        }
+    }
+
     pub fn precedence(self) -> Precedence {
         use Operation::*;
         match self {
             PointLiteral | ListLiteral | ListFill | ListMap | ListFilter
-            | Conditional | ExclusiveRange | InclusiveRange
+            | Conditional | ExclusiveRange | InclusiveRange | NamedArgument
                 => Precedence::Container,
             MemberAccess | BuiltIn
                 => Precedence::Access,
             Call | ActionCall | Index
                 => Precedence::Postfix,
-            Posate | Negate | Not
+            Posate | Negate | Not | BitwiseNot
                 => Precedence::Prefix,
             Exponent
                 => Precedence::Exponent,
-            Multiply | Divide | Modulus
+            Multiply | Divide | IntegerDivide | Modulus
                 => Precedence::Multiplicative,
             Add | Subtract
                 => Precedence::Additive,
+            ShiftLeft | ShiftRight
+                => Precedence::Shift,
+            BitwiseAnd
+                => Precedence::BitwiseAnd,
+            BitwiseXor
+                => Precedence::BitwiseXor,
+            BitwiseOr
+                => Precedence::BitwiseOr,
             LessThan | GreaterThan | LessEqual | GreaterEqual
                 => Precedence::Comparison,
             Equal | NotEqual
                 => Precedence::Equality,
             And | Or
                 => Precedence::Logical,
-            Assignment | Update
+            Assignment | Update | Regression
                 => Precedence::Assignment,
             With
                 => Precedence::With,
+            Lambda
+                => Precedence::Lambda,
         }
     }
 
@@ -197,7 +227,7 @@ impl Operation {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ExpressionValue {
     Literal(ConstantValue),
     Operator(Operation, Vec<Expression>),
@@ -209,12 +239,12 @@ impl ExpressionValue {
         if let TokenValue::Name(name) = value {
             Some(Self::Name(name.clone()))
         } else {
-            ConstantValue::from_token_value(value).map(|literal| Self::Literal(literal))
+            ConstantValue::from_token_value(value).map(Self::Literal)
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Expression {
     pub data_type: DataType,
     pub value: ExpressionValue,
@@ -238,6 +268,34 @@ impl Expression {
             _ => None
         }
     }
+
+    /// Whether this expression is always a boolean (as opposed to a number used as one), going by its type and, as
+    /// analysis does not type every operator, by the operator giving its value.
+    pub fn is_boolean(&self) -> bool {
+        match &self.value {
+            ExpressionValue::Literal(ConstantValue::Bool(_)) => true,
+            ExpressionValue::Operator(
+                Operation::Not | Operation::LessThan | Operation::GreaterThan | Operation::LessEqual
+                | Operation::GreaterEqual | Operation::Equal | Operation::NotEqual | Operation::And | Operation::Or,
+                _,
+            ) => true,
+            _ => self.data_type == DataType::Bool,
+        }
+    }
+
+    pub fn is_lambda(&self) -> bool {
+        matches!(self.value, ExpressionValue::Operator(Operation::Lambda, _))
+    }
+
+    /// Calls `visitor` on this expression and then on every sub-expression, outermost first.
+    pub fn visit<F: FnMut(&Expression)>(&self, visitor: &mut F) {
+        visitor(self);
+        if let ExpressionValue::Operator(_, operands) = &self.value {
+            for operand in operands {
+                operand.visit(visitor);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -264,7 +322,7 @@ impl<'a> Parser<'a> {
                     location,
                 ))
             },
-            |token| Ok(token),
+            Ok,
         )
     }
 
@@ -344,6 +402,19 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Reads a name which may be qualified by the namespace of an imported module, such as `easing::ease_out`,
+    /// leaving the parser at its last part.
+    pub fn expect_path(&mut self) -> Result<String, DesmosifyError> {
+        let mut path = self.expect_name()?;
+        while self.tokens.get(self.token_index + 1).is_some_and(|token| token.value == TokenValue::Symbol(Symbol::Colon2)) {
+            self.next();
+            self.next();
+            path.push_str("::");
+            path.push_str(&self.expect_name()?);
+        }
+        Ok(path)
+    }
+
     pub fn expect_string(&self) -> Result<String, DesmosifyError> {
         let token = self.token()?;
         if let TokenValue::String(name) = &token.value {
@@ -370,14 +441,7 @@ impl<'a> Parser<'a> {
     }
 
     fn get_operation(&self, expect_operand: bool) -> Option<Operation> {
-        let token = self.token().ok()?;
-        if let TokenValue::Symbol(symbol) = token.value {
-            Operation::from_symbol(symbol, expect_operand)
-        } else if let TokenValue::Keyword(keyword) = token.value {
-            Operation::from_keyword(keyword, expect_operand)
-        } else {
-            None
-        }
+        Operation::from_token_value(&self.token().ok()?.value, expect_operand)
     }
 
     fn wrap_top_operator_into_operand(&mut self, operators: &mut Vec<(Operation, usize)>, operands: &mut Vec<Expression>) -> Result<(), DesmosifyError> {
@@ -395,12 +459,12 @@ impl<'a> Parser<'a> {
             let child_operands: Vec<_> = operands
                 .splice((operands.len() - operand_count)..operands.len(), [])
                 .collect();
-            operands.push(Expression {
+            operands.push(higher_order::specialize(Expression {
                 data_type: DataType::Unknown,
-                start: child_operands.first().map_or(None, |first| first.start),
-                end: child_operands.last().map_or(None, |last| last.end),
+                start: child_operands.first().and_then(|first| first.start),
+                end: child_operands.last().and_then(|last| last.end),
                 value: ExpressionValue::Operator(operation, child_operands),
-            });
+            })?);
             Ok(())
         }
     }
@@ -425,8 +489,22 @@ impl<'a> Parser<'a> {
                         operands.push(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
                         if self.is_at_symbol(Symbol::Comma)? {
                             self.next();
-                            operands.push(self.parse_expression(&[Symbol::ParenRight], &[])?);
-                            operators.push((Operation::PointLiteral, 2));
+                            operands.push(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
+                            let mut component_count = 2;
+                            if self.is_at_symbol(Symbol::Comma)? {
+                                self.next();
+                                // A point split over several lines can end with a trailing comma
+                                if !self.is_at_symbol(Symbol::ParenRight)? {
+                                    // 3D point
+                                    operands.push(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
+                                    component_count = 3;
+                                    if self.is_at_symbol(Symbol::Comma)? {
+                                        self.next();
+                                    }
+                                }
+                            }
+                            self.expect_symbol(Symbol::ParenRight)?;
+                            operators.push((Operation::PointLiteral, component_count));
                             self.wrap_top_operator_into_operand(&mut operators, &mut operands)?;
                         }
                         self.next();
@@ -554,16 +632,19 @@ impl<'a> Parser<'a> {
                     Some(token.start),
                     Some(token.end),
                 ))?;
-                while operators.last().map_or(false, |(lhs, _)| lhs.precedes(operation)) {
+                // A prefix operator has no left operand to take from the operators before it, so `2^-x` is `2^(-x)`
+                // even though `-x^2` is `-(x^2)`
+                while !expect_operand && operators.last().is_some_and(|(lhs, _)| lhs.precedes(operation)) {
                     self.wrap_top_operator_into_operand(&mut operators, &mut operands)?;
                 }
 
                 if operation == Operation::ActionCall {
+                    let start = self.token()?.start;
                     self.next();
-                    let action_name = self.expect_name()?;
+                    let action_name = self.expect_path()?;
                     operands.push(Expression {
                         data_type: DataType::Action { name: action_name.clone() },
-                        start: Some(self.tokens[self.token_index - 1].start),
+                        start: Some(start),
                         end: Some(self.tokens[self.token_index].end),
                         value: ExpressionValue::Name(action_name),
                     });
@@ -581,7 +662,7 @@ impl<'a> Parser<'a> {
                         self.next();
                         operands.push(self.parse_expression(&[Symbol::SquareRight], &[])?);
                     }
-                    Operation::BuiltIn | Operation::Posate | Operation::Negate | Operation::Not => {
+                    Operation::BuiltIn | Operation::Posate | Operation::Negate | Operation::Not | Operation::BitwiseNot => {
                         operand_count = 1;
                     }
                     _ => {}
@@ -604,11 +685,17 @@ impl<'a> Parser<'a> {
                         Some(token.end),
                     ));
                 }
+                let start = token.start;
+                let value = if let TokenValue::Name(_) = token.value {
+                    ExpressionValue::Name(self.expect_path()?)
+                } else {
+                    ExpressionValue::from_token_value(&token.value).unwrap()
+                };
                 operands.push(Expression {
                     data_type: DataType::Unknown,
-                    start: Some(self.tokens[self.token_index].start),
+                    start: Some(start),
                     end: Some(self.tokens[self.token_index].end),
-                    value: ExpressionValue::from_token_value(&token.value).unwrap(),
+                    value,
                 });
                 expect_operand = false;
             }
@@ -636,12 +723,43 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the arguments of a call. A named argument, as in `speed: 2`, is kept as a `NamedArgument` operator with the
+    /// name and the value as its operands, which `semantics` matches up with the parameters of the callee.
     pub fn parse_call(&mut self) -> Result<Vec<Expression>, DesmosifyError> {
         self.expect_symbol(Symbol::ParenLeft)?;
         self.next();
         let mut arguments = Vec::new();
+        let mut has_named_arguments = false;
         while !self.is_at_symbol(Symbol::ParenRight)? {
-            arguments.push(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
+            let start = self.token()?.start;
+            let is_named = matches!(self.token()?.value, TokenValue::Name(_))
+                && self.tokens.get(self.token_index + 1).is_some_and(|token| token.value == TokenValue::Symbol(Symbol::Colon));
+            if is_named {
+                let name = Expression {
+                    data_type: DataType::Unknown,
+                    start: Some(start),
+                    end: Some(self.token()?.end),
+                    value: ExpressionValue::Name(self.expect_name()?),
+                };
+                self.next();
+                self.next();
+                let value = self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?;
+                arguments.push(higher_order::specialize(Expression {
+                    data_type: DataType::Unknown,
+                    start: Some(start),
+                    end: value.end,
+                    value: ExpressionValue::Operator(Operation::NamedArgument, vec![name, value]),
+                })?);
+                has_named_arguments = true;
+            } else if has_named_arguments {
+                return Err(DesmosifyError::new(
+                    String::from("positional arguments cannot follow named arguments"),
+                    Some(start),
+                    Some(self.token()?.end),
+                ));
+            } else {
+                arguments.push(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
+            }
             if self.is_at_symbol(Symbol::Comma)? {
                 self.next();
             }
@@ -649,6 +767,15 @@ impl<'a> Parser<'a> {
         Ok(arguments)
     }
 
+    /// Parses the arguments of a call which only takes positional arguments, such as a display attribute.
+    pub fn parse_positional_call(&mut self) -> Result<Vec<Expression>, DesmosifyError> {
+        let arguments = self.parse_call()?;
+        match arguments.iter().find(|argument| matches!(argument.value, ExpressionValue::Operator(Operation::NamedArgument, _))) {
+            Some(argument) => Err(DesmosifyError::new(semantics::message_named_argument(), argument.start, argument.end)),
+            None => Ok(arguments),
+        }
+    }
+
     pub fn parse_type(&mut self, end_symbols: &[Symbol], end_keywords: &[Keyword]) -> Result<DataType, DesmosifyError> {
         let is_list_type = if self.is_at_symbol(Symbol::SquareLeft)? {
             self.next();
@@ -659,7 +786,7 @@ impl<'a> Parser<'a> {
         let mut data_type = if self.is_at_symbol(Symbol::Question)? {
             DataType::Unknown
         } else {
-            DataType::from_name(&self.expect_name()?)
+            DataType::from_name(&self.expect_path()?)
         };
         self.next();
         if is_list_type {
@@ -681,16 +808,30 @@ impl<'a> Parser<'a> {
             self.next();
             while !self.is_at_symbol(Symbol::ParenRight)? {
                 let parameter_name = self.expect_name()?;
+                let name_token = self.token()?;
                 let mut parameter_type = DataType::Unknown;
+                let mut default = None;
                 self.next();
-                self.expect_one_of(&[Symbol::Colon, Symbol::Comma, Symbol::ParenRight], &[])?;
+                self.expect_one_of(&[Symbol::Colon, Symbol::Equal, Symbol::Comma, Symbol::ParenRight], &[])?;
                 if self.is_at_symbol(Symbol::Colon)? {
                     self.next();
-                    parameter_type = self.parse_type(&[Symbol::Comma, Symbol::ParenRight], &[])?;
+                    parameter_type = self.parse_type(&[Symbol::Equal, Symbol::Comma, Symbol::ParenRight], &[])?;
+                }
+                if self.is_at_symbol(Symbol::Equal)? {
+                    self.next();
+                    default = Some(self.parse_expression(&[Symbol::Comma, Symbol::ParenRight], &[])?);
+                } else if parameters.last().is_some_and(|parameter: &Parameter| parameter.default.is_some()) {
+                    // Otherwise the parameters before it could only be left out by naming every argument after them
+                    return Err(DesmosifyError::new(
+                        format!("parameter '{parameter_name}' needs a default value, as it follows a parameter with one"),
+                        Some(name_token.start),
+                        Some(name_token.end),
+                    ));
                 }
                 parameters.push(Parameter {
                     name: parameter_name,
                     data_type: parameter_type,
+                    default,
                 });
                 if self.is_at_symbol(Symbol::Comma)? {
                     self.next();
@@ -784,9 +925,21 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
     let mut definitions = Definitions::new();
 
     while parser.token_index < parser.tokens.len() {
-        let token = &parser.tokens[parser.token_index];
+        let is_exported = parser.is_at_keyword(Keyword::Pub)?;
+        if is_exported {
+            parser.next();
+            parser.expect_one_of(&[], &[
+                Keyword::Action,
+                Keyword::Const,
+                Keyword::Let,
+                Keyword::Var,
+                Keyword::Enum,
+            ])?;
+        }
+        let token = parser.token()?;
         parser.expect_one_of(&[
             Symbol::Semicolon,
+            Symbol::Hash,
         ], &[
             Keyword::Public,
             Keyword::Ticker,
@@ -796,10 +949,86 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
             Keyword::Let,
             Keyword::Var,
             Keyword::Enum,
+            Keyword::Section,
+            Keyword::Import,
+            Keyword::Pub,
         ])?;
 
         match token.value {
             TokenValue::Symbol(Symbol::Semicolon) => {},
+            TokenValue::Keyword(Keyword::Import) => {
+                parser.next();
+                let path_token = parser.token()?;
+                let (path, namespace) = match &path_token.value {
+                    TokenValue::String(path) => {
+                        let stem = std::path::Path::new(path).file_stem().unwrap_or_default().to_string_lossy();
+                        (path.clone(), String::from(stem))
+                    },
+                    TokenValue::Name(name) => (format!("{name}.desmos"), name.clone()),
+                    _ => return Err(DesmosifyError::new(
+                        String::from("expected a module name or a path"),
+                        Some(path_token.start),
+                        Some(path_token.end),
+                    )),
+                };
+                let is_valid_namespace = namespace.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+                    && namespace.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+                    && Keyword::from_literal(&namespace).is_none();
+                if !is_valid_namespace {
+                    return Err(DesmosifyError::new(
+                        format!("'{namespace}' cannot be used as the name of a module"),
+                        Some(path_token.start),
+                        Some(path_token.end),
+                    ));
+                }
+                parser.next();
+                let mut names = Vec::new();
+                if let TokenValue::Name(_) = path_token.value {
+                    if parser.is_at_symbol(Symbol::Colon2)? {
+                        parser.next();
+                        parser.expect_symbol(Symbol::CurlyLeft)?;
+                        parser.next();
+                        while !parser.is_at_symbol(Symbol::CurlyRight)? {
+                            let name_token = parser.token()?;
+                            names.push((parser.expect_name()?, name_token.start, name_token.end));
+                            parser.next();
+                            parser.expect_one_of(&[Symbol::Comma, Symbol::CurlyRight], &[])?;
+                            if parser.is_at_symbol(Symbol::Comma)? {
+                                parser.next();
+                            }
+                        }
+                        parser.next();
+                    }
+                }
+                parser.expect_symbol(Symbol::Semicolon)?;
+                definitions.imports.push(Import {
+                    path,
+                    namespace,
+                    names,
+                    start: path_token.start,
+                    end: path_token.end,
+                });
+            },
+            TokenValue::Symbol(Symbol::Hash) => {
+                parser.next();
+                let (pragma_start, pragma_end) = (parser.token()?.start, parser.token()?.end);
+                match parser.expect_name()?.as_str() {
+                    "target" => {
+                        if definitions.target.is_some() {
+                            return Err(DesmosifyError::new(String::from("only one '#target' pragma can be declared"), Some(token.start), Some(pragma_end)));
+                        }
+                        parser.next();
+                        definitions.target = Some(parser.expect_string()?);
+                        parser.next();
+                        parser.expect_symbol(Symbol::Semicolon)?;
+                    },
+                    pragma => return Err(DesmosifyError::new(
+                        format!("unknown pragma '#{pragma}'"),
+                        Some(pragma_start),
+                        Some(pragma_end),
+                    )),
+                }
+            },
             TokenValue::Keyword(Keyword::Public) => {
                 if definitions.public.is_some() {
                     return Err(DesmosifyError::new(String::from("only one 'public' block can be declared"), Some(token.start), Some(token.end)));
@@ -846,6 +1075,13 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                 }
                 definitions.display = Some(display);
             },
+            TokenValue::Keyword(Keyword::Section) => {
+                parser.next();
+                let title = parser.expect_string()?;
+                parser.next();
+                parser.expect_symbol(Symbol::Semicolon)?;
+                definitions.sections.push(Section::new(Some(title)));
+            },
             TokenValue::Keyword(Keyword::Action) => {
                 parser.next();
                 let (name_start, name_end) = (parser.token()?.start, parser.token()?.end);
@@ -860,6 +1096,11 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                         Some(name_end),
                     ));
                 }
+                signatures.locations.insert(name.clone(), (name_start, name_end));
+                if is_exported {
+                    signatures.exported.insert(name.clone());
+                }
+                definitions.declare(&name);
                 definitions.actions.insert(name, content);
             },
             TokenValue::Keyword(Keyword::Const) => {
@@ -884,6 +1125,11 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                         Some(name_end),
                     ));
                 }
+                signatures.locations.insert(name.clone(), (name_start, name_end));
+                if is_exported {
+                    signatures.exported.insert(name.clone());
+                }
+                definitions.declare(&name);
                 definitions.identifiers.insert(name, value);
             },
             TokenValue::Keyword(Keyword::Let) => {
@@ -908,6 +1154,11 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                         Some(name_end),
                     ));
                 }
+                signatures.locations.insert(name.clone(), (name_start, name_end));
+                if is_exported {
+                    signatures.exported.insert(name.clone());
+                }
+                definitions.declare(&name);
                 definitions.identifiers.insert(name, value);
             },
             TokenValue::Keyword(Keyword::Var) => {
@@ -938,6 +1189,11 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                         Some(name_end),
                     ));
                 }
+                signatures.locations.insert(name.clone(), (name_start, name_end));
+                if is_exported {
+                    signatures.exported.insert(name.clone());
+                }
+                definitions.declare(&name);
                 definitions.identifiers.insert(name, value);
             },
             TokenValue::Keyword(Keyword::Enum) => {
@@ -948,7 +1204,9 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                 parser.expect_symbol(Symbol::CurlyLeft)?;
                 parser.next();
                 let mut variants = Vec::new();
+                let mut variant_locations = Vec::new();
                 while !parser.is_at_symbol(Symbol::CurlyRight)? {
+                    variant_locations.push((parser.token()?.start, parser.token()?.end));
                     variants.push(parser.expect_name()?);
                     parser.next();
                     // TODO: =
@@ -957,14 +1215,21 @@ pub fn parse(tokens: &[Token]) -> Result<(Signatures, Definitions), DesmosifyErr
                         parser.next();
                     }
                 }
+                for (variant, &location) in variants.iter().zip(&variant_locations) {
+                    signatures.locations.insert(format!("{name}.{variant}"), location);
+                }
                 let signature = Signature::Enum { name: name.clone(), variants };
-                if let Some(original) = signatures.user_defined.insert(name, signature) {
+                if let Some(original) = signatures.user_defined.insert(name.clone(), signature) {
                     return Err(DesmosifyError::new(
                         message_identifier_conflict(original),
                         Some(name_start),
                         Some(name_end),
                     ));
                 }
+                if is_exported {
+                    signatures.exported.insert(name.clone());
+                }
+                signatures.locations.insert(name, (name_start, name_end));
             }
             _ => unreachable!()
         }
@@ -0,0 +1,1025 @@
+use super::*;
+use super::evaluate::Evaluator;
+use super::latex::{parse_latex, GREEK_LETTERS};
+
+use crate::DesmosifyError;
+use crate::interpret::Value;
+use crate::syntax::Precedence;
+use crate::token::Keyword;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use json::JsonValue;
+
+const INDENT: &str = "    ";
+
+/// Piecewise definitions longer than this are written with one branch per line.
+const MAX_INLINE_WIDTH: usize = 80;
+
+enum Content {
+    Definition {
+        key: String,
+        parameters: Option<Vec<String>>,
+        body: SyntaxNode,
+    },
+    Expression(SyntaxNode),
+    Text(String),
+    Empty,
+    Unparsed(String),
+}
+
+struct ImportedEntry<'a> {
+    json: &'a JsonValue,
+    folder_id: Option<&'a str>,
+    content: Content,
+}
+
+impl ImportedEntry<'_> {
+    fn is_hidden(&self) -> bool {
+        self.json["hidden"].as_bool().unwrap_or(false)
+    }
+
+    fn is_styled(&self) -> bool {
+        [
+            "colorLatex", "pointSize", "pointOpacity", "pointStyle", "lineWidth", "lineOpacity", "lineStyle",
+            "fillOpacity", "showLabel", "clickableInfo", "parametricDomain",
+        ].iter().any(|&key| !self.json[key].is_null())
+    }
+}
+
+/// The name which identifies a definition in Desmos, e.g. `A_{reset}` for `A_{reset}`, or `x` for `x`.
+pub(super) fn name_key(node: &SyntaxNode) -> Option<String> {
+    match node {
+        SyntaxNode::Letter(letter) => Some(letter.to_string()),
+        SyntaxNode::Subscript(base, subscript) => match (&**base, &**subscript) {
+            (SyntaxNode::Letter(letter), SyntaxNode::Alphanumeric(subscript)) => Some(format!("{letter}_{subscript}")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The inverse of `name_key`.
+fn key_node(key: &str) -> SyntaxNode {
+    let mut chars = key.chars();
+    let letter = SyntaxNode::Letter(chars.next().unwrap_or('_'));
+    match chars.as_str().strip_prefix('_') {
+        Some(subscript) => SyntaxNode::Subscript(Box::new(letter), Box::new(SyntaxNode::Alphanumeric(String::from(subscript)))),
+        None => letter,
+    }
+}
+
+fn letter_name(letter: char) -> String {
+    GREEK_LETTERS.iter()
+        .find(|&&(_, greek)| greek == letter)
+        .map_or_else(|| letter.to_string(), |&(name, _)| name.to_lowercase())
+}
+
+/// Turns a Desmos name into a readable desmosify name, e.g. `N_{turnState}` into `turn_state` and `n_{0}` into `n_0`.
+fn readable_name(node: &SyntaxNode) -> String {
+    match node {
+        SyntaxNode::Subscript(base, subscript) => match (&**base, &**subscript) {
+            (SyntaxNode::Letter(letter), SyntaxNode::Alphanumeric(subscript)) if !subscript.starts_with(|ch: char| ch.is_ascii_alphabetic()) => {
+                format!("{}_{subscript}", letter_name(*letter))
+            },
+            (_, SyntaxNode::Alphanumeric(subscript)) => {
+                let mut name = String::new();
+                let mut previous = None;
+                for ch in subscript.chars() {
+                    if ch.is_uppercase() && previous.is_some_and(|previous: char| previous.is_lowercase() || previous.is_ascii_digit()) {
+                        name.push('_');
+                    }
+                    name.extend(ch.to_lowercase());
+                    previous = Some(ch);
+                }
+                name
+            },
+            _ => String::from("unknown"),
+        },
+        SyntaxNode::Letter(letter) => letter_name(*letter),
+        _ => String::from("unknown"),
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    Keyword::from_literal(name).is_some() || name == "true" || name == "false"
+}
+
+/// Letters which Desmos treats as built-in variables rather than definitions.
+fn builtin_letter(letter: char) -> Option<&'static str> {
+    Some(match letter {
+        'x' => "x",
+        'y' => "y",
+        'z' => "z",
+        't' => "t",
+        'u' => "u",
+        'v' => "v",
+        'e' => "e",
+        'π' => "pi",
+        _ => return None,
+    })
+}
+
+/// Splits `name = value` and `f(a, b) = value` into the name, parameters and value, or gives back the node if it
+/// isn't a definition. A list of actions, `A = a \to 1, b \to 2`, is parsed as a sequence starting with the definition.
+pub(super) fn split_definition(node: SyntaxNode) -> Result<(String, Option<Vec<String>>, SyntaxNode), SyntaxNode> {
+    fn definition_lhs(lhs: &SyntaxNode) -> Option<(String, Option<Vec<String>>)> {
+        match lhs {
+            SyntaxNode::Call(callee, arguments) => {
+                let key = name_key(callee)?;
+                let SyntaxNode::Sequence(arguments) = &**arguments else {
+                    return None;
+                };
+                let parameters = arguments.iter().map(name_key).collect::<Option<Vec<_>>>()?;
+                Some((key, Some(parameters)))
+            },
+            SyntaxNode::Letter(letter) if builtin_letter(*letter).is_some() || *letter == 'r' => None,
+            _ => Some((name_key(lhs)?, None)),
+        }
+    }
+
+    // `with` applies to everything before it, so `f(a) = b with b = a` is parsed as `(f(a) = b) with b = a`
+    fn hoist_equality(node: SyntaxNode) -> SyntaxNode {
+        match node {
+            SyntaxNode::With(body, bindings) => match hoist_equality(*body) {
+                SyntaxNode::Equality(lhs, body) => SyntaxNode::Equality(lhs, Box::new(SyntaxNode::With(body, bindings))),
+                body => SyntaxNode::With(Box::new(body), bindings),
+            },
+            SyntaxNode::Sequence(mut elements) if !elements.is_empty() => {
+                let first = hoist_equality(elements.remove(0));
+                elements.insert(0, first);
+                SyntaxNode::Sequence(elements)
+            },
+            node => node,
+        }
+    }
+
+    match hoist_equality(node) {
+        SyntaxNode::Equality(lhs, rhs) => match definition_lhs(&lhs) {
+            Some((key, parameters)) => Ok((key, parameters, *rhs)),
+            None => Err(SyntaxNode::Equality(lhs, rhs)),
+        },
+        SyntaxNode::Sequence(mut elements) => {
+            if let Some(SyntaxNode::Equality(lhs, _)) = elements.first() {
+                if let Some((key, parameters)) = definition_lhs(lhs) {
+                    let SyntaxNode::Equality(_, first) = elements.remove(0) else {
+                        unreachable!();
+                    };
+                    elements.insert(0, *first);
+                    return Ok((key, parameters, SyntaxNode::Sequence(elements)));
+                }
+            }
+            Err(SyntaxNode::Sequence(elements))
+        },
+        node => Err(node),
+    }
+}
+
+/// Recognizes `{c: 1, 0} = 1` and `{c} = 1`, which is how conditions are stored as numbers, and gives back `c`.
+fn boolean_condition<'n>(lhs: &'n SyntaxNode, rhs: &SyntaxNode) -> Option<&'n SyntaxNode> {
+    if !matches!(rhs, SyntaxNode::Decimal(value) if *value == 1.0) {
+        return None;
+    }
+    let SyntaxNode::Piecewise(content) = lhs else {
+        return None;
+    };
+    let (branch, default) = match &**content {
+        SyntaxNode::Sequence(branches) if branches.len() == 2 => (&branches[0], Some(&branches[1])),
+        branch => (branch, None),
+    };
+    let condition = match branch {
+        SyntaxNode::Colon(condition, value) if matches!(&**value, SyntaxNode::Decimal(value) if *value == 1.0) => condition,
+        condition if is_condition(condition) => condition,
+        _ => return None,
+    };
+    match default {
+        None => Some(condition),
+        Some(SyntaxNode::Decimal(value)) if *value == 0.0 => Some(condition),
+        Some(_) => None,
+    }
+}
+
+fn is_condition(node: &SyntaxNode) -> bool {
+    matches!(node, SyntaxNode::Equality(..) | SyntaxNode::Inequality(..) | SyntaxNode::InequalityChain(..))
+}
+
+/// Whether `node` is an action, given the names of the definitions known to be actions.
+fn is_action(node: &SyntaxNode, actions: &BTreeSet<String>) -> bool {
+    match node {
+        SyntaxNode::RightArrow(..) => true,
+        SyntaxNode::Paren(content) => is_action(content, actions),
+        SyntaxNode::Sequence(elements) => !elements.is_empty() && elements.iter().all(|element| is_action(element, actions)),
+        SyntaxNode::Piecewise(content) => match &**content {
+            SyntaxNode::Sequence(branches) => !branches.is_empty() && branches.iter().all(|branch| is_action_branch(branch, actions)),
+            branch => is_action_branch(branch, actions),
+        },
+        SyntaxNode::Call(callee, _) => name_key(callee).is_some_and(|key| actions.contains(&key)),
+        _ => name_key(node).is_some_and(|key| actions.contains(&key)),
+    }
+}
+
+fn is_action_branch(branch: &SyntaxNode, actions: &BTreeSet<String>) -> bool {
+    match branch {
+        SyntaxNode::Colon(_, value) => is_action(value, actions),
+        branch => is_action(branch, actions),
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{value}")
+    }
+}
+
+/// Whether `node` is a number, point or list written out in full, which is all a variable can be initialized with.
+fn is_literal(node: &SyntaxNode) -> bool {
+    match node {
+        SyntaxNode::Decimal(_) => true,
+        SyntaxNode::Neg(operand) | SyntaxNode::Paren(operand) | SyntaxNode::List(operand) => is_literal(operand),
+        SyntaxNode::Sequence(items) => items.iter().all(is_literal),
+        _ => false
+    }
+}
+
+/// Writes out `value` as a literal, if it is a number, point or list of them that the source can spell.
+fn literal(value: &Value) -> Option<String> {
+    let number = |value: f64| value.is_finite().then(|| format_number(value));
+    match value {
+        &Value::Number(value) => number(value),
+        &Value::Point(x, y) => Some(format!("({}, {})", number(x)?, number(y)?)),
+        &Value::Point3(x, y, z) => Some(format!("({}, {}, {})", number(x)?, number(y)?, number(z)?)),
+        Value::List(items) => Some(format!("[{}]", items.iter().map(literal).collect::<Option<Vec<_>>>()?.join(", "))),
+        _ => None
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn describe(node: &SyntaxNode) -> &'static str {
+    match node {
+        SyntaxNode::Equality(..) => "an equation",
+        SyntaxNode::Inequality(..) | SyntaxNode::InequalityChain(..) => "an inequality",
+        SyntaxNode::Regression(..) => "a regression",
+        SyntaxNode::CrossMul(..) => "a cross product",
+        SyntaxNode::Factorial(..) => "a factorial",
+        SyntaxNode::Prime(..) => "a derivative (')",
+        SyntaxNode::Derivative(..) => "a derivative",
+        SyntaxNode::Integral(..) => "an integral",
+        SyntaxNode::Sum(..) => "a sum",
+        SyntaxNode::Product(..) => "a product",
+        SyntaxNode::Colon(..) => "a piecewise branch",
+        SyntaxNode::Ellipsis(..) => "a range",
+        SyntaxNode::For(..) => "a list comprehension",
+        SyntaxNode::PercentOf(..) => "a percentage",
+        SyntaxNode::RightArrow(..) => "an action",
+        SyntaxNode::Sequence(..) => "a comma-separated list",
+        SyntaxNode::Decimal(_) => "an infinite number",
+        _ => "this expression",
+    }
+}
+
+/// Writes `SyntaxNode`s as desmosify source, resolving Desmos names to the names chosen for them.
+struct Printer<'a> {
+    names: &'a BTreeMap<String, String>,
+    functions: &'a BTreeSet<String>,
+    actions: &'a BTreeSet<String>,
+    locals: Vec<BTreeMap<String, String>>,
+}
+
+impl Printer<'_> {
+    fn scoped<T>(&mut self, bindings: BTreeMap<String, String>, print: impl FnOnce(&mut Self) -> T) -> T {
+        self.locals.push(bindings);
+        let result = print(self);
+        self.locals.pop();
+        result
+    }
+
+    fn name(&self, node: &SyntaxNode) -> Result<String, String> {
+        let key = name_key(node).ok_or_else(|| format!("{} is not a name", describe(node)))?;
+        if let Some(local) = self.locals.iter().rev().find_map(|scope| scope.get(&key)) {
+            return Ok(local.clone());
+        }
+        if let Some(name) = self.names.get(&key) {
+            return Ok(name.clone());
+        }
+        if let SyntaxNode::Letter(letter) = node {
+            if let Some(builtin) = builtin_letter(*letter) {
+                return Ok(format!("@{builtin}"));
+            }
+        }
+        Ok(readable_name(node))
+    }
+
+    fn is_function(&self, node: &SyntaxNode) -> bool {
+        name_key(node).is_some_and(|key| {
+            self.functions.contains(&key) && !self.locals.iter().any(|scope| scope.contains_key(&key))
+        })
+    }
+
+    fn is_action_name(&self, node: &SyntaxNode) -> bool {
+        name_key(node).is_some_and(|key| self.actions.contains(&key))
+    }
+
+    fn expression(&mut self, node: &SyntaxNode) -> Result<String, String> {
+        self.expression_with_precedence(node).map(|(text, _)| text)
+    }
+
+    /// Prints `node`, parenthesizing it if it binds more loosely than `precedence`.
+    fn operand(&mut self, node: &SyntaxNode, precedence: Precedence, strict: bool) -> Result<String, String> {
+        let (text, node_precedence) = self.expression_with_precedence(node)?;
+        if node_precedence < precedence || (strict && node_precedence == precedence) {
+            Ok(format!("({text})"))
+        } else {
+            Ok(text)
+        }
+    }
+
+    fn binary(&mut self, lhs: &SyntaxNode, operator: &str, rhs: &SyntaxNode, precedence: Precedence) -> Result<(String, Precedence), String> {
+        // The operand on the side which the operator doesn't group towards needs brackets at the same precedence
+        let left_to_right = precedence.is_left_to_right_associative();
+        let lhs = self.operand(lhs, precedence, !left_to_right)?;
+        let rhs = self.operand(rhs, precedence, left_to_right)?;
+        Ok((format!("{lhs} {operator} {rhs}"), precedence))
+    }
+
+    fn arguments(&mut self, arguments: &SyntaxNode) -> Result<String, String> {
+        match arguments {
+            SyntaxNode::Sequence(arguments) => arguments.iter()
+                .map(|argument| self.expression(argument))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|arguments| arguments.join(", ")),
+            argument => self.expression(argument),
+        }
+    }
+
+    fn expression_with_precedence(&mut self, node: &SyntaxNode) -> Result<(String, Precedence), String> {
+        Ok(match node {
+            SyntaxNode::Decimal(value) if value.is_finite() => (format_number(*value), Precedence::Container),
+            SyntaxNode::Letter(_) | SyntaxNode::Subscript(..) => (self.name(node)?, Precedence::Container),
+            SyntaxNode::Command(name) => (format!("@{name}"), Precedence::Access),
+            SyntaxNode::Paren(content) => match &**content {
+                SyntaxNode::Sequence(components) if (2..=3).contains(&components.len()) => {
+                    (format!("({})", self.arguments(content)?), Precedence::Container)
+                },
+                SyntaxNode::Sequence(_) => return Err(String::from("points must have 2 or 3 components")),
+                content => self.expression_with_precedence(content)?,
+            },
+            SyntaxNode::List(content) => (self.list(content)?, Precedence::Container),
+            SyntaxNode::Piecewise(content) => (format!("{{{}}}", self.piecewise_branches(content)?.join(", ")), Precedence::Container),
+            SyntaxNode::Pipes(content) => (format!("@abs({})", self.expression(content)?), Precedence::Postfix),
+            SyntaxNode::Call(callee, arguments) => match &**callee {
+                SyntaxNode::Command(name) => (format!("@{name}({})", self.arguments(arguments)?), Precedence::Postfix),
+                _ if self.is_function(callee) || self.is_action_name(callee) => {
+                    (format!("{}({})", self.name(callee)?, self.arguments(arguments)?), Precedence::Postfix)
+                },
+                // Anything else followed by parentheses is multiplied with them
+                _ => match &**arguments {
+                    SyntaxNode::Sequence(arguments) if arguments.len() == 1 => (format!(
+                        "{} * ({})",
+                        self.operand(callee, Precedence::Multiplicative, false)?,
+                        self.expression(&arguments[0])?,
+                    ), Precedence::Multiplicative),
+                    arguments => (format!(
+                        "{} * ({})",
+                        self.operand(callee, Precedence::Multiplicative, false)?,
+                        self.arguments(arguments)?,
+                    ), Precedence::Multiplicative),
+                },
+            },
+            SyntaxNode::ImplicitCall(callee, argument) => {
+                let SyntaxNode::Command(name) = &**callee else {
+                    return Err(String::from("only commands can be applied without parentheses"));
+                };
+                (format!("@{name}({})", self.expression(argument)?), Precedence::Postfix)
+            },
+            SyntaxNode::Index(list, index) => if is_condition(index) {
+                (format!("[{} where {}]", self.expression(list)?, self.expression(index)?), Precedence::Container)
+            } else {
+                let index = match &**index {
+                    SyntaxNode::Sequence(_) | SyntaxNode::Ellipsis(..) => self.list(index)?,
+                    index => self.expression(index)?,
+                };
+                (format!("{}[{index}]", self.operand(list, Precedence::Postfix, false)?), Precedence::Postfix)
+            },
+            SyntaxNode::Dot(object, member) => match &**member {
+                SyntaxNode::Letter(component @ ('x' | 'y' | 'z')) => {
+                    (format!("{}.{component}", self.operand(object, Precedence::Access, false)?), Precedence::Access)
+                },
+                _ => return Err(String::from("only the .x, .y and .z members are supported")),
+            },
+            SyntaxNode::Add(lhs, rhs) => self.binary(lhs, "+", rhs, Precedence::Additive)?,
+            SyntaxNode::Sub(lhs, rhs) => self.binary(lhs, "-", rhs, Precedence::Additive)?,
+            SyntaxNode::Mul(lhs, rhs) | SyntaxNode::DotMul(lhs, rhs) | SyntaxNode::ImplicitMul(lhs, rhs) => {
+                self.binary(lhs, "*", rhs, Precedence::Multiplicative)?
+            },
+            SyntaxNode::Div(lhs, rhs) | SyntaxNode::Frac(lhs, rhs) => self.binary(lhs, "/", rhs, Precedence::Multiplicative)?,
+            SyntaxNode::Neg(value) => (format!("-{}", self.operand(value, Precedence::Prefix, false)?), Precedence::Prefix),
+            SyntaxNode::Pos(value) => (format!("+{}", self.operand(value, Precedence::Prefix, false)?), Precedence::Prefix),
+            SyntaxNode::Superscript(base, exponent) => self.binary(base, "^", exponent, Precedence::Exponent)?,
+            SyntaxNode::Sqrt(radicand) => (format!("@sqrt({})", self.expression(radicand)?), Precedence::Postfix),
+            SyntaxNode::NthRoot(index, radicand) => {
+                (format!("@nthroot({}, {})", self.expression(radicand)?, self.expression(index)?), Precedence::Postfix)
+            },
+            SyntaxNode::MixedNumber(whole, numerator, denominator) => {
+                (format!(
+                    "{} + {} / {}",
+                    self.operand(whole, Precedence::Additive, false)?,
+                    self.operand(numerator, Precedence::Multiplicative, false)?,
+                    self.operand(denominator, Precedence::Multiplicative, true)?,
+                ), Precedence::Additive)
+            },
+            SyntaxNode::Equality(lhs, rhs) => match boolean_condition(lhs, rhs) {
+                Some(condition) => self.expression_with_precedence(condition)?,
+                None => self.binary(lhs, "==", rhs, Precedence::Equality)?,
+            },
+            SyntaxNode::Inequality(lhs, inequality, rhs) => self.binary(lhs, Self::inequality(*inequality), rhs, Precedence::Comparison)?,
+            SyntaxNode::InequalityChain(lhs, inequality, rhs, chain) => {
+                let mut comparisons = vec![self.binary(lhs, Self::inequality(*inequality), rhs, Precedence::Comparison)?.0];
+                let mut previous = &**rhs;
+                for (inequality, value) in chain {
+                    comparisons.push(self.binary(previous, Self::inequality(*inequality), value, Precedence::Comparison)?.0);
+                    previous = value;
+                }
+                if comparisons.len() == 1 {
+                    (comparisons.pop().unwrap(), Precedence::Comparison)
+                } else {
+                    (comparisons.join(" && "), Precedence::Logical)
+                }
+            },
+            SyntaxNode::With(body, bindings) => self.with(body, bindings)?,
+            node => return Err(format!("{} is not supported", describe(node))),
+        })
+    }
+
+    fn inequality(inequality: InequalityType) -> &'static str {
+        match inequality {
+            InequalityType::Less => "<",
+            InequalityType::Greater => ">",
+            InequalityType::LessEqual => "<=",
+            InequalityType::GreaterEqual => ">=",
+        }
+    }
+
+    /// Splits `a = 1, b = 2` into local names, as bound by `for` and `with`.
+    fn bindings<'n>(&self, bindings: &'n SyntaxNode) -> Result<Vec<(String, String, &'n SyntaxNode)>, String> {
+        let bindings = match bindings {
+            SyntaxNode::Sequence(bindings) => bindings.iter().collect(),
+            binding => vec![binding],
+        };
+        bindings.into_iter()
+            .map(|binding| match binding {
+                SyntaxNode::Equality(name, value) => name_key(name)
+                    .map(|key| (key, readable_name(name), &**value))
+                    .ok_or_else(|| String::from("expected a name to bind")),
+                _ => Err(String::from("expected a binding such as 'n = 1'")),
+            })
+            .collect()
+    }
+
+    fn with(&mut self, body: &SyntaxNode, bindings: &SyntaxNode) -> Result<(String, Precedence), String> {
+        let bindings = self.bindings(bindings)?;
+        let mut values = Vec::new();
+        for (_, name, value) in &bindings {
+            values.push(format!("{name} = {}", self.operand(value, Precedence::Assignment, true)?));
+        }
+        let scope = BTreeMap::from_iter(bindings.into_iter().map(|(key, name, _)| (key, name)));
+        let mut text = self.scoped(scope, |printer| printer.operand(body, Precedence::With, true))?;
+        if values.is_empty() {
+            return Err(String::from("expected a binding after 'with'"));
+        }
+        // Each binding wraps the expression so far
+        for (index, value) in values.iter().enumerate() {
+            if index > 0 {
+                text = format!("({text})");
+            }
+            text = format!("{text} with {value}");
+        }
+        Ok((text, Precedence::With))
+    }
+
+    fn list(&mut self, content: &SyntaxNode) -> Result<String, String> {
+        Ok(match content {
+            SyntaxNode::Sequence(items) => {
+                let mut printed = Vec::new();
+                for (index, item) in items.iter().enumerate() {
+                    printed.push(match item {
+                        SyntaxNode::Ellipsis(start, Some(end)) if index + 1 == items.len() => {
+                            format!("{} ..= {}", self.expression(start)?, self.expression(end)?)
+                        },
+                        item => self.expression(item)?,
+                    });
+                }
+                format!("[{}]", printed.join(", "))
+            },
+            SyntaxNode::Ellipsis(start, Some(end)) => format!("[{} ..= {}]", self.expression(start)?, self.expression(end)?),
+            SyntaxNode::For(body, bindings) => {
+                let bindings = self.bindings(bindings)?;
+                let mut clauses = Vec::new();
+                for (_, name, list) in &bindings {
+                    clauses.push(format!("for {name} in {}", self.expression(list)?));
+                }
+                let scope = BTreeMap::from_iter(bindings.into_iter().map(|(key, name, _)| (key, name)));
+                let body = self.scoped(scope, |printer| printer.expression(body))?;
+                format!("[{body} {}]", clauses.join(" "))
+            },
+            item => format!("[{}]", self.expression(item)?),
+        })
+    }
+
+    fn piecewise_branches(&mut self, content: &SyntaxNode) -> Result<Vec<String>, String> {
+        let branches = match content {
+            SyntaxNode::Sequence(branches) if branches.is_empty() => return Err(String::from("empty piecewise")),
+            SyntaxNode::Sequence(branches) => branches.iter().collect(),
+            branch => vec![branch],
+        };
+        let mut printed = Vec::new();
+        for (index, branch) in branches.iter().enumerate() {
+            printed.push(match branch {
+                SyntaxNode::Colon(condition, value) => format!("{}: {}", self.expression(condition)?, self.expression(value)?),
+                condition if is_condition(condition) => format!("{}: 1", self.expression(condition)?),
+                default if index + 1 == branches.len() => self.expression(default)?,
+                _ => return Err(String::from("a piecewise default must be its last branch")),
+            });
+        }
+        Ok(printed)
+    }
+
+    /// Prints an expression shown on the graph, where `=` and `~` relate both sides rather than compare them.
+    fn relation(&mut self, node: &SyntaxNode) -> Result<String, String> {
+        match node {
+            SyntaxNode::Equality(lhs, rhs) => Ok(format!("{} = {}", self.expression(lhs)?, self.expression(rhs)?)),
+            SyntaxNode::Regression(lhs, rhs) => Ok(format!("{} ~ {}", self.expression(lhs)?, self.expression(rhs)?)),
+            node => self.expression(node),
+        }
+    }
+
+    /// Prints the statements of an action, one per line, each followed by a comma.
+    fn action(&mut self, node: &SyntaxNode, depth: usize, lines: &mut Vec<String>) -> Result<(), String> {
+        let indent = INDENT.repeat(depth);
+        match node {
+            SyntaxNode::Paren(content) => self.action(content, depth, lines)?,
+            SyntaxNode::Sequence(elements) => for element in elements {
+                self.action(element, depth, lines)?;
+            },
+            SyntaxNode::RightArrow(target, value) => {
+                lines.push(format!("{indent}{} := {},", self.name(target)?, self.expression(value)?));
+            },
+            SyntaxNode::Call(callee, arguments) if self.is_action_name(callee) => {
+                lines.push(format!("{indent}action {}({}),", self.name(callee)?, self.arguments(arguments)?));
+            },
+            node if self.is_action_name(node) => lines.push(format!("{indent}action {}(),", self.name(node)?)),
+            SyntaxNode::Piecewise(content) => {
+                let branches = match &**content {
+                    SyntaxNode::Sequence(branches) => branches.iter().collect(),
+                    branch => vec![branch],
+                };
+                for (index, &branch) in branches.iter().enumerate() {
+                    let (header, body) = match branch {
+                        SyntaxNode::Colon(condition, body) => {
+                            let keyword = if index == 0 { "if" } else { "} elif" };
+                            (format!("{keyword} {}: {{", self.expression(condition)?), &**body)
+                        },
+                        body if index > 0 && index + 1 == branches.len() && !is_condition(body) => (String::from("} else: {"), body),
+                        _ => return Err(String::from("conditional actions need a condition for each branch")),
+                    };
+                    lines.push(format!("{indent}{header}"));
+                    self.action(body, depth + 1, lines)?;
+                }
+                lines.push(format!("{indent}}},"));
+            },
+            node => return Err(format!("{} is not an action", describe(node))),
+        }
+        Ok(())
+    }
+}
+
+/// Turns a Desmos graph state, as saved by `bookmarklets/export_graph.js`, into desmosify source.
+///
+/// Definitions become `let`, `var` and `action` declarations, folders become sections, and styled expressions
+/// become display elements. Anything which can't be expressed in desmosify is kept as a comment.
+pub fn decompile(state: &JsonValue) -> Result<String, Vec<DesmosifyError>> {
+    let list = &state["expressions"]["list"];
+    if !list.is_array() {
+        return Err(vec![DesmosifyError::new(String::from("graph state has no expression list"), None, None)]);
+    }
+
+    let mut folders = Vec::new();
+    let mut entries = Vec::new();
+    for entry in list.members() {
+        let folder_id = entry["folderId"].as_str();
+        let content = match entry["type"].as_str() {
+            Some("folder") => {
+                // The secret folder holds geometry construction state, not anything from the source
+                if !entry["secret"].as_bool().unwrap_or(false) {
+                    folders.push((entry["id"].as_str().unwrap_or_default(), entry["title"].as_str().unwrap_or_default()));
+                }
+                continue;
+            },
+            Some("text") => Content::Text(String::from(entry["text"].as_str().unwrap_or_default())),
+            Some("expression") => match entry["latex"].as_str() {
+                None | Some("") => Content::Empty,
+                Some(latex) => match parse_latex(latex) {
+                    Ok(node) => match split_definition(node) {
+                        Ok((key, parameters, body)) => Content::Definition { key, parameters, body },
+                        Err(node) => Content::Expression(node),
+                    },
+                    Err(error) => Content::Unparsed(error.to_string()),
+                },
+            },
+            _ => continue,
+        };
+        entries.push(ImportedEntry { json: entry, folder_id, content });
+    }
+
+    let clickable = |entry: &ImportedEntry| -> Option<SyntaxNode> {
+        let info = &entry.json["clickableInfo"];
+        if info["enabled"].as_bool().unwrap_or(false) {
+            info["latex"].as_str().and_then(|latex| parse_latex(latex).ok())
+        } else {
+            None
+        }
+    };
+    let ticker = &state["expressions"]["ticker"];
+    let ticker_handler = ticker["handlerLatex"].as_str().and_then(|latex| parse_latex(latex).ok());
+
+    // Anything updated by an action is a variable
+    let mut updated = BTreeSet::new();
+    let mut collect_updates = |node: &SyntaxNode| node.visit(&mut |node| {
+        if let SyntaxNode::RightArrow(target, _) = node {
+            if let Some(key) = name_key(target) {
+                updated.insert(key);
+            }
+        }
+    });
+    for entry in &entries {
+        if let Content::Definition { body, .. } = &entry.content {
+            collect_updates(body);
+        }
+        if let Some(handler) = clickable(entry) {
+            collect_updates(&handler);
+        }
+    }
+    if let Some(handler) = &ticker_handler {
+        collect_updates(handler);
+    }
+
+    // Actions may consist only of calls to other actions, so keep looking until no more are found
+    let mut actions = BTreeSet::new();
+    loop {
+        let found = entries.iter()
+            .filter_map(|entry| match &entry.content {
+                Content::Definition { key, body, .. } if !actions.contains(key) && is_action(body, &actions) => Some(key.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            break;
+        }
+        actions.extend(found);
+    }
+
+    let mut names = BTreeMap::new();
+    let mut used_names = BTreeSet::new();
+    let mut functions = BTreeSet::new();
+    for entry in &entries {
+        let Content::Definition { key, parameters, .. } = &entry.content else {
+            continue;
+        };
+        if parameters.is_some() {
+            functions.insert(key.clone());
+        }
+        if names.contains_key(key) {
+            continue;
+        }
+        let node = key_node(key);
+        let readable = readable_name(&node);
+        let prefixed = format!("{}_{readable}", letter_name(key.chars().next().unwrap()));
+        let mut name = readable.clone();
+        let mut suffix = 1;
+        while is_reserved(&name) || used_names.contains(&name) {
+            name = if suffix == 1 { prefixed.clone() } else { format!("{prefixed}_{suffix}") };
+            suffix += 1;
+        }
+        used_names.insert(name.clone());
+        names.insert(key.clone(), name);
+    }
+
+    // Works out the values that variables start with, when they are not written as literals
+    let evaluator = Evaluator::new(state).ok();
+
+    let mut printer = Printer {
+        names: &names,
+        functions: &functions,
+        actions: &actions,
+        locals: Vec::new(),
+    };
+    let mut source = String::new();
+
+    let target = match state["graph"]["product"].as_str() {
+        Some("geometry-calculator") => "desmos-geometry",
+        Some("graphing-3d") => "desmos-graphing-3d",
+        _ => "desmos-graphing",
+    };
+    source.push_str(&format!("#target {};\n", quote(target)));
+
+    let mut public = Vec::new();
+    let mut display = Vec::new();
+    let mut declarations: BTreeMap<Option<&str>, Vec<String>> = BTreeMap::new();
+
+    for entry in &entries {
+        let latex = entry.json["latex"].as_str().unwrap_or_default();
+        let lines = declarations.entry(entry.folder_id).or_default();
+        match &entry.content {
+            Content::Empty => {},
+            Content::Text(text) => if entry.folder_id.is_none() {
+                public.push(format!("{};", quote(text)));
+            } else {
+                lines.extend(text.lines().map(|line| format!("// {line}")));
+            },
+            Content::Unparsed(message) => lines.push(format!("// could not import: {latex} ({message})")),
+            Content::Definition { key, parameters, body } => {
+                let name = &names[key];
+                let parameter_names = parameters.iter().flatten().map(|parameter| {
+                    (parameter.clone(), readable_name(&key_node(parameter)))
+                }).collect::<BTreeMap<_, _>>();
+                let parameter_list = parameters.as_ref().map(|parameters| {
+                    format!("({})", parameters.iter().map(|parameter| parameter_names[parameter].as_str()).collect::<Vec<_>>().join(", "))
+                });
+                let declaration = printer.scoped(parameter_names.clone(), |printer| -> Result<Vec<String>, String> {
+                    if actions.contains(key) {
+                        let mut declaration = vec![format!("action {name}{}", parameter_list.as_deref().unwrap_or("()")) + " {"];
+                        printer.action(body, 1, &mut declaration)?;
+                        declaration.push(String::from("}"));
+                        return Ok(declaration);
+                    }
+                    let keyword = if parameters.is_none() && updated.contains(key) {
+                        if entry.json["slider"]["isPlaying"].as_bool().unwrap_or(false) {
+                            "var timer"
+                        } else {
+                            "var"
+                        }
+                    } else {
+                        "let"
+                    };
+                    if keyword != "let" && !is_literal(body) {
+                        // Variables must start with a constant, so one computed by the graph is replaced by its value
+                        let initial_value = evaluator.as_ref()
+                            .and_then(|evaluator| evaluator.evaluate(body).ok())
+                            .and_then(|value| literal(&value));
+                        return Ok(match initial_value {
+                            Some(initial_value) => vec![format!("{keyword} {name} = {initial_value};")],
+                            None => vec![
+                                String::from("// TODO: actions update this, but its initial value could not be worked out"),
+                                format!("let {name} = {};", printer.expression(body)?),
+                            ],
+                        });
+                    }
+                    let head = format!("{keyword} {name}{} = ", parameter_list.as_deref().unwrap_or_default());
+                    let value = printer.expression(body)?;
+                    if let (SyntaxNode::Piecewise(content), true) = (body, head.len() + value.len() > MAX_INLINE_WIDTH) {
+                        let mut declaration = vec![format!("{head}{{")];
+                        declaration.extend(printer.piecewise_branches(content)?.into_iter().map(|branch| format!("{INDENT}{branch},")));
+                        declaration.push(String::from("};"));
+                        Ok(declaration)
+                    } else {
+                        Ok(vec![format!("{head}{value};")])
+                    }
+                });
+                match declaration {
+                    Ok(declaration) => lines.extend(declaration),
+                    Err(message) => lines.push(format!("// could not import: {latex} ({message})")),
+                }
+                if entry.folder_id.is_none() {
+                    public.push(if actions.contains(key) {
+                        format!("action {name}();")
+                    } else {
+                        format!("{name};")
+                    });
+                } else if !entry.is_hidden() && entry.is_styled() {
+                    match element(&mut printer, name.clone(), entry) {
+                        Ok(element) => display.push(element),
+                        Err(message) => display.push(format!("// could not import the style of {name} ({message})")),
+                    }
+                }
+            },
+            Content::Expression(node) => {
+                if entry.folder_id.is_none() && !entry.is_styled() {
+                    if entry.is_hidden() {
+                        continue;
+                    }
+                    public.push(match node {
+                        node if printer.is_action_name(node) => printer.name(node).map(|name| format!("action {name}();")),
+                        SyntaxNode::Call(callee, arguments) if printer.is_action_name(callee) => printer.name(callee)
+                            .and_then(|name| Ok(format!("action {name}({});", printer.arguments(arguments)?))),
+                        node => printer.relation(node).map(|text| format!("{text};")),
+                    }.unwrap_or_else(|message| format!("// could not import: {latex} ({message})")));
+                    continue;
+                }
+                if entry.is_hidden() && !entry.json["showLabel"].as_bool().unwrap_or(false) {
+                    lines.push(format!("// hidden expression not imported: {latex}"));
+                    continue;
+                }
+                let what = printer.relation(node);
+                match what.and_then(|what| element(&mut printer, what, entry)) {
+                    Ok(element) => display.push(element),
+                    Err(message) => display.push(format!("// could not import: {latex} ({message})")),
+                }
+            },
+        }
+    }
+
+    if !public.is_empty() {
+        source.push_str("\npublic {\n");
+        for line in &public {
+            source.push_str(&format!("{INDENT}{line}\n"));
+        }
+        source.push_str("}\n");
+    }
+
+    let write_lines = |source: &mut String, lines: &[String]| {
+        for line in lines {
+            source.push_str(line);
+            source.push('\n');
+        }
+    };
+    if let Some(lines) = declarations.get(&None).filter(|lines| !lines.is_empty()) {
+        source.push('\n');
+        write_lines(&mut source, lines);
+    }
+    for &(id, title) in &folders {
+        if let Some(lines) = declarations.get(&Some(id)).filter(|lines| !lines.is_empty()) {
+            source.push_str(&format!("\nsection {};\n\n", quote(title)));
+            write_lines(&mut source, lines);
+        }
+    }
+
+    if let Some(handler) = &ticker_handler {
+        let interval = ticker["minStepLatex"].as_str()
+            .map(|latex| parse_latex(latex).map_err(|error| error.to_string()).and_then(|node| printer.expression(&node)))
+            .transpose();
+        let mut lines = Vec::new();
+        match interval.and_then(|interval| printer.action(handler, 1, &mut lines).map(|_| interval)) {
+            Ok(interval) => {
+                let interval = interval.map(|interval| format!(" ({interval})")).unwrap_or_default();
+                source.push_str(&format!("\nticker{interval} {{\n"));
+                write_lines(&mut source, &lines);
+                source.push_str("}\n");
+            },
+            Err(message) => source.push_str(&format!("\n// could not import the ticker ({message})\n")),
+        }
+    }
+
+    if !display.is_empty() {
+        source.push_str("\ndisplay {\n");
+        for line in &display {
+            source.push_str(&format!("{INDENT}{line}\n"));
+        }
+        source.push_str("}\n");
+    }
+
+    // The lines above are only roughly laid out, so they are rewritten in the canonical style where possible
+    Ok(crate::format::format(&source).unwrap_or(source))
+}
+
+/// Converts the styling of an expression entry into a display element showing `what`.
+fn element(printer: &mut Printer, what: String, entry: &ImportedEntry) -> Result<String, String> {
+    let json = entry.json;
+    let mut latex = |key: &str| -> Result<Option<String>, String> {
+        match json[key].as_str() {
+            Some(latex) if !latex.is_empty() => parse_latex(latex)
+                .map_err(|error| error.to_string())
+                .and_then(|node| printer.expression(&node))
+                .map(Some),
+            _ => Ok(None),
+        }
+    };
+
+    let color = match latex("colorLatex")? {
+        Some(color) => color,
+        None => {
+            let hex = json["color"].as_str().unwrap_or("#000000").trim_start_matches('#');
+            let component = |index: usize| hex.get(index..index + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok()).unwrap_or(0);
+            format!("@rgb({}, {}, {})", component(0), component(2), component(4))
+        },
+    };
+
+    let hidden = entry.is_hidden();
+    let mut attributes = Vec::new();
+    let point_size = latex("pointSize")?;
+    let point_opacity = latex("pointOpacity")?;
+    let point_style = match json["pointStyle"].as_str() {
+        Some("OPEN") => Some("open"),
+        Some("CROSS") => Some("cross"),
+        _ => None,
+    };
+    if !hidden && json["points"].as_bool() != Some(false) && (point_size.is_some() || point_opacity.is_some() || point_style.is_some()) {
+        let mut arguments = vec![point_size.unwrap_or_else(|| String::from("9"))];
+        if point_opacity.is_some() || point_style.is_some() {
+            arguments.push(point_opacity.clone().unwrap_or_else(|| String::from("1")));
+        }
+        if let Some(style) = point_style {
+            arguments.push(quote(style));
+        }
+        attributes.push(format!("point({})", arguments.join(", ")));
+    }
+
+    let line_width = latex("lineWidth")?;
+    let line_opacity = latex("lineOpacity")?;
+    let line_style = match json["lineStyle"].as_str() {
+        Some("DASH") => Some("dashed"),
+        Some("DOT") => Some("dotted"),
+        _ => None,
+    };
+    if !hidden && json["lines"].as_bool() != Some(false) && (line_width.is_some() || line_opacity.is_some() || line_style.is_some()) {
+        let mut arguments = vec![line_width.unwrap_or_else(|| String::from("2.5"))];
+        if line_opacity.is_some() || line_style.is_some() {
+            arguments.push(line_opacity.unwrap_or_else(|| String::from("0.9")));
+        }
+        if let Some(style) = line_style {
+            arguments.push(quote(style));
+        }
+        attributes.push(format!("stroke({})", arguments.join(", ")));
+    }
+
+    let fill_opacity = latex("fillOpacity")?;
+    if !hidden && json["fill"].as_bool() != Some(false) && (fill_opacity.is_some() || json["fill"].as_bool() == Some(true)) {
+        attributes.push(format!("fill({})", fill_opacity.unwrap_or_else(|| String::from("0.4"))));
+    }
+
+    if json["showLabel"].as_bool().unwrap_or(false) {
+        // A label on a hidden point takes the point's opacity
+        let opacity = if hidden { point_opacity } else { None };
+        let orientation = json["labelOrientation"].as_str().filter(|&orientation| orientation != "default" && orientation != "center");
+        let mut arguments = vec![
+            quote(json["label"].as_str().unwrap_or_default()),
+            opacity.unwrap_or_else(|| String::from("1")),
+            latex("labelSize")?.unwrap_or_else(|| String::from("1")),
+            latex("labelAngle")?.unwrap_or_else(|| String::from("0")),
+            orientation.map(quote).unwrap_or_else(|| quote("center")),
+        ];
+        let defaults = ["", "1", "1", "0", "\"center\""];
+        while arguments.len() > 1 && arguments.last().map(String::as_str) == Some(defaults[arguments.len() - 1]) {
+            arguments.pop();
+        }
+        attributes.push(format!("label({})", arguments.join(", ")));
+    }
+
+    match json["dragMode"].as_str() {
+        Some("XY") => attributes.push(String::from("drag(\"xy\")")),
+        Some("X") => attributes.push(String::from("drag(\"x\")")),
+        Some("Y") => attributes.push(String::from("drag(\"y\")")),
+        _ => {},
+    }
+
+    let info = &json["clickableInfo"];
+    if let (Some(true), Some(handler)) = (info["enabled"].as_bool(), info["latex"].as_str()) {
+        let handler = parse_latex(handler).map_err(|error| error.to_string())?;
+        let mut lines = Vec::new();
+        printer.action(&handler, 0, &mut lines)?;
+        let statements = lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join(" ");
+        attributes.push(format!("click {{ {} }}", statements.trim_end_matches(',')));
+    }
+
+    if let Some(description) = json["description"].as_str() {
+        attributes.push(format!("description({})", quote(description)));
+    }
+
+    let mut domain = |key: &str| -> Result<Option<(String, String)>, String> {
+        let domain = &json[key];
+        match (domain["min"].as_str(), domain["max"].as_str()) {
+            (Some(min), Some(max)) => {
+                let mut bound = |latex: &str| parse_latex(latex).map_err(|error| error.to_string()).and_then(|node| printer.expression(&node));
+                Ok(Some((bound(min)?, bound(max)?)))
+            },
+            _ => Ok(None),
+        }
+    };
+    if let Some((min, max)) = domain("parametricDomain")? {
+        attributes.push(format!("domain({min}, {max})"));
+    }
+    if let (Some((u_min, u_max)), Some((v_min, v_max))) = (domain("parametricDomain3Du")?, domain("parametricDomain3Dv")?) {
+        attributes.push(format!("surface_domain({u_min}, {u_max}, {v_min}, {v_max})"));
+    }
+
+    let mut element = format!("{what}: {color}");
+    for attribute in attributes {
+        element.push_str(", ");
+        element.push_str(&attribute);
+    }
+    element.push(';');
+    Ok(element)
+}
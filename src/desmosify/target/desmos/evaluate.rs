@@ -0,0 +1,494 @@
+use super::*;
+use super::decompile::{name_key, split_definition};
+use super::latex::parse_latex;
+
+use crate::interpret::{self, Value};
+
+/// A definition in the graph, such as `X_{f}(X_{a}) = ...`, keyed by its `name_key`.
+#[derive(Debug)]
+struct Definition {
+    parameters: Option<Vec<String>>,
+    body: SyntaxNode,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Bindings {
+    locals: BTreeMap<String, Value>,
+    dt: Option<f64>,
+    index: Option<f64>,
+}
+
+/// Evaluates the graph state produced by the Desmos targets the way Desmos itself would, so that the output of code
+/// generation can be checked against `interpret::Interpreter` without a browser.
+///
+/// Only the subset of `SyntaxNode` which the targets emit is supported: numbers, points, lists (with broadcasting,
+/// ranges, filters and `for`), piecewise definitions, `with`, function calls, the commands available in the
+/// graph's calculator, and actions.
+pub struct Evaluator {
+    commands: &'static [&'static str],
+    definitions: BTreeMap<String, Definition>,
+    /// Values which actions have assigned, replacing the original definitions.
+    values: BTreeMap<String, Value>,
+    ticker: Option<SyntaxNode>,
+}
+
+impl Evaluator {
+    /// Reads the definitions and ticker from graph state, as produced by `Target::compile`.
+    pub fn new(state: &JsonValue) -> Result<Self, DesmosifyError> {
+        let commands = match state["graph"]["product"].as_str() {
+            Some("geometry-calculator") => GeometryTarget::VALID_COMMANDS,
+            Some("graphing-3d") => Graphing3DTarget::VALID_COMMANDS,
+            _ => GraphingTarget::VALID_COMMANDS,
+        };
+        let mut definitions = BTreeMap::new();
+        for entry in state["expressions"]["list"].members() {
+            let Some(latex) = entry["latex"].as_str().filter(|latex| !latex.is_empty()) else {
+                continue;
+            };
+            if let Ok((key, parameters, body)) = split_definition(parse_latex(latex)?) {
+                definitions.insert(key, Definition { parameters, body });
+            }
+        }
+        let ticker = state["expressions"]["ticker"]["handlerLatex"].as_str()
+            .map(parse_latex)
+            .transpose()?;
+        Ok(Self {
+            commands,
+            definitions,
+            values: BTreeMap::new(),
+            ticker,
+        })
+    }
+
+    /// Evaluates an expression written in Desmos LaTeX, such as `X_{score}`.
+    pub fn evaluate_latex(&self, latex: &str) -> Result<Value, DesmosifyError> {
+        self.evaluate(&parse_latex(latex)?)
+    }
+
+    pub fn evaluate(&self, node: &SyntaxNode) -> Result<Value, DesmosifyError> {
+        self.evaluate_in(node, &Bindings::default()).map_err(|message| DesmosifyError::new(message, None, None))
+    }
+
+    /// Runs an action written in Desmos LaTeX, such as `X_{reset}` or `X_{move}(3)`.
+    pub fn run_latex(&mut self, latex: &str) -> Result<(), DesmosifyError> {
+        self.run(&parse_latex(latex)?)
+    }
+
+    pub fn run(&mut self, node: &SyntaxNode) -> Result<(), DesmosifyError> {
+        self.run_in(node, &Bindings::default()).map_err(|message| DesmosifyError::new(message, None, None))
+    }
+
+    /// Runs the ticker's handler once with `dt` set to `dt_ms`.
+    pub fn tick(&mut self, dt_ms: f64) -> Result<(), DesmosifyError> {
+        let Some(ticker) = self.ticker.take() else {
+            return Ok(());
+        };
+        let bindings = Bindings {
+            dt: Some(dt_ms),
+            ..Default::default()
+        };
+        let result = self.run_in(&ticker, &bindings);
+        self.ticker = Some(ticker);
+        result.map_err(|message| DesmosifyError::new(message, None, None))
+    }
+
+    fn run_in(&mut self, node: &SyntaxNode, bindings: &Bindings) -> Result<(), String> {
+        let mut updates = Vec::new();
+        self.collect_updates(node, bindings, &mut updates)?;
+        for (position, (key, _)) in updates.iter().enumerate() {
+            if updates[..position].iter().any(|(other, _)| other == key) {
+                return Err(format!("'{key}' is updated more than once by the same action"));
+            }
+        }
+        self.values.extend(updates);
+        Ok(())
+    }
+
+    /// Collects the assignments made by an action, computing every value from the state before the action.
+    fn collect_updates(&self, node: &SyntaxNode, bindings: &Bindings, updates: &mut Vec<(String, Value)>) -> Result<(), String> {
+        match node {
+            SyntaxNode::RightArrow(target, value) => {
+                let key = name_key(target).ok_or_else(|| String::from("expected a variable to update"))?;
+                if !self.definitions.contains_key(&key) {
+                    return Err(format!("'{key}' is not defined"));
+                }
+                updates.push((key, self.evaluate_in(value, bindings)?));
+                Ok(())
+            },
+            SyntaxNode::Paren(content) => self.collect_updates(content, bindings, updates),
+            SyntaxNode::Sequence(actions) => {
+                for action in actions {
+                    self.collect_updates(action, bindings, updates)?;
+                }
+                Ok(())
+            },
+            SyntaxNode::Piecewise(content) => {
+                for piece in sequence_items(content) {
+                    match piece {
+                        SyntaxNode::Colon(condition, action) => match self.evaluate_condition(condition, bindings)? {
+                            Value::Number(1.0) => return self.collect_updates(action, bindings, updates),
+                            Value::Number(_) => {},
+                            _ => return Err(String::from("the conditions of an action must not be lists")),
+                        },
+                        action => return self.collect_updates(action, bindings, updates),
+                    }
+                }
+                Ok(())
+            },
+            SyntaxNode::Call(callee, arguments) => {
+                let key = name_key(callee).ok_or_else(|| String::from("expected the name of an action"))?;
+                let arguments = sequence_items(arguments).into_iter()
+                    .map(|argument| self.evaluate_in(argument, bindings))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (parameters, body) = self.function(&key, arguments.len())?;
+                let bindings = Bindings {
+                    locals: parameters.iter().cloned().zip(arguments).collect(),
+                    ..Default::default()
+                };
+                self.collect_updates(body, &bindings, updates)
+            },
+            // Actions without parameters are defined as `X_{name}() = ...`, but can also be run by name alone
+            _ => match name_key(node).and_then(|key| self.definitions.get(&key)) {
+                Some(Definition { parameters, body }) if parameters.as_ref().is_none_or(Vec::is_empty) => {
+                    self.collect_updates(body, &Bindings::default(), updates)
+                },
+                _ => Err(String::from("expected an action")),
+            },
+        }
+    }
+
+    fn function(&self, key: &str, argument_count: usize) -> Result<(&[String], &SyntaxNode), String> {
+        match self.definitions.get(key) {
+            Some(Definition { parameters: Some(parameters), body }) if parameters.len() == argument_count => Ok((parameters, body)),
+            Some(Definition { parameters: Some(parameters), .. }) => {
+                Err(format!("'{key}' expects {} argument(s), but was provided {argument_count}", parameters.len()))
+            },
+            _ => Err(format!("'{key}' is not a function")),
+        }
+    }
+
+    fn evaluate_in(&self, node: &SyntaxNode, bindings: &Bindings) -> Result<Value, String> {
+        match node {
+            &SyntaxNode::Decimal(value) => Ok(Value::Number(value)),
+            SyntaxNode::Letter('π') => Ok(Value::Number(std::f64::consts::PI)),
+            SyntaxNode::Letter('e') => Ok(Value::Number(std::f64::consts::E)),
+            SyntaxNode::Letter(_) | SyntaxNode::Subscript(..) => {
+                let key = name_key(node).ok_or_else(|| String::from("expected a name"))?;
+                self.name_value(&key, bindings)
+            },
+            SyntaxNode::Command(name) => match (name.as_str(), bindings) {
+                ("dt", Bindings { dt: Some(dt), .. }) => Ok(Value::Number(*dt)),
+                ("index", Bindings { index: Some(index), .. }) => Ok(Value::Number(*index)),
+                _ => Err(format!("'{name}' has no value here")),
+            },
+            SyntaxNode::Paren(content) => match &**content {
+                SyntaxNode::Sequence(components) => {
+                    let components = components.iter()
+                        .map(|component| self.evaluate_in(component, bindings))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    interpret::broadcast(&components, &|components| {
+                        let coordinates = components.iter()
+                            .map(|component| component.number().ok_or_else(|| format!("cannot use a {} as a coordinate", component.type_name())))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Value::from_coordinates(&coordinates).ok_or_else(|| String::from("points must have 2 or 3 coordinates"))
+                    })
+                },
+                content => self.evaluate_in(content, bindings),
+            },
+            SyntaxNode::Pos(operand) => self.evaluate_in(operand, bindings),
+            SyntaxNode::Neg(operand) => {
+                let operand = self.evaluate_in(operand, bindings)?;
+                interpret::map_value(&operand, &|value| match value.coordinates() {
+                    Some(coordinates) => Ok(Value::from_coordinates(&Vec::from_iter(coordinates.iter().map(|coordinate| -coordinate))).unwrap()),
+                    None => interpret::map_number(value, &|value| -value),
+                })
+            },
+            SyntaxNode::Add(lhs, rhs) => self.arithmetic(lhs, rhs, bindings, add),
+            SyntaxNode::Sub(lhs, rhs) => self.arithmetic(lhs, rhs, bindings, subtract),
+            SyntaxNode::Mul(lhs, rhs) | SyntaxNode::DotMul(lhs, rhs) => self.arithmetic(lhs, rhs, bindings, multiply),
+            SyntaxNode::Div(lhs, rhs) | SyntaxNode::Frac(lhs, rhs) => self.arithmetic(lhs, rhs, bindings, divide),
+            SyntaxNode::Superscript(base, exponent) => self.arithmetic(base, exponent, bindings, |values| {
+                interpret::numbers(values, |values| values[0].powf(values[1]))
+            }),
+            SyntaxNode::Call(callee, arguments) => {
+                let arguments = sequence_items(arguments).into_iter()
+                    .map(|argument| self.evaluate_in(argument, bindings))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let SyntaxNode::Command(name) = &**callee {
+                    return if self.commands.contains(&name.as_str()) {
+                        interpret::call_builtin(name, &arguments)
+                    } else {
+                        Err(format!("'{name}' is not available in this calculator"))
+                    };
+                }
+                let key = name_key(callee).ok_or_else(|| String::from("expected the name of a function"))?;
+                if let (Some(Definition { parameters: None, .. }), [argument]) = (self.definitions.get(&key), arguments.as_slice()) {
+                    // Desmos reads a variable followed by brackets as multiplication
+                    let value = self.name_value(&key, bindings)?;
+                    return interpret::broadcast(&[value, argument.clone()], &multiply);
+                }
+                let (parameters, body) = self.function(&key, arguments.len())?;
+                let bindings = Bindings {
+                    locals: parameters.iter().cloned().zip(arguments).collect(),
+                    ..Default::default()
+                };
+                self.evaluate_in(body, &bindings)
+            },
+            SyntaxNode::Index(list, index) => {
+                let list = self.evaluate_in(list, bindings)?;
+                if is_condition(index) {
+                    let Value::List(items) = list else {
+                        return Err(format!("cannot filter a {}", list.type_name()));
+                    };
+                    return Ok(Value::List(match self.evaluate_condition(index, bindings)? {
+                        Value::List(conditions) => items.into_iter().zip(conditions)
+                            .filter(|(_, condition)| condition.is_true())
+                            .map(|(item, _)| item)
+                            .collect(),
+                        condition if condition.is_true() => items,
+                        _ => Vec::new(),
+                    }));
+                }
+                interpret::index(&list, &self.evaluate_in(index, bindings)?)
+            },
+            SyntaxNode::List(content) => self.evaluate_list(content, bindings),
+            SyntaxNode::Piecewise(content) => self.evaluate_piecewise(content, bindings),
+            SyntaxNode::With(body, assignments) => {
+                let mut inner_bindings = bindings.clone();
+                for assignment in sequence_items(assignments) {
+                    let SyntaxNode::Equality(name, value) = assignment else {
+                        return Err(String::from("expected an assignment after 'with'"));
+                    };
+                    let key = name_key(name).ok_or_else(|| String::from("expected a name to assign"))?;
+                    inner_bindings.locals.insert(key, self.evaluate_in(value, bindings)?);
+                }
+                self.evaluate_in(body, &inner_bindings)
+            },
+            SyntaxNode::Dot(object, member) => {
+                let component = match **member {
+                    SyntaxNode::Letter('x') => 0,
+                    SyntaxNode::Letter('y') => 1,
+                    SyntaxNode::Letter('z') => 2,
+                    _ => return Err(String::from("unknown member")),
+                };
+                let object = self.evaluate_in(object, bindings)?;
+                interpret::map_value(&object, &|value| match value.coordinates() {
+                    Some(coordinates) if component < coordinates.len() => Ok(Value::Number(coordinates[component])),
+                    _ => Err(format!("cannot access a coordinate of a {}", value.type_name())),
+                })
+            },
+            SyntaxNode::Equality(..) | SyntaxNode::Inequality(..) | SyntaxNode::InequalityChain(..) => {
+                Err(String::from("a condition only has a value inside a piecewise definition"))
+            },
+            _ => Err(format!("the evaluator does not support {node:?}")),
+        }
+    }
+
+    fn name_value(&self, key: &str, bindings: &Bindings) -> Result<Value, String> {
+        if let Some(value) = bindings.locals.get(key).or_else(|| self.values.get(key)) {
+            return Ok(value.clone());
+        }
+        match self.definitions.get(key) {
+            Some(Definition { parameters: None, body }) => self.evaluate_in(body, &Bindings::default()),
+            Some(_) => Err(format!("'{key}' is a function, so it must be called")),
+            None => Err(format!("'{key}' is not defined")),
+        }
+    }
+
+    fn arithmetic(&self, lhs: &SyntaxNode, rhs: &SyntaxNode, bindings: &Bindings, function: fn(&[Value]) -> Result<Value, String>) -> Result<Value, String> {
+        let values = [self.evaluate_in(lhs, bindings)?, self.evaluate_in(rhs, bindings)?];
+        interpret::broadcast(&values, &function)
+    }
+
+    /// Evaluates `a=b`, `a<b` or `a<b<c` to 1 where it holds and 0 where it doesn't, broadcasting over lists.
+    fn evaluate_condition(&self, node: &SyntaxNode, bindings: &Bindings) -> Result<Value, String> {
+        let (first, chain) = match node {
+            SyntaxNode::Equality(lhs, rhs) => (lhs, vec![(None, &**rhs)]),
+            &SyntaxNode::Inequality(ref lhs, inequality, ref rhs) => (lhs, vec![(Some(inequality), &**rhs)]),
+            SyntaxNode::InequalityChain(lhs, inequality, rhs, rest) => {
+                let mut chain = vec![(Some(*inequality), &**rhs)];
+                chain.extend(rest.iter().map(|(inequality, operand)| (Some(*inequality), operand)));
+                (lhs, chain)
+            },
+            _ => return Err(String::from("expected a condition")),
+        };
+        let mut operands = vec![self.evaluate_in(first, bindings)?];
+        for (_, operand) in &chain {
+            operands.push(self.evaluate_in(operand, bindings)?);
+        }
+        let relations = Vec::from_iter(chain.iter().map(|&(inequality, _)| inequality));
+        interpret::broadcast(&operands, &|operands| interpret::numbers(operands, |operands| {
+            interpret::bool_number(operands.windows(2).zip(&relations).all(|(pair, relation)| match relation {
+                None => pair[0] == pair[1],
+                Some(InequalityType::Less) => pair[0] < pair[1],
+                Some(InequalityType::Greater) => pair[0] > pair[1],
+                Some(InequalityType::LessEqual) => pair[0] <= pair[1],
+                Some(InequalityType::GreaterEqual) => pair[0] >= pair[1],
+            }))
+        }))
+    }
+
+    /// A bare condition such as `{x<0}` has the value 1 where it holds, and the last piece may be a default value.
+    fn evaluate_piecewise(&self, content: &SyntaxNode, bindings: &Bindings) -> Result<Value, String> {
+        let pieces = sequence_items(content);
+        let mut conditions = Vec::new();
+        for (position, piece) in pieces.iter().enumerate() {
+            let condition = match piece {
+                SyntaxNode::Colon(condition, _) => condition,
+                piece if is_condition(piece) => *piece,
+                _ if position + 1 == pieces.len() => break,
+                _ => return Err(String::from("only the last piece of a piecewise definition can be a default")),
+            };
+            match self.evaluate_condition(condition, bindings)? {
+                condition @ Value::List(_) => {
+                    conditions.push(condition);
+                    return self.evaluate_list_piecewise(&pieces, conditions, bindings);
+                },
+                condition if condition.is_true() => return self.piece_value(piece, bindings),
+                condition => conditions.push(condition),
+            }
+        }
+        match pieces.last() {
+            Some(piece) if conditions.len() < pieces.len() => self.evaluate_in(piece, bindings),
+            _ => Ok(Value::Number(f64::NAN)),
+        }
+    }
+
+    /// Once any condition is a list, Desmos evaluates the whole piecewise definition once for every item.
+    fn evaluate_list_piecewise(&self, pieces: &[&SyntaxNode], mut conditions: Vec<Value>, bindings: &Bindings) -> Result<Value, String> {
+        let mut operands = Vec::new();
+        for (position, piece) in pieces.iter().enumerate() {
+            let condition = match piece {
+                SyntaxNode::Colon(condition, _) => Some(&**condition),
+                piece if is_condition(piece) => Some(*piece),
+                _ => None,
+            };
+            match condition {
+                Some(condition) => {
+                    let condition = if position < conditions.len() {
+                        std::mem::replace(&mut conditions[position], Value::Number(0.0))
+                    } else {
+                        self.evaluate_condition(condition, bindings)?
+                    };
+                    operands.push(condition);
+                    operands.push(self.piece_value(piece, bindings)?);
+                },
+                None => operands.push(self.evaluate_in(piece, bindings)?),
+            }
+        }
+        interpret::broadcast(&operands, &|operands| {
+            let mut pieces = operands.chunks_exact(2);
+            for piece in pieces.by_ref() {
+                if piece[0].is_true() {
+                    return Ok(piece[1].clone());
+                }
+            }
+            Ok(pieces.remainder().first().cloned().unwrap_or(Value::Number(f64::NAN)))
+        })
+    }
+
+    fn piece_value(&self, piece: &SyntaxNode, bindings: &Bindings) -> Result<Value, String> {
+        match piece {
+            SyntaxNode::Colon(_, value) => self.evaluate_in(value, bindings),
+            _ => Ok(Value::Number(1.0)),
+        }
+    }
+
+    fn evaluate_list(&self, content: &SyntaxNode, bindings: &Bindings) -> Result<Value, String> {
+        if let SyntaxNode::For(body, assignments) = content {
+            // The first variable varies fastest
+            let mut variables = Vec::new();
+            for assignment in sequence_items(assignments) {
+                let SyntaxNode::Equality(name, list) = assignment else {
+                    return Err(String::from("expected an assignment after 'for'"));
+                };
+                let key = name_key(name).ok_or_else(|| String::from("expected a name to assign"))?;
+                let Value::List(items) = self.evaluate_in(list, bindings)? else {
+                    return Err(String::from("expected a list to iterate over"));
+                };
+                variables.push((key, items));
+            }
+            let length = variables.iter().map(|(_, items)| items.len()).product::<usize>();
+            if interpret::MAX_LIST_LENGTH < length {
+                return Err(format!("lists can have at most {} items, but this has {length}", interpret::MAX_LIST_LENGTH));
+            }
+            let mut results = Vec::with_capacity(length);
+            for position in 0..length {
+                let mut inner_bindings = bindings.clone();
+                let mut remainder = position;
+                for (key, items) in &variables {
+                    inner_bindings.locals.insert(key.clone(), items[remainder % items.len()].clone());
+                    remainder /= items.len();
+                }
+                results.push(self.evaluate_in(body, &inner_bindings)?);
+            }
+            return Ok(Value::List(results));
+        }
+
+        let mut items = sequence_items(content);
+        if let Some(SyntaxNode::Ellipsis(start, Some(end))) = items.last() {
+            let mut bounds = items[..items.len() - 1].iter()
+                .map(|item| self.evaluate_in(item, bindings))
+                .collect::<Result<Vec<_>, _>>()?;
+            if 1 < bounds.len() {
+                return Err(String::from("a range can only have one item before its '...'"));
+            }
+            bounds.push(self.evaluate_in(start, bindings)?);
+            bounds.push(self.evaluate_in(end, bindings)?);
+            return interpret::range(&bounds, true);
+        }
+        items.retain(|item| !matches!(item, SyntaxNode::Sequence(empty) if empty.is_empty()));
+        let items = items.into_iter()
+            .map(|item| self.evaluate_in(item, bindings))
+            .collect::<Result<Vec<_>, _>>()?;
+        if items.iter().any(|item| matches!(item, Value::List(_))) {
+            return Err(String::from("Desmos does not support lists of lists"));
+        }
+        Ok(Value::List(items))
+    }
+}
+
+fn sequence_items(node: &SyntaxNode) -> Vec<&SyntaxNode> {
+    match node {
+        SyntaxNode::Sequence(items) => items.iter().collect(),
+        item => vec![item],
+    }
+}
+
+fn is_condition(node: &SyntaxNode) -> bool {
+    matches!(node, SyntaxNode::Equality(..) | SyntaxNode::Inequality(..) | SyntaxNode::InequalityChain(..))
+}
+
+fn add(values: &[Value]) -> Result<Value, String> {
+    match (values[0].coordinates(), values[1].coordinates()) {
+        (Some(lhs), Some(rhs)) if lhs.len() == rhs.len() => Ok(Value::from_coordinates(&Vec::from_iter(lhs.iter().zip(&rhs)
+            .map(|(lhs, rhs)| lhs + rhs))).unwrap()),
+        _ => interpret::numbers(values, |values| values[0] + values[1]),
+    }
+}
+
+fn subtract(values: &[Value]) -> Result<Value, String> {
+    match (values[0].coordinates(), values[1].coordinates()) {
+        (Some(lhs), Some(rhs)) if lhs.len() == rhs.len() => Ok(Value::from_coordinates(&Vec::from_iter(lhs.iter().zip(&rhs)
+            .map(|(lhs, rhs)| lhs - rhs))).unwrap()),
+        _ => interpret::numbers(values, |values| values[0] - values[1]),
+    }
+}
+
+fn multiply(values: &[Value]) -> Result<Value, String> {
+    match (&values[0], &values[1]) {
+        (point, &Value::Number(scale)) | (&Value::Number(scale), point) if point.coordinates().is_some() => Ok(
+            Value::from_coordinates(&Vec::from_iter(point.coordinates().unwrap().iter().map(|coordinate| coordinate * scale))).unwrap(),
+        ),
+        _ => interpret::numbers(values, |values| values[0] * values[1]),
+    }
+}
+
+fn divide(values: &[Value]) -> Result<Value, String> {
+    match (&values[0], &values[1]) {
+        (point, &Value::Number(scale)) if point.coordinates().is_some() => Ok(
+            Value::from_coordinates(&Vec::from_iter(point.coordinates().unwrap().iter().map(|coordinate| interpret::divide(*coordinate, scale)))).unwrap(),
+        ),
+        _ => interpret::numbers(values, |values| interpret::divide(values[0], values[1])),
+    }
+}
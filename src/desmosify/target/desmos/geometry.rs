@@ -0,0 +1,178 @@
+use super::*;
+
+use crate::{Definitions, DesmosifyError, Signatures};
+
+use json::JsonValue;
+
+#[derive(Default)]
+pub struct GeometryTarget {
+    pub layout: Layout,
+}
+
+impl GeometryTarget {
+    pub fn new(layout: Layout) -> Self {
+        Self { layout }
+    }
+
+    pub const VALID_COMMANDS: &'static [&'static str] = &[
+        // Trig Functions
+        "sin",
+        "cos",
+        "tan",
+        "csc",
+        "sec",
+        "cot",
+        // Inverse Trig Functions
+        "arcsin",
+        "arccos",
+        "arctan",
+        "arccsc",
+        "arcsec",
+        "arccot",
+        // Statistics
+        "mean",
+        "median",
+        "min",
+        "max",
+        "quartile",
+        "quantile",
+        "stdev",
+        "stdevp",
+        "var", // FIXME: this probably doesn't work lol
+        "mad",
+        "cov",
+        "covp",
+        "corr",
+        "spearman",
+        "stats",
+        "count",
+        "total",
+        // List Operations
+        "join",
+        "sort",
+        "shuffle",
+        "unique",
+        // Visualizations
+        "histogram",
+        "dotplot",
+        "boxplot",
+        // Distributions
+        "normaldist",
+        "tdist",
+        "poissondist",
+        "binomialdist",
+        "uniformdist",
+        "pdf",
+        "cdf",
+        "inversecdf",
+        "random",
+        // Statistical Tests
+        "ttest",
+        "tscore",
+        "ittest",
+        // Calculus
+        "exp",
+        "ln",
+        "log",
+        "log_base",
+        "derivative",
+        "integral",
+        "sum",
+        "product",
+        // Hyperbolic Trig Functions
+        "sinh",
+        "cosh",
+        "tanh",
+        "csch",
+        "sech",
+        "coth",
+        // Geometry Tools
+        "midpoint",
+        "intersection",
+        "segment",
+        "line",
+        "ray",
+        "vector",
+        "parallel",
+        "perpendicular",
+        "circle",
+        "arc",
+        "angle",
+        "directedangle",
+        "polygon",
+        "glider",
+        // Properties & Measurements
+        "distance",
+        "length",
+        "area",
+        "perimeter",
+        "vertices",
+        "angles",
+        "directedangles",
+        "segments",
+        "radius",
+        "center",
+        "coterminal",
+        "supplement",
+        "start",
+        "end",
+        // Transformations
+        "dilate",
+        "rotate",
+        "reflect",
+        "translate",
+        // Custom Colors
+        "rgb",
+        "hsv",
+        // Sound (Beta)
+        "tone",
+        // Number Theory
+        "lcm",
+        "gcd",
+        "mod",
+        "ceil",
+        "floor",
+        "round",
+        "sign",
+        "sqrt",
+        "cbrt",
+        "nthroot",
+        "nPr",
+        "nCr",
+    ];
+}
+
+impl DesmosTarget for GeometryTarget {
+    fn product(&self) -> &'static str {
+        "geometry-calculator"
+    }
+
+    fn valid_commands(&self) -> &'static [&'static str] {
+        Self::VALID_COMMANDS
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn initial_entries(&self) -> Vec<Box<dyn Entry>> {
+        vec![Box::new(FolderEntry {
+            id: "**dcg_geo_folder**".into(),
+            title: "geometry".into(),
+            collapsed: true,
+            secret: true,
+        })]
+    }
+}
+
+impl crate::target::Target for GeometryTarget {
+    type Output = JsonValue;
+
+    fn name(&self) -> &'static str {
+        "desmos-geometry"
+    }
+
+    fn compile(&self, definitions: &Definitions, signatures: &Signatures) -> Result<Self::Output, Vec<DesmosifyError>> {
+        self.compile_state(definitions, signatures).map(|state| state.to_json())
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+
+use crate::{Definitions, DesmosifyError, Signatures};
+
+use json::JsonValue;
+
+#[derive(Default)]
+pub struct Graphing3DTarget {
+    pub layout: Layout,
+}
+
+impl Graphing3DTarget {
+    pub fn new(layout: Layout) -> Self {
+        Self { layout }
+    }
+
+    pub const VALID_COMMANDS: &'static [&'static str] = &[
+        // Trig Functions
+        "sin",
+        "cos",
+        "tan",
+        "csc",
+        "sec",
+        "cot",
+        // Inverse Trig Functions
+        "arcsin",
+        "arccos",
+        "arctan",
+        "arccsc",
+        "arcsec",
+        "arccot",
+        // Statistics
+        "mean",
+        "median",
+        "min",
+        "max",
+        "quartile",
+        "quantile",
+        "stdev",
+        "stdevp",
+        "var", // FIXME: this probably doesn't work lol
+        "mad",
+        "cov",
+        "covp",
+        "corr",
+        "spearman",
+        "stats",
+        "count",
+        "total",
+        // List Operations
+        "join",
+        "sort",
+        "shuffle",
+        "unique",
+        // Visualizations
+        "histogram",
+        "dotplot",
+        "boxplot",
+        // Distributions
+        "normaldist",
+        "tdist",
+        "poissondist",
+        "binomialdist",
+        "uniformdist",
+        "pdf",
+        "cdf",
+        "inversecdf",
+        "random",
+        // Statistical Tests
+        "ttest",
+        "tscore",
+        "ittest",
+        // Calculus
+        "exp",
+        "ln",
+        "log",
+        "log_base",
+        "derivative",
+        "integral",
+        "sum",
+        "product",
+        // Hyperbolic Trig Functions
+        "sinh",
+        "cosh",
+        "tanh",
+        "csch",
+        "sech",
+        "coth",
+        // 3D
+        "segment",
+        "triangle",
+        "sphere",
+        "vector",
+        "length",
+        "distance",
+        "midpoint",
+        // Custom Colors
+        "rgb",
+        "hsv",
+        // Sound (Beta)
+        "tone",
+        // Number Theory
+        "lcm",
+        "gcd",
+        "mod",
+        "ceil",
+        "floor",
+        "round",
+        "sign",
+        "sqrt",
+        "cbrt",
+        "nthroot",
+        "nPr",
+        "nCr",
+    ];
+}
+
+impl DesmosTarget for Graphing3DTarget {
+    fn product(&self) -> &'static str {
+        "graphing-3d"
+    }
+
+    fn valid_commands(&self) -> &'static [&'static str] {
+        Self::VALID_COMMANDS
+    }
+
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn supports_3d(&self) -> bool {
+        true
+    }
+}
+
+impl crate::target::Target for Graphing3DTarget {
+    type Output = JsonValue;
+
+    fn name(&self) -> &'static str {
+        "desmos-graphing-3d"
+    }
+
+    fn compile(&self, definitions: &Definitions, signatures: &Signatures) -> Result<Self::Output, Vec<DesmosifyError>> {
+        self.compile_state(definitions, signatures).map(|state| state.to_json())
+    }
+}
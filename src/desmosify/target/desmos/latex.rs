@@ -0,0 +1,729 @@
+use super::*;
+
+use crate::DesmosifyError;
+
+/// Greek letters which Desmos writes as escapes, such as `\theta`.
+pub const GREEK_LETTERS: &[(&str, char)] = &[
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Delta", 'Δ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+    ("Omega", 'Ω'),
+];
+
+/// Commands which can be applied without parentheses, as in `\sin x`.
+const IMPLICIT_CALL_COMMANDS: &[&str] = &[
+    "sin", "cos", "tan", "csc", "sec", "cot",
+    "arcsin", "arccos", "arctan", "arccsc", "arcsec", "arccot",
+    "sinh", "cosh", "tanh", "csch", "sech", "coth",
+    "ln", "log", "exp",
+];
+
+/// Escapes which act as operators rather than values.
+const OPERATOR_ESCAPES: &[&str] = &[
+    "to", "cdot", "times", "sim", "lt", "gt", "le", "ge", "leq", "geq",
+];
+
+#[derive(Clone, PartialEq, Debug)]
+enum LatexToken {
+    Number(String),
+    Letter(char),
+    Escape(String),
+    OperatorName(String),
+    Left(BracketType),
+    Right(BracketType),
+    GroupLeft,
+    GroupRight,
+    Symbol(char),
+    Ellipsis,
+}
+
+fn latex_error(message: String) -> DesmosifyError {
+    DesmosifyError::new(format!("could not parse LaTeX: {message}"), None, None)
+}
+
+fn tokenize(source: &str) -> Result<Vec<LatexToken>, DesmosifyError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    let take_while = |index: &mut usize, predicate: &dyn Fn(char) -> bool| {
+        let start = *index;
+        while chars.get(*index).is_some_and(|&ch| predicate(ch)) {
+            *index += 1;
+        }
+        String::from_iter(&chars[start..*index])
+    };
+
+    while let Some(&ch) = chars.get(index) {
+        match ch {
+            ch if ch.is_whitespace() => index += 1,
+            '.' if chars.get(index + 1) == Some(&'.') && chars.get(index + 2) == Some(&'.') => {
+                tokens.push(LatexToken::Ellipsis);
+                index += 3;
+            },
+            '0'..='9' | '.' if ch != '.' || chars.get(index + 1).is_some_and(char::is_ascii_digit) => {
+                let mut number = take_while(&mut index, &|ch| ch.is_ascii_digit());
+                if chars.get(index) == Some(&'.') && chars.get(index + 1).is_some_and(char::is_ascii_digit) {
+                    index += 1;
+                    number.push('.');
+                    number.push_str(&take_while(&mut index, &|ch| ch.is_ascii_digit()));
+                }
+                tokens.push(LatexToken::Number(number));
+            },
+            '\\' => {
+                index += 1;
+                let Some(&next) = chars.get(index) else {
+                    return Err(latex_error(String::from("unexpected end of input after '\\'")));
+                };
+                if !next.is_ascii_alphabetic() {
+                    index += 1;
+                    match next {
+                        // Spacing
+                        ' ' | ',' | ':' | ';' | '!' => {},
+                        '{' => tokens.push(LatexToken::Left(BracketType::Curly)),
+                        '}' => tokens.push(LatexToken::Right(BracketType::Curly)),
+                        _ => tokens.push(LatexToken::Symbol(next)),
+                    }
+                    continue;
+                }
+                let name = take_while(&mut index, &|ch| ch.is_ascii_alphabetic());
+                match name.as_str() {
+                    "left" | "right" => {
+                        while chars.get(index).is_some_and(|ch| ch.is_whitespace()) {
+                            index += 1;
+                        }
+                        let bracket = match chars.get(index) {
+                            Some('(' | ')') => BracketType::Paren,
+                            Some('[' | ']') => BracketType::Square,
+                            Some('|') => BracketType::Pipe,
+                            Some('\\') if matches!(chars.get(index + 1), Some('{' | '}')) => {
+                                index += 1;
+                                BracketType::Curly
+                            },
+                            _ => return Err(latex_error(format!("expected a bracket after '\\{name}'"))),
+                        };
+                        index += 1;
+                        tokens.push(if name == "left" {
+                            LatexToken::Left(bracket)
+                        } else {
+                            LatexToken::Right(bracket)
+                        });
+                    },
+                    "operatorname" => {
+                        if chars.get(index) != Some(&'{') {
+                            return Err(latex_error(String::from("expected '{' after '\\operatorname'")));
+                        }
+                        index += 1;
+                        let content = take_while(&mut index, &|ch| ch != '}');
+                        if chars.get(index).is_none() {
+                            return Err(latex_error(String::from("unterminated '\\operatorname'")));
+                        }
+                        index += 1;
+                        tokens.push(LatexToken::OperatorName(content));
+                    },
+                    _ => tokens.push(LatexToken::Escape(name)),
+                }
+            },
+            '{' => {
+                tokens.push(LatexToken::GroupLeft);
+                index += 1;
+            },
+            '}' => {
+                tokens.push(LatexToken::GroupRight);
+                index += 1;
+            },
+            ch if ch.is_alphabetic() => {
+                tokens.push(LatexToken::Letter(ch));
+                index += 1;
+            },
+            _ => {
+                tokens.push(LatexToken::Symbol(ch));
+                index += 1;
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn describe(token: Option<&LatexToken>) -> String {
+    match token {
+        None => String::from("end of input"),
+        Some(LatexToken::Number(number)) => format!("'{number}'"),
+        Some(LatexToken::Letter(letter)) => format!("'{letter}'"),
+        Some(LatexToken::Escape(name)) => format!("'\\{name}'"),
+        Some(LatexToken::OperatorName(name)) => format!("'\\operatorname{{{name}}}'"),
+        Some(LatexToken::Left(bracket)) => format!("'\\left{}'", bracket.left()),
+        Some(LatexToken::Right(bracket)) => format!("'\\right{}'", bracket.right()),
+        Some(LatexToken::GroupLeft) => String::from("'{'"),
+        Some(LatexToken::GroupRight) => String::from("'}'"),
+        Some(LatexToken::Symbol(symbol)) => format!("'{symbol}'"),
+        Some(LatexToken::Ellipsis) => String::from("'...'"),
+    }
+}
+
+enum Comparison {
+    Equal,
+    Regression,
+    Inequality(InequalityType),
+}
+
+struct LatexParser {
+    tokens: Vec<LatexToken>,
+    index: usize,
+    /// How many integrals are being parsed, whose bodies end at the differential (`dx`).
+    integral_depth: usize,
+}
+
+impl LatexParser {
+    fn peek(&self) -> Option<&LatexToken> {
+        self.tokens.get(self.index)
+    }
+
+    fn peek_ahead(&self, offset: usize) -> Option<&LatexToken> {
+        self.tokens.get(self.index + offset)
+    }
+
+    fn next(&mut self) -> Option<LatexToken> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn is_at(&self, token: &LatexToken) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn is_at_escape(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(LatexToken::Escape(escape)) if escape == name)
+    }
+
+    fn is_at_operator_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(LatexToken::OperatorName(operator)) if operator == name)
+    }
+
+    fn unexpected(&self) -> DesmosifyError {
+        latex_error(format!("unexpected {}", describe(self.peek())))
+    }
+
+    fn expect(&mut self, token: LatexToken) -> Result<(), DesmosifyError> {
+        if self.is_at(&token) {
+            self.next();
+            Ok(())
+        } else {
+            Err(latex_error(format!("expected {}, found {}", describe(Some(&token)), describe(self.peek()))))
+        }
+    }
+
+    fn is_at_sequence_end(&self) -> bool {
+        matches!(self.peek(), None | Some(LatexToken::Right(_) | LatexToken::GroupRight))
+    }
+
+    fn is_at_differential(&self) -> bool {
+        self.integral_depth > 0
+            && self.is_at(&LatexToken::Letter('d'))
+            && matches!(self.peek_ahead(1), Some(LatexToken::Letter(_) | LatexToken::Escape(_)))
+    }
+
+    /// Whether the next token begins a value, which is implicitly multiplied with whatever precedes it.
+    fn is_at_factor(&self) -> bool {
+        if self.is_at_differential() {
+            return false;
+        }
+        match self.peek() {
+            Some(LatexToken::Number(_) | LatexToken::Letter(_) | LatexToken::Left(_) | LatexToken::GroupLeft) => true,
+            Some(LatexToken::Escape(name)) => !OPERATOR_ESCAPES.contains(&name.as_str()),
+            Some(LatexToken::OperatorName(name)) => !matches!(name.as_str(), "for" | "with" | "of"),
+            _ => false,
+        }
+    }
+
+    /// Parses comma-separated items. A single item without a comma is returned on its own.
+    fn parse_sequence(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let mut items = Vec::new();
+        let mut separated = false;
+        while !self.is_at_sequence_end() {
+            if self.is_at(&LatexToken::Ellipsis) {
+                // `a, ..., b` continues the item before it
+                self.next();
+                let Some(start) = items.pop() else {
+                    return Err(latex_error(String::from("'...' must follow a value")));
+                };
+                if self.is_at(&LatexToken::Symbol(',')) {
+                    self.next();
+                }
+                let end = if self.is_at_sequence_end() {
+                    None
+                } else {
+                    Some(Box::new(self.parse_item()?))
+                };
+                items.push(SyntaxNode::Ellipsis(Box::new(start), end));
+            } else {
+                let item = self.parse_item()?;
+                if self.is_at_operator_name("for") || self.is_at_operator_name("with") {
+                    let is_for = self.is_at_operator_name("for");
+                    self.next();
+                    let bindings = Box::new(self.parse_sequence()?);
+                    items.push(if is_for {
+                        SyntaxNode::For(Box::new(item), bindings)
+                    } else {
+                        SyntaxNode::With(Box::new(item), bindings)
+                    });
+                    break;
+                }
+                items.push(item);
+            }
+            if self.is_at(&LatexToken::Symbol(',')) {
+                self.next();
+                separated = true;
+            } else {
+                break;
+            }
+        }
+        if !self.is_at_sequence_end() {
+            return Err(self.unexpected());
+        }
+        Ok(if items.len() == 1 && !separated {
+            items.pop().unwrap()
+        } else {
+            SyntaxNode::Sequence(items)
+        })
+    }
+
+    fn parse_item(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let lhs = self.parse_relation()?;
+        if self.is_at(&LatexToken::Symbol(':')) {
+            self.next();
+            Ok(SyntaxNode::Colon(Box::new(lhs), Box::new(self.parse_relation()?)))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    /// `\to` binds more tightly than `=`, so `A = a \to 1` defines `A` as the action `a \to 1`.
+    fn parse_arrow(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let lhs = self.parse_range()?;
+        if self.is_at_escape("to") {
+            self.next();
+            Ok(SyntaxNode::RightArrow(Box::new(lhs), Box::new(self.parse_range()?)))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    fn comparison(&self) -> Option<Comparison> {
+        Some(match self.peek()? {
+            LatexToken::Symbol('=') => Comparison::Equal,
+            LatexToken::Symbol('~') => Comparison::Regression,
+            LatexToken::Symbol('<') => Comparison::Inequality(InequalityType::Less),
+            LatexToken::Symbol('>') => Comparison::Inequality(InequalityType::Greater),
+            LatexToken::Symbol('≤') => Comparison::Inequality(InequalityType::LessEqual),
+            LatexToken::Symbol('≥') => Comparison::Inequality(InequalityType::GreaterEqual),
+            LatexToken::Escape(name) => match name.as_str() {
+                "sim" => Comparison::Regression,
+                "lt" => Comparison::Inequality(InequalityType::Less),
+                "gt" => Comparison::Inequality(InequalityType::Greater),
+                "le" | "leq" => Comparison::Inequality(InequalityType::LessEqual),
+                "ge" | "geq" => Comparison::Inequality(InequalityType::GreaterEqual),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+
+    fn parse_relation(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let first = self.parse_arrow()?;
+        let mut comparisons = Vec::new();
+        while let Some(comparison) = self.comparison() {
+            self.next();
+            comparisons.push((comparison, self.parse_arrow()?));
+        }
+        let mut comparisons = comparisons.into_iter();
+        Ok(match comparisons.next() {
+            None => first,
+            Some((Comparison::Equal, rhs)) if comparisons.len() == 0 => SyntaxNode::Equality(Box::new(first), Box::new(rhs)),
+            Some((Comparison::Regression, rhs)) if comparisons.len() == 0 => SyntaxNode::Regression(Box::new(first), Box::new(rhs)),
+            Some((Comparison::Inequality(inequality), rhs)) if comparisons.len() == 0 => {
+                SyntaxNode::Inequality(Box::new(first), inequality, Box::new(rhs))
+            },
+            Some((Comparison::Inequality(inequality), rhs)) => {
+                let chain = comparisons
+                    .map(|(comparison, value)| match comparison {
+                        Comparison::Inequality(inequality) => Ok((inequality, value)),
+                        _ => Err(latex_error(String::from("only inequalities can be chained"))),
+                    })
+                    .collect::<Result<_, _>>()?;
+                SyntaxNode::InequalityChain(Box::new(first), inequality, Box::new(rhs), chain)
+            },
+            Some(_) => return Err(latex_error(String::from("only inequalities can be chained"))),
+        })
+    }
+
+    fn parse_range(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let start = self.parse_additive()?;
+        if !self.is_at(&LatexToken::Ellipsis) {
+            return Ok(start);
+        }
+        self.next();
+        if self.is_at(&LatexToken::Symbol(',')) {
+            self.next();
+        }
+        let end = if self.is_at_sequence_end() {
+            None
+        } else {
+            Some(Box::new(self.parse_additive()?))
+        };
+        Ok(SyntaxNode::Ellipsis(Box::new(start), end))
+    }
+
+    fn parse_additive(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let mut lhs = self.parse_multiplicative()?;
+        loop {
+            lhs = match self.peek() {
+                Some(LatexToken::Symbol('+')) => {
+                    self.next();
+                    SyntaxNode::Add(Box::new(lhs), Box::new(self.parse_multiplicative()?))
+                },
+                Some(LatexToken::Symbol('-')) => {
+                    self.next();
+                    SyntaxNode::Sub(Box::new(lhs), Box::new(self.parse_multiplicative()?))
+                },
+                _ => return Ok(lhs),
+            };
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        if self.is_at(&LatexToken::Symbol('-')) {
+            self.next();
+            return Ok(SyntaxNode::Neg(Box::new(self.parse_multiplicative()?)));
+        }
+        if self.is_at(&LatexToken::Symbol('+')) {
+            self.next();
+            return Ok(SyntaxNode::Pos(Box::new(self.parse_multiplicative()?)));
+        }
+        let mut lhs = self.parse_postfix()?;
+        loop {
+            lhs = match self.peek() {
+                Some(LatexToken::Escape(name)) if name == "cdot" => {
+                    self.next();
+                    SyntaxNode::DotMul(Box::new(lhs), Box::new(self.parse_postfix()?))
+                },
+                Some(LatexToken::Escape(name)) if name == "times" => {
+                    self.next();
+                    SyntaxNode::CrossMul(Box::new(lhs), Box::new(self.parse_postfix()?))
+                },
+                Some(LatexToken::Symbol('*')) => {
+                    self.next();
+                    SyntaxNode::Mul(Box::new(lhs), Box::new(self.parse_postfix()?))
+                },
+                Some(LatexToken::Symbol('/')) => {
+                    self.next();
+                    SyntaxNode::Div(Box::new(lhs), Box::new(self.parse_postfix()?))
+                },
+                Some(LatexToken::Symbol('%')) => {
+                    self.next();
+                    if !self.is_at_operator_name("of") {
+                        return Err(latex_error(String::from("expected '\\operatorname{of}' after '%'")));
+                    }
+                    self.next();
+                    SyntaxNode::PercentOf(Box::new(lhs), Box::new(self.parse_multiplicative()?))
+                },
+                _ if self.is_at_factor() => SyntaxNode::ImplicitMul(Box::new(lhs), Box::new(self.parse_postfix()?)),
+                _ => return Ok(lhs),
+            };
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let mut value = self.parse_atom()?;
+        if let SyntaxNode::Command(name) = &value {
+            if IMPLICIT_CALL_COMMANDS.contains(&name.as_str()) && self.is_at_factor() && !self.is_at(&LatexToken::Left(BracketType::Paren)) {
+                return Ok(SyntaxNode::ImplicitCall(Box::new(value), Box::new(self.parse_postfix()?)));
+            }
+        }
+        loop {
+            value = match self.peek() {
+                Some(LatexToken::Symbol('!')) => {
+                    self.next();
+                    SyntaxNode::Factorial(Box::new(value))
+                },
+                Some(LatexToken::Symbol('\'')) => {
+                    self.next();
+                    SyntaxNode::Prime(Box::new(value))
+                },
+                Some(LatexToken::Symbol('^')) => {
+                    self.next();
+                    SyntaxNode::Superscript(Box::new(value), Box::new(self.parse_script()?))
+                },
+                Some(LatexToken::Symbol('.')) if matches!(self.peek_ahead(1), Some(LatexToken::Letter(_) | LatexToken::OperatorName(_))) => {
+                    self.next();
+                    SyntaxNode::Dot(Box::new(value), Box::new(self.parse_atom()?))
+                },
+                Some(LatexToken::Left(BracketType::Paren)) if Self::is_callable(&value) => {
+                    self.next();
+                    let arguments = match self.parse_sequence()? {
+                        sequence @ SyntaxNode::Sequence(_) => sequence,
+                        argument => SyntaxNode::Sequence(vec![argument]),
+                    };
+                    self.expect(LatexToken::Right(BracketType::Paren))?;
+                    SyntaxNode::Call(Box::new(value), Box::new(arguments))
+                },
+                Some(LatexToken::Left(BracketType::Square)) if !matches!(value, SyntaxNode::Decimal(_)) => {
+                    self.next();
+                    let index = self.parse_sequence()?;
+                    self.expect(LatexToken::Right(BracketType::Square))?;
+                    SyntaxNode::Index(Box::new(value), Box::new(index))
+                },
+                _ => return Ok(value),
+            };
+        }
+    }
+
+    fn is_callable(value: &SyntaxNode) -> bool {
+        match value {
+            SyntaxNode::Letter(_) | SyntaxNode::Command(_) => true,
+            SyntaxNode::Subscript(base, _) | SyntaxNode::Prime(base) => Self::is_callable(base),
+            _ => false,
+        }
+    }
+
+    /// Parses the argument of `^`, `_`, `\frac` and so on, which is either a `{...}` group or a single character.
+    fn parse_script(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        match self.peek().cloned() {
+            Some(LatexToken::GroupLeft) => self.parse_group(),
+            Some(LatexToken::Number(number)) if number.len() > 1 => {
+                // Only the first digit belongs to the script
+                let (first, rest) = number.split_at(1);
+                self.tokens[self.index] = LatexToken::Number(String::from(rest));
+                Ok(SyntaxNode::Decimal(first.parse().map_err(|_| self.unexpected())?))
+            },
+            _ => self.parse_atom(),
+        }
+    }
+
+    fn parse_group(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        self.expect(LatexToken::GroupLeft)?;
+        let content = self.parse_sequence()?;
+        self.expect(LatexToken::GroupRight)?;
+        Ok(content)
+    }
+
+    fn parse_subscript(&mut self, base: SyntaxNode) -> Result<SyntaxNode, DesmosifyError> {
+        if !self.is_at(&LatexToken::Symbol('_')) {
+            return Ok(base);
+        }
+        self.next();
+        let mut subscript = String::new();
+        if self.is_at(&LatexToken::GroupLeft) {
+            self.next();
+            while !self.is_at(&LatexToken::GroupRight) {
+                match self.next() {
+                    Some(LatexToken::Letter(letter)) => subscript.push(letter),
+                    Some(LatexToken::Number(number)) => subscript.push_str(&number),
+                    _ => return Err(latex_error(String::from("subscripts may only contain letters and digits"))),
+                }
+            }
+            self.next();
+        } else {
+            match self.next() {
+                Some(LatexToken::Letter(letter)) => subscript.push(letter),
+                Some(LatexToken::Number(number)) => {
+                    let (first, rest) = number.split_at(1);
+                    subscript.push_str(first);
+                    if !rest.is_empty() {
+                        self.index -= 1;
+                        self.tokens[self.index] = LatexToken::Number(String::from(rest));
+                    }
+                },
+                _ => return Err(latex_error(String::from("expected a subscript after '_'"))),
+            }
+        }
+        Ok(SyntaxNode::Subscript(Box::new(base), Box::new(SyntaxNode::Alphanumeric(subscript))))
+    }
+
+    fn parse_bracketed(&mut self, bracket: BracketType) -> Result<SyntaxNode, DesmosifyError> {
+        let content = Box::new(self.parse_sequence()?);
+        self.expect(LatexToken::Right(bracket))?;
+        Ok(match bracket {
+            BracketType::Paren => SyntaxNode::Paren(content),
+            BracketType::Square => SyntaxNode::List(content),
+            BracketType::Curly => SyntaxNode::Piecewise(content),
+            BracketType::Pipe => SyntaxNode::Pipes(content),
+        })
+    }
+
+    /// Parses the lower and upper bounds of `\sum`, `\prod` and `\int`.
+    fn parse_bounds(&mut self) -> Result<(SyntaxNode, SyntaxNode), DesmosifyError> {
+        self.expect(LatexToken::Symbol('_'))?;
+        let lower = self.parse_script()?;
+        self.expect(LatexToken::Symbol('^'))?;
+        let upper = self.parse_script()?;
+        Ok((lower, upper))
+    }
+
+    fn parse_atom(&mut self) -> Result<SyntaxNode, DesmosifyError> {
+        let Some(token) = self.next() else {
+            return Err(latex_error(String::from("unexpected end of input")));
+        };
+        match token {
+            LatexToken::Number(number) => {
+                let value = number.parse().map_err(|_| latex_error(format!("invalid number '{number}'")))?;
+                if !number.contains('.') && self.is_at_escape("frac") {
+                    // `1\frac{1}{2}` is a mixed number, as long as the fraction is made of numbers
+                    let start = self.index;
+                    self.next();
+                    let numerator = self.parse_script()?;
+                    let denominator = self.parse_script()?;
+                    if matches!((&numerator, &denominator), (SyntaxNode::Decimal(_), SyntaxNode::Decimal(_))) {
+                        return Ok(SyntaxNode::MixedNumber(
+                            Box::new(SyntaxNode::Decimal(value)),
+                            Box::new(numerator),
+                            Box::new(denominator),
+                        ));
+                    }
+                    self.index = start;
+                }
+                Ok(SyntaxNode::Decimal(value))
+            },
+            LatexToken::Letter(letter) => self.parse_subscript(SyntaxNode::Letter(letter)),
+            LatexToken::Escape(name) => {
+                if let Some(&(_, letter)) = GREEK_LETTERS.iter().find(|&&(greek, _)| greek == name) {
+                    return self.parse_subscript(SyntaxNode::Letter(letter));
+                }
+                match name.as_str() {
+                    "frac" => {
+                        let numerator = self.parse_script()?;
+                        let denominator = self.parse_script()?;
+                        match (numerator, denominator) {
+                            (SyntaxNode::Letter('d'), SyntaxNode::ImplicitMul(d, differential)) if matches!(*d, SyntaxNode::Letter('d')) => {
+                                Ok(SyntaxNode::Derivative(differential, Box::new(self.parse_multiplicative()?)))
+                            },
+                            (numerator, denominator) => Ok(SyntaxNode::Frac(Box::new(numerator), Box::new(denominator))),
+                        }
+                    },
+                    "sqrt" => if self.is_at(&LatexToken::Symbol('[')) {
+                        self.next();
+                        let index = self.parse_sequence_until(']')?;
+                        Ok(SyntaxNode::NthRoot(Box::new(index), Box::new(self.parse_script()?)))
+                    } else {
+                        Ok(SyntaxNode::Sqrt(Box::new(self.parse_script()?)))
+                    },
+                    "infty" => Ok(SyntaxNode::Decimal(f64::INFINITY)),
+                    "sum" | "prod" => {
+                        let (lower, upper) = self.parse_bounds()?;
+                        let body = Box::new(self.parse_multiplicative()?);
+                        Ok(if name == "sum" {
+                            SyntaxNode::Sum(Box::new(lower), Box::new(upper), body)
+                        } else {
+                            SyntaxNode::Product(Box::new(lower), Box::new(upper), body)
+                        })
+                    },
+                    "int" => {
+                        let (lower, upper) = self.parse_bounds()?;
+                        self.integral_depth += 1;
+                        let body = if self.is_at_differential() {
+                            None
+                        } else {
+                            Some(Box::new(self.parse_additive()?))
+                        };
+                        self.integral_depth -= 1;
+                        if !self.is_at(&LatexToken::Letter('d')) {
+                            return Err(latex_error(String::from("expected a differential to end the integral")));
+                        }
+                        self.next();
+                        let differential = self.parse_atom()?;
+                        Ok(SyntaxNode::Integral(Box::new(differential), Box::new(lower), Box::new(upper), body))
+                    },
+                    _ if OPERATOR_ESCAPES.contains(&name.as_str()) => {
+                        self.index -= 1;
+                        Err(self.unexpected())
+                    },
+                    _ => Ok(SyntaxNode::Command(name)),
+                }
+            },
+            LatexToken::OperatorName(name) => if matches!(name.as_str(), "for" | "with" | "of") {
+                self.index -= 1;
+                Err(self.unexpected())
+            } else {
+                Ok(SyntaxNode::Command(name))
+            },
+            LatexToken::Left(bracket) => self.parse_bracketed(bracket),
+            LatexToken::GroupLeft => {
+                let content = self.parse_sequence()?;
+                self.expect(LatexToken::GroupRight)?;
+                Ok(content)
+            },
+            _ => {
+                self.index -= 1;
+                Err(self.unexpected())
+            },
+        }
+    }
+
+    /// Parses a sequence which ends at a plain symbol rather than a bracket, such as the index of `\sqrt[n]{x}`.
+    fn parse_sequence_until(&mut self, end: char) -> Result<SyntaxNode, DesmosifyError> {
+        let end_index = self.tokens[self.index..]
+            .iter()
+            .position(|token| *token == LatexToken::Symbol(end))
+            .map(|offset| self.index + offset)
+            .ok_or_else(|| latex_error(format!("expected '{end}'")))?;
+        let mut inner = LatexParser {
+            tokens: self.tokens[self.index..end_index].to_vec(),
+            index: 0,
+            integral_depth: 0,
+        };
+        let content = inner.parse_sequence()?;
+        if inner.peek().is_some() {
+            return Err(inner.unexpected());
+        }
+        self.index = end_index + 1;
+        Ok(content)
+    }
+}
+
+/// Parses an expression written in Desmos's dialect of LaTeX, as found in the `latex` of graph state entries.
+///
+/// This is the inverse of `SyntaxNode::to_latex`: see `SyntaxNode` for which trees read back unchanged.
+/// Brackets are kept as `Paren` nodes, and a single item is never wrapped in a `Sequence`, except for call arguments.
+pub fn parse_latex(source: &str) -> Result<SyntaxNode, DesmosifyError> {
+    let mut parser = LatexParser {
+        tokens: tokenize(source)?,
+        index: 0,
+        integral_depth: 0,
+    };
+    let node = parser.parse_sequence()?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    Ok(node)
+}
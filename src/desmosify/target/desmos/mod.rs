@@ -0,0 +1,1054 @@
+use crate::{Definitions, DesmosifyError, Signatures};
+use crate::target::{TargetInfo, TargetOptions, TargetRegistry};
+
+use std::collections::BTreeMap;
+use std::ops::Add;
+
+use json::JsonValue;
+
+pub mod decompile;
+pub mod evaluate;
+pub mod geometry;
+pub mod graphing;
+pub mod graphing_3d;
+pub mod latex;
+pub mod translate;
+
+pub use geometry::GeometryTarget;
+pub use graphing::GraphingTarget;
+pub use graphing_3d::Graphing3DTarget;
+pub use translate::DesmosTarget;
+
+/// Adds every Desmos target to `registry`.
+pub fn register_targets(registry: &mut TargetRegistry) {
+    registry.register(TargetInfo {
+        name: "desmos-geometry",
+        description: "Desmos Geometry (geometry-calculator)",
+        options: Layout::OPTIONS,
+        create: |options| Ok(Box::new(GeometryTarget::new(Layout::from_options(options)?))),
+    });
+    registry.register(TargetInfo {
+        name: "desmos-graphing",
+        description: "Desmos Graphing Calculator",
+        options: Layout::OPTIONS,
+        create: |options| Ok(Box::new(GraphingTarget::new(Layout::from_options(options)?))),
+    });
+    registry.register(TargetInfo {
+        name: "desmos-graphing-3d",
+        description: "Desmos 3D Calculator",
+        options: Layout::OPTIONS,
+        create: |options| Ok(Box::new(Graphing3DTarget::new(Layout::from_options(options)?))),
+    });
+}
+
+/// The commands (`@name(...)` built-ins) available in the Desmos target named `name`, if there is one.
+pub fn valid_commands(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "desmos-geometry" => Some(GeometryTarget::VALID_COMMANDS),
+        "desmos-graphing" => Some(GraphingTarget::VALID_COMMANDS),
+        "desmos-graphing-3d" => Some(Graphing3DTarget::VALID_COMMANDS),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+pub mod colors {
+    use super::Color;
+
+    pub const RED: Color = Color::new(0xC7, 0x44, 0x40);
+    pub const BLUE: Color = Color::new(0x2D, 0x70, 0xB3);
+    pub const GREEN: Color = Color::new(0x38, 0x8C, 0x46);
+    pub const PURPLE: Color = Color::new(0x60, 0x42, 0xA6);
+    pub const ORANGE: Color = Color::new(0xFA, 0x7E, 0x19);
+    pub const BLACK: Color = Color::new(0x00, 0x00, 0x00);
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> JsonValue;
+}
+
+pub trait Entry: ToJson + std::fmt::Debug {
+    fn type_name(&self) -> &str;
+    fn id(&self) -> &str;
+}
+
+#[derive(Debug)]
+pub struct FolderEntry {
+    pub id: String,
+    pub title: String,
+    pub collapsed: bool,
+    pub secret: bool,
+}
+
+impl ToJson for FolderEntry {
+    fn to_json(&self) -> JsonValue {
+        let mut object = json::object!{
+            "type": self.type_name(),
+            "id": self.id(),
+            "title": self.title.as_str(),
+            "collapsed": self.collapsed,
+        };
+        if self.secret {
+            object["secret"] = true.into();
+        }
+        object
+    }
+}
+
+impl Entry for FolderEntry {
+    fn type_name(&self) -> &str {
+        "folder"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Styling and interaction settings of an expression, as set by the attributes of a display element.
+#[derive(Debug, Default)]
+pub struct ExpressionStyle {
+    pub color: Option<Box<SyntaxNode>>,
+    pub points: Option<bool>,
+    pub point_size: Option<Box<SyntaxNode>>,
+    pub point_opacity: Option<Box<SyntaxNode>>,
+    pub point_style: Option<&'static str>,
+    pub lines: Option<bool>,
+    pub line_width: Option<Box<SyntaxNode>>,
+    pub line_opacity: Option<Box<SyntaxNode>>,
+    pub line_style: Option<&'static str>,
+    pub fill: Option<bool>,
+    pub fill_opacity: Option<Box<SyntaxNode>>,
+    pub label: Option<String>,
+    pub label_size: Option<Box<SyntaxNode>>,
+    pub label_angle: Option<Box<SyntaxNode>>,
+    pub label_orientation: Option<&'static str>,
+    pub drag_mode: Option<&'static str>,
+    pub click_handler: Option<Box<SyntaxNode>>,
+    pub description: Option<String>,
+    pub parametric_domain: Option<(Box<SyntaxNode>, Box<SyntaxNode>)>,
+    pub parametric_domain_3d_u: Option<(Box<SyntaxNode>, Box<SyntaxNode>)>,
+    pub parametric_domain_3d_v: Option<(Box<SyntaxNode>, Box<SyntaxNode>)>,
+}
+
+impl ExpressionStyle {
+    fn write_json(&self, object: &mut JsonValue) {
+        if let Some(color) = &self.color {
+            object["color"] = colors::BLACK.to_string().into();
+            object["colorLatex"] = color.to_latex().to_string().into();
+        }
+        if let Some(points) = self.points {
+            object["points"] = points.into();
+        }
+        if let Some(point_size) = &self.point_size {
+            object["pointSize"] = point_size.to_latex().to_string().into();
+        }
+        if let Some(point_opacity) = &self.point_opacity {
+            object["pointOpacity"] = point_opacity.to_latex().to_string().into();
+        }
+        if let Some(point_style) = self.point_style {
+            object["pointStyle"] = point_style.into();
+        }
+        if let Some(lines) = self.lines {
+            object["lines"] = lines.into();
+        }
+        if let Some(line_width) = &self.line_width {
+            object["lineWidth"] = line_width.to_latex().to_string().into();
+        }
+        if let Some(line_opacity) = &self.line_opacity {
+            object["lineOpacity"] = line_opacity.to_latex().to_string().into();
+        }
+        if let Some(line_style) = self.line_style {
+            object["lineStyle"] = line_style.into();
+        }
+        if let Some(fill) = self.fill {
+            object["fill"] = fill.into();
+        }
+        if let Some(fill_opacity) = &self.fill_opacity {
+            object["fillOpacity"] = fill_opacity.to_latex().to_string().into();
+        }
+        if let Some(label) = &self.label {
+            object["showLabel"] = true.into();
+            object["label"] = label.as_str().into();
+        }
+        if let Some(label_size) = &self.label_size {
+            object["labelSize"] = label_size.to_latex().to_string().into();
+        }
+        if let Some(label_angle) = &self.label_angle {
+            object["labelAngle"] = label_angle.to_latex().to_string().into();
+        }
+        if let Some(label_orientation) = self.label_orientation {
+            object["labelOrientation"] = label_orientation.into();
+        }
+        if let Some(drag_mode) = self.drag_mode {
+            object["dragMode"] = drag_mode.into();
+        }
+        if let Some(click_handler) = &self.click_handler {
+            object["clickableInfo"] = json::object!{
+                "enabled": true,
+                "latex": click_handler.to_latex().to_string(),
+            };
+        }
+        if let Some(description) = &self.description {
+            object["description"] = description.as_str().into();
+        }
+        if let Some((min, max)) = &self.parametric_domain {
+            object["parametricDomain"] = json::object!{
+                "min": min.to_latex().to_string(),
+                "max": max.to_latex().to_string(),
+            };
+        }
+        if let Some((min, max)) = &self.parametric_domain_3d_u {
+            object["parametricDomain3Du"] = json::object!{
+                "min": min.to_latex().to_string(),
+                "max": max.to_latex().to_string(),
+            };
+        }
+        if let Some((min, max)) = &self.parametric_domain_3d_v {
+            object["parametricDomain3Dv"] = json::object!{
+                "min": min.to_latex().to_string(),
+                "max": max.to_latex().to_string(),
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpressionEntry {
+    pub id: String,
+    pub folder_id: Option<String>,
+    pub content: Option<Box<SyntaxNode>>,
+    pub hidden: bool,
+    pub style: ExpressionStyle,
+}
+
+impl ToJson for ExpressionEntry {
+    fn to_json(&self) -> JsonValue {
+        let mut object = json::object!{
+            "type": self.type_name(),
+            "id": self.id(),
+        };
+        if let Some(folder_id) = &self.folder_id {
+            object["folderId"] = folder_id.as_str().into();
+        }
+        object["latex"] = self.content.as_ref().map_or_else(String::new, |content| content.to_latex().to_string()).into();
+        if self.hidden {
+            object["hidden"] = true.into();
+        }
+        self.style.write_json(&mut object);
+        object
+    }
+}
+
+impl Entry for ExpressionEntry {
+    fn type_name(&self) -> &str {
+        "expression"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Debug)]
+pub struct TextEntry {
+    pub id: String,
+    pub folder_id: Option<String>,
+    pub content: String,
+}
+
+impl ToJson for TextEntry {
+    fn to_json(&self) -> JsonValue {
+        let mut object = json::object!{
+            "type": self.type_name(),
+            "id": self.id(),
+            "text": self.content.as_str(),
+        };
+        if let Some(folder_id) = &self.folder_id {
+            object["folderId"] = folder_id.as_str().into();
+        }
+        object
+    }
+}
+
+impl Entry for TextEntry {
+    fn type_name(&self) -> &str {
+        "text"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Debug)]
+pub struct Ticker {
+    pub playing: bool,
+    pub handler: Option<Box<SyntaxNode>>,
+    pub min_step: Option<Box<SyntaxNode>>,
+}
+
+impl ToJson for Ticker {
+    fn to_json(&self) -> JsonValue {
+        let mut object = json::object!{
+            "open": true,
+            "playing": self.playing,
+        };
+        if let Some(handler) = &self.handler {
+            object["handlerLatex"] = handler.to_latex().to_string().into();
+        }
+        if let Some(min_step) = &self.min_step {
+            object["minStepLatex"] = min_step.to_latex().to_string().into();
+        }
+        object
+    }
+}
+
+#[derive(Debug)]
+pub struct Expressions {
+    pub list: Vec<Box<dyn Entry>>,
+    pub ticker: Option<Ticker>,
+}
+
+impl ToJson for Expressions {
+    fn to_json(&self) -> JsonValue {
+        let list = Vec::from_iter(self.list.iter().map(|entry| entry.to_json()));
+        let mut object = json::object!{
+            "list": list,
+        };
+        if let Some(ticker) = &self.ticker {
+            object["ticker"] = ticker.to_json();
+        }
+        object
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphSettings {
+    pub product: String,
+}
+
+impl ToJson for GraphSettings {
+    fn to_json(&self) -> JsonValue {
+        json::object!{
+            "product": self.product.as_str(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphState {
+    pub version: i32,
+    pub graph: GraphSettings,
+    pub expressions: Expressions,
+}
+
+impl ToJson for GraphState {
+    fn to_json(&self) -> JsonValue {
+        json::object!{
+            "version": self.version,
+            "graph": self.graph.to_json(),
+            "expressions": self.expressions.to_json(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum DefinitionOrder {
+    /// Every definition comes after the definitions it depends on, otherwise keeping source order.
+    #[default]
+    Dependency,
+    /// Definitions appear exactly in the order they were declared.
+    Source,
+}
+
+impl std::str::FromStr for DefinitionOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dependency" => Ok(Self::Dependency),
+            "source" => Ok(Self::Source),
+            _ => Err(String::from("expected 'dependency' or 'source'"))
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum Grouping {
+    /// One folder per `section`, with undivided parts of a file placed in a folder named after the file.
+    #[default]
+    Section,
+    /// One folder per source file.
+    File,
+    /// One folder for actions and one for all other definitions.
+    Kind,
+}
+
+impl std::str::FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "section" => Ok(Self::Section),
+            "file" => Ok(Self::File),
+            "kind" => Ok(Self::Kind),
+            _ => Err(String::from("expected 'section', 'file', or 'kind'"))
+        }
+    }
+}
+
+/// A folder of definitions in the expression list, as decided by a [`Layout`].
+#[derive(Debug)]
+pub struct DefinitionFolder {
+    pub id: String,
+    pub title: String,
+    pub names: Vec<String>,
+}
+
+/// Controls how definitions are ordered and grouped into folders in the expression list.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Layout {
+    pub order: DefinitionOrder,
+    pub grouping: Grouping,
+}
+
+impl Layout {
+    /// The target options which configure the layout, shared by every Desmos target.
+    pub const OPTIONS: &'static [(&'static str, &'static str)] = &[
+        ("order", "how definitions are ordered: 'dependency' or 'source'"),
+        ("group", "how definitions are grouped into folders: 'section', 'file', or 'kind'"),
+    ];
+
+    pub fn from_options(options: &TargetOptions) -> Result<Self, DesmosifyError> {
+        let mut layout = Self::default();
+        if let Some(order) = options.get("order") {
+            layout.order = order.parse()
+                .map_err(|err| DesmosifyError::new(format!("invalid value for option 'order': {err}"), None, None))?;
+        }
+        if let Some(grouping) = options.get("group") {
+            layout.grouping = grouping.parse()
+                .map_err(|err| DesmosifyError::new(format!("invalid value for option 'group': {err}"), None, None))?;
+        }
+        Ok(layout)
+    }
+
+    fn ordered_names(&self, definitions: &Definitions, signatures: &Signatures) -> Result<Vec<String>, DesmosifyError> {
+        match self.order {
+            DefinitionOrder::Dependency => crate::dependencies::dependency_order(signatures, definitions),
+            DefinitionOrder::Source => {
+                let mut names = Vec::from_iter(definitions.source_order()
+                    .filter(|name| definitions.identifiers.contains_key(*name) || definitions.actions.contains_key(*name))
+                    .map(String::from));
+                for name in definitions.identifiers.keys().chain(definitions.actions.keys()) {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                Ok(names)
+            },
+        }
+    }
+
+    /// Splits all identifiers and actions into folders, each listing its definitions in the order they should appear.
+    /// Folders with nothing in them are left out.
+    pub fn folders(&self, definitions: &Definitions, signatures: &Signatures) -> Result<Vec<DefinitionFolder>, DesmosifyError> {
+        let names = self.ordered_names(definitions, signatures)?;
+
+        let mut folders = match self.grouping {
+            Grouping::Kind => vec![
+                DefinitionFolder {
+                    id: "desmosify:actions".into(),
+                    title: "Actions".into(),
+                    names: names.iter().filter(|name| definitions.actions.contains_key(*name)).cloned().collect(),
+                },
+                DefinitionFolder {
+                    id: "desmosify:defs".into(),
+                    title: "Definitions".into(),
+                    names: names.iter().filter(|name| definitions.identifiers.contains_key(*name)).cloned().collect(),
+                },
+            ],
+            Grouping::Section | Grouping::File => {
+                let mut folders: Vec<DefinitionFolder> = Vec::new();
+                let mut folder_indices = BTreeMap::new();
+                for section in &definitions.sections {
+                    let file_title = section.file_name.as_deref()
+                        .and_then(|file_name| std::path::Path::new(file_name).file_stem())
+                        .map_or_else(|| String::from("Definitions"), |stem| stem.to_string_lossy().into_owned());
+                    let title = match (self.grouping, &section.title) {
+                        (Grouping::Section, Some(title)) => title.clone(),
+                        _ => file_title,
+                    };
+                    // Sections sharing a title (such as the untitled parts of one file) share a folder
+                    let folder_index = match folders.iter().position(|folder| folder.title == title) {
+                        Some(index) => index,
+                        None => {
+                            folders.push(DefinitionFolder {
+                                id: format!("desmosify:folder:{}", folders.len()),
+                                title,
+                                names: Vec::new(),
+                            });
+                            folders.len() - 1
+                        },
+                    };
+                    for name in &section.names {
+                        folder_indices.insert(name.as_str(), folder_index);
+                    }
+                }
+                for name in &names {
+                    match folder_indices.get(name.as_str()) {
+                        Some(&index) => folders[index].names.push(name.clone()),
+                        None => {
+                            if folders.is_empty() {
+                                folders.push(DefinitionFolder {
+                                    id: "desmosify:defs".into(),
+                                    title: "Definitions".into(),
+                                    names: Vec::new(),
+                                });
+                            }
+                            folders[0].names.push(name.clone());
+                        },
+                    }
+                }
+                folders
+            },
+        };
+
+        folders.retain(|folder| !folder.names.is_empty());
+        // Place folders by their earliest definition, so that with dependency order, folders also come after the folders they depend on
+        folders.sort_by_key(|folder| names.iter().position(|name| folder.names.first() == Some(name)));
+        Ok(folders)
+    }
+}
+
+#[derive(Debug)]
+pub enum ValueType {
+    ErrorType = 12,
+    SeedType = 13,
+    EmptyList = 11,
+    Action = 5,
+    Any = 0,
+    ListOfAny = 6,
+    Number = 1,
+    ListOfNumber = 7,
+    Bool = 2,
+    ListOfBool = 8,
+    Point = 3,
+    ListOfPoint = 9,
+    Distribution = 4,
+    ListOfDistribution = 10,
+    RGBColor = 14,
+    ListOfColor = 15,
+    Polygon = 16,
+    ListOfPolygon = 17,
+    Segment = 18,
+    ListOfSegment = 19,
+    Circle = 20,
+    ListOfCircle = 21,
+    Arc = 22,
+    ListOfArc = 23,
+    Line = 24,
+    ListOfLine = 25,
+    Ray = 26,
+    ListOfRay = 27,
+    Angle = 28,
+    ListOfAngle = 29,
+    DirectedAngle = 30,
+    ListOfDirectedAngle = 31,
+    Transformation = 32,
+    ListOfTransformation = 33,
+    Vector = 34,
+    ListOfVector = 35,
+    Point3D = 100,
+    ListOfPoint3D = 101,
+    Segment3D = 102,
+    ListOfSegment3D = 103,
+    Triangle3D = 104,
+    ListOfTriangle3D = 105,
+    Sphere3D = 106,
+    ListOfSphere3D = 107,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BracketType {
+    Paren,
+    Square,
+    Curly,
+    Pipe,
+}
+
+impl BracketType {
+    pub fn left(&self) -> &str {
+        match self {
+            Self::Paren => "(",
+            Self::Square => "[",
+            Self::Curly => "\\{",
+            Self::Pipe => "|",
+        }
+    }
+
+    pub fn right(&self) -> &str {
+        match self {
+            Self::Paren => ")",
+            Self::Square => "]",
+            Self::Curly => "\\}",
+            Self::Pipe => "|",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LatexNode {
+    Group {
+        content: Latex,
+    },
+    Sqrt {
+        index: Option<Latex>,
+        radicand: Latex,
+    },
+    Frac {
+        numerator: Latex,
+        denominator: Latex,
+    },
+    Subscript {
+        content: Latex,
+    },
+    Superscript {
+        content: Latex,
+    },
+    Left {
+        bracket_type: BracketType,
+    },
+    Right {
+        bracket_type: BracketType,
+    },
+    OperatorName {
+        content: String,
+    },
+    Escape {
+        value: String,
+    },
+    Symbol {
+        value: char,
+    },
+    Symbols {
+        value: String,
+    },
+}
+
+impl LatexNode {
+    fn last_char_is_alphabetic(&self) -> bool {
+        match self {
+            Self::Group { .. } => false,
+            Self::Sqrt { .. } => false,
+            Self::Frac { .. } => false,
+            Self::Subscript { .. } => false,
+            Self::Superscript { .. } => false,
+            Self::Left { .. } => false,
+            Self::Right { .. } => false,
+            Self::OperatorName { .. } => false,
+            Self::Escape { value } => value.ends_with(|c: char| c.is_alphabetic()),
+            // `~` is written as `\sim`
+            Self::Symbol { value } => *value == '~' || value.is_alphabetic(),
+            Self::Symbols { value } => value.ends_with(|c: char| c.is_alphabetic()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Latex {
+    nodes: Vec<LatexNode>,
+}
+
+impl Latex {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    pub fn from_nodes(nodes: Vec<LatexNode>) -> Self {
+        Self { nodes }
+    }
+
+    pub fn add_node(mut self, node: LatexNode) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn add_group(mut self, content: Latex) -> Self {
+        self.nodes.push(LatexNode::Group { content });
+        self
+    }
+
+    pub fn add_sqrt(mut self, index: Option<Latex>, radicand: Latex) -> Self {
+        self.nodes.push(LatexNode::Sqrt { index, radicand });
+        self
+    }
+
+    pub fn add_frac(mut self, numerator: Latex, denominator: Latex) -> Self {
+        self.nodes.push(LatexNode::Frac { numerator, denominator });
+        self
+    }
+
+    pub fn add_superscript(mut self, content: Latex) -> Self {
+        self.nodes.push(LatexNode::Superscript { content });
+        self
+    }
+
+    pub fn add_subscript(mut self, content: Latex) -> Self {
+        self.nodes.push(LatexNode::Subscript { content });
+        self
+    }
+
+    pub fn add_left(mut self, bracket_type: BracketType) -> Self {
+        self.nodes.push(LatexNode::Left { bracket_type });
+        self
+    }
+
+    pub fn add_right(mut self, bracket_type: BracketType) -> Self {
+        self.nodes.push(LatexNode::Right { bracket_type });
+        self
+    }
+
+    pub fn add_operator_name(mut self, content: String) -> Self {
+        self.nodes.push(LatexNode::OperatorName { content });
+        self
+    }
+
+    pub fn add_escape(mut self, value: String) -> Self {
+        self.nodes.push(LatexNode::Escape { value });
+        self
+    }
+
+    pub fn add_symbol(mut self, value: char) -> Self {
+        self.nodes.push(LatexNode::Symbol { value });
+        self
+    }
+
+    pub fn add_symbols(mut self, value: String) -> Self {
+        self.nodes.push(LatexNode::Symbols { value });
+        self
+    }
+}
+
+impl Default for Latex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Add for Latex {
+    type Output = Self;
+
+    fn add(mut self, mut latex: Latex) -> Self {
+        if self.nodes.is_empty() {
+            self.nodes = latex.nodes;
+        } else {
+            self.nodes.append(&mut latex.nodes);
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for Latex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut end_is_alphabetic = false;
+
+        for node in &self.nodes {
+            match node {
+                LatexNode::Group { content } => {
+                    write!(f, "{{{content}}}")?;
+                },
+                LatexNode::Sqrt { index: Some(index), radicand } => {
+                    write!(f, "\\sqrt[{index}]{{{radicand}}}")?;
+                },
+                LatexNode::Sqrt { index: None, radicand } => {
+                    write!(f, "\\sqrt{{{radicand}}}")?;
+                },
+                LatexNode::Frac { numerator, denominator } => {
+                    write!(f, "\\frac{{{numerator}}}{{{denominator}}}")?;
+                },
+                LatexNode::Superscript { content } => {
+                    write!(f, "^{{{content}}}")?;
+                },
+                LatexNode::Subscript { content } => {
+                    write!(f, "_{{{content}}}")?;
+                },
+                LatexNode::Left { bracket_type } => {
+                    write!(f, "\\left{}", bracket_type.left())?;
+                },
+                LatexNode::Right { bracket_type } => {
+                    write!(f, "\\right{}", bracket_type.right())?;
+                },
+                LatexNode::OperatorName { content } => {
+                    write!(f, "\\operatorname{{{content}}}")?;
+                },
+                LatexNode::Escape { value } => {
+                    write!(f, "\\{value}")?;
+                },
+                LatexNode::Symbol { value } => match *value {
+                    '&' | '%' | '$' | '#' | '{' | '}' => write!(f, "\\{value}")?,
+                    '~' => write!(f, "\\sim")?,
+                    c if end_is_alphabetic && c.is_alphabetic() => write!(f, " {value}")?,
+                    _ => write!(f, "{value}")?,
+                },
+                LatexNode::Symbols { value } => if end_is_alphabetic && value.starts_with(|c: char| c.is_alphabetic()) {
+                    write!(f, " {value}")?;
+                } else {
+                    write!(f, "{value}")?;
+                },
+            }
+
+            end_is_alphabetic = node.last_char_is_alphabetic();
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InequalityType {
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl InequalityType {
+    pub fn to_latex_node(&self) -> LatexNode {
+        LatexNode::Escape {
+            value: String::from(match self {
+                Self::Less => "lt",
+                Self::Greater => "gt",
+                Self::LessEqual => "le",
+                Self::GreaterEqual => "ge",
+            }),
+        }
+    }
+}
+
+/// A node of Desmos's LaTeX syntax.
+///
+/// The tree mirrors how Desmos reads the LaTeX rather than what it means, so anything which needs brackets to be
+/// read correctly must be wrapped in `Paren`. Trees built that way, using `number` for numbers and `sequence` for
+/// lists of items, come back unchanged from `latex::parse_latex(&node.to_latex().to_string())`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SyntaxNode {
+    Equality(Box<SyntaxNode>, Box<SyntaxNode>),
+    Inequality(Box<SyntaxNode>, InequalityType, Box<SyntaxNode>),
+    InequalityChain(Box<SyntaxNode>, InequalityType, Box<SyntaxNode>, Vec<(InequalityType, SyntaxNode)>),
+    Regression(Box<SyntaxNode>, Box<SyntaxNode>),
+    Pos(Box<SyntaxNode>),
+    Neg(Box<SyntaxNode>),
+    Add(Box<SyntaxNode>, Box<SyntaxNode>),
+    Sub(Box<SyntaxNode>, Box<SyntaxNode>),
+    Mul(Box<SyntaxNode>, Box<SyntaxNode>),
+    DotMul(Box<SyntaxNode>, Box<SyntaxNode>),
+    CrossMul(Box<SyntaxNode>, Box<SyntaxNode>),
+    Div(Box<SyntaxNode>, Box<SyntaxNode>),
+    Factorial(Box<SyntaxNode>),
+    Call(Box<SyntaxNode>, Box<SyntaxNode>),
+    ImplicitCall(Box<SyntaxNode>, Box<SyntaxNode>),
+    Index(Box<SyntaxNode>, Box<SyntaxNode>),
+    Paren(Box<SyntaxNode>),
+    List(Box<SyntaxNode>),
+    Pipes(Box<SyntaxNode>),
+    Subscript(Box<SyntaxNode>, Box<SyntaxNode>),
+    Superscript(Box<SyntaxNode>, Box<SyntaxNode>),
+    Prime(Box<SyntaxNode>),
+    Sequence(Vec<SyntaxNode>),
+    Sqrt(Box<SyntaxNode>),
+    NthRoot(Box<SyntaxNode>, Box<SyntaxNode>),
+    Frac(Box<SyntaxNode>, Box<SyntaxNode>),
+    Derivative(Box<SyntaxNode>, Box<SyntaxNode>),
+    Integral(Box<SyntaxNode>, Box<SyntaxNode>, Box<SyntaxNode>, Option<Box<SyntaxNode>>),
+    Sum(Box<SyntaxNode>, Box<SyntaxNode>, Box<SyntaxNode>),
+    Product(Box<SyntaxNode>, Box<SyntaxNode>, Box<SyntaxNode>),
+    Piecewise(Box<SyntaxNode>),
+    Colon(Box<SyntaxNode>, Box<SyntaxNode>),
+    Ellipsis(Box<SyntaxNode>, Option<Box<SyntaxNode>>),
+    For(Box<SyntaxNode>, Box<SyntaxNode>),
+    With(Box<SyntaxNode>, Box<SyntaxNode>),
+    Dot(Box<SyntaxNode>, Box<SyntaxNode>),
+    PercentOf(Box<SyntaxNode>, Box<SyntaxNode>),
+    RightArrow(Box<SyntaxNode>, Box<SyntaxNode>),
+    MixedNumber(Box<SyntaxNode>, Box<SyntaxNode>, Box<SyntaxNode>),
+    ImplicitMul(Box<SyntaxNode>, Box<SyntaxNode>),
+    Letter(char),
+    Decimal(f64),
+    Command(String),
+    Alphanumeric(String),
+}
+
+impl SyntaxNode {
+    /// A number as Desmos reads it back: negative numbers are negated and bracketed, so `-2^2` can't come out as
+    /// `-(2^2)`, and NaN is written as `0/0`.
+    pub fn number(value: f64) -> Self {
+        if value.is_nan() {
+            Self::Frac(Box::new(Self::Decimal(0.0)), Box::new(Self::Decimal(0.0)))
+        } else if value < 0.0 {
+            Self::Paren(Box::new(Self::Neg(Box::new(Self::Decimal(-value)))))
+        } else {
+            // Also turns -0 into 0
+            Self::Decimal(value.abs())
+        }
+    }
+
+    /// Comma-separated items, where a single item stands on its own as it does in LaTeX.
+    /// Call arguments are the exception, and are always a `Sequence`.
+    pub fn sequence(mut items: Vec<SyntaxNode>) -> Self {
+        if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Self::Sequence(items)
+        }
+    }
+
+    /// Calls `visitor` on this node and every node inside it.
+    pub fn visit<F: FnMut(&SyntaxNode)>(&self, visitor: &mut F) {
+        visitor(self);
+        match self {
+            Self::Equality(lhs, rhs) | Self::Inequality(lhs, _, rhs) | Self::Regression(lhs, rhs)
+            | Self::Add(lhs, rhs) | Self::Sub(lhs, rhs) | Self::Mul(lhs, rhs) | Self::DotMul(lhs, rhs)
+            | Self::CrossMul(lhs, rhs) | Self::Div(lhs, rhs) | Self::Call(lhs, rhs) | Self::ImplicitCall(lhs, rhs)
+            | Self::Index(lhs, rhs) | Self::Subscript(lhs, rhs) | Self::Superscript(lhs, rhs) | Self::NthRoot(lhs, rhs)
+            | Self::Frac(lhs, rhs) | Self::Derivative(lhs, rhs) | Self::Colon(lhs, rhs) | Self::For(lhs, rhs)
+            | Self::With(lhs, rhs) | Self::Dot(lhs, rhs) | Self::PercentOf(lhs, rhs) | Self::RightArrow(lhs, rhs)
+            | Self::ImplicitMul(lhs, rhs) => {
+                lhs.visit(visitor);
+                rhs.visit(visitor);
+            },
+            Self::InequalityChain(lhs, _, rhs, chain) => {
+                lhs.visit(visitor);
+                rhs.visit(visitor);
+                for (_, value) in chain {
+                    value.visit(visitor);
+                }
+            },
+            Self::Pos(value) | Self::Neg(value) | Self::Factorial(value) | Self::Paren(value) | Self::List(value)
+            | Self::Pipes(value) | Self::Prime(value) | Self::Sqrt(value) | Self::Piecewise(value) => value.visit(visitor),
+            Self::Ellipsis(lhs, rhs) => {
+                lhs.visit(visitor);
+                if let Some(rhs) = rhs {
+                    rhs.visit(visitor);
+                }
+            },
+            Self::Sequence(elements) => for element in elements {
+                element.visit(visitor);
+            },
+            Self::Integral(differential, from, to, body) => {
+                differential.visit(visitor);
+                from.visit(visitor);
+                to.visit(visitor);
+                if let Some(body) = body {
+                    body.visit(visitor);
+                }
+            },
+            Self::Sum(bottom, top, body) | Self::Product(bottom, top, body) | Self::MixedNumber(bottom, top, body) => {
+                bottom.visit(visitor);
+                top.visit(visitor);
+                body.visit(visitor);
+            },
+            Self::Letter(_) | Self::Decimal(_) | Self::Command(_) | Self::Alphanumeric(_) => {},
+        }
+    }
+
+    pub fn to_latex(&self) -> Latex {
+        match self {
+            Self::Equality(lhs, rhs) => lhs.to_latex().add_symbol('=').add(rhs.to_latex()),
+            Self::Inequality(lhs, inequality, rhs) => lhs.to_latex().add_node(inequality.to_latex_node()).add(rhs.to_latex()),
+            Self::InequalityChain(lhs, inequality, rhs, chain) => {
+                let mut latex = lhs.to_latex().add_node(inequality.to_latex_node()).add(rhs.to_latex());
+                for (inequality, value) in chain {
+                    latex = latex.add_node(inequality.to_latex_node()).add(value.to_latex());
+                }
+                latex
+            },
+            Self::Regression(lhs, rhs) => lhs.to_latex().add_symbol('~').add(rhs.to_latex()),
+            Self::Pos(value) => Latex::new().add_symbol('+').add(value.to_latex()),
+            Self::Neg(value) => Latex::new().add_symbol('-').add(value.to_latex()),
+            Self::Add(lhs, rhs) => lhs.to_latex().add_symbol('+').add(rhs.to_latex()),
+            Self::Sub(lhs, rhs) => lhs.to_latex().add_symbol('-').add(rhs.to_latex()),
+            Self::Mul(lhs, rhs) => lhs.to_latex().add_symbol('*').add(rhs.to_latex()),
+            Self::DotMul(lhs, rhs) => lhs.to_latex().add_escape(String::from("cdot")).add(rhs.to_latex()),
+            Self::CrossMul(lhs, rhs) => lhs.to_latex().add_escape(String::from("times")).add(rhs.to_latex()),
+            Self::Div(lhs, rhs) => lhs.to_latex().add_symbol('/').add(rhs.to_latex()),
+            Self::Factorial(value) => value.to_latex().add_symbol('!'),
+            Self::Call(callee, args) => callee.to_latex().add_left(BracketType::Paren).add(args.to_latex()).add_right(BracketType::Paren),
+            Self::ImplicitCall(callee, arg) => callee.to_latex().add(arg.to_latex()),
+            Self::Index(indexee, index) => indexee.to_latex().add_left(BracketType::Square).add(index.to_latex()).add_right(BracketType::Square),
+            Self::Paren(content) => Latex::new().add_left(BracketType::Paren).add(content.to_latex()).add_right(BracketType::Paren),
+            Self::List(content) => Latex::new().add_left(BracketType::Square).add(content.to_latex()).add_right(BracketType::Square),
+            Self::Pipes(content) => Latex::new().add_left(BracketType::Pipe).add(content.to_latex()).add_right(BracketType::Pipe),
+            Self::Subscript(base, script) => base.to_latex().add_subscript(script.to_latex()),
+            Self::Superscript(base, script) => base.to_latex().add_superscript(script.to_latex()),
+            Self::Prime(value) => value.to_latex().add_symbol('\''),
+            Self::Sequence(elements) => if let Some(first) = elements.first() {
+                let mut latex = first.to_latex();
+                if let Some(others) = elements.get(1..) {
+                    for element in others {
+                        latex = latex.add_symbol(',').add(element.to_latex());
+                    }
+                }
+                latex
+            } else {
+                Latex::new()
+            },
+            Self::Sqrt(radicand) => Latex::new().add_sqrt(None, radicand.to_latex()),
+            Self::NthRoot(index, radicand) => Latex::new().add_sqrt(Some(index.to_latex()), radicand.to_latex()),
+            Self::Frac(numerator, denominator) => Latex::new().add_frac(numerator.to_latex(), denominator.to_latex()),
+            Self::Derivative(differential, body) => Latex::new().add_frac(Latex::new().add_symbol('d'), Latex::new().add_symbol('d').add(differential.to_latex())).add(body.to_latex()),
+            Self::Integral(differential, from, to, body) => if let Some(body) = body {
+                Latex::new().add_escape(String::from("int")).add_subscript(from.to_latex()).add_superscript(to.to_latex()).add(body.to_latex()).add_symbol('d').add(differential.to_latex())
+            } else {
+                Latex::new().add_escape(String::from("int")).add_subscript(from.to_latex()).add_superscript(to.to_latex()).add_symbol('d').add(differential.to_latex())
+            },
+            Self::Sum(bottom, top, body) => Latex::new().add_escape(String::from("sum")).add_subscript(bottom.to_latex()).add_superscript(top.to_latex()).add(body.to_latex()),
+            Self::Product(bottom, top, body) => Latex::new().add_escape(String::from("prod")).add_subscript(bottom.to_latex()).add_superscript(top.to_latex()).add(body.to_latex()),
+            Self::Piecewise(content) => Latex::new().add_left(BracketType::Curly).add(content.to_latex()).add_right(BracketType::Curly),
+            Self::Colon(lhs, rhs) => lhs.to_latex().add_symbol(':').add(rhs.to_latex()),
+            Self::Ellipsis(lhs, rhs) => if let Some(rhs) = rhs {
+                lhs.to_latex().add_symbols(String::from("...")).add(rhs.to_latex())
+            } else {
+                lhs.to_latex().add_symbols(String::from("..."))
+            },
+            Self::For(lhs, rhs) => lhs.to_latex().add_operator_name(String::from("for")).add(rhs.to_latex()),
+            Self::With(lhs, rhs) => lhs.to_latex().add_operator_name(String::from("with")).add(rhs.to_latex()),
+            Self::Dot(lhs, rhs) => lhs.to_latex().add_symbol('.').add(rhs.to_latex()),
+            Self::PercentOf(lhs, rhs) => lhs.to_latex().add_symbol('%').add_operator_name(String::from("of")).add(rhs.to_latex()),
+            Self::RightArrow(lhs, rhs) => lhs.to_latex().add_escape(String::from("to")).add(rhs.to_latex()),
+            Self::MixedNumber(whole, numerator, denominator) => whole.to_latex().add_frac(numerator.to_latex(), denominator.to_latex()),
+            Self::ImplicitMul(lhs, rhs) => lhs.to_latex().add(rhs.to_latex()),
+            Self::Letter(letter) => match latex::GREEK_LETTERS.iter().find(|&&(_, greek)| greek == *letter) {
+                Some(&(name, _)) => Latex::new().add_escape(String::from(name)),
+                None => Latex::new().add_symbol(*letter),
+            },
+            Self::Decimal(number) => if number.is_nan() {
+                Latex::new().add_frac(Latex::new().add_symbol('0'), Latex::new().add_symbol('0'))
+            } else if number.is_infinite() {
+                Latex::new().add_escape(String::from("infty"))
+            } else {
+                Latex::new().add_symbols(number.to_string())
+            },
+            Self::Command(name) => Latex::new().add_operator_name(name.clone()),
+            Self::Alphanumeric(value) => Latex::new().add_symbols(value.clone()),
+        }
+    }
+}
\ No newline at end of file
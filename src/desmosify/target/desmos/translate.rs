@@ -0,0 +1,962 @@
+use super::*;
+
+use crate::{Action, ConstantValue, DataType, Definitions, DesmosifyError, Signature, Signatures, VariableQualifier};
+use crate::display::{self, DragMode, LabelOrientation, PointStyle, StrokeStyle};
+use crate::syntax::{Expression, ExpressionValue, Operation};
+
+use std::collections::BTreeSet;
+
+/// Built-ins which refer to variables provided by Desmos rather than to commands.
+pub const BUILTIN_VARIABLES: &[&str] = &["index", "dt", "x", "y", "z", "t", "u", "v", "e", "pi"];
+
+/// Translation of analyzed programs into Desmos graph state, shared by every Desmos calculator.
+pub trait DesmosTarget: crate::target::Target {
+    /// The `graph.product` of the state, which tells Desmos which calculator the graph is for.
+    fn product(&self) -> &'static str;
+
+    /// The commands (`@name(...)` built-ins) which are available in this calculator.
+    fn valid_commands(&self) -> &'static [&'static str];
+
+    fn layout(&self) -> Layout;
+
+    /// Whether this calculator has the 3D types, such as `point3` and `sphere`.
+    fn supports_3d(&self) -> bool {
+        false
+    }
+
+    /// Entries which must be placed at the very top of the expression list.
+    fn initial_entries(&self) -> Vec<Box<dyn Entry>> {
+        Vec::new()
+    }
+
+    /// Creates an error reporting that `feature`, used by `expression`, is not supported by this target.
+    fn unsupported(&self, feature: &str, expression: &Expression) -> DesmosifyError {
+        DesmosifyError::new(
+            format!("{feature} is not supported by target {}", self.name()),
+            expression.start,
+            expression.end,
+        )
+    }
+
+    /// Reports every command used in the program which is not available in this calculator. Commands missing from
+    /// every calculator's list are left for Desmos to report.
+    fn check_commands(&self, definitions: &Definitions) -> Vec<DesmosifyError> {
+        let mut errors = Vec::new();
+        definitions.visit_expressions(&mut |expression| {
+            if let ExpressionValue::Operator(Operation::BuiltIn, operands) = &expression.value {
+                if let ExpressionValue::Name(name) = &operands[0].value {
+                    if !BUILTIN_VARIABLES.contains(&name.as_str()) && !self.valid_commands().contains(&name.as_str()) && is_known_command(name) {
+                        errors.push(self.unsupported(&format!("'@{name}'"), expression));
+                    }
+                }
+            }
+        });
+        errors
+    }
+
+    /// Reports every value with a 3D type, and every definition declared with one, if this calculator has none. Only
+    /// the outermost 3D part of a value is reported.
+    fn check_types(&self, definitions: &Definitions, signatures: &Signatures) -> Vec<DesmosifyError> {
+        if self.supports_3d() {
+            return Vec::new();
+        }
+        let mut errors = Vec::new();
+        let mut reported = BTreeSet::new();
+        let mut report = |data_type: &DataType, expression: &Expression, reported: &mut BTreeSet<*const Expression>| {
+            errors.push(self.unsupported(&format!("type '{data_type}'"), expression));
+            expression.visit(&mut |part| {
+                reported.insert(part as *const Expression);
+            });
+        };
+        // Declared types go first, so that `let p: point3 = (1, 2, 3)` is reported with the type it was declared with
+        for (name, value) in &definitions.identifiers {
+            let Some(signature) = signatures.user_defined.get(name) else { continue };
+            let value_type = match signature {
+                Signature::Const { value_type, .. } | Signature::Let { value_type, .. } | Signature::Var { value_type, .. } => Some(value_type),
+                _ => None,
+            };
+            let parameter_types = signature.parameters().unwrap_or_default().iter().map(|parameter| &parameter.data_type);
+            if let Some(data_type) = value_type.into_iter().chain(parameter_types).find(|data_type| data_type.is_3d()) {
+                report(data_type, value, &mut reported);
+            }
+        }
+        definitions.visit_expressions(&mut |expression| {
+            if !reported.contains(&(expression as *const Expression)) {
+                if let Some(data_type) = type_3d(expression) {
+                    report(&data_type, expression, &mut reported);
+                }
+            }
+        });
+        errors
+    }
+
+    /// Translates a constant value. Enum variants become their position among the enum's variants.
+    fn translate_constant_value(&self, value: &ConstantValue) -> Box<SyntaxNode> {
+        Box::new(match value {
+            ConstantValue::Real(value) => SyntaxNode::number(*value),
+            ConstantValue::Int(value) => SyntaxNode::number(*value as f64),
+            ConstantValue::Bool(value) => SyntaxNode::Decimal(if *value { 1.0 } else { 0.0 }),
+            ConstantValue::Point(x_value, y_value) => SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                SyntaxNode::number(*x_value),
+                SyntaxNode::number(*y_value),
+            ]))),
+            ConstantValue::IPoint(x_value, y_value) => SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                SyntaxNode::number(*x_value as f64),
+                SyntaxNode::number(*y_value as f64),
+            ]))),
+            ConstantValue::Point3(x_value, y_value, z_value) => SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                SyntaxNode::number(*x_value),
+                SyntaxNode::number(*y_value),
+                SyntaxNode::number(*z_value),
+            ]))),
+            ConstantValue::IPoint3(x_value, y_value, z_value) => SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                SyntaxNode::number(*x_value as f64),
+                SyntaxNode::number(*y_value as f64),
+                SyntaxNode::number(*z_value as f64),
+            ]))),
+            ConstantValue::Color(color) => match *color {
+                crate::Color::Rgb { red, green, blue } => SyntaxNode::Call(
+                    Box::new(SyntaxNode::Command("rgb".into())),
+                    Box::new(SyntaxNode::Sequence(vec![
+                        SyntaxNode::number(red),
+                        SyntaxNode::number(green),
+                        SyntaxNode::number(blue),
+                    ])),
+                ),
+                crate::Color::Hsv { hue, saturation, value } => SyntaxNode::Call(
+                    Box::new(SyntaxNode::Command("hsv".into())),
+                    Box::new(SyntaxNode::Sequence(vec![
+                        SyntaxNode::number(hue),
+                        SyntaxNode::number(saturation),
+                        SyntaxNode::number(value),
+                    ])),
+                ),
+            },
+            ConstantValue::Polygon(points) => SyntaxNode::Call(
+                Box::new(SyntaxNode::Command("polygon".into())),
+                Box::new(SyntaxNode::Sequence(Vec::from_iter(points.iter().map(|&(x_value, y_value)| {
+                    SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                        SyntaxNode::number(x_value),
+                        SyntaxNode::number(y_value),
+                    ])))
+                })))),
+            ),
+            ConstantValue::Segment((x1_value, y1_value), (x2_value, y2_value)) => SyntaxNode::Call(
+                Box::new(SyntaxNode::Command("segment".into())),
+                Box::new(SyntaxNode::Sequence(vec![
+                    SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                        SyntaxNode::number(*x1_value),
+                        SyntaxNode::number(*y1_value),
+                    ]))),
+                    SyntaxNode::Paren(Box::new(SyntaxNode::Sequence(vec![
+                        SyntaxNode::number(*x2_value),
+                        SyntaxNode::number(*y2_value),
+                    ]))),
+                ])),
+            ),
+            ConstantValue::Str(content) => SyntaxNode::Alphanumeric(content.clone()),
+            ConstantValue::List(_, values) => SyntaxNode::List(
+                Box::new(SyntaxNode::sequence(values.iter().map(|value| *self.translate_constant_value(value)).collect()))
+            ),
+            &ConstantValue::EnumVariant(_, _, position) => SyntaxNode::number(position as f64),
+        })
+    }
+    
+    fn translate_name(&self, name: &str) -> Box<SyntaxNode> {
+        Box::new(SyntaxNode::Subscript(
+            Box::new(SyntaxNode::Letter('X')),
+            Box::new(SyntaxNode::Alphanumeric(name_subscript(name))),
+        ))
+    }
+
+    /// Reports declarations which would end up with the same name in the graph, since subscripts drop the `_` and
+    /// `::` that tell them apart in the source.
+    fn check_names(&self, signatures: &Signatures) -> Vec<DesmosifyError> {
+        let mut errors = Vec::new();
+        let mut names_by_subscript = BTreeMap::new();
+        for (name, signature) in &signatures.user_defined {
+            if let Signature::Enum { .. } = signature {
+                continue;
+            }
+            let subscript = name_subscript(name);
+            if let Some(original) = names_by_subscript.insert(subscript.clone(), name) {
+                let (start, end) = signatures.locations.get(name).copied().unzip();
+                errors.push(DesmosifyError::new(
+                    format!("'{original}' and '{name}' would both be named 'X_{{{subscript}}}' in the graph"),
+                    start,
+                    end,
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Translates a built-in such as `@index` or `@sin`. Built-in variables become the corresponding Desmos
+    /// variable, and everything else becomes a command.
+    fn translate_builtin(&self, name: &str) -> Box<SyntaxNode> {
+        Box::new(match name {
+            "x" => SyntaxNode::Letter('x'),
+            "y" => SyntaxNode::Letter('y'),
+            "z" => SyntaxNode::Letter('z'),
+            "t" => SyntaxNode::Letter('t'),
+            "u" => SyntaxNode::Letter('u'),
+            "v" => SyntaxNode::Letter('v'),
+            "e" => SyntaxNode::Letter('e'),
+            "pi" => SyntaxNode::Letter('π'),
+            _ => SyntaxNode::Command(String::from(name)),
+        })
+    }
+
+    fn translate_operator(&self, expression: &Expression, operation: Operation, operands: &[Expression]) -> Result<Box<SyntaxNode>, DesmosifyError> {
+        // These operations have operands which are not expressions of their own, such as names being bound or accessed
+        match operation {
+            Operation::Lambda => return Err(self.unsupported("a lambda", expression)),
+            Operation::NamedArgument => return Err(self.unsupported("a named argument", expression)),
+            Operation::BuiltIn => {
+                let ExpressionValue::Name(name) = &operands[0].value else {
+                    return Err(self.unsupported("a built-in without a name", expression));
+                };
+                return Ok(self.translate_builtin(name));
+            },
+            Operation::MemberAccess => {
+                let ExpressionValue::Name(member) = &operands[1].value else {
+                    return Err(self.unsupported("member access without a member name", expression));
+                };
+                let component = match member.as_str() {
+                    "x" => 'x',
+                    "y" => 'y',
+                    "z" => 'z',
+                    _ => return Err(self.unsupported(&format!("member access '.{member}'"), expression)),
+                };
+                return Ok(Box::new(SyntaxNode::Dot(
+                    self.translate_expression(&operands[0])?,
+                    Box::new(SyntaxNode::Letter(component)),
+                )));
+            },
+            Operation::ListMap => {
+                // Nested `for` clauses become a single Desmos comprehension over every variable
+                let mut assignments = Vec::new();
+                let mut body = expression;
+                while let ExpressionValue::Operator(Operation::ListMap, operands) = &body.value {
+                    assignments.push(SyntaxNode::Equality(
+                        self.translate_expression(&operands[1])?,
+                        self.translate_expression(&operands[2])?,
+                    ));
+                    body = &operands[0];
+                }
+                assignments.reverse();
+                return Ok(Box::new(SyntaxNode::List(
+                    Box::new(SyntaxNode::For(
+                        self.translate_expression(body)?,
+                        Box::new(SyntaxNode::sequence(assignments)),
+                    )),
+                )));
+            },
+            Operation::With => {
+                return Ok(Box::new(SyntaxNode::Paren(
+                    Box::new(SyntaxNode::With(
+                        self.translate_expression(&operands[0])?,
+                        self.translate_expression(&operands[1])?,
+                    )),
+                )));
+            },
+            // Conditions are translated as Desmos conditions rather than as values
+            Operation::ListFilter => {
+                return Ok(Box::new(SyntaxNode::Index(
+                    self.translate_expression(&operands[0])?,
+                    self.translate_condition(&operands[1])?,
+                )));
+            },
+            Operation::Conditional => {
+                // Each branch is chosen over everything after it
+                let mut otherwise = match operands.len() % 2 {
+                    1 => Some(*self.translate_expression(&operands[operands.len() - 1])?),
+                    _ => None,
+                };
+                for branch in operands.chunks_exact(2).rev() {
+                    let pieces = self.translate_branch(&branch[0], *self.translate_expression(&branch[1])?, otherwise)?;
+                    otherwise = Some(piecewise(pieces));
+                }
+                return Ok(Box::new(otherwise.unwrap()));
+            },
+            // A bare condition is 1 where it holds, and the default 0 elsewhere
+            Operation::LessThan | Operation::GreaterThan | Operation::LessEqual | Operation::GreaterEqual | Operation::Equal => {
+                return Ok(Box::new(SyntaxNode::Piecewise(
+                    Box::new(SyntaxNode::Sequence(vec![
+                        *self.translate_condition(expression)?,
+                        SyntaxNode::Decimal(0.0),
+                    ])),
+                )));
+            },
+            // The other booleans choose between 1 and 0, so `!x` is also true for numbers other than 0 and 1
+            Operation::Not | Operation::NotEqual | Operation::And | Operation::Or => {
+                return Ok(Box::new(piecewise(self.translate_branch(
+                    expression,
+                    SyntaxNode::Decimal(1.0),
+                    Some(SyntaxNode::Decimal(0.0)),
+                )?)));
+            },
+            _ => {}
+        }
+
+        let mut operands = operands.iter()
+            .rev()
+            .map(|operand| self.translate_expression(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Box::new(match operation {
+            crate::Operation::PointLiteral => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Sequence(
+                    operands.into_iter().rev().map(|component| *component).collect(),
+                )),
+            ),
+            crate::Operation::ListLiteral => SyntaxNode::List(
+                Box::new(SyntaxNode::sequence(
+                    operands.into_iter().rev().map(|item| *item).collect(),
+                )),
+            ),
+            // `[value; count]` becomes `[value for n = [1...count]]`; `n` can't clash with a translated name
+            crate::Operation::ListFill => SyntaxNode::List(
+                Box::new(SyntaxNode::For(
+                    operands.pop().unwrap(),
+                    Box::new(SyntaxNode::Equality(
+                        Box::new(SyntaxNode::Letter('n')),
+                        Box::new(SyntaxNode::List(
+                            Box::new(SyntaxNode::Ellipsis(
+                                Box::new(SyntaxNode::Decimal(1.0)),
+                                Some(operands.pop().unwrap()),
+                            )),
+                        )),
+                    )),
+                )),
+            ),
+            crate::Operation::ListMap | crate::Operation::MemberAccess | crate::Operation::BuiltIn | crate::Operation::With
+            | crate::Operation::ListFilter | crate::Operation::Not | crate::Operation::LessThan | crate::Operation::GreaterThan
+            | crate::Operation::LessEqual | crate::Operation::GreaterEqual | crate::Operation::Equal | crate::Operation::NotEqual
+            | crate::Operation::And | crate::Operation::Or | crate::Operation::Conditional | crate::Operation::Lambda
+            | crate::Operation::NamedArgument => unreachable!(),
+            crate::Operation::Call => SyntaxNode::Call(
+                operands.pop().unwrap(),
+                Box::new(SyntaxNode::Sequence(
+                    operands.into_iter().rev().map(|argument| *argument).collect(),
+                )),
+            ),
+            crate::Operation::ActionCall => SyntaxNode::Call(
+                operands.pop().unwrap(),
+                Box::new(SyntaxNode::Sequence(
+                    operands.into_iter().rev().map(|argument| *argument).collect(),
+                )),
+            ),
+            crate::Operation::Index => SyntaxNode::Index(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            ),
+            crate::Operation::Posate => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Pos(
+                    operands.pop().unwrap(),
+                )),
+            ),
+            crate::Operation::Negate => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Neg(
+                    operands.pop().unwrap(),
+                )),
+            ),
+            // `a^b^c` would be read as a double superscript, so a power of a power is bracketed
+            crate::Operation::Exponent => SyntaxNode::Superscript(
+                operands.pop().map(|base| if matches!(*base, SyntaxNode::Superscript(..)) {
+                    Box::new(SyntaxNode::Paren(base))
+                } else {
+                    base
+                }).unwrap(),
+                operands.pop().unwrap(),
+            ),
+            crate::Operation::Multiply => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Mul(
+                    operands.pop().unwrap(),
+                    operands.pop().unwrap(),
+                ))
+            ),
+            crate::Operation::Divide => SyntaxNode::Frac(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            ),
+            crate::Operation::IntegerDivide => command("floor", vec![SyntaxNode::Frac(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            )]),
+            crate::Operation::Modulus => SyntaxNode::Call(
+                Box::new(SyntaxNode::Command("mod".into())),
+                Box::new(SyntaxNode::Sequence(vec![
+                    *operands.pop().unwrap(),
+                    *operands.pop().unwrap(),
+                ])),
+            ),
+            // Desmos numbers aren't integers, so bitwise operators are sums over the base-2 digits of their operands
+            // (see `semantics::fold_bitwise`), `~x` is `-x - 1`, and shifts multiply or divide by a power of two
+            crate::Operation::BitwiseNot => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Sub(
+                    Box::new(SyntaxNode::Neg(Box::new(command("floor", vec![*operands.pop().unwrap()])))),
+                    Box::new(SyntaxNode::Decimal(1.0)),
+                )),
+            ),
+            crate::Operation::BitwiseAnd => bit_sum(SyntaxNode::Mul(
+                Box::new(bits(digits(*operands.pop().unwrap()))),
+                Box::new(bits(digits(*operands.pop().unwrap()))),
+            )),
+            // The bits are added rather than the digits, which could be too large to add exactly
+            crate::Operation::BitwiseXor => bit_sum(command("mod", vec![
+                SyntaxNode::Add(
+                    Box::new(bits(digits(*operands.pop().unwrap()))),
+                    Box::new(bits(digits(*operands.pop().unwrap()))),
+                ),
+                SyntaxNode::Decimal(2.0),
+            ])),
+            crate::Operation::BitwiseOr => bit_sum(command("sign", vec![SyntaxNode::Add(
+                Box::new(bits(digits(*operands.pop().unwrap()))),
+                Box::new(bits(digits(*operands.pop().unwrap()))),
+            )])),
+            crate::Operation::ShiftLeft => command("floor", vec![SyntaxNode::Mul(
+                operands.pop().unwrap(),
+                Box::new(SyntaxNode::Superscript(Box::new(SyntaxNode::Decimal(2.0)), operands.pop().unwrap())),
+            )]),
+            crate::Operation::ShiftRight => command("floor", vec![SyntaxNode::Frac(
+                operands.pop().unwrap(),
+                Box::new(SyntaxNode::Superscript(Box::new(SyntaxNode::Decimal(2.0)), operands.pop().unwrap())),
+            )]),
+            crate::Operation::Add => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Add(
+                    operands.pop().unwrap(),
+                    operands.pop().unwrap(),
+                ))
+            ),
+            crate::Operation::Subtract => SyntaxNode::Paren(
+                Box::new(SyntaxNode::Sub(
+                    operands.pop().unwrap(),
+                    operands.pop().unwrap(),
+                ))
+            ),
+            crate::Operation::ExclusiveRange if operands.len() == 2 => SyntaxNode::List(
+                Box::new(SyntaxNode::Ellipsis(
+                    operands.pop().unwrap(),
+                    Some(Box::new(SyntaxNode::Sub(
+                        operands.pop().unwrap(),
+                        Box::new(SyntaxNode::Decimal(1.0)),
+                    ))),
+                )),
+            ),
+            crate::Operation::ExclusiveRange => return Err(self.unsupported("an exclusive range with a step", expression)),
+            crate::Operation::InclusiveRange => {
+                let end = operands.remove(0);
+                let mut items = Vec::from_iter(operands.into_iter().rev().map(|item| *item));
+                let last = items.pop().unwrap();
+                items.push(SyntaxNode::Ellipsis(Box::new(last), Some(end)));
+                SyntaxNode::List(Box::new(SyntaxNode::sequence(items)))
+            },
+            crate::Operation::Assignment => SyntaxNode::Equality(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            ),
+            crate::Operation::Update => SyntaxNode::RightArrow(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            ),
+            crate::Operation::Regression => SyntaxNode::Regression(
+                operands.pop().unwrap(),
+                operands.pop().unwrap(),
+            ),
+        }))
+    }
+
+    /// Translates a boolean used as a single condition, as in a filter, into a Desmos condition which holds exactly
+    /// where the boolean is true. Comparisons become native conditions such as `a<b`, and any other boolean is
+    /// compared to 1.
+    fn translate_condition(&self, expression: &Expression) -> Result<Box<SyntaxNode>, DesmosifyError> {
+        let ExpressionValue::Operator(operation, operands) = &expression.value else {
+            return Ok(Box::new(SyntaxNode::Equality(self.translate_expression(expression)?, Box::new(SyntaxNode::Decimal(1.0)))));
+        };
+        if *operation == Operation::Equal {
+            return Ok(Box::new(SyntaxNode::Equality(
+                self.translate_expression(&operands[0])?,
+                self.translate_expression(&operands[1])?,
+            )));
+        }
+        let Some(inequality) = inequality_type(*operation) else {
+            return Ok(Box::new(SyntaxNode::Equality(self.translate_expression(expression)?, Box::new(SyntaxNode::Decimal(1.0)))));
+        };
+        Ok(Box::new(SyntaxNode::Inequality(
+            self.translate_expression(&operands[0])?,
+            inequality,
+            self.translate_expression(&operands[1])?,
+        )))
+    }
+
+    /// Translates a choice between `then` and `otherwise` into the pieces of a Desmos piecewise expression. Without
+    /// `otherwise`, a value is left undefined and an action does nothing where the condition does not hold.
+    ///
+    /// Comparisons become native conditions. `&&` tests its right operand within the branch of its left, `||` tests
+    /// each operand in turn, and `!` and `!=` swap the branches, falling back to `translate_condition` if there is no
+    /// `otherwise` to swap with. Nesting `&&` repeats `otherwise` and testing each operand of `||` repeats `then`, so
+    /// unless that is only a number or a name, the whole condition is worked out as 0 or 1 and compared to 1 instead,
+    /// as a chain of branches would otherwise grow exponentially.
+    fn translate_branch(
+        &self,
+        condition: &Expression,
+        then: SyntaxNode,
+        otherwise: Option<SyntaxNode>,
+    ) -> Result<Vec<SyntaxNode>, DesmosifyError> {
+        let (operation, operands) = match &condition.value {
+            ExpressionValue::Operator(operation, operands) => (Some(*operation), operands.as_slice()),
+            _ => (None, &[][..]),
+        };
+        match (operation, otherwise) {
+            (Some(Operation::Not), Some(otherwise)) => self.translate_branch(&operands[0], otherwise, Some(then)),
+            (Some(Operation::NotEqual), Some(otherwise)) => Ok(vec![
+                SyntaxNode::Colon(
+                    Box::new(SyntaxNode::Equality(
+                        self.translate_expression(&operands[0])?,
+                        self.translate_expression(&operands[1])?,
+                    )),
+                    Box::new(otherwise),
+                ),
+                then,
+            ]),
+            (Some(Operation::And), otherwise) if otherwise.as_ref().is_none_or(is_copyable) => {
+                let inner = self.translate_branch(&operands[1], then, otherwise.clone())?;
+                self.translate_branch(&operands[0], piecewise(inner), otherwise)
+            },
+            (Some(Operation::Or), otherwise) if is_copyable(&then) => {
+                let rest = self.translate_branch(&operands[1], then.clone(), otherwise)?;
+                self.translate_branch(&operands[0], then, Some(piecewise(rest)))
+            },
+            (Some(Operation::And | Operation::Or), otherwise) => {
+                let value = piecewise(self.translate_branch(condition, SyntaxNode::Decimal(1.0), Some(SyntaxNode::Decimal(0.0)))?);
+                let condition = SyntaxNode::Equality(Box::new(value), Box::new(SyntaxNode::Decimal(1.0)));
+                Ok(Vec::from_iter([SyntaxNode::Colon(Box::new(condition), Box::new(then))].into_iter().chain(otherwise)))
+            },
+            (_, otherwise) => Ok(Vec::from_iter(
+                [SyntaxNode::Colon(self.translate_condition(condition)?, Box::new(then))].into_iter().chain(otherwise),
+            )),
+        }
+    }
+
+    fn translate_expression(&self, expression: &Expression) -> Result<Box<SyntaxNode>, DesmosifyError> {
+        match &expression.value {
+            ExpressionValue::Literal(value) => Ok(self.translate_constant_value(value)),
+            ExpressionValue::Name(name) => Ok(self.translate_name(name)),
+            ExpressionValue::Operator(operation, operands) => self.translate_operator(expression, *operation, operands),
+        }
+    }
+
+    fn translate_action(&self, action: &Action) -> Result<Box<SyntaxNode>, DesmosifyError> {
+        Ok(match action {
+            Action::Block(subactions) => Box::new(SyntaxNode::Paren(
+                Box::new(SyntaxNode::sequence(subactions.iter()
+                    .map(|subaction| self.translate_action(subaction).map(|subaction| *subaction))
+                    .collect::<Result<_, _>>()?)),
+            )),
+            Action::Update(target, value) => Box::new(SyntaxNode::RightArrow(
+               self.translate_expression(target)?,
+               self.translate_expression(value)?,
+            )),
+            Action::Call(name, arguments) => Box::new(SyntaxNode::Call(
+                self.translate_expression(name)?,
+                Box::new(SyntaxNode::Sequence(arguments.iter()
+                    .map(|argument| self.translate_expression(argument).map(|argument| *argument))
+                    .collect::<Result<_, _>>()?)),
+            )),
+            Action::Conditional(branches, default_branch) => {
+                let mut otherwise = match default_branch {
+                    Some(default_branch) => Some(*self.translate_action(default_branch)?),
+                    None => None,
+                };
+                for (condition, branch) in branches.iter().rev() {
+                    otherwise = Some(piecewise(self.translate_branch(condition, *self.translate_action(branch)?, otherwise)?));
+                }
+                Box::new(otherwise.unwrap())
+            },
+        })
+    }
+
+    /// Translates an expression which is displayed on its own, where a top-level comparison or `=` is a relation
+    /// to be plotted (such as `@y = @x^2` or `@y < @x`) rather than a value.
+    fn translate_relation(&self, expression: &Expression) -> Result<Box<SyntaxNode>, DesmosifyError> {
+        let ExpressionValue::Operator(operation, operands) = &expression.value else {
+            return self.translate_expression(expression);
+        };
+        match operation {
+            Operation::Assignment | Operation::Equal => Ok(Box::new(SyntaxNode::Equality(
+                self.translate_expression(&operands[0])?,
+                self.translate_expression(&operands[1])?,
+            ))),
+            _ if inequality_type(*operation).is_some() => {
+                // `a < b < c` parses as `(a < b) < c`, so unravel it into a chain
+                let mut chain = Vec::new();
+                let mut current = expression;
+                while let ExpressionValue::Operator(operation, operands) = &current.value {
+                    let Some(inequality) = inequality_type(*operation) else {
+                        break;
+                    };
+                    chain.push((inequality, *self.translate_expression(&operands[1])?));
+                    current = &operands[0];
+                }
+                chain.reverse();
+                let lhs = self.translate_expression(current)?;
+                let (inequality, rhs) = chain.remove(0);
+                Ok(Box::new(if chain.is_empty() {
+                    SyntaxNode::Inequality(lhs, inequality, Box::new(rhs))
+                } else {
+                    SyntaxNode::InequalityChain(lhs, inequality, Box::new(rhs), chain)
+                }))
+            },
+            _ => self.translate_expression(expression),
+        }
+    }
+
+    /// Builds the expression entry for a display element, applying each of its attributes.
+    fn translate_element(&self, element: &display::Element, id: String, folder_id: Option<String>) -> Result<ExpressionEntry, DesmosifyError> {
+        let mut style = ExpressionStyle {
+            color: Some(self.translate_expression(&element.color)?),
+            drag_mode: Some("NONE"),
+            ..Default::default()
+        };
+        let mut hidden = false;
+
+        if let Some(point) = &element.point {
+            style.points = Some(true);
+            style.point_size = Some(self.translate_expression(&point.size_pixels)?);
+            style.point_opacity = Some(self.translate_expression(&point.opacity)?);
+            style.point_style = Some(match point.style {
+                PointStyle::Point => "POINT",
+                PointStyle::Open => "OPEN",
+                PointStyle::Cross => "CROSS",
+            });
+        }
+        if let Some(stroke) = &element.stroke {
+            style.lines = Some(true);
+            style.line_width = Some(self.translate_expression(&stroke.width_pixels)?);
+            style.line_opacity = Some(self.translate_expression(&stroke.opacity)?);
+            style.line_style = Some(match stroke.style {
+                StrokeStyle::Solid => "SOLID",
+                StrokeStyle::Dashed => "DASH",
+                StrokeStyle::Dotted => "DOT",
+            });
+        } else if element.point.is_some() || element.label.is_some() {
+            style.lines = Some(false);
+        }
+        if let Some(fill) = &element.fill {
+            style.fill = Some(true);
+            style.fill_opacity = Some(self.translate_expression(&fill.opacity)?);
+        } else if element.stroke.is_some() {
+            style.fill = Some(false);
+        }
+        if let Some(label) = &element.label {
+            style.label = Some(label.text.clone());
+            style.label_size = Some(self.translate_expression(&label.scale_factor)?);
+            style.label_angle = Some(self.translate_expression(&label.angle_degrees)?);
+            style.label_orientation = Some(match label.orientation {
+                LabelOrientation::Center => "center",
+                LabelOrientation::Left => "left",
+                LabelOrientation::Right => "right",
+                LabelOrientation::Above => "above",
+                LabelOrientation::Below => "below",
+                LabelOrientation::AboveLeft => "above_left",
+                LabelOrientation::AboveRight => "above_right",
+                LabelOrientation::BelowLeft => "below_left",
+                LabelOrientation::BelowRight => "below_right",
+            });
+            if element.point.is_none() {
+                // A label on its own is drawn on a hidden point, whose opacity also applies to the label
+                hidden = true;
+                style.point_opacity = Some(self.translate_expression(&label.opacity)?);
+            }
+        }
+        if let Some(drag) = &element.drag {
+            style.drag_mode = Some(match drag.mode {
+                DragMode::XY => "XY",
+                DragMode::X => "X",
+                DragMode::Y => "Y",
+            });
+        }
+        if let Some(click) = &element.click {
+            style.click_handler = Some(self.translate_action(&click.action)?);
+        }
+        if let Some(description) = &element.description {
+            style.description = Some(description.text.clone());
+        }
+        if let Some(surface_domain) = &element.surface_domain {
+            style.parametric_domain_3d_u = Some((
+                self.translate_expression(&surface_domain.u_min)?,
+                self.translate_expression(&surface_domain.u_max)?,
+            ));
+            style.parametric_domain_3d_v = Some((
+                self.translate_expression(&surface_domain.v_min)?,
+                self.translate_expression(&surface_domain.v_max)?,
+            ));
+        }
+        if let Some(domain) = &element.domain {
+            style.parametric_domain = Some((
+                self.translate_expression(&domain.min)?,
+                self.translate_expression(&domain.max)?,
+            ));
+        }
+
+        Ok(ExpressionEntry {
+            id,
+            folder_id,
+            content: Some(self.translate_relation(&element.what)?),
+            hidden,
+            style,
+        })
+    }
+
+    /// Builds the ticker, which runs the `ticker` action and advances every `var timer` by the elapsed time.
+    fn translate_ticker(&self, definitions: &Definitions, signatures: &Signatures) -> Result<Option<Ticker>, DesmosifyError> {
+        let mut handlers = Vec::new();
+        if let Some(ticker) = &definitions.ticker {
+            handlers.push(*self.translate_action(&ticker.tick_action)?);
+        }
+        for (name, signature) in &signatures.user_defined {
+            if let Signature::Var { qualifier: Some(VariableQualifier::Timer), .. } = signature {
+                handlers.push(SyntaxNode::RightArrow(
+                    self.translate_name(name),
+                    Box::new(SyntaxNode::Add(
+                        self.translate_name(name),
+                        Box::new(SyntaxNode::Frac(
+                            self.translate_builtin("dt"),
+                            Box::new(SyntaxNode::Decimal(1000.0)),
+                        )),
+                    )),
+                ));
+            }
+        }
+
+        if handlers.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Ticker {
+                playing: true,
+                handler: Some(Box::new(SyntaxNode::sequence(handlers))),
+                min_step: definitions.ticker.as_ref()
+                    .and_then(|ticker| ticker.interval_ms.as_ref())
+                    .map(|interval_ms| self.translate_expression(interval_ms))
+                    .transpose()?,
+            }))
+        }
+    }
+
+    /// Translates the whole program into graph state for this calculator, reporting every construct which could
+    /// not be translated rather than stopping at the first.
+    fn compile_state(&self, definitions: &Definitions, signatures: &Signatures) -> Result<GraphState, Vec<DesmosifyError>> {
+        let mut errors = self.check_commands(definitions);
+        errors.extend(self.check_types(definitions, signatures));
+        errors.extend(self.check_names(signatures));
+        let mut state = GraphState {
+            version: 11,
+            graph: GraphSettings {
+                product: self.product().into()
+            },
+            expressions: Expressions {
+                list: self.initial_entries(),
+                ticker: None,
+            },
+        };
+        let mut next_id: usize = 0;
+        let mut get_next_id = || {
+            let id = next_id.to_string();
+            next_id += 1;
+            id
+        };
+
+        if let Some(public) = &definitions.public {
+            for expression in public {
+                let entry: Box<dyn Entry> = match self.translate_relation(expression).map(|content| *content) {
+                    Ok(SyntaxNode::Alphanumeric(content)) => {
+                        Box::new(TextEntry {
+                            id: get_next_id(),
+                            folder_id: None,
+                            content,
+                        })
+                    },
+                    Ok(content) => {
+                        Box::new(ExpressionEntry {
+                            id: get_next_id(),
+                            folder_id: None,
+                            content: Some(Box::new(content)),
+                            hidden: false,
+                            style: ExpressionStyle::default(),
+                        })
+                    },
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    },
+                };
+
+                state.expressions.list.push(entry);
+            }
+        }
+
+        if let Some(elements) = &definitions.display {
+            state.expressions.list.push(Box::new(FolderEntry {
+                id: "desmosify:display".into(),
+                title: "Display".into(),
+                collapsed: true,
+                secret: false,
+            }));
+
+            for element in elements {
+                match self.translate_element(element, get_next_id(), Some("desmosify:display".into())) {
+                    Ok(entry) => state.expressions.list.push(Box::new(entry)),
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+
+        let folders = match self.layout().folders(definitions, signatures) {
+            Ok(folders) => folders,
+            Err(error) => {
+                errors.push(error);
+                Vec::new()
+            },
+        };
+        for folder in folders {
+            state.expressions.list.push(Box::new(FolderEntry {
+                id: folder.id.clone(),
+                title: folder.title,
+                collapsed: true,
+                secret: false,
+            }));
+
+            for name in &folder.names {
+                let Some(signature) = signatures.user_defined.get(name) else {
+                    errors.push(DesmosifyError::new(format!("could not find a definition for '{name}'"), None, None));
+                    continue;
+                };
+                let (definition, hidden) = if let Some(action) = definitions.actions.get(name) {
+                    (self.translate_action(action), false)
+                } else {
+                    (self.translate_expression(&definitions.identifiers[name]), true)
+                };
+                let definition = match definition {
+                    Ok(definition) => definition,
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    },
+                };
+
+                state.expressions.list.push(Box::new(ExpressionEntry {
+                    id: get_next_id(),
+                    folder_id: Some(folder.id.clone()),
+                    content: Some(Box::new(SyntaxNode::Equality(
+                        signature.parameters().map_or_else(|| self.translate_name(name), |parameters| Box::new(SyntaxNode::Call(
+                            self.translate_name(name),
+                            Box::new(SyntaxNode::Sequence(parameters.iter().map(|parameter| *self.translate_name(&parameter.name)).collect())),
+                        ))),
+                        definition,
+                    ))),
+                    hidden,
+                    style: ExpressionStyle::default(),
+                }));
+            }
+        }
+
+        match self.translate_ticker(definitions, signatures) {
+            Ok(ticker) => state.expressions.ticker = ticker,
+            Err(error) => errors.push(error),
+        }
+
+        if errors.is_empty() {
+            Ok(state)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn command(name: &str, arguments: Vec<SyntaxNode>) -> SyntaxNode {
+    SyntaxNode::Call(
+        Box::new(SyntaxNode::Command(name.into())),
+        Box::new(SyntaxNode::Sequence(arguments)),
+    )
+}
+
+/// `2^[0...31]`, the value of each bit used by bitwise operators.
+fn bit_values() -> SyntaxNode {
+    SyntaxNode::Superscript(
+        Box::new(SyntaxNode::Decimal(2.0)),
+        Box::new(SyntaxNode::List(Box::new(SyntaxNode::Ellipsis(
+            Box::new(SyntaxNode::Decimal(0.0)),
+            Some(Box::new(SyntaxNode::Decimal(31.0))),
+        )))),
+    )
+}
+
+/// `floor(value / 2^[0...31])`, the list of `value` shifted right by each bit, whose parities are its bits.
+fn digits(value: SyntaxNode) -> SyntaxNode {
+    command("floor", vec![SyntaxNode::Frac(Box::new(value), Box::new(bit_values()))])
+}
+
+fn bits(digits: SyntaxNode) -> SyntaxNode {
+    command("mod", vec![digits, SyntaxNode::Decimal(2.0)])
+}
+
+/// `total(2^[0...31] * bits)`, the number with the given list of bits.
+fn bit_sum(bits: SyntaxNode) -> SyntaxNode {
+    command("total", vec![SyntaxNode::Mul(Box::new(bit_values()), Box::new(bits))])
+}
+
+/// A piecewise expression made of `pieces`. One whose last piece is itself a piecewise expression takes its pieces
+/// instead, as `{a: 1, {b: 2, 3}}` means the same as `{a: 1, b: 2, 3}`.
+fn piecewise(mut pieces: Vec<SyntaxNode>) -> SyntaxNode {
+    if let Some(SyntaxNode::Piecewise(_)) = pieces.last() {
+        let Some(SyntaxNode::Piecewise(rest)) = pieces.pop() else { unreachable!() };
+        match *rest {
+            SyntaxNode::Sequence(rest) => pieces.extend(rest),
+            rest => pieces.push(rest),
+        }
+    }
+    SyntaxNode::Piecewise(Box::new(SyntaxNode::sequence(pieces)))
+}
+
+/// The 3D type of `expression`, if it has one. Semantics leaves some expressions (such as the arguments of built-ins)
+/// without a type, but a point with three components is always 3D.
+fn type_3d(expression: &Expression) -> Option<DataType> {
+    match &expression.value {
+        _ if expression.data_type.is_3d() => Some(expression.data_type.clone()),
+        ExpressionValue::Operator(Operation::PointLiteral, components) if components.len() == 3 => Some(DataType::Point3),
+        _ => None,
+    }
+}
+
+/// Whether `node` is small enough to be written out more than once, such as a number or a name.
+fn is_copyable(node: &SyntaxNode) -> bool {
+    match node {
+        SyntaxNode::Decimal(_) => true,
+        SyntaxNode::Paren(inner) => matches!(&**inner, SyntaxNode::Neg(value) if matches!(**value, SyntaxNode::Decimal(_))),
+        SyntaxNode::Subscript(letter, subscript) => matches!((&**letter, &**subscript), (SyntaxNode::Letter(_), SyntaxNode::Alphanumeric(_))),
+        _ => false,
+    }
+}
+
+fn inequality_type(operation: Operation) -> Option<InequalityType> {
+    match operation {
+        Operation::LessThan => Some(InequalityType::Less),
+        Operation::GreaterThan => Some(InequalityType::Greater),
+        Operation::LessEqual => Some(InequalityType::LessEqual),
+        Operation::GreaterEqual => Some(InequalityType::GreaterEqual),
+        _ => None
+    }
+}
+
+/// The subscript which a declared name is written with in Desmos, such as `easeout` for `ease_out`.
+pub fn name_subscript(name: &str) -> String {
+    name.chars().filter(|ch| ch.is_alphanumeric()).collect()
+}
+
+/// Whether `name` is a command of any Desmos calculator.
+pub fn is_known_command(name: &str) -> bool {
+    GeometryTarget::VALID_COMMANDS.contains(&name)
+        || GraphingTarget::VALID_COMMANDS.contains(&name)
+        || Graphing3DTarget::VALID_COMMANDS.contains(&name)
+}
@@ -0,0 +1,91 @@
+pub mod desmos;
+
+use crate::DesmosifyError;
+
+use std::collections::BTreeMap;
+
+use json::JsonValue;
+
+pub trait Target {
+    type Output;
+
+    fn name(&self) -> &'static str;
+
+    /// Compiles the analyzed program, or reports everything in it which this target cannot compile.
+    fn compile(&self, definitions: &crate::Definitions, signatures: &crate::Signatures) -> Result<Self::Output, Vec<DesmosifyError>>;
+}
+
+/// Target-specific options, given as `key=value` pairs.
+pub type TargetOptions = BTreeMap<String, String>;
+
+/// A target whose output is Desmos-style JSON, as created by the registry.
+pub type BoxedTarget = Box<dyn Target<Output = JsonValue>>;
+
+/// The name of the target used when neither the command line nor the source selects one.
+pub const DEFAULT_TARGET: &str = "desmos-geometry";
+
+/// Describes a target which can be selected by name, along with the options it accepts.
+pub struct TargetInfo {
+    /// The name of the target, which must match its `Target::name()`.
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Pairs of option names and descriptions.
+    pub options: &'static [(&'static str, &'static str)],
+    /// Creates the target from options which have already been checked against `options`.
+    pub create: fn(&TargetOptions) -> Result<BoxedTarget, DesmosifyError>,
+}
+
+/// Holds every target which can be selected by name.
+pub struct TargetRegistry {
+    targets: Vec<TargetInfo>,
+}
+
+impl TargetRegistry {
+    /// Creates a registry with no targets. Use `TargetRegistry::default()` for the built-in targets.
+    pub fn new() -> Self {
+        Self {
+            targets: Vec::new(),
+        }
+    }
+
+    /// Adds a target, replacing any target previously registered with the same name.
+    pub fn register(&mut self, info: TargetInfo) {
+        self.targets.retain(|target| target.name != info.name);
+        self.targets.push(info);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TargetInfo> {
+        self.targets.iter().find(|target| target.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TargetInfo> {
+        self.targets.iter()
+    }
+
+    /// Creates the target named `name`, rejecting options which it does not accept.
+    pub fn create(&self, name: &str, options: &TargetOptions) -> Result<BoxedTarget, DesmosifyError> {
+        let info = self.get(name).ok_or_else(|| DesmosifyError::new(
+            format!("unknown target '{name}' (use --list-targets to see the available targets)"),
+            None,
+            None,
+        ))?;
+        for key in options.keys() {
+            if !info.options.iter().any(|&(option, _)| option == key) {
+                return Err(DesmosifyError::new(
+                    format!("target {name} does not accept option '{key}'"),
+                    None,
+                    None,
+                ));
+            }
+        }
+        (info.create)(options)
+    }
+}
+
+impl Default for TargetRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        desmos::register_targets(&mut registry);
+        registry
+    }
+}
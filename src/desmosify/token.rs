@@ -173,6 +173,10 @@ pub enum Keyword {
     In,
     Where,
     With,
+    Div,
+    Section,
+    Import,
+    Pub,
 }
 
 impl Keyword {
@@ -194,6 +198,10 @@ impl Keyword {
             "in" => Some(Self::In),
             "where" => Some(Self::Where),
             "with" => Some(Self::With),
+            "div" => Some(Self::Div),
+            "section" => Some(Self::Section),
+            "import" => Some(Self::Import),
+            "pub" => Some(Self::Pub),
             _ => None,
         }
     }
@@ -216,6 +224,10 @@ impl Keyword {
             Self::In => "in",
             Self::Where => "where",
             Self::With => "with",
+            Self::Div => "div",
+            Self::Section => "section",
+            Self::Import => "import",
+            Self::Pub => "pub",
         }
     }
 }
@@ -258,29 +270,65 @@ impl Token {
     }
 
     pub fn is_symbol_or_keyword(&self) -> bool {
-        if let TokenValue::Symbol(_) | TokenValue::Keyword(_) = self.value {
-            true
-        } else {
-            false
-        }
+        matches!(self.value, TokenValue::Symbol(_) | TokenValue::Keyword(_))
+    }
+}
+
+/// The kinds of source text that are not part of any token.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,
+    BlockComment,
+}
+
+/// A run of whitespace or a comment, kept so that the source can be reproduced exactly (see `cst`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub start: SourceLocation,
+    pub end: SourceLocation,
+}
+
+impl Trivia {
+    pub fn is_comment(&self) -> bool {
+        self.kind != TriviaKind::Whitespace
+    }
+
+    pub fn line_break_count(&self) -> usize {
+        self.text.matches('\n').count()
     }
 }
 
 struct Lexer<'a> {
+    source: &'a str,
     stream: std::iter::Peekable<std::str::Chars<'a>>,
     location: SourceLocation,
     tokens: Vec<Token>,
+    trivia: Vec<Trivia>,
 }
 
 impl<'a> Lexer<'a> {
     fn new(source: &'a str) -> Self {
         Lexer {
+            source,
             stream: source.chars().peekable(),
             location: SourceLocation::start(),
             tokens: Vec::new(),
+            trivia: Vec::new(),
         }
     }
 
+    fn push_trivia(&mut self, kind: TriviaKind, start: SourceLocation) {
+        self.trivia.push(Trivia {
+            kind,
+            text: String::from(&self.source[start.index..self.location.index]),
+            start,
+            end: self.location,
+        });
+    }
+
     fn next_char(&mut self) -> Option<char> {
         let next = self.stream.next();
         match next {
@@ -304,11 +352,23 @@ impl<'a> Lexer<'a> {
 }
 
 pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
+    lex(source).map(|lexer| lexer.tokens)
+}
+
+/// Like `tokenize`, but also returns the whitespace and comments between the tokens, in order.
+pub fn tokenize_with_trivia(source: &str) -> Result<(Vec<Token>, Vec<Trivia>), DesmosifyError> {
+    lex(source).map(|lexer| (lexer.tokens, lexer.trivia))
+}
+
+fn lex(source: &str) -> Result<Lexer<'_>, DesmosifyError> {
     let mut lexer = Lexer::new(source);
     while let Some(&next) = lexer.peek_char() {
         let start = lexer.location;
         if next.is_whitespace() {
-            lexer.next_char();
+            while lexer.peek_char().is_some_and(|next| next.is_whitespace()) {
+                lexer.next_char();
+            }
+            lexer.push_trivia(TriviaKind::Whitespace, start);
         } else if next.is_ascii_alphabetic() || next == '_' {
             let mut word = String::new();
             while let Some(&next) = lexer.peek_char() {
@@ -419,8 +479,9 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
             if next == '/' {
                 match lexer.peek_char() {
                     Some(&'/') => {
+                        // The line break ending the comment is left to be read as whitespace
                         let mut ignore_next_newline = false;
-                        while let Some(next) = lexer.next_char() {
+                        while let Some(&next) = lexer.peek_char() {
                             if ignore_next_newline {
                                 if next == '\n' || !next.is_whitespace() {
                                     ignore_next_newline = false;
@@ -430,7 +491,9 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
                             } else if next == '\\' {
                                 ignore_next_newline = true;
                             }
+                            lexer.next_char();
                         }
+                        lexer.push_trivia(TriviaKind::LineComment, start);
                         continue;
                     }
                     Some(&'*') => {
@@ -446,6 +509,7 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
                                 can_close = true;
                             }
                         }
+                        lexer.push_trivia(TriviaKind::BlockComment, start);
                         continue;
                     }
                     _ => {}
@@ -490,7 +554,7 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
             let mut symbol_peek = raw_symbol.clone();
             while let Some(&next) = lexer.peek_char() {
                 symbol_peek.push(next);
-                if let None = Symbol::from_literal(&symbol_peek) {
+                if Symbol::from_literal(&symbol_peek).is_none() {
                     break;
                 }
                 raw_symbol.push(lexer.next_char().unwrap());
@@ -517,5 +581,5 @@ pub fn tokenize(source: &str) -> Result<Vec<Token>, DesmosifyError> {
             ));
         }
     }
-    Ok(lexer.tokens)
+    Ok(lexer)
 }
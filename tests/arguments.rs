@@ -0,0 +1,119 @@
+//! Tests for default parameter values and named arguments, which `semantics` expands into positional arguments.
+
+mod common;
+
+use common::Field;
+use desmosify::interpret::{Interpreter, Value};
+use desmosify::repl::Repl;
+
+const SPAWN: &str = "\
+var x = 0;
+var y = 0;
+let scaled(value: real, factor: real = 2, offset: real = 0) = value * factor + offset;
+action spawn(pos: point, speed: real = 1) {
+    x := pos.x * speed,
+    y := pos.y * speed,
+}
+";
+
+#[test]
+fn calls_pass_every_argument_by_position() {
+    let latex = common::compile(&format!("{SPAWN}\
+let a = scaled(3);
+let b = scaled(3, offset: 1);
+let c = scaled(factor: 10, value: 1);
+let d = [scaled(item, offset: item) for item in [1, 2]];
+ticker {{
+    action spawn(pos: (1, 2)),
+}}
+public {{ a; b; c; d; }}
+"), &[Field::Expressions, Field::Ticker]).unwrap();
+    for expected in [
+        r"X_{a}=X_{scaled}\left(3,2,0\right)",
+        r"X_{b}=X_{scaled}\left(3,2,1\right)",
+        r"X_{c}=X_{scaled}\left(1,10,0\right)",
+        r"X_{d}=\left[X_{scaled}\left(X_{item},2,X_{item}\right)\operatorname{for}X_{item}=\left[1,2\right]\right]",
+        r"X_{spawn}\left(\left(1,2\right),1\right)",
+    ] {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn expanded_calls_give_the_same_values() {
+    let source = format!("{SPAWN}\
+let a = scaled(3);
+let b = scaled(offset: 1, value: 3);
+action go() {{
+    action spawn((1, 2), speed: 3),
+}}
+");
+    let tokens = desmosify::token::tokenize(&source).unwrap();
+    let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+    desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+    let mut interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    assert_eq!(interpreter.value("a").unwrap(), Value::Number(6.0));
+    assert_eq!(interpreter.value("b").unwrap(), Value::Number(7.0));
+    interpreter.run("go", &[]).unwrap();
+    assert_eq!([interpreter.value("x").unwrap(), interpreter.value("y").unwrap()], [Value::Number(3.0), Value::Number(6.0)]);
+}
+
+#[test]
+fn the_repl_shows_and_fills_in_default_values() {
+    let mut repl = Repl::new();
+    assert_eq!(
+        repl.execute("let scaled(value: real, factor: real = 2) = value * factor;").unwrap(),
+        ["let scaled(value: real, factor: real = 2): real"],
+    );
+    assert_eq!(repl.execute("scaled(4)").unwrap()[1], "value: 8");
+    assert_eq!(repl.execute("scaled(factor: 3, value: 4)").unwrap()[1], "value: 12");
+}
+
+#[test]
+fn arguments_must_match_the_parameters() {
+    let scaled = "let scaled(value: real, factor: real = 2) = value * factor;\n";
+    let errors = |call: &str| common::compile(&format!("{scaled}let s = {call};\npublic {{ s; }}\n"), &[Field::Expressions, Field::Ticker]).unwrap_err();
+    assert_eq!(errors("scaled(1, size: 2)"), ["(line 2:19) function scaled has no parameter named 'size'"]);
+    assert_eq!(errors("scaled(1, value: 2)"), ["(line 2:19) function scaled was provided more than one argument for 'value'"]);
+    assert_eq!(errors("scaled(factor: 2)"), ["(line 2:9) function scaled was not provided an argument for 'value'"]);
+    assert_eq!(errors("scaled(1, 2, 3)"), ["(line 2:9) function scaled expects 2 argument(s), but was provided 3"]);
+    assert_eq!(errors("scaled(factor: 2, 1)"), ["(line 2:27) positional arguments cannot follow named arguments"]);
+    assert_eq!(errors("@floor(value: 2.5)"), ["(line 2:16) named arguments can only be passed to user-defined functions and actions"]);
+
+    assert_eq!(
+        common::compile("let f(a: real = 1, b: real) = a + b;\n", &[Field::Expressions, Field::Ticker]).unwrap_err(),
+        ["(line 1:20) parameter 'b' needs a default value, as it follows a parameter with one"],
+    );
+    assert_eq!(
+        common::compile("let f(a: real, b: real = (1, 2)) = a;\n", &[Field::Expressions, Field::Ticker]).unwrap_err(),
+        ["(line 1:27) cannot coerce value of type 'ipoint' to 'real'"],
+    );
+    assert_eq!(
+        common::compile("let f(a: real, b: real = f(1)) = a + b;\n", &[Field::Expressions, Field::Ticker]).unwrap_err(),
+        ["(line 1:26) the default value of 'b' leads back to function f"],
+    );
+    assert_eq!(
+        common::compile("display { (1, 2): @rgb(0, 0, 0), point(5, opacity: 1); }\n", &[Field::Expressions, Field::Ticker]).unwrap_err(),
+        ["(line 1:43) named arguments can only be passed to user-defined functions and actions"],
+    );
+}
+
+#[test]
+fn defaults_are_not_hidden_by_names_at_the_call() {
+    let offset = "var a = 10;\nlet g(x: real, b: real = a) = x + b;\n";
+    let errors = |definition: &str| common::compile(&format!("{offset}{definition}\n"), &[Field::Expressions, Field::Ticker]).unwrap_err();
+    let message = "the default value of 'b' refers to 'a', which is hidden here; pass the argument to function g explicitly";
+    assert_eq!(errors("let h(a: real) = g(a);"), [format!("(line 3:18) {message}")]);
+    assert_eq!(errors("let h = [g(a) for a in [1, 2]];"), [format!("(line 3:10) {message}")]);
+    assert_eq!(errors("let h = (g(a) with a = 1);"), [format!("(line 3:10) {message}")]);
+    assert_eq!(errors("action go(a: real) { a := g(1), }"), [format!("(line 3:27) {message}")]);
+
+    // The default still refers to the variable wherever nothing hides it
+    let source = format!("{offset}let h(y: real) = g(y);\nlet k(a: real) = g(a, b: a);\nlet from_h = h(1);\nlet from_k = k(1);\n");
+    let tokens = desmosify::token::tokenize(&source).unwrap();
+    let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+    desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+    let interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    assert_eq!(interpreter.value("from_h").unwrap(), Value::Number(11.0));
+    assert_eq!(interpreter.value("from_k").unwrap(), Value::Number(2.0));
+}
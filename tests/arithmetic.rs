@@ -0,0 +1,80 @@
+//! Tests for how powers group (`^` and its alias `**`) and for integer division with `div`.
+
+use desmosify::cli;
+use desmosify::link::SourceFile;
+use desmosify::optimize::OptimizationLevel;
+use desmosify::target::desmos::decompile::decompile;
+use desmosify::target::{TargetOptions, TargetRegistry};
+use desmosify::DataType;
+
+/// Compiles `source` at `level`, returning the LaTeX of every expression.
+fn compile(source: &str, level: OptimizationLevel) -> Vec<String> {
+    let (output, _) = cli::compile_with_warnings(
+        &TargetRegistry::default(),
+        &[SourceFile::new("main.desmos", source)],
+        Some("desmos-graphing"),
+        &TargetOptions::new(),
+        level,
+    ).unwrap();
+    Vec::from_iter(output["expressions"]["list"].members().filter_map(|entry| entry["latex"].as_str().map(String::from)))
+}
+
+fn assert_contains(latex: &[String], expected: &[&str]) {
+    for expected in expected {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn powers_group_to_the_right_and_bind_tighter_than_negation() {
+    let latex = compile("\
+var a = 2;
+var b = 3;
+var c = 2;
+let right = a ^ b ^ c;
+let left = (a ^ b) ^ c;
+let star = a ** b ** c;
+let negated = -a ^ 2;
+let negative_exponent = 2 ^ -a ^ b;
+let negative_base = (-a) ^ 2;
+let product = 2 * a ^ b;
+", OptimizationLevel::None);
+    assert_contains(&latex, &[
+        r"X_{right}=X_{a}^{X_{b}^{X_{c}}}",
+        r"X_{left}=\left(X_{a}^{X_{b}}\right)^{X_{c}}",
+        r"X_{star}=X_{a}^{X_{b}^{X_{c}}}",
+        r"X_{negated}=\left(-X_{a}^{2}\right)",
+        r"X_{negativeexponent}=2^{\left(-X_{a}^{X_{b}}\right)}",
+        r"X_{negativebase}=\left(-X_{a}\right)^{2}",
+        r"X_{product}=\left(2*X_{a}^{X_{b}}\right)",
+    ]);
+}
+
+#[test]
+fn powers_are_decompiled_with_the_brackets_they_need() {
+    let state = json::parse(r#"{"expressions": {"list": [
+        {"type": "expression", "id": "1", "latex": "p=\\left(a^{b}\\right)^{c}"},
+        {"type": "expression", "id": "2", "latex": "q=a^{b^{c}}"},
+        {"type": "expression", "id": "3", "latex": "r=-a^{2}"}
+    ]}}"#).unwrap();
+    let source = decompile(&state).unwrap();
+    for expected in ["let p = (a^b)^c;", "let q = a^b^c;", "r = -a^2;"] {
+        assert!(source.contains(expected), "{expected} is missing from {source}");
+    }
+}
+
+#[test]
+fn integer_division_is_floored_and_typed_as_int() {
+    let latex = compile("var a = 2;\nlet quotient = 7.5 div a;\nlet halves = [1, 2, 3] div 2;\n", OptimizationLevel::None);
+    assert_contains(&latex, &[
+        r"X_{quotient}=\operatorname{floor}\left(\frac{7.5}{X_{a}}\right)",
+        r"X_{halves}=\operatorname{floor}\left(\frac{\left[1,2,3\right]}{2}\right)",
+    ]);
+    assert_contains(&compile("let folded = 7 div 2 + -7 div 2 + 7.5 div -2;\n", OptimizationLevel::Simplify), &[r"X_{folded}=\left(-5\right)"]);
+
+    let tokens = desmosify::token::tokenize("var a = 2;\nlet quotient = 7.5 div a;\nlet halves = [1, 2, 3] div 2;\n").unwrap();
+    let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+    desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+    assert_eq!(definitions.identifiers["quotient"].data_type, DataType::Int);
+    assert_eq!(definitions.identifiers["halves"].data_type, DataType::List { item_type: Box::new(DataType::Int) });
+}
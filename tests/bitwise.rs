@@ -0,0 +1,37 @@
+//! Tests for the bitwise and shift operators, which Desmos doesn't have and which are lowered to arithmetic.
+
+mod common;
+
+use common::Field;
+
+#[test]
+fn constants_are_folded_without_optimization() {
+    let latex = common::compile("\
+const FLAG = 1 << 3;
+const MASK = ~0 ^^ 5 | 2;
+const LOW = 4294967301 & 255;
+const QUARTER = 101 >> 2;
+const INVERTED = ~5;
+const RESTORED = ~~(-1);
+var flags: int = 8 | 1;
+", &[Field::Expressions]).unwrap();
+    for expected in [
+        r"X_{FLAG}=8", r"X_{MASK}=4294967290", r"X_{LOW}=5", r"X_{QUARTER}=25", r"X_{INVERTED}=\left(-6\right)",
+        r"X_{RESTORED}=\left(-1\right)", r"X_{flags}=9",
+    ] {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn operators_bind_tighter_than_comparisons() {
+    let latex = common::compile("var flags: int = 9;\nlet has_low = flags & 1 == 1;\nlet shifted = flags << 1 + 1;\n", &[Field::Expressions]).unwrap();
+    assert!(latex.iter().any(|entry| entry.starts_with(r"X_{haslow}=\left\{\operatorname{total}\left(") && entry.ends_with(r"\right)=1,0\right\}")), "{latex:#?}");
+    assert!(latex.contains(&String::from(r"X_{shifted}=\operatorname{floor}\left(X_{flags}*2^{\left(1+1\right)}\right)")), "{latex:#?}");
+}
+
+#[test]
+fn operands_must_be_integers() {
+    assert_eq!(common::compile("let bits = 0.5 | 1;\n", &[Field::Expressions]).unwrap_err(), ["(line 1:12) cannot coerce value of type 'real' to 'int'"]);
+    assert_eq!(common::compile("let bits = ~[1, 2];\n", &[Field::Expressions]).unwrap_err(), ["(line 1:14) cannot coerce value of type '[int]' to 'int'"]);
+}
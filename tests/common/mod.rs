@@ -0,0 +1,34 @@
+//! Helpers shared by the integration tests. Every test crate includes this module but only uses part of it.
+#![allow(dead_code)]
+
+use desmosify::cli;
+use desmosify::link::SourceFile;
+use desmosify::optimize::OptimizationLevel;
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+/// A part of the compiled graph state whose LaTeX is collected by [`compile`].
+pub enum Field {
+    /// The LaTeX of every expression, in order.
+    Expressions,
+    /// The handler of the ticker, if there is one.
+    Ticker,
+}
+
+/// Compiles `source` for the graphing calculator without optimizations, returning the LaTeX of each of `fields` or
+/// the errors.
+pub fn compile(source: &str, fields: &[Field]) -> Result<Vec<String>, Vec<String>> {
+    let (output, _) = cli::compile_with_warnings(
+        &TargetRegistry::default(),
+        &[SourceFile::new("main.desmos", source)],
+        Some("desmos-graphing"),
+        &TargetOptions::new(),
+        OptimizationLevel::None,
+    ).map_err(|errors| Vec::from_iter(errors.iter().map(ToString::to_string)))?;
+    let expressions = &output["expressions"];
+    Ok(Vec::from_iter(fields.iter()
+        .flat_map(|field| match field {
+            Field::Expressions => Vec::from_iter(expressions["list"].members().map(|entry| &entry["latex"])),
+            Field::Ticker => vec![&expressions["ticker"]["handlerLatex"]],
+        })
+        .filter_map(|latex| latex.as_str().map(String::from))))
+}
@@ -0,0 +1,35 @@
+//! Tests for how `&&`, `||`, `!` and `!=` in conditions are lowered to Desmos piecewise expressions.
+
+mod common;
+
+use common::Field;
+
+/// The length of the LaTeX of a chain of `branches` branches, each of which tests two comparisons with `&&`.
+fn chain_length(branches: usize) -> usize {
+    let pieces = Vec::from_iter((0..branches).map(|i| format!("a > {i} && a < {i} + 1: {i}")));
+    let source = format!("var a: real = 0;\nlet r = {{{}, 0}};\n", pieces.join(", "));
+    common::compile(&source, &[Field::Expressions]).unwrap().iter().map(String::len).sum()
+}
+
+#[test]
+fn chains_of_branches_grow_linearly() {
+    let lengths = [4, 8, 16].map(chain_length);
+    // Every branch adds about as much as the one before, rather than repeating the rest of the chain
+    let (first, second) = (lengths[1] - lengths[0], lengths[2] - lengths[1]);
+    assert!(second <= first * 2 + first / 4, "{lengths:?}");
+}
+
+#[test]
+fn branches_are_written_out_once() {
+    let latex = common::compile("\
+var a: real = 0;
+var b: real = 0;
+var count: int = 0;
+action step() {
+    if a > 0 || b > 0: count := count + 123,
+}
+let either = {a > 0 && b > 0: a * 456, 0};
+", &[Field::Expressions]).unwrap().join("\n");
+    assert_eq!(latex.matches("123").count(), 1, "{latex}");
+    assert_eq!(latex.matches("456").count(), 1, "{latex}");
+}
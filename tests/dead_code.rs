@@ -0,0 +1,85 @@
+//! Tests for leaving definitions that nothing in the graph uses out of the output.
+
+use desmosify::cli;
+use desmosify::link::SourceFile;
+use desmosify::optimize::OptimizationLevel;
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+fn compile(source: &str) -> (String, Vec<String>) {
+    let (output, warnings) = cli::compile_with_warnings(
+        &TargetRegistry::default(),
+        &[SourceFile::new("main.desmos", source)],
+        Some("desmos-graphing"),
+        &TargetOptions::new(),
+        OptimizationLevel::default(),
+    ).unwrap();
+    (output.to_string(), Vec::from_iter(warnings.iter().map(ToString::to_string)))
+}
+
+#[test]
+fn unreachable_definitions_are_removed_with_warnings() {
+    let (output, warnings) = compile("\
+var count: int = 0;
+var unused_count: int = 0;
+let step = 2;
+let shown = 3 * count;
+let helper(n: int) = n + 1;
+let unused = helper(1);
+action reset() {
+    count := 0,
+}
+action bump() {
+    count := count + step,
+}
+action unused_action() {
+    count := unused,
+}
+ticker(interval) {
+    action bump(),
+}
+let interval = 100;
+display {
+    (shown, 0): @rgb(0, 0, 0), click { action reset() };
+}
+");
+    assert_eq!(warnings, [
+        "(line 5:5) let 'helper' is never used, so it is left out (list it in 'public' to keep it)",
+        "(line 6:5) let 'unused' is never used, so it is left out (list it in 'public' to keep it)",
+        "(line 13:8) action 'unused_action' is never used, so it is left out (list it in 'public' to keep it)",
+    ]);
+    // Reached from the display element, its click handler and the ticker, and variables are always kept
+    for name in ["X_{count}", "X_{unusedcount}", "X_{step}", "X_{shown}", "X_{reset}", "X_{bump}", "X_{interval}"] {
+        assert!(output.contains(name), "{name} is missing from {output}");
+    }
+    for name in ["X_{helper}", "X_{unused}", "X_{unusedaction}"] {
+        assert!(!output.contains(name), "{name} should not be in {output}");
+    }
+}
+
+#[test]
+fn public_definitions_are_kept() {
+    let (output, warnings) = compile("let a = 1; let b = a + 1; let c = 3; public { b; }");
+    assert_eq!(warnings, ["(line 1:31) let 'c' is never used, so it is left out (list it in 'public' to keep it)"]);
+    assert!(output.contains("X_{a}=1") && output.contains("X_{b}") && !output.contains("X_{c}"), "{output}");
+
+    // Without any of the blocks the graph is made of, everything is kept
+    let (output, warnings) = compile("let a = 1; let b = a + 1; let c = 3;");
+    assert!(warnings.is_empty(), "{warnings:?}");
+    assert!(output.contains("X_{c}=3"), "{output}");
+}
+
+#[test]
+fn emptied_sections_are_removed() {
+    let (output, warnings) = compile("\
+section \"Kept\";
+let a = 1;
+section \"Unused\";
+let b = 2;
+section \"Also kept\";
+let c = a + 1;
+public { c; }
+");
+    assert_eq!(warnings.len(), 1);
+    assert!(!output.contains("\"title\":\"Unused\""), "{output}");
+    assert!(output.contains("\"id\":\"desmosify:folder:2\",\"title\":\"Also kept\""), "{output}");
+}
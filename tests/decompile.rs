@@ -0,0 +1,66 @@
+//! Tests for importing graph states: every example in `src/desmosify-test` is compiled, imported back into source and
+//! compiled again, and the imported source must already be formatted.
+
+use std::path::PathBuf;
+
+use desmosify::cli::compile_source;
+use desmosify::format::format;
+use desmosify::target::desmos::decompile::decompile;
+use desmosify::target::{TargetOptions, TargetRegistry};
+use json::JsonValue;
+
+/// Compiles `source` for the target it asks for, or the default target.
+fn compile(source: &str, file_name: &str) -> Result<JsonValue, String> {
+    compile_source(&TargetRegistry::default(), source, file_name, None, &TargetOptions::new())
+        .map_err(|errors| errors.iter().map(|error| format!("{error}\n")).collect())
+}
+
+/// Imports `state`, checking that the source is formatted and compiles.
+fn import(state: &JsonValue, name: &str) -> String {
+    let source = decompile(state).unwrap_or_else(|errors| panic!("could not import {name}: {errors:?}"));
+    let formatted = format(&source).unwrap_or_else(|errors| panic!("could not format the source imported from {name}: {errors:?}"));
+    assert_eq!(formatted, source, "the source imported from {name} is not formatted");
+    if let Err(errors) = compile(&source, "imported.desmos") {
+        panic!("the source imported from {name} does not compile:\n{errors}\n{source}");
+    }
+    source
+}
+
+#[test]
+fn examples_survive_a_round_trip() {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "desmos"))
+        .collect();
+    paths.sort();
+
+    let mut imported = 0;
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = std::fs::read_to_string(&path).unwrap();
+        // Examples that don't compile are covered by their snapshots
+        let Ok(state) = compile(&source, &name) else { continue };
+        import(&state, &name);
+        imported += 1;
+    }
+    assert!(imported > 0, "no examples compiled");
+}
+
+#[test]
+fn variables_computed_by_the_graph_start_with_their_value() {
+    let state = json::parse(r#"{
+        "version": 11,
+        "graph": {"product": "graphing"},
+        "expressions": {"list": [
+            {"type": "expression", "id": "1", "latex": "S_{ize}=3"},
+            {"type": "expression", "id": "2", "latex": "X_{cells}=\\left[1...S_{ize}\\right]\\cdot2"},
+            {"type": "expression", "id": "3", "latex": "P_{cursor}=\\left(S_{ize},-S_{ize}\\right)"},
+            {"type": "expression", "id": "4", "latex": "A_{step}=X_{cells}\\to X_{cells}+1,P_{cursor}\\to P_{cursor}+\\left(1,0\\right)"}
+        ]}
+    }"#).unwrap();
+    let source = import(&state, "the graph");
+    assert!(source.contains("var cells = [2, 4, 6];"), "{source}");
+    assert!(source.contains("var cursor = (3, -3);"), "{source}");
+}
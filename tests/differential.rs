@@ -0,0 +1,364 @@
+//! Differential tests for code generation: random programs are run by the source-level interpreter and, after being
+//! compiled for Desmos, by the evaluator for Desmos graph state. Any difference is a bug in one of the two (usually in
+//! the lowering done by `translate_operator`). Programs can also be optimized before they are compiled, in which case
+//! the interpreter still runs them as written.
+
+use desmosify::interpret::{Interpreter, Value};
+use desmosify::optimize::{self, OptimizationLevel};
+use desmosify::target::desmos::evaluate::Evaluator;
+use desmosify::target::TargetRegistry;
+
+const PROGRAMS: usize = 40;
+const RESULTS_PER_PROGRAM: usize = 15;
+const MAX_DEPTH: usize = 4;
+/// Unrolled folds nest every step in the one before, deeper than the default stack of a test thread allows for a debug
+/// build.
+const STACK_SIZE: usize = 16 << 20;
+
+/// A small xorshift generator, so that failures can be reproduced from the seed alone.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn choose<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.below(options.len())]
+    }
+}
+
+/// Generates source for expressions of the program's types, using the declarations in `PRELUDE`.
+struct Generator {
+    random: Random,
+    /// Whether the logical operators may be given numbers other than 0 and 1.
+    loose_booleans: bool,
+    /// Whether numbers may be computed with bitwise and shift operators, whose operands are integers.
+    bitwise: bool,
+    /// Whether lists and numbers may be computed with the higher-order built-ins, given lambdas.
+    higher_order: bool,
+    /// Names bound by enclosing `for` and `with` expressions.
+    locals: Vec<&'static str>,
+}
+
+const PRELUDE: &str = r#"
+#target "desmos-graphing";
+
+var a = 3;
+var b = 0.5;
+var c = 0;
+var flag = true;
+var timer clock = 0;
+
+let numbers = [4, 1, 3, 2];
+let double(n) = n * 2 + 1;
+"#;
+
+impl Generator {
+    fn number(&mut self, depth: usize) -> String {
+        if self.bitwise && self.random.below(3) == 0 {
+            return self.integer(depth);
+        }
+        if self.higher_order && depth > 0 && self.random.below(4) == 0 {
+            return self.higher_order_number(depth - 1);
+        }
+        if depth == 0 || self.random.below(4) == 0 {
+            return self.number_leaf();
+        }
+        let depth = depth - 1;
+        match self.random.below(16) {
+            0 => format!("({} + {})", self.number(depth), self.number(depth)),
+            1 => format!("({} - {})", self.number(depth), self.number(depth)),
+            2 => format!("({} * {})", self.number(depth), self.number(depth)),
+            3 => format!("({} / {})", self.number(depth), self.number(depth)),
+            4 => format!("({} % {})", self.number(depth), self.number(depth)),
+            5 => format!("({} ^ {})", self.number(depth), self.random.below(4)),
+            6 => format!("-{}", self.number(depth)),
+            7 => format!("{{{}: {}, {}}}", self.boolean(depth), self.number(depth), self.number(depth)),
+            8 => format!("{{{}: {}, {}: {}, {}}}", self.boolean(depth), self.number(depth), self.boolean(depth), self.number(depth), self.number(depth)),
+            9 => format!("double({})", self.number(depth)),
+            10 => format!("@{}({})", self.random.choose(&["floor", "ceil", "round", "sign", "sqrt"]), self.number(depth)),
+            11 => format!("@{}({}, {})", self.random.choose(&["min", "max", "mod"]), self.number(depth), self.number(depth)),
+            12 => format!("@{}({})", self.random.choose(&["total", "count", "mean", "min", "max"]), self.list(depth)),
+            // Unbracketed, as powers group to the right and bind tighter than negation
+            13 => format!("-{} ** {} ^ {}", self.number_leaf(), self.random.below(3), self.random.below(3)),
+            14 => format!("({} div {})", self.number(depth), self.number(depth)),
+            _ => {
+                let value = self.number(depth);
+                self.locals.push("k");
+                let body = self.number(depth);
+                self.locals.pop();
+                format!("({body} with k = {value})")
+            },
+        }
+    }
+
+    fn number_leaf(&mut self) -> String {
+        match self.random.below(8) {
+            0 | 1 => self.random.below(10).to_string(),
+            2 => String::from(self.random.choose(&["0.5", "2.25", "1.5"])),
+            3 => format!("numbers[{}]", self.random.below(6)),
+            4 if !self.locals.is_empty() => String::from(self.locals[self.random.below(self.locals.len())]),
+            _ => String::from(self.random.choose(&["a", "b", "c", "@pi"])),
+        }
+    }
+
+    /// Integers (including negative ones and ones larger than 32 bits) for the bitwise and shift operators.
+    fn integer(&mut self, depth: usize) -> String {
+        if depth == 0 || self.random.below(4) == 0 {
+            return match self.random.below(4) {
+                0 => self.random.below(300).to_string(),
+                1 => String::from(self.random.choose(&["-1", "-6", "4294967301", "-4294967296"])),
+                _ => String::from(self.random.choose(&["a", "c", "2", "5"])),
+            };
+        }
+        let depth = depth - 1;
+        match self.random.below(8) {
+            0 => format!("({} & {})", self.integer(depth), self.integer(depth)),
+            1 => format!("({} ^^ {})", self.integer(depth), self.integer(depth)),
+            2 => format!("({} | {})", self.integer(depth), self.integer(depth)),
+            3 => format!("~{}", self.integer(depth)),
+            4 => format!("({} << {})", self.integer(depth), self.random.below(40)),
+            5 => format!("({} >> {})", self.integer(depth), self.random.below(40)),
+            6 => format!("({} + {})", self.integer(depth), self.integer(depth)),
+            _ => format!("({} * {})", self.integer(depth), self.integer(depth)),
+        }
+    }
+
+    /// Numbers from the higher-order built-ins. Folds which can't be written with `@total`, `@max` or `@min` are made of
+    /// leaves, as they are written out as a step per item nested in the one before.
+    fn higher_order_number(&mut self, depth: usize) -> String {
+        match self.random.below(4) {
+            0 | 1 => {
+                let list = if self.random.below(2) == 0 {
+                    String::from("numbers")
+                } else {
+                    format!("[{}, {}, {}]", self.number_leaf(), self.number_leaf(), self.number_leaf())
+                };
+                let body = self.lambda_body(&["acc", "j"], |generator| format!(
+                    "{} {} {}",
+                    generator.number_leaf(),
+                    generator.random.choose(&["+", "-", "*", "/", "%"]),
+                    generator.number_leaf(),
+                ));
+                if self.random.below(2) == 0 {
+                    format!("@fold({list}, {}, (acc, j) => {body})", self.number_leaf())
+                } else {
+                    format!("@reduce({list}, (acc, j) => {body})")
+                }
+            },
+            2 => {
+                let list = self.list(depth);
+                let initial = self.number(depth);
+                let item = self.lambda_body(&["j"], |generator| generator.number(depth));
+                let operation = self.random.choose(&["acc + ITEM", "ITEM + acc", "@max(acc, ITEM)", "@min(ITEM, acc)"]);
+                format!("@fold({list}, {initial}, (acc, j) => {})", operation.replace("ITEM", &item))
+            },
+            _ => {
+                let list = self.list(depth);
+                let body = self.lambda_body(&["j"], |generator| generator.boolean(depth));
+                format!("@count({list}, j => {body})")
+            },
+        }
+    }
+
+    fn lambda_body(&mut self, parameters: &[&'static str], body: impl FnOnce(&mut Self) -> String) -> String {
+        self.locals.extend(parameters);
+        let body = body(self);
+        self.locals.truncate(self.locals.len() - parameters.len());
+        body
+    }
+
+    fn boolean(&mut self, depth: usize) -> String {
+        if depth == 0 || self.random.below(4) == 0 {
+            return String::from(self.random.choose(&["true", "false", "flag"]));
+        }
+        let depth = depth - 1;
+        if self.higher_order && self.random.below(4) == 0 {
+            let list = self.list(depth);
+            let body = self.lambda_body(&["j"], |generator| generator.boolean(depth));
+            return format!("@{}({list}, j => {body})", self.random.choose(&["any", "all"]));
+        }
+        let operand = |generator: &mut Self| if generator.loose_booleans {
+            generator.number(depth)
+        } else {
+            generator.boolean(depth)
+        };
+        match self.random.below(9) {
+            0 => format!("!{}", operand(self)),
+            1 => format!("({} && {})", operand(self), operand(self)),
+            2 => format!("({} || {})", operand(self), operand(self)),
+            comparison => format!(
+                "({} {} {})",
+                self.number(depth),
+                ["<", ">", "<=", ">=", "==", "!="][comparison - 3],
+                self.number(depth),
+            ),
+        }
+    }
+
+    fn list(&mut self, depth: usize) -> String {
+        if depth == 0 {
+            return String::from("numbers");
+        }
+        let depth = depth - 1;
+        if self.higher_order && self.random.below(3) == 0 {
+            let list = self.list(depth);
+            return match self.random.below(5) {
+                0 => format!("@map({list}, j => {})", self.lambda_body(&["j"], |generator| generator.number(depth))),
+                1 => format!("@filter({list}, j => {})", self.lambda_body(&["j"], |generator| generator.boolean(depth))),
+                2 => format!("@sort_by({list}, j => {})", self.lambda_body(&["j"], |generator| generator.number(depth))),
+                3 => {
+                    let other = self.list(depth);
+                    format!("@zip({list}, {other}, (j, k) => {})", self.lambda_body(&["j", "k"], |generator| generator.number(depth)))
+                },
+                _ => format!("@map({list}, double)"),
+            };
+        }
+        match self.random.below(7) {
+            0 => format!("[{}, {}, {}]", self.number(depth), self.number(depth), self.number(depth)),
+            1 => {
+                self.locals.push("j");
+                let body = self.number(depth);
+                self.locals.pop();
+                format!("[{body} for j in {}]", self.list(depth))
+            },
+            2 => format!("[numbers where numbers > {}]", self.number(depth)),
+            3 => {
+                let first = self.random.below(4);
+                format!("[{first} ..= {}]", first + self.random.below(5))
+            },
+            4 => {
+                let first = self.random.below(4);
+                format!("[{first} .. {}]", first + 1 + self.random.below(5))
+            },
+            5 => format!("[{}; {}]", self.number(depth), 1 + self.random.below(4)),
+            _ => String::from("numbers"),
+        }
+    }
+}
+
+fn same_value(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (&Value::Number(lhs), &Value::Number(rhs)) => {
+            (lhs.is_nan() && rhs.is_nan()) || lhs == rhs || (lhs - rhs).abs() <= 1e-9 * lhs.abs().max(rhs.abs())
+        },
+        (Value::List(lhs), Value::List(rhs)) => lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| same_value(lhs, rhs)),
+        _ => lhs == rhs,
+    }
+}
+
+/// Compiles `source`, then checks that the interpreter and the evaluator agree on the value of every `let` named in
+/// `results`, and on the value of every variable after running `step` (if `run_step`) and ticking.
+fn compare(source: &str, results: &[(String, String)], run_step: bool, level: OptimizationLevel) -> Vec<String> {
+    let analyze = || {
+        let tokens = desmosify::token::tokenize(source).unwrap();
+        let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+        desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+        (signatures, definitions)
+    };
+    let (signatures, definitions) = analyze();
+    let (mut optimized_signatures, mut optimized_definitions) = analyze();
+    optimize::optimize(&mut optimized_signatures, &mut optimized_definitions, level);
+    let target = TargetRegistry::default().create("desmos-graphing", &Default::default()).unwrap();
+    let state = target.compile(&optimized_definitions, &optimized_signatures).unwrap();
+
+    let mut interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    let mut evaluator = Evaluator::new(&state).unwrap();
+    let mut mismatches = Vec::new();
+    let mut check = |interpreter: &Interpreter, evaluator: &Evaluator, name: &str, description: &str| {
+        let expected = interpreter.value(name).map_err(|error| error.to_string());
+        let actual = evaluator.evaluate_latex(&format!("X_{{{}}}", name.replace('_', ""))).map_err(|error| error.to_string());
+        let agree = match (&expected, &actual) {
+            (Ok(expected), Ok(actual)) => same_value(expected, actual),
+            _ => false,
+        };
+        if !agree {
+            mismatches.push(format!("{description}\n    interpreter: {expected:?}\n    evaluator:   {actual:?}"));
+        }
+    };
+
+    for (name, expression) in results {
+        check(&interpreter, &evaluator, name, expression);
+    }
+    if run_step {
+        interpreter.run("step", &[]).unwrap();
+        evaluator.run_latex("X_{step}").unwrap();
+        interpreter.tick(16.0).unwrap();
+        evaluator.tick(16.0).unwrap();
+        for name in ["a", "b", "c", "flag", "clock"] {
+            check(&interpreter, &evaluator, name, &format!("{name} after step and tick"));
+        }
+    }
+    mismatches
+}
+
+fn run(seed: u64, loose_booleans: bool, bitwise: bool, higher_order: bool, run_step: bool, level: OptimizationLevel) {
+    std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let mut generator = Generator {
+            random: Random(seed),
+            loose_booleans,
+            bitwise,
+            higher_order,
+            locals: Vec::new(),
+        };
+        let mut mismatches = Vec::new();
+        for _ in 0..PROGRAMS {
+            let mut source = String::from(PRELUDE);
+            let mut results = Vec::new();
+            for index in 0..RESULTS_PER_PROGRAM {
+                let name = format!("result_{index}");
+                let expression = if generator.random.below(3) == 0 {
+                    generator.boolean(MAX_DEPTH)
+                } else {
+                    generator.number(MAX_DEPTH)
+                };
+                source.push_str(&format!("let {name} = {expression};\n"));
+                results.push((name, expression));
+            }
+            // Every update in an action sees the values from before it, so swapping `a` and `b` must work
+            source.push_str(&format!(
+                "action step() {{\n    a := b,\n    b := a,\n    c := {},\n    flag := {},\n}}\n",
+                generator.number(MAX_DEPTH),
+                generator.boolean(MAX_DEPTH),
+            ));
+            source.push_str(&format!("ticker {{\n    c := c + @dt * {},\n}}\n", generator.number(2)));
+            mismatches.extend(compare(&source, &results, run_step, level));
+        }
+        assert!(mismatches.is_empty(), "{} mismatch(es):\n{}", mismatches.len(), mismatches.join("\n"));
+    }).unwrap().join().unwrap();
+}
+
+#[test]
+fn expressions_and_actions_agree() {
+    run(0x5eed_1234_abcd_0001, false, false, false, true, OptimizationLevel::None);
+}
+
+#[test]
+fn logical_operators_on_numbers_agree() {
+    run(0x5eed_1234_abcd_0002, true, false, false, false, OptimizationLevel::None);
+}
+
+#[test]
+fn optimized_programs_agree() {
+    run(0x5eed_1234_abcd_0003, false, false, false, true, OptimizationLevel::Full);
+}
+
+#[test]
+fn bitwise_operators_agree() {
+    run(0x5eed_1234_abcd_0004, false, true, false, true, OptimizationLevel::None);
+    run(0x5eed_1234_abcd_0005, false, true, false, true, OptimizationLevel::Full);
+}
+
+#[test]
+fn higher_order_builtins_agree() {
+    run(0x5eed_1234_abcd_0006, false, false, true, true, OptimizationLevel::None);
+    run(0x5eed_1234_abcd_0007, false, false, true, true, OptimizationLevel::Full);
+}
@@ -0,0 +1,148 @@
+//! Tests for the lossless syntax tree and the formatter built on it. Every source that parses must come back unchanged
+//! from the syntax tree, and formatting must be idempotent and must not change what a program compiles to.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use desmosify::cli::{self, compile_source, DesmosifyArgs};
+use desmosify::target::{TargetOptions, TargetRegistry};
+use desmosify::{cst, format};
+
+/// Every source in the repository that the parser accepts: the examples and the error fixtures that fail later on.
+fn parsable_sources() -> Vec<(PathBuf, String)> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut sources = Vec::new();
+    for directory in [root.join("src/desmosify-test"), root.join("tests/errors")] {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "desmos"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let source = std::fs::read_to_string(&path).unwrap();
+            if format::format(&source).is_ok() {
+                sources.push((path, source));
+            }
+        }
+    }
+    assert!(sources.len() > 2, "expected most sources to parse");
+    sources
+}
+
+const MESSY: &str = r#"#target   "desmos-graphing"  ;
+import   easing::{ ease_out ,ease_linear, };
+// counter
+var  count : int=0;   // trailing
+let xs=[1,2,3,];
+let origin=(0,0,);
+
+
+action bump(){ if count>2: count:=0, else: count := count+1,   }
+pub  let sign(a:real)={a>0:1,a<0:-1,0};
+let scaled(a:real,k:real=2)=a*k;
+let tripled=scaled(1,k :3);
+"#;
+
+const FORMATTED: &str = r#"#target "desmos-graphing";
+import easing::{ease_out, ease_linear};
+// counter
+var count: int = 0; // trailing
+let xs = [1, 2, 3];
+let origin = (0, 0);
+
+action bump() {
+    if count > 2: {
+        count := 0,
+    } else: {
+        count := count + 1,
+    },
+}
+pub let sign(a: real) = {
+    a > 0: 1,
+    a < 0: -1,
+    0
+};
+let scaled(a: real, k: real = 2) = a * k;
+let tripled = scaled(1, k: 3);
+"#;
+
+#[test]
+fn syntax_tree_is_lossless() {
+    for (path, source) in parsable_sources().into_iter().chain([(PathBuf::from("<messy>"), MESSY.to_string())]) {
+        let tree = cst::parse(&source).unwrap_or_else(|error| panic!("{}: {error}", path.display()));
+        assert_eq!(tree.text(), source, "{} does not round-trip through the syntax tree", path.display());
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for (path, source) in parsable_sources() {
+        let formatted = format::format(&source).unwrap();
+        let again = format::format(&formatted)
+            .unwrap_or_else(|errors| panic!("formatted {} does not parse: {errors:?}", path.display()));
+        assert_eq!(again, formatted, "formatting {} twice changes it", path.display());
+    }
+}
+
+#[test]
+fn formatting_canonicalizes_layout() {
+    assert_eq!(format::format(MESSY).unwrap(), FORMATTED);
+    assert_eq!(format::format(FORMATTED).unwrap(), FORMATTED);
+}
+
+#[test]
+fn formatting_preserves_compiled_output() {
+    let registry = TargetRegistry::default();
+    let options = TargetOptions::new();
+    for (path, source) in parsable_sources() {
+        let formatted = format::format(&source).unwrap();
+        // The real path is given so that imports are found
+        let file_name = path.to_string_lossy();
+        let Ok(expected) = compile_source(&registry, &source, &file_name, None, &options) else {
+            continue;
+        };
+        let actual = compile_source(&registry, &formatted, &file_name, None, &options)
+            .unwrap_or_else(|errors| panic!("formatted {} does not compile: {errors:?}", path.display()));
+        assert_eq!(actual, expected, "formatting {} changes its output", path.display());
+    }
+}
+
+#[test]
+fn comments_stay_next_to_their_tokens() {
+    let source = "\
+let f(/* input */ a: real) = {a > 0: /* inner */ 1, // after branch
+0};
+let xs = [1 /* one */, 2, // two
+3];
+/* leading */ let p = f(/* argument */ 1) + /* more */ 2 /* last */;
+";
+    assert_eq!(format::format(source).unwrap(), "\
+let f(/* input */ a: real) = {
+    a > 0: /* inner */ 1, // after branch
+    0
+};
+let xs = [
+    1 /* one */,
+    2, // two
+    3,
+];
+/* leading */ let p = f(/* argument */ 1) + /* more */ 2 /* last */;
+");
+}
+
+#[test]
+fn unreadable_files_do_not_stop_the_others_from_being_formatted() {
+    let directory = std::env::temp_dir().join(format!("desmosify-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let missing = directory.join("missing.desmos").to_string_lossy().into_owned();
+    let messy = directory.join("messy.desmos").to_string_lossy().into_owned();
+    std::fs::write(&messy, MESSY).unwrap();
+
+    let args = DesmosifyArgs::try_parse_from(["desmosify", "fmt", &missing, &messy]).unwrap();
+    let errors = cli::invoke(&args).unwrap_err();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].to_string().starts_with(&format!("could not read '{missing}'")), "{}", errors[0]);
+    assert_eq!(std::fs::read_to_string(&messy).unwrap(), FORMATTED);
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,187 @@
+//! Golden-file tests. Every example in `src/desmosify-test` is compiled and its output compared to the snapshots in
+//! `tests/snapshots`: `<name>.json` holds the graph state and `<name>.latex` one line per expression, which is easier to
+//! review in a diff. Examples that fail to compile are compared to `<name>.errors` instead. Every fixture in
+//! `tests/errors` must fail to compile with exactly the diagnostics in the `.errors` file next to it. The files in
+//! `tests/layout` are compiled together with every definition order and grouping, into `layout/<order>-<group>.latex`,
+//! and `tests/targets/main.desmos` is compiled for every registered target, into `targets/<target>.json`.
+//!
+//! Run with `DESMOSIFY_BLESS=1` to write the current output as the expected output.
+
+use std::path::{Path, PathBuf};
+
+use desmosify::cli::{compile_source, compile_sources, describe_targets};
+use desmosify::link::SourceFile;
+use desmosify::target::{TargetOptions, TargetRegistry};
+use json::JsonValue;
+
+const BLESS_VARIABLE: &str = "DESMOSIFY_BLESS";
+
+fn blessing() -> bool {
+    std::env::var_os(BLESS_VARIABLE).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+fn sources(directory: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)
+        .unwrap_or_else(|error| panic!("could not read '{}': {error}", directory.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "desmos"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Compiles the file at `path` like the command line would, with the path relative to the repository in diagnostics
+/// so that the snapshots do not depend on where the repository is. Tests run from the repository, so imports are
+/// still found.
+fn compile(path: &Path) -> Result<JsonValue, String> {
+    let source = std::fs::read_to_string(path).unwrap();
+    let file_name = path.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap_or(path).to_string_lossy();
+    compile_source(&TargetRegistry::default(), &source, &file_name, None, &TargetOptions::new())
+        .map_err(|errors| errors.iter().map(|error| format!("{error}\n")).collect())
+}
+
+/// Lists the LaTeX (or text, for notes and folders) of every entry in a graph state, then the ticker handler.
+fn latex_listing(state: &JsonValue) -> String {
+    let mut listing = String::new();
+    for entry in state["expressions"]["list"].members() {
+        let line = match entry["type"].as_str() {
+            Some("expression") => entry["latex"].as_str(),
+            Some("text") => entry["text"].as_str(),
+            Some("folder") => entry["title"].as_str(),
+            _ => None,
+        };
+        listing.push_str(&format!("{}: {}\n", entry["type"], line.unwrap_or_default().replace('\n', "\\n")));
+    }
+    if let Some(handler) = state["expressions"]["ticker"]["handlerLatex"].as_str() {
+        listing.push_str(&format!("ticker: {handler}\n"));
+    }
+    listing
+}
+
+/// Compares `actual` to the contents of `path` (or writes it there when blessing), recording any difference.
+fn check_snapshot(path: &Path, actual: &str, failures: &mut Vec<String>) {
+    if blessing() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, actual).unwrap();
+        return;
+    }
+    match std::fs::read_to_string(path) {
+        Ok(expected) if expected == actual => {},
+        Ok(expected) => failures.push(format!(
+            "{} differs from the output\n{}",
+            path.display(),
+            difference(&expected, actual),
+        )),
+        Err(_) => failures.push(format!("{} does not exist; run with {BLESS_VARIABLE}=1 to create it", path.display())),
+    }
+}
+
+/// Removes a snapshot for an outcome that no longer happens, so that blessing does not leave stale files behind.
+fn check_absent(path: &Path, failures: &mut Vec<String>) {
+    if !path.exists() {
+        return;
+    }
+    if blessing() {
+        std::fs::remove_file(path).unwrap();
+    } else {
+        failures.push(format!("{} exists but is not produced; run with {BLESS_VARIABLE}=1 to remove it", path.display()));
+    }
+}
+
+/// Describes the first line at which two snapshots differ.
+fn difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => {},
+            (expected, actual) => return format!(
+                "    first difference at line {line}\n    expected: {}\n    actual:   {}",
+                expected.unwrap_or("<end of file>"),
+                actual.unwrap_or("<end of file>"),
+            ),
+        }
+    }
+    unreachable!()
+}
+
+fn finish(failures: Vec<String>) {
+    assert!(failures.is_empty(), "{} failure(s):\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn examples_match_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let snapshots = root.join("tests/snapshots");
+    let mut failures = Vec::new();
+    for path in sources(&root.join("src/desmosify-test")) {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let json_path = snapshots.join(format!("{name}.json"));
+        let latex_path = snapshots.join(format!("{name}.latex"));
+        let errors_path = snapshots.join(format!("{name}.errors"));
+        match compile(&path) {
+            Ok(state) => {
+                check_snapshot(&json_path, &(state.pretty(4) + "\n"), &mut failures);
+                check_snapshot(&latex_path, &latex_listing(&state), &mut failures);
+                check_absent(&errors_path, &mut failures);
+            },
+            Err(errors) => {
+                check_snapshot(&errors_path, &errors, &mut failures);
+                check_absent(&json_path, &mut failures);
+                check_absent(&latex_path, &mut failures);
+            },
+        }
+    }
+    finish(failures);
+}
+
+#[test]
+fn error_fixtures_report_exact_diagnostics() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/errors");
+    let mut failures = Vec::new();
+    for path in sources(&directory) {
+        match compile(&path) {
+            Ok(_) => failures.push(format!("{} compiled without errors", path.display())),
+            Err(errors) => check_snapshot(&path.with_extension("errors"), &errors, &mut failures),
+        }
+    }
+    finish(failures);
+}
+
+#[test]
+fn layouts_match_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let files = Vec::from_iter(sources(&root.join("tests/layout")).iter().map(|path| SourceFile::new(
+        &path.file_name().unwrap().to_string_lossy(),
+        &std::fs::read_to_string(path).unwrap(),
+    )));
+    let mut failures = Vec::new();
+    for order in ["dependency", "source"] {
+        for group in ["section", "file", "kind"] {
+            let mut options = TargetOptions::new();
+            options.insert(String::from("order"), String::from(order));
+            options.insert(String::from("group"), String::from(group));
+            let state = compile_sources(&TargetRegistry::default(), &files, None, &options)
+                .unwrap_or_else(|errors| panic!("the layout fixture should compile: {errors:?}"));
+            let path = root.join(format!("tests/snapshots/layout/{order}-{group}.latex"));
+            check_snapshot(&path, &latex_listing(&state), &mut failures);
+        }
+    }
+    finish(failures);
+}
+
+#[test]
+fn targets_match_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let snapshots = root.join("tests/snapshots/targets");
+    let registry = TargetRegistry::default();
+    let source = std::fs::read_to_string(root.join("tests/targets/main.desmos")).unwrap();
+    let mut failures = Vec::new();
+    check_snapshot(&snapshots.join("list.txt"), &describe_targets(&registry), &mut failures);
+    for info in registry.iter() {
+        let state = compile_source(&registry, &source, "main.desmos", Some(info.name), &TargetOptions::new())
+            .unwrap_or_else(|errors| panic!("the fixture should compile for {}: {errors:?}", info.name));
+        check_snapshot(&snapshots.join(format!("{}.json", info.name)), &(state.pretty(4) + "\n"), &mut failures);
+    }
+    finish(failures);
+}
@@ -0,0 +1,150 @@
+//! Tests for lambdas and the higher-order built-ins, which are specialized into comprehensions, filters and unrolled
+//! steps as they are parsed.
+
+mod common;
+
+use common::Field;
+use desmosify::cli;
+use desmosify::interpret::{Interpreter, Value};
+use desmosify::link::SourceFile;
+use desmosify::optimize::OptimizationLevel;
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+/// Runs `source` in the interpreter, returning the value of each of `names`.
+fn values(source: &str, names: &[&str]) -> Vec<Value> {
+    let tokens = desmosify::token::tokenize(source).unwrap();
+    let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+    desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+    let interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    Vec::from_iter(names.iter().map(|name| interpreter.value(name).unwrap()))
+}
+
+fn numbers(values: &[f64]) -> Value {
+    Value::List(Vec::from_iter(values.iter().copied().map(Value::Number)))
+}
+
+#[test]
+fn builtins_are_specialized_into_comprehensions_and_filters() {
+    let latex = common::compile("\
+let L = [3, 1, 4];
+let double(x: real) = x * 2;
+let doubled = @map(L, x => x * 2);
+let named = @map(L, double);
+let large = @filter(L, x => x > 2);
+let sorted = @sort_by(L, x => -x);
+let squares = @fold(L, 1, (acc, x) => acc + x ^ 2);
+let largest = @reduce(L, (a, b) => @max(a, b));
+", &[Field::Expressions]).unwrap();
+    for expected in [
+        r"X_{doubled}=\left[\left(X_{x}*2\right)\operatorname{for}X_{x}=X_{L}\right]",
+        r"X_{named}=\left[X_{double}\left(X_{item}\right)\operatorname{for}X_{item}=X_{L}\right]",
+        r"X_{large}=X_{L}\left[\left[\left\{X_{x}\gt2,0\right\}\operatorname{for}X_{x}=X_{L}\right]=1\right]",
+        r"X_{sorted}=\operatorname{sort}\left(X_{L},\left[\left(-X_{x}\right)\operatorname{for}X_{x}=X_{L}\right]\right)",
+        r"X_{squares}=\left(1+\operatorname{total}\left(\left[X_{x}^{2}\operatorname{for}X_{x}=X_{L}\right]\right)\right)",
+        r"X_{largest}=\operatorname{max}\left(X_{L}\right)",
+    ] {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn specialized_builtins_give_the_values_they_stand_for() {
+    let values = values("\
+let L = [3, 1, 4, 1, 5];
+let add(a: real, b: real) = a + b;
+let doubled = @map(L, x => x * 2);
+let odd = @filter(L, x => x % 2 == 1);
+let large = @count(L, x => x > 2);
+let some = @any(L, x => x > 4);
+let every = @all(L, x => x > 1);
+let sorted = @sort_by(L, x => -x);
+let pairs = @zip(L, [1, 2]);
+let sums = @zip(L, [10, 20, 30], add);
+let digits = @fold([1, 2, 3], 0, (acc, x) => acc * 10 + x);
+let differences = @reduce(L, (a, b) => a - b);
+let named = @fold(L, 100, add);
+let nothing = @reduce([], (a, b) => a + b);
+let many = [1 ..= 40];
+let long = @fold(many, 0, (acc, x) => acc * 1 + x);
+", &["doubled", "odd", "large", "some", "every", "sorted", "pairs", "sums", "digits", "differences", "named", "nothing", "long"]);
+    assert_eq!(values[0], numbers(&[6.0, 2.0, 8.0, 2.0, 10.0]));
+    assert_eq!(values[1], numbers(&[3.0, 1.0, 1.0, 5.0]));
+    assert_eq!(values[2], Value::Number(3.0));
+    assert_eq!(values[3], Value::Number(1.0));
+    assert_eq!(values[4], Value::Number(0.0));
+    assert_eq!(values[5], numbers(&[5.0, 4.0, 3.0, 1.0, 1.0]));
+    assert_eq!(values[6], Value::List(vec![Value::Point(3.0, 1.0), Value::Point(1.0, 2.0)]));
+    assert_eq!(values[7], numbers(&[13.0, 21.0, 34.0]));
+    assert_eq!(values[8], Value::Number(123.0));
+    assert_eq!(values[9], Value::Number(-8.0));
+    assert_eq!(values[10], Value::Number(114.0));
+    // Reducing no items is undefined, and folds which need a step per item stop at the longest list they unroll for
+    assert!(matches!(values[11], Value::Number(value) if value.is_nan()));
+    assert!(matches!(values[12], Value::Number(value) if value.is_nan()));
+}
+
+#[test]
+fn generated_names_do_not_capture_names_in_the_arguments() {
+    let values = values("\
+let items = [1, 2];
+let offset = 10;
+let acc = [1, 2, 3];
+let shifted = @fold(@map(items, x => x + offset), 0, (acc, x) => acc * 10 + x);
+let own = @fold(acc, 0, (acc, x) => acc * 10 + x);
+", &["shifted", "own"]);
+    assert_eq!(values, [Value::Number(122.0), Value::Number(123.0)]);
+}
+
+#[test]
+fn folds_over_lists_of_known_length_take_a_step_per_item() {
+    let latex = common::compile("let product = @reduce([1, 2, 3], (a, b) => a * b);\n", &[Field::Expressions]).unwrap();
+    assert_eq!(latex, [concat!(
+        r"X_{product}=\left(\left(\left(\left(X_{a}*X_{b}\right)\operatorname{with}X_{b}=X_{items}\left[3\right]\right)",
+        r"\operatorname{with}X_{a}=\left(\left(\left(X_{a}*X_{b}\right)\operatorname{with}X_{b}=X_{items}\left[2\right]\right)",
+        r"\operatorname{with}X_{a}=X_{items}\left[1\right]\right)\right)\operatorname{with}X_{items}=\left[1,2,3\right]\right)",
+    )]);
+}
+
+#[test]
+fn folds_over_lists_longer_than_they_unroll_for_are_reported() {
+    assert_eq!(
+        common::compile("let long = @fold([1 ..= 40], 0, (acc, x) => acc * 10 + x);\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 1:19) @fold can only step through lists of up to 32 items, but this one has 40"],
+    );
+    assert_eq!(
+        common::compile("let long = @reduce([0, 2 ..= 66], (a, b) => a - b);\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 1:21) @reduce can only step through lists of up to 32 items, but this one has 34"],
+    );
+
+    let warnings = |source: &str| {
+        let (_, warnings) = cli::compile_with_warnings(
+            &TargetRegistry::default(),
+            &[SourceFile::new("main.desmos", source)],
+            Some("desmos-graphing"),
+            &TargetOptions::new(),
+            OptimizationLevel::default(),
+        ).unwrap();
+        Vec::from_iter(warnings.iter().map(ToString::to_string))
+    };
+    assert_eq!(
+        warnings("var L: [real] = [1, 2];\nlet digits = @fold(L, 0, (acc, x) => acc * 10 + x);\n"),
+        ["(line 2:15) this fold steps through at most 32 items, so it is undefined for a longer list"],
+    );
+    // Lists which are known to be short enough, and folds written with `@total`, are fine
+    assert!(warnings("let digits = @fold([1 ..= 32], 0, (acc, x) => acc * 10 + x);\n").is_empty());
+    assert!(warnings("var L: [real] = [1, 2];\nlet sum = @fold(L, 0, (acc, x) => acc + x);\n").is_empty());
+}
+
+#[test]
+fn lambdas_can_only_be_passed_to_higher_order_builtins() {
+    let message = "lambdas can only be passed to higher-order built-ins such as '@map'";
+    assert_eq!(common::compile("let f = x => x * 2;\n", &[Field::Expressions]).unwrap_err(), [format!("(line 1:9) {message}")]);
+    assert_eq!(common::compile("let f(x: real) = x;\nlet g = f(y => y);\n", &[Field::Expressions]).unwrap_err(), [format!("(line 2:11) {message}")]);
+    assert_eq!(common::compile("let m = @map([1, 2], 2);\n", &[Field::Expressions]).unwrap_err(), ["(line 1:22) @map expects a lambda or the name of a function"]);
+    assert_eq!(common::compile("let m = @map([1, 2]);\n", &[Field::Expressions]).unwrap_err(), ["(line 1:10) @map expects 2 arguments, but was provided 1"]);
+    assert_eq!(
+        common::compile("let s = @fold([1, 2], 0, x => x);\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 1:26) @fold expects a lambda with 2 parameter(s), but it has 1"],
+    );
+    assert_eq!(common::compile("let m = @map([1, 2], 2 => 3);\n", &[Field::Expressions]).unwrap_err(), ["(line 1:22) expected the names of the parameters before '=>'"]);
+}
@@ -0,0 +1,122 @@
+//! Tests for `interpret::Interpreter`: values are worked out the way Desmos would, and actions update the state all at
+//! once.
+
+use desmosify::interpret::{Interpreter, Value};
+use desmosify::{Definitions, Signatures};
+
+/// Parses and analyzes `source`, ready to be interpreted.
+fn analyze(source: &str) -> (Signatures, Definitions) {
+    let tokens = desmosify::token::tokenize(source).unwrap();
+    let (signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+    desmosify::semantics::analyze(&signatures, &mut definitions).unwrap();
+    (signatures, definitions)
+}
+
+/// Shows the value of every name in `names`, so that a whole program can be checked at once.
+fn values(interpreter: &Interpreter, names: &[&str]) -> Vec<String> {
+    Vec::from_iter(names.iter().map(|name| format!("{name} = {}", interpreter.value(name).unwrap())))
+}
+
+#[test]
+fn arithmetic_follows_desmos() {
+    let (signatures, definitions) = analyze("\
+var a = 7;
+let sum = a + 2 * 3;
+let power = 2 ^ 3 ^ 2;
+let negative_power = -a ^ 2;
+let quotient = -a div 2;
+let remainder = @mod(-a, 3);
+let remainder_of_negative = @mod(a, -3);
+let halved = a / 2;
+let by_zero = a / 0;
+let root = @sqrt(-1);
+let point_sum = (1, 2) + (a, 1);
+");
+    let interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    assert_eq!(values(&interpreter, &[
+        "sum", "power", "negative_power", "quotient", "remainder", "remainder_of_negative", "halved", "by_zero", "root",
+        "point_sum",
+    ]), [
+        "sum = 13",
+        "power = 512",
+        "negative_power = -49",
+        "quotient = -4",
+        "remainder = 2",
+        "remainder_of_negative = -2",
+        "halved = 3.5",
+        "by_zero = undefined",
+        "root = undefined",
+        "point_sum = (8, 3)",
+    ]);
+}
+
+#[test]
+fn lists_broadcast_and_index_from_one() {
+    let (signatures, definitions) = analyze("\
+let numbers = [4, 1, 3, 2];
+let first = numbers[1];
+let outside = numbers[5];
+let shifted = numbers + 1;
+let pairwise = numbers * [1, 2];
+let filtered = [numbers where numbers > 2];
+let squares = [n ^ 2 for n in [1 ..= 3]];
+let length = @length(numbers);
+let total = @total(numbers);
+");
+    let interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    assert_eq!(values(&interpreter, &[
+        "first", "outside", "shifted", "pairwise", "filtered", "squares", "length", "total",
+    ]), [
+        "first = 4",
+        "outside = undefined",
+        "shifted = [5, 2, 4, 3]",
+        "pairwise = [4, 2]",
+        "filtered = [4, 3]",
+        "squares = [1, 4, 9]",
+        "length = 4",
+        "total = 10",
+    ]);
+}
+
+#[test]
+fn actions_update_every_variable_at_once() {
+    let (signatures, definitions) = analyze("\
+var a: int = 1;
+var b: int = 2;
+var timer clock = 0;
+var moved = 0;
+var clicked = 0;
+action swap() {
+    a := b,
+    b := a,
+}
+action restart(start: real) {
+    moved := start,
+    action swap(),
+}
+ticker {
+    moved := moved + @dt,
+}
+display {
+    [1, 2, 3]: @rgb(0, 0, 0), click { clicked := @index };
+}
+");
+    let mut interpreter = Interpreter::new(&signatures, &definitions).unwrap();
+    interpreter.run("swap", &[]).unwrap();
+    assert_eq!(values(&interpreter, &["a", "b"]), ["a = 2", "b = 1"]);
+
+    // Calls to other actions are part of the same update
+    interpreter.run("restart", &[Value::Number(10.0)]).unwrap();
+    assert_eq!(values(&interpreter, &["a", "b", "moved"]), ["a = 1", "b = 2", "moved = 10"]);
+
+    interpreter.tick(250.0).unwrap();
+    interpreter.tick(250.0).unwrap();
+    assert_eq!(values(&interpreter, &["moved", "clock"]), ["moved = 510", "clock = 0.5"]);
+
+    interpreter.click(0, 3).unwrap();
+    assert_eq!(values(&interpreter, &["clicked"]), ["clicked = 3"]);
+
+    interpreter.set_value("a", Value::Number(5.0)).unwrap();
+    assert_eq!(values(&interpreter, &["a"]), ["a = 5"]);
+    assert!(interpreter.set_value("swap", Value::Number(5.0)).is_err());
+}
@@ -0,0 +1,104 @@
+//! Tests for `parse_latex`: the LaTeX of every compiled example reads back unchanged, as text and as the tree it was
+//! printed from, and the forms Desmos itself writes are understood.
+
+use std::path::PathBuf;
+
+use desmosify::cli::compile_source;
+use desmosify::link::{self, SourceFile};
+use desmosify::target::desmos::latex::parse_latex;
+use desmosify::target::desmos::translate::DesmosTarget;
+use desmosify::target::desmos::{Graphing3DTarget, GeometryTarget, GraphingTarget, Layout, SyntaxNode};
+use desmosify::target::{TargetOptions, TargetRegistry};
+use desmosify::Definitions;
+use json::JsonValue;
+
+fn examples() -> Vec<PathBuf> {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "desmos"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Collects every LaTeX string in a graph state, such as `latex`, `colorLatex` and `sliderBounds.min`.
+fn collect_latex(value: &JsonValue, key: &str, latex: &mut Vec<String>) {
+    match value {
+        JsonValue::Object(object) => for (key, value) in object.iter() {
+            collect_latex(value, key, latex);
+        },
+        JsonValue::Array(values) => for value in values {
+            collect_latex(value, key, latex);
+        },
+        _ => if key.to_lowercase().ends_with("latex") {
+            if let Some(text) = value.as_str().filter(|text| !text.is_empty()) {
+                latex.push(String::from(text));
+            }
+        },
+    }
+}
+
+/// Translates every definition and action with `target`, skipping what it can't translate.
+fn translate<T: DesmosTarget>(target: &T, definitions: &Definitions) -> Vec<SyntaxNode> {
+    let values = definitions.identifiers.values().filter_map(|value| target.translate_expression(value).ok());
+    let actions = definitions.actions.values().filter_map(|action| target.translate_action(action).ok());
+    Vec::from_iter(values.chain(actions).map(|node| *node))
+}
+
+#[test]
+fn compiled_latex_reads_back_unchanged() {
+    let mut latex = Vec::new();
+    for path in examples() {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        if let Ok(state) = compile_source(&TargetRegistry::default(), &source, &file_name, None, &TargetOptions::new()) {
+            collect_latex(&state, "", &mut latex);
+        }
+    }
+    assert!(!latex.is_empty(), "no examples compiled");
+
+    for printed in latex {
+        let parsed = parse_latex(&printed).unwrap_or_else(|error| panic!("could not parse {printed}: {error}"));
+        assert_eq!(parsed.to_latex().to_string(), printed, "{parsed:?} does not print as it was read");
+    }
+}
+
+#[test]
+fn translated_trees_read_back_unchanged() {
+    let mut nodes = Vec::new();
+    for path in examples() {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let Ok((_, definitions)) = link::link(&[SourceFile::new(&file_name, &source)]) else { continue };
+        nodes.extend(match definitions.target.as_deref() {
+            Some("desmos-geometry") => translate(&GeometryTarget::new(Layout::default()), &definitions),
+            Some("desmos-graphing-3d") => translate(&Graphing3DTarget::new(Layout::default()), &definitions),
+            _ => translate(&GraphingTarget::new(Layout::default()), &definitions),
+        });
+    }
+    assert!(!nodes.is_empty(), "no examples were translated");
+
+    for node in nodes {
+        let printed = node.to_latex().to_string();
+        let parsed = parse_latex(&printed).unwrap_or_else(|error| panic!("could not parse {printed}: {error}"));
+        assert_eq!(parsed, node, "{printed} was read as a different tree");
+    }
+}
+
+#[test]
+fn desmos_authored_latex_is_understood() {
+    for (authored, printed) in [
+        (r"\max\left(a,1\right)", r"\operatorname{max}\left(a,1\right)"),
+        (r"\min\left(a\right)", r"\operatorname{min}\left(a\right)"),
+        (r"a<b", r"a\lt b"),
+        (r"0<x<1", r"0\lt x\lt1"),
+        (r"\left\{a<0:1,2\right\}", r"\left\{a\lt0:1,2\right\}"),
+        (r"a\ b", r"a b"),
+        (r"a\ <\ \max\left(b,c\right)", r"a\lt\operatorname{max}\left(b,c\right)"),
+    ] {
+        let parsed = parse_latex(authored).unwrap_or_else(|error| panic!("could not parse {authored}: {error}"));
+        assert_eq!(parsed.to_latex().to_string(), printed, "{authored} was read as {parsed:?}");
+    }
+}
@@ -0,0 +1,123 @@
+//! Tests for compiling several source files, either linked into one program or each on its own.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use desmosify::cli::{self, DesmosifyArgs};
+use desmosify::link::{self, SourceFile};
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+const SHAPES: &str = "\
+enum Shape { CIRCLE, SQUARE }
+var count: int = 0;
+let doubled = 2 * count;
+";
+
+const MAIN: &str = "\
+var shape: Shape = 0;
+action grow() {
+    count := doubled + 1,
+}
+public {
+    @total([count]);
+}
+";
+
+fn scratch_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("desmosify-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn errors(files: &[SourceFile]) -> Vec<String> {
+    match link::link(files) {
+        Ok(_) => panic!("linking should fail"),
+        Err(errors) => Vec::from_iter(errors.iter().map(ToString::to_string)),
+    }
+}
+
+#[test]
+fn names_resolve_across_files() {
+    let files = [SourceFile::new("shapes.desmos", SHAPES), SourceFile::new("main.desmos", MAIN)];
+    let (signatures, definitions) = link::link(&files).unwrap();
+    assert!(signatures.user_defined.contains_key("Shape"));
+    assert!(signatures.locations.contains_key("Shape.SQUARE"));
+    assert!(definitions.actions.contains_key("grow"));
+    assert!(definitions.public.is_some());
+    let file_names = Vec::from_iter(definitions.sections.iter().map(|section| section.file_name.as_deref()));
+    assert_eq!(file_names, [Some(link::PRELUDE_FILE_NAME), Some("shapes.desmos"), Some("main.desmos")]);
+
+    // Either file alone is missing the other's names
+    assert!(cli::compile_source(&TargetRegistry::default(), MAIN, "main.desmos", None, &TargetOptions::new()).is_err());
+
+    let mut options = TargetOptions::new();
+    options.insert(String::from("group"), String::from("file"));
+    let output = cli::compile_sources(&TargetRegistry::default(), &files, None, &options).unwrap().to_string();
+    assert!(output.contains("\"title\":\"shapes\"") && output.contains("\"title\":\"main\""), "{output}");
+}
+
+#[test]
+fn duplicate_definitions_name_both_files() {
+    let errors = errors(&[
+        SourceFile::new("shapes.desmos", SHAPES),
+        SourceFile::new("main.desmos", "let a = 1;\nconst count = 3;\n"),
+    ]);
+    assert_eq!(errors, ["main.desmos (line 2:7) name conflicts with 'var count' declared in 'shapes.desmos' (line 2:5)"]);
+
+    let errors = self::errors(&[
+        SourceFile::new("a.desmos", "#target \"desmos-graphing\"; ticker { }"),
+        SourceFile::new("b.desmos", "#target \"desmos-geometry\"; ticker { }"),
+    ]);
+    assert_eq!(errors, [
+        "b.desmos only one 'ticker' block can be declared, but one is also declared in 'a.desmos'",
+        "b.desmos '#target \"desmos-geometry\"' conflicts with '#target \"desmos-graphing\"' in 'a.desmos'",
+    ]);
+}
+
+#[test]
+fn errors_are_reported_for_every_file() {
+    let errors = errors(&[
+        SourceFile::new("first.desmos", "let a = missing;"),
+        SourceFile::new("second.desmos", "let b = a +;"),
+        SourceFile::new("third.desmos", "let c = ;"),
+    ]);
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].starts_with("second.desmos (line 1:"), "{errors:?}");
+    assert!(errors[1].starts_with("third.desmos (line 1:"), "{errors:?}");
+
+    // Cycles between files are only visible once the files are linked
+    let errors = self::errors(&[SourceFile::new("a.desmos", "let a = b;"), SourceFile::new("b.desmos", "let b = a;")]);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("cyclic definition"), "{errors:?}");
+}
+
+#[test]
+fn out_dir_writes_one_output_per_source() {
+    let directory = scratch_directory("out-dir");
+    let source = |name: &str, contents: &str| {
+        let path = directory.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    };
+    let first = source("first.desmos", "let a = 1;");
+    let second = source("second.desmos", "let b = 2;");
+    let output_directory = directory.join("out");
+    let output_text = output_directory.to_string_lossy().into_owned();
+
+    let args = DesmosifyArgs::try_parse_from(["desmosify", "-s", &first, "-s", &second, "--out-dir", &output_text]).unwrap();
+    cli::compile_files(&TargetRegistry::default(), &args).unwrap();
+    let first_output = std::fs::read_to_string(output_directory.join("first.json")).unwrap();
+    let second_output = std::fs::read_to_string(output_directory.join("second.json")).unwrap();
+    assert!(first_output.contains("X_{a}") && !first_output.contains("X_{b}"), "{first_output}");
+    assert!(second_output.contains("X_{b}") && !second_output.contains("X_{a}"), "{second_output}");
+
+    // Files are compiled on their own, so one cannot use another's names
+    let third = source("third.desmos", "let c = a;");
+    let args = DesmosifyArgs::try_parse_from(["desmosify", "-s", &first, "-s", &third, "--out-dir", &output_text]).unwrap();
+    let errors = cli::compile_files(&TargetRegistry::default(), &args).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().starts_with(&format!("{third} (line 1:9)")), "{}", errors[0]);
+
+    assert!(DesmosifyArgs::try_parse_from(["desmosify", "-s", &first, "-o", "out.json", "--out-dir", &output_text]).is_err());
+    std::fs::remove_dir_all(directory).unwrap();
+}
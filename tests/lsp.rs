@@ -0,0 +1,231 @@
+//! Tests for the language server, which script a session of JSON-RPC messages and check the messages sent back.
+
+use desmosify::lsp;
+use json::JsonValue;
+
+const URI: &str = "file:///project/counter.desmos";
+
+const SOURCE: &str = r#"#target "desmos-graphing";
+enum Mode { IDLE, RUNNING }
+var count: int = 0;
+var mode: Mode = 0;
+let doubled = 2 * count;
+let scaled(factor: real): real = factor * count;
+action bump() {
+    count := count + 1,
+    mode := Mode.RUNNING,
+}
+public {
+    @total([count, doubled]);
+}
+"#;
+
+/// Runs a whole session, returning every message the server sent and whether it exited cleanly.
+fn session(messages: &[JsonValue]) -> (Vec<JsonValue>, bool) {
+    let mut input = Vec::new();
+    for message in messages {
+        lsp::write_message(&mut input, message).unwrap();
+    }
+    let mut output = Vec::new();
+    let is_clean = lsp::run(input.as_slice(), &mut output).unwrap();
+    let mut reader = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(content) = lsp::read_message(&mut reader).unwrap() {
+        replies.push(json::parse(&content).unwrap());
+    }
+    (replies, is_clean)
+}
+
+fn request(id: i32, method: &str, params: JsonValue) -> JsonValue {
+    json::object!{ jsonrpc: "2.0", id: id, method: method, params: params }
+}
+
+fn notification(method: &str, params: JsonValue) -> JsonValue {
+    json::object!{ jsonrpc: "2.0", method: method, params: params }
+}
+
+fn open(text: &str) -> JsonValue {
+    notification("textDocument/didOpen", json::object!{
+        textDocument: { uri: URI, languageId: "desmosify", version: 1, text: text },
+    })
+}
+
+fn at(line: usize, character: usize) -> JsonValue {
+    json::object!{ textDocument: { uri: URI }, position: { line: line, character: character } }
+}
+
+/// Runs a session which opens `text` and then sends `requests`, returning the response to each request by id.
+fn responses(text: &str, requests: Vec<JsonValue>) -> Vec<JsonValue> {
+    let mut messages = vec![request(0, "initialize", json::object!{ capabilities: {} }), open(text)];
+    messages.extend(requests);
+    let (replies, _) = session(&messages);
+    let count = messages.iter().filter(|message| !message["id"].is_null()).count() - 1;
+    (1..=count)
+        .map(|id| replies.iter().find(|reply| reply["id"] == id).cloned().unwrap_or_else(|| panic!("no response to request {id}")))
+        .collect()
+}
+
+fn diagnostics(text: &str) -> JsonValue {
+    let (replies, _) = session(&[open(text)]);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(replies[0]["params"]["uri"], URI);
+    replies[0]["params"]["diagnostics"].clone()
+}
+
+#[test]
+fn lifecycle() {
+    let (replies, is_clean) = session(&[
+        request(1, "initialize", json::object!{ capabilities: {} }),
+        notification("initialized", json::object!{}),
+        request(2, "workspace/symbol", json::object!{ query: "" }),
+        request(3, "shutdown", JsonValue::Null),
+        notification("exit", JsonValue::Null),
+        request(4, "initialize", json::object!{ capabilities: {} }),
+    ]);
+    assert_eq!(replies.len(), 3);
+    let capabilities = &replies[0]["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["renameProvider"], true);
+    assert_eq!(replies[1]["error"]["code"], -32601);
+    assert!(replies[2]["result"].is_null() && replies[2]["error"].is_null());
+    assert!(is_clean);
+
+    let (_, is_clean) = session(&[notification("exit", JsonValue::Null)]);
+    assert!(!is_clean);
+}
+
+#[test]
+fn diagnostics_follow_changes() {
+    assert!(diagnostics(SOURCE).is_empty());
+
+    let errors = diagnostics("let a = 1;\nlet b = c;\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["message"], "could not find a definition for 'c'");
+    assert_eq!(errors[0]["severity"], 1);
+    assert_eq!(errors[0]["range"], json::object!{
+        start: { line: 1, character: 8 },
+        end: { line: 1, character: 9 },
+    });
+
+    let (replies, _) = session(&[
+        open("let a = 1"),
+        notification("textDocument/didChange", json::object!{
+            textDocument: { uri: URI, version: 2 },
+            contentChanges: [{ text: "let a = 1;" }],
+        }),
+        notification("textDocument/didClose", json::object!{ textDocument: { uri: URI } }),
+    ]);
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[0]["params"]["diagnostics"].len(), 1);
+    assert!(replies[1]["params"]["diagnostics"].is_empty());
+    assert!(replies[2]["params"]["diagnostics"].is_empty());
+}
+
+#[test]
+fn definitions_of_names_and_variants() {
+    let replies = responses(SOURCE, vec![
+        request(1, "textDocument/definition", at(7, 14)),
+        request(2, "textDocument/definition", at(8, 17)),
+        request(3, "textDocument/definition", at(11, 6)),
+        request(4, "textDocument/definition", at(5, 35)),
+    ]);
+    let range = |line: usize, start: usize, end: usize| json::object!{
+        start: { line: line, character: start },
+        end: { line: line, character: end },
+    };
+    assert_eq!(replies[0]["result"]["uri"], URI);
+    assert_eq!(replies[0]["result"]["range"], range(2, 4, 9));
+    assert_eq!(replies[1]["result"]["range"], range(1, 18, 25));
+    // Built-ins and parameters have no declaration to go to
+    assert!(replies[2]["result"].is_null());
+    assert!(replies[3]["result"].is_null());
+}
+
+#[test]
+fn hover_shows_types() {
+    let replies = responses(SOURCE, vec![
+        request(1, "textDocument/hover", at(5, 4)),
+        request(2, "textDocument/hover", at(8, 4)),
+        request(3, "textDocument/hover", at(8, 18)),
+        request(4, "textDocument/hover", at(11, 6)),
+        request(5, "textDocument/hover", at(6, 8)),
+    ]);
+    let contents = |reply: &JsonValue| reply["result"]["contents"]["value"].to_string();
+    assert_eq!(contents(&replies[0]), "```desmosify\nlet scaled(factor: real): real\n```");
+    assert_eq!(contents(&replies[1]), "```desmosify\nvar mode: Mode\n```");
+    assert_eq!(contents(&replies[2]), "```desmosify\nMode.RUNNING: Mode\n```");
+    assert_eq!(contents(&replies[3]), "`@total`: built-in command");
+    assert_eq!(contents(&replies[4]), "```desmosify\naction bump()\n```");
+}
+
+#[test]
+fn completion_of_names_variants_and_builtins() {
+    // The document no longer parses after the change, so completion falls back to the last version which did
+    let text = format!("{SOURCE}let x = Mode.\nlet y = @\nlet z = co\n");
+    let replies = responses(SOURCE, vec![
+        notification("textDocument/didChange", json::object!{
+            textDocument: { uri: URI, version: 2 },
+            contentChanges: [{ text: text.as_str() }],
+        }),
+        request(1, "textDocument/completion", at(13, 13)),
+        request(2, "textDocument/completion", at(14, 9)),
+        request(3, "textDocument/completion", at(15, 10)),
+    ]);
+    let labels = |reply: &JsonValue| Vec::from_iter(reply["result"].members().map(|item| item["label"].to_string()));
+    assert_eq!(labels(&replies[0]), ["IDLE", "RUNNING"]);
+    let builtins = labels(&replies[1]);
+    assert!(builtins.contains(&String::from("total")) && builtins.contains(&String::from("dt")));
+    assert_eq!(builtins.iter().filter(|name| ["count", "fold"].contains(&name.as_str())).count(), 2);
+    assert_eq!(diagnostics(&text).len(), 1);
+    let names = labels(&replies[2]);
+    assert_eq!(names, ["Mode", "bump", "count", "doubled", "mode", "scaled"]);
+    let detail = replies[2]["result"].members().find(|item| item["label"] == "count").unwrap()["detail"].to_string();
+    assert_eq!(detail, "var count: int");
+}
+
+#[test]
+fn rename_goes_through_user_defined_names() {
+    let replies = responses(SOURCE, vec![
+        request(1, "textDocument/rename", json::object!{ textDocument: { uri: URI }, position: { line: 7, character: 5 }, newName: "total" }),
+        request(2, "textDocument/rename", json::object!{ textDocument: { uri: URI }, position: { line: 8, character: 18 }, newName: "BUSY" }),
+        request(3, "textDocument/rename", json::object!{ textDocument: { uri: URI }, position: { line: 7, character: 5 }, newName: "mode" }),
+        request(4, "textDocument/rename", json::object!{ textDocument: { uri: URI }, position: { line: 7, character: 5 }, newName: "let" }),
+        request(5, "textDocument/rename", json::object!{ textDocument: { uri: URI }, position: { line: 11, character: 6 }, newName: "sum" }),
+    ]);
+    let edited = |reply: &JsonValue| {
+        let mut edits = Vec::from_iter(reply["result"]["changes"][URI].members().cloned());
+        // Apply from the end so that earlier ranges stay valid
+        edits.sort_by_key(|edit| (edit["range"]["start"]["line"].as_usize(), edit["range"]["start"]["character"].as_usize()));
+        let mut lines = Vec::from_iter(SOURCE.lines().map(String::from));
+        for edit in edits.iter().rev() {
+            let start = &edit["range"]["start"];
+            let end = &edit["range"]["end"];
+            assert_eq!(start["line"], end["line"]);
+            let line = &mut lines[start["line"].as_usize().unwrap()];
+            line.replace_range(start["character"].as_usize().unwrap()..end["character"].as_usize().unwrap(), edit["newText"].as_str().unwrap());
+        }
+        lines.join("\n") + "\n"
+    };
+    assert_eq!(edited(&replies[0]), SOURCE.replace("count", "total"));
+    assert_eq!(edited(&replies[1]), SOURCE.replace("RUNNING", "BUSY"));
+    assert_eq!(replies[2]["error"]["message"], "'mode' is already declared");
+    assert_eq!(replies[3]["error"]["message"], "'let' is not a valid name");
+    assert_eq!(replies[4]["error"]["message"], "'@total' is built in and cannot be renamed");
+}
+
+#[test]
+fn every_prefix_of_the_examples_gets_diagnostics() {
+    // Documents are analyzed on every keystroke, so the server must cope with every incomplete program
+    for example in ["test.desmos", "stratego.desmos", "maze.desmos"] {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test").join(example);
+        let source = std::fs::read_to_string(path).unwrap();
+        let mut server = lsp::Server::new();
+        for (index, _) in source.char_indices().step_by(13) {
+            let replies = server.handle(&open(&source[..index]));
+            assert_eq!(replies.len(), 1, "{example} up to byte {index}");
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Tests for modules: importing files, namespaces and `pub` visibility.
+
+use std::path::{Path, PathBuf};
+
+use desmosify::cli;
+use desmosify::link::{self, SourceFile};
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+/// A directory of source files for one test, removed when dropped.
+struct Project {
+    directory: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let directory = std::env::temp_dir().join(format!("desmosify-module-{name}-{}", std::process::id()));
+        for (path, source) in files {
+            let path = directory.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        Self { directory }
+    }
+
+    fn path(&self, path: &str) -> String {
+        self.directory.join(path).to_string_lossy().into_owned()
+    }
+
+    fn link(&self, path: &str) -> Result<(desmosify::Signatures, desmosify::Definitions), Vec<String>> {
+        let source = std::fs::read_to_string(self.directory.join(path)).unwrap();
+        link::link(&[SourceFile::new(&self.path(path), &source)])
+            .map_err(|errors| Vec::from_iter(errors.iter().map(|error| error.to_string().replace(&self.path(""), ""))))
+    }
+
+    fn compile(&self, path: &str) -> Result<String, Vec<String>> {
+        let source = std::fs::read_to_string(self.directory.join(path)).unwrap();
+        cli::compile_source(&TargetRegistry::default(), &source, &self.path(path), Some("desmos-graphing"), &TargetOptions::new())
+            .map(|output| output.to_string())
+            .map_err(|errors| Vec::from_iter(errors.iter().map(|error| error.to_string().replace(&self.path(""), ""))))
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.directory);
+    }
+}
+
+const EASING: &str = "\
+pub enum Curve { LINEAR, OUT }
+pub let ease_out(t: real): real = 1 - (1 - t)^2;
+pub let ease(curve: Curve, t: real): real = { curve == 0: t, ease_out(t) };
+let helper = 2;
+pub action restart() {
+    start := helper,
+}
+var start: real = 0;
+";
+
+#[test]
+fn imported_names_and_qualified_names() {
+    let project = Project::new("names", &[
+        ("lib/easing.desmos", EASING),
+        ("main.desmos", "\
+import \"lib/easing.desmos\";
+import lib_easing::{ease_out};
+var time: real = 0;
+let eased = ease_out(time) + easing::ease(0, time);
+action reset() {
+    time := 0,
+    action easing::restart(),
+}
+"),
+        ("lib_easing.desmos", "import \"lib/easing.desmos\";\npub let ease_out(t: real): real = easing::ease_out(t) / 2;\n"),
+    ]);
+    let (signatures, definitions) = project.link("main.desmos").unwrap();
+    for name in ["easing::Curve", "easing::ease_out", "easing::helper", "easing::restart", "lib_easing::ease_out", "eased", "reset"] {
+        assert!(signatures.user_defined.contains_key(name), "{name} is missing");
+    }
+    assert_eq!(signatures.user_defined["easing::helper"].name(), "easing::helper");
+    assert!(signatures.locations.contains_key("easing::Curve.OUT"));
+    // Each module comes before the files importing it, and is only included once
+    let file_names = Vec::from_iter(definitions.sections.iter().map(|section| section.file_name.clone().unwrap().replace(&project.path(""), "")));
+    assert_eq!(file_names, [link::PRELUDE_FILE_NAME, "lib/easing.desmos", "lib_easing.desmos", "main.desmos"]);
+
+    let output = project.compile("main.desmos").unwrap();
+    assert!(output.contains(r"X_{eased}=\\left(X_{libeasingeaseout}\\left(X_{time}\\right)+X_{easingease}\\left(0,X_{time}\\right)\\right)"), "{output}");
+    assert!(output.contains(r"X_{easingrestart}\\left(\\right)=X_{easingstart}\\to X_{easinghelper}"), "{output}");
+}
+
+#[test]
+fn only_pub_declarations_can_be_used() {
+    let project = Project::new("visibility", &[
+        ("easing.desmos", EASING),
+        ("private.desmos", "import easing;\nlet a = easing::helper;\n"),
+        ("imported.desmos", "import easing::{helper};\n"),
+        ("missing.desmos", "import easing::{ease_in};\nlet a = easing::ease_in(1);\n"),
+        ("unimported.desmos", "let a = easing::ease_out(1);\n"),
+        ("clash.desmos", "import easing::{ease_out};\nlet ease_out = 1;\n"),
+        ("outside.desmos", "import outside_module;\nvar count: int = 0;\n"),
+        ("outside_module.desmos", "pub let doubled = 2 * count;\n"),
+    ]);
+    assert_eq!(project.link("private.desmos").unwrap_err(), ["private.desmos (line 2:9) 'helper' is not 'pub' in module 'easing'"]);
+    assert_eq!(project.link("imported.desmos").unwrap_err(), ["imported.desmos (line 1:17) 'helper' is not 'pub' in module 'easing'"]);
+    assert_eq!(project.link("missing.desmos").unwrap_err(), ["missing.desmos (line 1:17) module 'easing' has no declaration named 'ease_in'"]);
+    assert_eq!(project.link("unimported.desmos").unwrap_err(), ["(line 1:9) module 'easing' is not imported"]);
+    assert_eq!(project.link("clash.desmos").unwrap_err(), ["clash.desmos (line 1:17) 'ease_out' is imported from module 'easing' but also declared here"]);
+    // Modules cannot see the declarations of the files that import them
+    assert_eq!(project.link("outside.desmos").unwrap_err(), ["outside_module.desmos (line 1:23) could not find a definition for 'count'"]);
+}
+
+#[test]
+fn import_cycles_and_missing_files_are_reported() {
+    let project = Project::new("cycles", &[
+        ("main.desmos", "import first;\nlet a = first::one;\n"),
+        ("first.desmos", "import second;\npub let one = 1;\n"),
+        ("second.desmos", "import \"first.desmos\";\npub let two = 2;\n"),
+        ("missing.desmos", "import \"nowhere/gone.desmos\";\n"),
+        ("clash.desmos", "import \"one/value.desmos\";\nimport \"two/value.desmos\";\n"),
+        ("one/value.desmos", "pub let one = 1;\n"),
+        ("two/value.desmos", "pub let two = 2;\n"),
+    ]);
+    let errors = project.link("main.desmos").unwrap_err();
+    assert_eq!(errors, ["second.desmos (line 1:8) import cycle: 'first.desmos' -> 'second.desmos' -> 'first.desmos'"]);
+
+    let errors = project.link("missing.desmos").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("missing.desmos (line 1:8) could not import 'nowhere/gone.desmos'"), "{errors:?}");
+
+    let errors = project.link("clash.desmos").unwrap_err();
+    assert_eq!(errors, ["clash.desmos (line 2:8) 'two/value.desmos' cannot be imported as module 'value', which is already 'one/value.desmos'"]);
+}
+
+#[test]
+fn generated_names_stay_unique() {
+    let project = Project::new("unique", &[
+        ("first.desmos", "pub let value = helper + 1;\nlet helper = 2;\n"),
+        ("second.desmos", "pub let value = helper + 3;\nlet helper = 4;\n"),
+        ("main.desmos", "import first;\nimport second;\nlet total = first::value + second::value;\n"),
+        ("clash.desmos", "import first;\nlet first_value = first::value;\n"),
+    ]);
+    let output = project.compile("main.desmos").unwrap();
+    for name in ["X_{firstvalue}=", "X_{firsthelper}=2", "X_{secondvalue}=", "X_{secondhelper}=4"] {
+        assert!(output.contains(name), "{name} is missing from {output}");
+    }
+
+    assert_eq!(project.compile("clash.desmos").unwrap_err(), ["(line 2:5) 'first::value' and 'first_value' would both be named 'X_{firstvalue}' in the graph"]);
+}
+
+#[test]
+fn examples_share_the_easing_module() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test");
+    let path = examples.join("test.desmos");
+    let source = std::fs::read_to_string(&path).unwrap();
+    let output = cli::compile_source(&TargetRegistry::default(), &source, &path.to_string_lossy(), None, &TargetOptions::new()).unwrap();
+    assert!(output.to_string().contains("X_{easingeaseout}"));
+}
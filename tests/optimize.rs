@@ -0,0 +1,153 @@
+//! Tests for the `-O` levels: constant folding, simplification, inlining and sharing repeated subexpressions.
+
+use clap::Parser;
+use desmosify::cli::{self, DesmosifyArgs};
+use desmosify::link::SourceFile;
+use desmosify::optimize::OptimizationLevel;
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+const SOURCE: &str = "\
+const SIZE = 4;
+var count: int = 0;
+let doubled = 2 * count;
+let area = SIZE * SIZE + 0;
+let scaled = doubled * 1 + (3 - 1) * count;
+let sign(n: int) = { n > 0: 1, { n < 0: -1, 0 } };
+let known = { 1 > 2: 5, true: count, 7 };
+let positive = !!(count > 1);
+let same = --count + +count;
+";
+
+/// Compiles `source` at `level`, returning the LaTeX of every expression and the warnings.
+fn compile(source: &str, level: OptimizationLevel) -> (Vec<String>, Vec<String>) {
+    let (output, warnings) = cli::compile_with_warnings(
+        &TargetRegistry::default(),
+        &[SourceFile::new("main.desmos", source)],
+        Some("desmos-graphing"),
+        &TargetOptions::new(),
+        level,
+    ).unwrap();
+    let latex = Vec::from_iter(output["expressions"]["list"].members().filter_map(|entry| entry["latex"].as_str().map(String::from)));
+    (latex, Vec::from_iter(warnings.iter().map(ToString::to_string)))
+}
+
+fn assert_contains(latex: &[String], expected: &[&str]) {
+    for expected in expected {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn level_is_chosen_on_the_command_line() {
+    let level = |args: &[&str]| DesmosifyArgs::try_parse_from([&["desmosify", "-s", "a.desmos", "-o", "a.json"], args].concat())
+        .map(|args| args.optimization_level());
+    assert_eq!(level(&[]).unwrap(), OptimizationLevel::None);
+    assert_eq!(level(&["-O1"]).unwrap(), OptimizationLevel::Simplify);
+    assert_eq!(level(&["--opt-level", "0"]).unwrap(), OptimizationLevel::None);
+    assert_eq!(level(&["-O"]).unwrap(), OptimizationLevel::Full);
+    assert!(level(&["-O3"]).is_err());
+}
+
+#[test]
+fn nothing_changes_without_optimization() {
+    let (latex, _) = compile(SOURCE, OptimizationLevel::None);
+    assert_contains(&latex, &[
+        r"X_{area}=\left(\left(X_{SIZE}*X_{SIZE}\right)+0\right)",
+        r"X_{same}=\left(\left(-\left(-X_{count}\right)\right)+\left(+X_{count}\right)\right)",
+    ]);
+}
+
+#[test]
+fn constants_identities_and_piecewise_are_simplified() {
+    let (latex, _) = compile(SOURCE, OptimizationLevel::Simplify);
+    assert_contains(&latex, &[
+        r"X_{SIZE}=4",
+        r"X_{area}=\left(X_{SIZE}*X_{SIZE}\right)",
+        r"X_{scaled}=\left(X_{doubled}+\left(2*X_{count}\right)\right)",
+        r"X_{sign}\left(X_{n}\right)=\left\{X_{n}\gt0:1,X_{n}\lt0:\left(-1\right),0\right\}",
+        r"X_{known}=X_{count}",
+        r"X_{positive}=\left\{X_{count}\gt1,0\right\}",
+        r"X_{same}=\left(X_{count}+X_{count}\right)",
+    ]);
+
+    // `!!` normalizes numbers to booleans, so it is only removed from booleans
+    let (latex, _) = compile("var count = 0;\nlet truthy = !!count;\nlet quotient = 7 / 2;\nlet whole = 8 / 2;\n", OptimizationLevel::Simplify);
+    assert_contains(&latex, &[r"X_{truthy}=\left\{X_{count}=1:1,0\right\}", r"X_{quotient}=\frac{7}{2}", r"X_{whole}=4"]);
+}
+
+#[test]
+fn consts_and_small_lets_are_inlined() {
+    let (latex, _) = compile(SOURCE, OptimizationLevel::Full);
+    assert_contains(&latex, &[
+        r"X_{area}=16",
+        r"X_{scaled}=\left(\left(2*X_{count}\right)+\left(2*X_{count}\right)\right)",
+    ]);
+
+    let (latex, warnings) = compile("\
+const SIZE = 4;
+var count: int = 0;
+let doubled = 2 * count;
+let total = [1, 2, 3];
+let roll = @random(1, count)[1];
+let shadowed(count: int) = doubled + count;
+let bound = (count with count = SIZE) + doubled;
+let mapped = [doubled * item for item in total];
+let rolled = roll + 1;
+public {
+    doubled;
+    bound;
+    mapped;
+    rolled;
+    shadowed(1);
+}
+", OptimizationLevel::Full);
+    assert!(warnings.is_empty(), "{warnings:?}");
+    assert_contains(&latex, &[
+        // A name on its own stays, as does a list, a random value, and a value whose names would be captured
+        r"X_{doubled}",
+        r"X_{doubled}=\left(2*X_{count}\right)",
+        r"X_{total}=\left[1,2,3\right]",
+        r"X_{shadowed}\left(X_{count}\right)=\left(X_{doubled}+X_{count}\right)",
+        r"X_{bound}=\left(\left(X_{count}\operatorname{with}X_{count}=4\right)+\left(2*X_{count}\right)\right)",
+        r"X_{mapped}=\left[\left(\left(2*X_{count}\right)*X_{item}\right)\operatorname{for}X_{item}=X_{total}\right]",
+        r"X_{rolled}=\left(X_{roll}+1\right)",
+    ]);
+    // Inlined everywhere, so left out without a warning
+    assert!(!latex.iter().any(|entry| entry.starts_with("X_{SIZE}")), "{latex:#?}");
+}
+
+#[test]
+fn repeated_subexpressions_are_computed_once() {
+    let (latex, _) = compile("\
+var a: real = 3;
+var b: real = 4;
+let f(n: real) = n * n + 1;
+let hypotenuse = @sqrt(a * a + b * b);
+let scaled = 2 * @sqrt(a * a + b * b);
+let twice = f(a) + f(a) * f(a);
+let g(p: real, q: real) = @max(p * q + a, 0) - @min(p * q + a, 1);
+let h(p: real) = [@sqrt(p + k) + @sqrt(p + k) for k in [1, 2]];
+let r = @random(1, 2)[1] + @random(1, 2)[1];
+action step() {
+    a := @sqrt(a * a + b * b),
+    b := f(a),
+}
+", OptimizationLevel::Full);
+    assert_contains(&latex, &[
+        // A `let` with the repeated value is used where there is one, and otherwise one is generated
+        r"X_{hypotenuse}=\operatorname{sqrt}\left(\left(\left(X_{a}*X_{a}\right)+\left(X_{b}*X_{b}\right)\right)\right)",
+        r"X_{scaled}=\left(2*X_{hypotenuse}\right)",
+        r"X_{shared1}=X_{f}\left(X_{a}\right)",
+        r"X_{twice}=\left(X_{shared1}+\left(X_{shared1}*X_{shared1}\right)\right)",
+        r"X_{step}\left(\right)=\left(X_{a}\to X_{hypotenuse},X_{b}\to X_{shared1}\right)",
+        // Repeats using parameters are bound in the function
+        r"X_{g}\left(X_{p},X_{q}\right)=\left(\left(\operatorname{max}\left(X_{shared2},0\right)-\operatorname{min}\left(X_{shared2},1\right)\right)\operatorname{with}X_{shared2}=\left(\left(X_{p}*X_{q}\right)+X_{a}\right)\right)",
+        // But not repeats using a comprehension variable, or random values
+        r"X_{h}\left(X_{p}\right)=\left[\left(\operatorname{sqrt}\left(\left(X_{p}+X_{k}\right)\right)+\operatorname{sqrt}\left(\left(X_{p}+X_{k}\right)\right)\right)\operatorname{for}X_{k}=\left[1,2\right]\right]",
+        r"X_{r}=\left(\operatorname{random}\left(1,2\right)\left[1\right]+\operatorname{random}\left(1,2\right)\left[1\right]\right)",
+    ]);
+
+    // Generated names never clash with the program's own
+    let (latex, _) = compile("var a = 1;\nlet shared1 = 2;\nlet x = @sqrt(a) + @sqrt(a);\n", OptimizationLevel::Full);
+    assert_contains(&latex, &[r"X_{shared2}=\operatorname{sqrt}\left(X_{a}\right)", r"X_{x}=\left(X_{shared2}+X_{shared2}\right)"]);
+}
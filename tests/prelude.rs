@@ -0,0 +1,65 @@
+//! Tests for the standard library, which is linked into every program but only emitted where it is used.
+
+use desmosify::cli;
+use desmosify::format;
+use desmosify::link::{self, SourceFile};
+use desmosify::repl::Repl;
+use desmosify::target::{TargetOptions, TargetRegistry};
+
+fn compile(source: &str) -> Result<String, Vec<String>> {
+    cli::compile_source(&TargetRegistry::default(), source, "main.desmos", Some("desmos-graphing"), &TargetOptions::new())
+        .map(|output| output.to_string())
+        .map_err(|errors| Vec::from_iter(errors.iter().map(ToString::to_string)))
+}
+
+#[test]
+fn only_used_declarations_are_emitted() {
+    let output = compile("\
+import std::{normalize};
+var angle: real = 0;
+let direction = normalize(std::rotate((3, 4), angle));
+").unwrap();
+    // `normalize` uses `length`, which uses `dot`
+    for name in ["X_{stdnormalize}", "X_{stdlength}", "X_{stddot}", "X_{stdrotate}", "\"title\":\"std\""] {
+        assert!(output.contains(name), "{name} is missing from {output}");
+    }
+    for name in ["X_{stdeaseout}", "X_{stdpoke}", "X_{stdred}"] {
+        assert!(!output.contains(name), "{name} should not be in {output}");
+    }
+
+    // A program which does not use the standard library compiles as it would without it
+    let output = compile("let a = 1;").unwrap();
+    assert!(!output.contains("std"), "{output}");
+}
+
+#[test]
+fn names_must_exist_and_std_is_reserved() {
+    assert_eq!(compile("let a = std::missing(1);").unwrap_err(), ["(line 1:9) module 'std' has no declaration named 'missing'"]);
+    assert_eq!(compile("import std::{missing};").unwrap_err(), ["(line 1:14) module 'std' has no declaration named 'missing'"]);
+    let errors = link::link(&[SourceFile::new("main.desmos", "import \"lib/std.desmos\";")]).unwrap_err();
+    assert_eq!(errors[0].to_string(), "main.desmos (line 1:8) 'lib/std.desmos' cannot be imported as module 'std', which is already 'std'");
+}
+
+#[test]
+fn declarations_evaluate_as_documented() {
+    let mut repl = Repl::new();
+    let mut value = |expression: &str| repl.execute(expression).unwrap().into_iter().find(|line| line.starts_with("value: ")).unwrap();
+    assert_eq!(value("std::dot((1, 2), (3, 4))"), "value: 11");
+    assert_eq!(value("std::normalize((3, 4))"), "value: (0.6, 0.8)");
+    assert_eq!(value("std::rotate((1, 0), 0)"), "value: (1, 0)");
+    assert_eq!(value("std::poke([1, 2, 3], 2, 9)"), "value: [1, 9, 3]");
+    assert_eq!(value("std::index_of([4, 5, 6], 5)"), "value: 2");
+    assert_eq!(value("std::index_of([4, 5, 6], 7)"), "value: 0");
+    assert_eq!(value("std::count_where([1, 5, 7] > 2)"), "value: 2");
+    assert_eq!(value("std::last([1, 2, 3])"), "value: 3");
+    assert_eq!(value("std::grid_point(5, 3)"), "value: (1, 1)");
+    assert_eq!(value("std::grid_index(std::grid_point(5, 3), 3)"), "value: 5");
+    assert_eq!(value("std::in_grid((3, 1), 3, 2)"), "value: 0");
+    assert_eq!(value("std::ease_in_out(0.25)"), "value: 0.125");
+    assert_eq!(value("std::clamp(5, 0, 1)"), "value: 1");
+}
+
+#[test]
+fn source_is_formatted() {
+    assert_eq!(format::format(link::PRELUDE_SOURCE).unwrap(), link::PRELUDE_SOURCE);
+}
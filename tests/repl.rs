@@ -0,0 +1,160 @@
+//! Tests for the REPL, which feed it input the way a terminal would and check what it prints.
+
+use desmosify::repl::{self, Repl};
+
+fn transcript(input: &str) -> String {
+    let mut output = Vec::new();
+    repl::run(&mut Repl::new(), input.as_bytes(), &mut output, false).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn declarations_expressions_and_actions() {
+    let output = transcript("\
+var count: int = 2
+action bump() {
+    count := count + 1,
+}
+let doubled = 2 * count;
+(1, 2)
+doubled
+action bump()
+count := count * 10, if count > 0: count := 0,
+:vars
+");
+    assert_eq!(output, "\
+var count: int = 2
+action bump()
+let doubled: int
+type:  ipoint
+value: (1, 2) (constant)
+latex: \\left(1,2\\right)
+type:  ?
+value: 4
+latex: X_{doubled}
+count = 3
+error: (line 1:36) 'count' is updated more than once by the same action
+count = 3
+");
+}
+
+#[test]
+fn declaring_again_replaces_the_declaration() {
+    let mut repl = Repl::new();
+    repl.execute("var count: int = 1; var other: int = 5;").unwrap();
+    repl.execute("count := 4, other := 6").unwrap();
+    assert_eq!(repl.execute("let total = count + other").unwrap(), ["let total: int"]);
+    // Redeclaring a variable resets it, but the others keep their values
+    assert_eq!(repl.execute("var count: int = 0").unwrap(), ["var count: int = 0"]);
+    assert_eq!(repl.execute(":vars").unwrap(), ["count = 0", "other = 6"]);
+    assert_eq!(repl.execute("total").unwrap(), ["type:  ?", "value: 6", "latex: X_{total}"]);
+    assert_eq!(repl.execute(":list").unwrap(), ["var other: int = 5;", "let total = count + other;", "var count: int = 0;"]);
+
+    // A failed declaration leaves the session as it was, and locations are relative to the input
+    let error = repl.execute("let broken = missing;").unwrap_err();
+    assert_eq!(error.to_string(), "(line 1:14) could not find a definition for 'missing'");
+    assert_eq!(repl.execute(":list").unwrap().len(), 3);
+    assert_eq!(repl.execute(":reset").unwrap(), ["variables reset"]);
+    assert_eq!(repl.execute(":vars").unwrap(), ["count = 0", "other = 5"]);
+}
+
+#[test]
+fn ticks_and_clicks_run_against_the_live_state() {
+    let mut repl = Repl::new();
+    repl.execute("var timer elapsed = 0; var count: int = 0;").unwrap();
+    repl.execute("ticker(250) { count := count + 1 }").unwrap();
+    repl.execute("display { (count, 0): @rgb(1, 0, 0), click { count := 0 }; }").unwrap();
+    assert_eq!(repl.execute(":tick").unwrap(), ["count = 1", "elapsed = 0.25"]);
+    assert_eq!(repl.execute(":tick 750").unwrap(), ["count = 2", "elapsed = 1"]);
+    assert_eq!(repl.execute(":click 0").unwrap(), ["count = 0"]);
+    assert_eq!(repl.execute(":click 0").unwrap(), ["no variables changed"]);
+    assert!(repl.execute(":click 1").is_err());
+}
+
+#[test]
+fn target_pragma_can_be_entered() {
+    let mut repl = Repl::new();
+    repl.execute("let a = 1;").unwrap();
+    let geometry = repl.execute("@sqrt(a)").unwrap();
+    assert_eq!(geometry.last().unwrap(), "latex: \\operatorname{sqrt}\\left(X_{a}\\right)");
+    assert_eq!(repl.execute("#target \"desmos-graphing\"").unwrap(), ["#target \"desmos-graphing\""]);
+    assert_eq!(repl.execute("@sqrt(a)").unwrap(), geometry);
+}
+
+#[test]
+fn loading_an_example() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test/test.desmos");
+    let mut repl = Repl::new();
+    let lines = repl.execute(&format!(":load {}", path.display())).unwrap();
+    assert!(lines.iter().any(|line| line.starts_with("action next(")), "{lines:?}");
+    assert!(repl.execute(":quit").unwrap().is_empty());
+    assert!(repl.is_finished());
+}
+
+#[test]
+fn importing_a_module() {
+    let mut repl = Repl::new();
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/desmosify-test/easing.desmos");
+    assert_eq!(repl.execute(&format!("import \"{}\"", path.display())).unwrap(), ["import easing"]);
+    assert_eq!(repl.execute("easing::ease_out(1)").unwrap()[1], "value: 1");
+    let error = repl.execute("let a = easing::missing(1);").unwrap_err();
+    assert_eq!(error.to_string(), "(line 1:9) module 'easing' has no declaration named 'missing'");
+}
+
+#[test]
+fn operators_have_the_types_semantics_infers() {
+    let output = transcript("\
+var a: int = 3
+var x: real = 5
+var b: bool = true
+1 + 2
+a * 2
+x ^ 2
+a / 2
+[1, 2, 3] * 2
+(1, 2) * x
+!b
+b && !b
+(x == 5) || (x == 1)
+!(a == 4)
+[1, 2, 3] > 2
+");
+    assert_eq!(output, "\
+var a: int = 3
+var x: real = 5
+var b: bool = 1
+type:  int
+value: 3
+latex: \\left(1+2\\right)
+type:  int
+value: 6
+latex: \\left(X_{a}*2\\right)
+type:  real
+value: 25
+latex: X_{x}^{2}
+type:  real
+value: 1.5
+latex: \\frac{X_{a}}{2}
+type:  [int]
+value: [2, 4, 6]
+latex: \\left(\\left[1,2,3\\right]*2\\right)
+type:  point
+value: (5, 10)
+latex: \\left(\\left(1,2\\right)*X_{x}\\right)
+type:  bool
+value: 0
+latex: \\left\\{X_{b}=1:0,1\\right\\}
+type:  bool
+value: 0
+latex: \\left\\{X_{b}=1:\\left\\{X_{b}=1:0,1\\right\\},0\\right\\}
+type:  bool
+value: 1
+latex: \\left\\{X_{x}=5:1,X_{x}=1:1,0\\right\\}
+type:  bool
+value: 1
+latex: \\left\\{X_{a}=4:0,1\\right\\}
+type:  [bool]
+value: [0, 0, 1]
+latex: \\left\\{\\left[1,2,3\\right]\\gt2,0\\right\\}
+");
+}
@@ -0,0 +1,71 @@
+//! Tests for `semantics::analyze`: the values worked out at compile-time, and definitions that did not come straight
+//! from the parser.
+
+mod common;
+
+use common::Field;
+use desmosify::semantics;
+
+#[test]
+fn constants_and_initial_values_can_use_other_constants() {
+    let latex = common::compile("\
+enum Mode { SETUP, PLAY }
+const SIZE: int = 3;
+const COUNTS: [int] = [1, SIZE, SIZE * SIZE];
+const DELAY: real = 1.5 + SIZE;
+var mode: Mode = Mode.PLAY;
+var cells: [Mode] = [Mode.SETUP; SIZE - 1];
+var wait: real = DELAY;
+public { COUNTS; mode; cells; wait; }
+", &[Field::Expressions]).unwrap();
+    for expected in [
+        r"X_{COUNTS}=\left[1,3,9\right]",
+        r"X_{mode}=1",
+        r"X_{cells}=\left[0,0\right]",
+        r"X_{wait}=4.5",
+    ] {
+        assert!(latex.iter().any(|entry| entry == expected), "{expected} is missing from {latex:#?}");
+    }
+}
+
+#[test]
+fn enum_variants_are_numbered_in_declaration_order() {
+    let latex = common::compile("\
+enum Player { RED, BLUE }
+var player: Player = Player.RED;
+let next(p: Player): Player = { p == Player.RED: Player.BLUE, Player.RED };
+public { next(player); }
+", &[Field::Expressions]).unwrap();
+    assert!(latex.contains(&String::from(r"X_{next}\left(X_{p}\right)=\left\{X_{p}=0:1,0\right\}")), "{latex:#?}");
+
+    assert_eq!(
+        common::compile("enum Player { RED, BLUE }\nlet p = Player.GREEN;\npublic { p; }\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 2:9) enum Player has no variant named 'GREEN'"],
+    );
+}
+
+#[test]
+fn values_that_need_the_graph_are_not_constants() {
+    assert_eq!(
+        common::compile("let step = 2;\nconst SIZE: int = step * 2;\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 2:19) the definition of const SIZE could not be evaluated at compile-time"],
+    );
+    assert_eq!(
+        common::compile("var a: real = 1;\nvar b: real = a;\n", &[Field::Expressions]).unwrap_err(),
+        ["(line 2:15) var b must be initialized with a constant value"],
+    );
+}
+
+#[test]
+fn definitions_without_a_signature_are_errors() {
+    let tokens = desmosify::token::tokenize("let a = 1;\naction go() { }\n").unwrap();
+    for (name, expected) in [
+        ("a", "(line 1:5) 'a' is defined without a declaration"),
+        ("go", "(line 2:8) 'go' is defined without a declaration"),
+    ] {
+        let (mut signatures, mut definitions) = desmosify::syntax::parse(&tokens).unwrap();
+        signatures.user_defined.remove(name);
+        let error = semantics::analyze(&signatures, &mut definitions).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+}
@@ -0,0 +1,72 @@
+//! Tests for the pieces of watch mode which do not loop forever.
+
+use std::path::PathBuf;
+
+use desmosify::cli::{write_if_changed, Watcher};
+use desmosify::link::{self, SourceFile};
+
+fn scratch_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("desmosify-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn watcher_reports_changes_to_contents() {
+    let directory = scratch_directory("watcher");
+    let path = directory.join("main.desmos").to_string_lossy().into_owned();
+    let mut watcher = Watcher::new(std::slice::from_ref(&path));
+
+    // Missing files are not a change until they appear
+    assert!(!watcher.poll());
+    std::fs::write(&path, "let a = 1;").unwrap();
+    assert!(watcher.poll());
+    assert!(!watcher.poll());
+
+    // Saving the same contents again is not a change
+    std::fs::write(&path, "let a = 1;").unwrap();
+    assert!(!watcher.poll());
+    std::fs::write(&path, "let a = 2;").unwrap();
+    assert!(watcher.poll());
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(watcher.poll());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn output_is_only_written_when_it_changes() {
+    let directory = scratch_directory("output");
+    let path = directory.join("out.json");
+    let path_text = path.to_string_lossy();
+
+    assert!(write_if_changed(&path_text, "{}").unwrap());
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert!(!write_if_changed(&path_text, "{}").unwrap());
+    assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    assert!(write_if_changed(&path_text, "{\"a\":1}").unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"a\":1}");
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn imported_modules_are_watched() {
+    let directory = scratch_directory("watch-modules");
+    let main = directory.join("main.desmos").to_string_lossy().into_owned();
+    let module = directory.join("easing.desmos").to_string_lossy().into_owned();
+    std::fs::write(&main, "import easing;").unwrap();
+    std::fs::write(&module, "pub let a = 1;").unwrap();
+    let modules = link::module_paths(&[SourceFile::new(&main, "import easing;")]);
+    assert_eq!(modules, std::slice::from_ref(&module));
+
+    let mut watcher = Watcher::new(std::slice::from_ref(&main));
+    assert!(watcher.poll());
+    // Modules start out unchanged, and are only added once
+    watcher.add(&modules);
+    watcher.add(&modules);
+    assert!(!watcher.poll());
+    std::fs::write(&module, "pub let a = 2;").unwrap();
+    assert!(watcher.poll());
+    std::fs::remove_dir_all(directory).unwrap();
+}